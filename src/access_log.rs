@@ -0,0 +1,136 @@
+// src/access_log.rs
+//
+// One line per completed request (ACCESS_LOG=combined|json|off).
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HttpDate},
+    middleware::Next,
+    web, Error, HttpMessage, HttpRequest,
+};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::auth::AuthUser;
+use crate::consts::Config;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    Off,
+    /// Apache combined-style text line.
+    Combined,
+    Json,
+}
+
+struct Entry {
+    ip: String,
+    method: String,
+    path: String,
+    version: String,
+    referer: String,
+    user_agent: String,
+    status: u16,
+    bytes: Option<u64>,
+    micros: u128,
+    sub: Option<String>,
+}
+
+/// Middleware for `App::wrap(from_fn(..))`; reads the format from `Config`.
+pub async fn log(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let (format, trust_proxy) = req
+        .app_data::<web::Data<Config>>()
+        .map(|c| (c.access_log, c.trust_proxy))
+        .unwrap_or((AccessLogFormat::Off, false));
+    if format == AccessLogFormat::Off {
+        return next.call(req).await;
+    }
+
+    let started = Instant::now();
+    let header_str = |name| req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("-").to_string();
+    let mut entry = Entry {
+        ip: client_ip(req.request(), trust_proxy),
+        method: req.method().to_string(),
+        // path only: query strings may carry credentials
+        path: req.path().to_string(),
+        version: format!("{:?}", req.version()),
+        referer: header_str(header::REFERER),
+        user_agent: header_str(header::USER_AGENT),
+        status: 0,
+        bytes: None,
+        micros: 0,
+        sub: None,
+    };
+
+    let res = next.call(req).await;
+    entry.micros = started.elapsed().as_micros();
+    match &res {
+        Ok(res) => {
+            entry.status = res.status().as_u16();
+            entry.bytes = match res.response().body().size() {
+                BodySize::Sized(n) => Some(n),
+                // streamed bodies announce their length in the header
+                _ => res
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|s| s.parse().ok()),
+            };
+            entry.sub = res.request().extensions().get::<AuthUser>().and_then(|u| u.sub.clone());
+        }
+        Err(e) => entry.status = e.as_response_error().status_code().as_u16(),
+    }
+    println!("{}", entry.render(format));
+    res
+}
+
+/// Peer address, or the first `X-Forwarded-For` hop when behind a trusted proxy.
+/// The caller's address: the first X-Forwarded-For hop under TRUST_PROXY,
+/// else the peer address.
+pub(crate) fn client_ip(req: &HttpRequest, trust_proxy: bool) -> String {
+    if trust_proxy {
+        if let Some(first) = req
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+        {
+            return first.to_string();
+        }
+    }
+    req.peer_addr().map(|a| a.ip().to_string()).unwrap_or_else(|| "-".into())
+}
+
+impl Entry {
+    fn render(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Json => serde_json::json!({
+                "ts": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+                "ip": self.ip,
+                "sub": self.sub,
+                "method": self.method,
+                "path": self.path,
+                "status": self.status,
+                "bytes": self.bytes,
+                "duration_ms": self.micros as f64 / 1000.0,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+            })
+            .to_string(),
+            _ => format!(
+                "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {:.3}ms",
+                self.ip,
+                self.sub.as_deref().unwrap_or("-"),
+                HttpDate::from(SystemTime::now()),
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.bytes.map(|b| b.to_string()).unwrap_or_else(|| "-".into()),
+                self.referer,
+                self.user_agent,
+                self.micros as f64 / 1000.0,
+            ),
+        }
+    }
+}
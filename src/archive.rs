@@ -0,0 +1,431 @@
+// src/archive.rs
+//
+// Prefix downloads as zip / tar / tar.gz (`GET /objects-archive`). The
+// archive is written on a blocking thread into a bounded channel that feeds
+// the response body, so memory stays flat however large the prefix is and a
+// client that stops reading stalls the writer instead of buffering.
+//
+// Uploads (`POST /objects-archive`) go the other way: the spooled archive is
+// expanded entry by entry, each one landing like a PUT of its key.
+use actix_web::web::Bytes;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::sync::mpsc;
+
+use crate::events::{self, notify};
+use crate::meta;
+use crate::pending::now_secs;
+use crate::retention;
+use crate::routes::objects::resolve_key;
+use crate::storage::{is_internal, key_of, TempUpload};
+use crate::AppState;
+
+/// Bytes per body chunk; the channel holds a few of these at most.
+const CHUNK: usize = 64 * 1024;
+const CHANNEL_CHUNKS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "zip" => Some(Format::Zip),
+            "tar" => Some(Format::Tar),
+            "tar.gz" | "tgz" => Some(Format::TarGz),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::Tar => "tar",
+            Format::TarGz => "tar.gz",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Zip => "application/zip",
+            Format::Tar => "application/x-tar",
+            Format::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// One object to archive.
+pub struct Entry {
+    pub path: PathBuf,
+    /// Entry name: the key relative to the requested prefix.
+    pub name: String,
+    pub size: u64,
+    pub modified: u64,
+}
+
+/// Visible objects at or under `base`, sorted by name (blocking).
+pub fn collect(state: &AppState, base: &Path, now: u64) -> io::Result<Vec<Entry>> {
+    let root = &state.root;
+    let mut out = Vec::new();
+    let mut push = |path: PathBuf, meta: &std::fs::Metadata, name: String| {
+        if state.is_visible(&key_of(root, &path), now) {
+            let modified = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()).unwrap_or(0);
+            out.push(Entry { path, name, size: meta.len(), modified });
+        }
+    };
+    match std::fs::metadata(base) {
+        Ok(m) if m.is_file() => {
+            let name = base.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            push(base.to_path_buf(), &m, name);
+            return Ok(out);
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(out),
+        Err(e) => return Err(e),
+    }
+    let mut stack = vec![base.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            // named buckets aren't part of the default bucket's archive
+            if dir == *root && (is_internal(&entry.file_name()) || entry.file_name().to_str().is_some_and(|n| state.buckets.contains(n))) {
+                continue;
+            }
+            let ft = entry.file_type()?;
+            if ft.is_dir() {
+                stack.push(entry.path());
+            } else if ft.is_file() {
+                let path = entry.path();
+                let name = key_of(base, &path);
+                push(path, &entry.metadata()?, name);
+            }
+        }
+    }
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(out)
+}
+
+/// Starts writing `entries` as `format`; the receiver yields the archive in chunks.
+/// A write error (or a file that vanished mid-way) ends the stream with an error,
+/// so the client sees a broken transfer rather than a silently short archive.
+pub fn spawn(format: Format, entries: Vec<Entry>) -> mpsc::Receiver<io::Result<Bytes>> {
+    let (tx, rx) = mpsc::channel(CHANNEL_CHUNKS);
+    actix_web::rt::task::spawn_blocking(move || {
+        let mut out = ChannelWriter { tx: tx.clone(), buf: Vec::with_capacity(CHUNK), gone: false };
+        let res = match format {
+            Format::Zip => write_zip(&mut out, &entries),
+            Format::Tar => write_tar(&mut out, &entries),
+            Format::TarGz => {
+                let mut gz = flate2::write::GzEncoder::new(&mut out, flate2::Compression::fast());
+                write_tar(&mut gz, &entries).and_then(|()| gz.finish().map(drop))
+            }
+        }
+        .and_then(|()| out.flush());
+        if let Err(e) = res {
+            if !out.gone {
+                eprintln!("⚠️  archive stream failed: {e}");
+                let _ = tx.blocking_send(Err(e));
+            }
+        }
+    });
+    rx
+}
+
+fn write_tar(out: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    let mut tar = tar::Builder::new(out);
+    for entry in entries {
+        let (file, size) = open(entry)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(entry.modified);
+        tar.append_data(&mut header, &entry.name, file.take(size))?;
+    }
+    tar.finish()
+}
+
+fn write_zip(out: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new_stream(out);
+    for entry in entries {
+        let (mut file, size) = open(entry)?;
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .large_file(size >= u32::MAX as u64)
+            .unix_permissions(0o644);
+        if let Some(t) = zip_time(entry.modified) {
+            options = options.last_modified_time(t);
+        }
+        zip.start_file(entry.name.as_str(), options).map_err(io::Error::other)?;
+        io::copy(&mut (&mut file).take(size), &mut zip)?;
+    }
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// What an archive upload wrote and what it left out.
+#[derive(Serialize, Default, Debug)]
+pub struct Summary {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub skipped: Vec<Skipped>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Skipped {
+    pub name: String,
+    /// `invalid_path`, `not_a_file`, `path_conflict`, `legal_hold` or `object_locked` (under retention).
+    pub reason: &'static str,
+}
+
+#[derive(Debug)]
+pub enum ExtractError {
+    /// The extracted total passed MAX_UPLOAD_BYTES.
+    TooLarge,
+    /// MAX_OBJECT_COUNT reached.
+    CountExceeded,
+    /// The body isn't a readable archive of the requested format.
+    Invalid(String),
+    /// Writing to the bucket failed.
+    Io(io::Error),
+}
+
+pub struct Limits {
+    /// Cap on the extracted total (MAX_UPLOAD_BYTES).
+    pub max_bytes: Option<u64>,
+    pub max_objects: Option<u64>,
+}
+
+/// Expands `archive` under `prefix` (blocking). Entries are committed one at a
+/// time, so on error the summary still says exactly what was written.
+pub fn extract(state: &AppState, archive: &Path, format: Format, prefix: &str, limits: Limits) -> (Summary, Result<(), ExtractError>) {
+    let prefix = match prefix.trim_matches('/') {
+        "" => String::new(),
+        p => format!("{p}/"),
+    };
+    let mut x = Extractor { state, prefix, limits, extracted: 0, summary: Summary::default() };
+    let res = File::open(archive).map_err(ExtractError::Io).and_then(|file| match format {
+        Format::Zip => x.zip(file),
+        Format::Tar => x.tar(file),
+        Format::TarGz => x.tar(flate2::read::MultiGzDecoder::new(file)),
+    });
+    (x.summary, res)
+}
+
+struct Extractor<'a> {
+    state: &'a AppState,
+    prefix: String,
+    limits: Limits,
+    extracted: u64,
+    summary: Summary,
+}
+
+impl Extractor<'_> {
+    fn tar(&mut self, reader: impl Read) -> Result<(), ExtractError> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries().map_err(invalid)? {
+            let mut entry = entry.map_err(invalid)?;
+            // raw bytes: `path()` would quietly turn `/etc/x` into `etc/x`
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            match entry.header().entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let size = entry.size();
+                    self.entry(&name, size, &mut entry)?
+                }
+                tar::EntryType::Directory | tar::EntryType::XGlobalHeader => {}
+                _ => self.skip(name, "not_a_file"),
+            }
+        }
+        Ok(())
+    }
+
+    fn zip(&mut self, file: File) -> Result<(), ExtractError> {
+        let mut archive = zip::ZipArchive::new(file).map_err(invalid)?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(invalid)?;
+            let name = entry.name().to_string();
+            if entry.is_dir() {
+                continue;
+            }
+            if entry.is_symlink() || !entry.is_file() {
+                self.skip(name, "not_a_file");
+                continue;
+            }
+            let size = entry.size();
+            self.entry(&name, size, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, name: String, reason: &'static str) {
+        self.summary.skipped.push(Skipped { name, reason });
+    }
+
+    /// Writes one regular entry the way a PUT would: temp file, rename, fresh sidecar.
+    fn entry(&mut self, name: &str, size: u64, reader: &mut impl Read) -> Result<(), ExtractError> {
+        let state = self.state;
+        let root = &state.root;
+        // `tar -C dir .` names everything `./…`
+        let mut rel = name;
+        while let Some(rest) = rel.strip_prefix("./") {
+            rel = rest;
+        }
+        // checked on its own too: under a prefix, `/etc/x` would join as `site//etc/x`
+        let plain = Path::new(rel).components().all(|c| matches!(c, Component::Normal(_)));
+        let Some(path) = resolve_key(root, &format!("{}{rel}", self.prefix))
+            .filter(|p| plain && !state.buckets.owns(&key_of(root, p)))
+        else {
+            self.skip(name.to_string(), "invalid_path");
+            return Ok(());
+        };
+        let on_disk = std::fs::metadata(&path).ok();
+        if on_disk.as_ref().is_some_and(|m| !m.is_file()) || path.ancestors().skip(1).take_while(|a| *a != root.as_path()).any(Path::is_file) {
+            self.skip(name.to_string(), "path_conflict");
+            return Ok(());
+        }
+
+        if on_disk.is_some() && state.holds.contains(&key_of(root, &path)) {
+            self.skip(name.to_string(), "legal_hold");
+            return Ok(());
+        }
+        if on_disk.is_some() && retention::locked_at(root, &path, now_secs()).map_err(ExtractError::Io)?.is_some() {
+            self.skip(name.to_string(), "object_locked");
+            return Ok(());
+        }
+
+        let key = key_of(root, &path);
+        let was_pending = state.pending.is_pending(&key);
+        let expired = state.expiries.is_expired(&key, now_secs());
+        let slot = match on_disk {
+            Some(_) => None,
+            None => Some(state.objects.try_reserve(self.limits.max_objects).ok_or(ExtractError::CountExceeded)?),
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(ExtractError::Io)?;
+        }
+        let (tmp, mut file) = TempUpload::create_blocking(root).map_err(ExtractError::Io)?;
+        // a cut-off archive can read as a clean, short entry
+        if self.copy(reader, &mut file)? != size {
+            return Err(ExtractError::Invalid(format!("{name}: truncated entry")));
+        }
+        drop(file);
+        tmp.persist_blocking(&path).map_err(ExtractError::Io)?;
+        if let Some(side) = meta::sidecar_path(root, &path) {
+            match std::fs::remove_file(side) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(ExtractError::Io(e)),
+                _ => {}
+            }
+        }
+        state.pending.cancel(&key);
+        state.expiries.clear(&key);
+        state.forget_cached(&path);
+        if let Some(slot) = slot {
+            slot.commit();
+        }
+
+        let existed = on_disk.is_some() && !was_pending && !expired;
+        if events::wanted(state) {
+            if let Ok(fm) = std::fs::metadata(&path) {
+                notify(state, if existed { "object.overwritten" } else { "object.created" }, &path, &fm);
+            }
+        }
+        if existed { &mut self.summary.overwritten } else { &mut self.summary.created }.push(key);
+        Ok(())
+    }
+
+    /// Copies an entry's bytes, counting them against the extracted-total cap.
+    fn copy(&mut self, reader: &mut impl Read, out: &mut File) -> Result<u64, ExtractError> {
+        let mut buf = vec![0u8; CHUNK];
+        let mut copied = 0;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(copied),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(invalid(e)),
+            };
+            copied += n as u64;
+            self.extracted += n as u64;
+            if self.limits.max_bytes.is_some_and(|max| self.extracted > max) {
+                return Err(ExtractError::TooLarge);
+            }
+            out.write_all(&buf[..n]).map_err(ExtractError::Io)?;
+        }
+    }
+}
+
+fn invalid(e: impl std::fmt::Display) -> ExtractError {
+    ExtractError::Invalid(e.to_string())
+}
+
+/// Opens an entry, sized as of now (it may have changed since `collect`).
+fn open(entry: &Entry) -> io::Result<(File, u64)> {
+    let file = File::open(&entry.path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Unix seconds as a zip (MS-DOS, UTC) timestamp; `None` outside 1980..2107.
+fn zip_time(secs: u64) -> Option<zip::DateTime> {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // civil-from-days (Howard Hinnant)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let year = u16::try_from(year).ok()?;
+    zip::DateTime::from_date_and_time(year, month, day, (rem / 3600) as u8, (rem % 3600 / 60) as u8, (rem % 60) as u8).ok()
+}
+
+/// `Write` into the response channel, in CHUNK-sized pieces. Once the client
+/// has gone, the first write fails and later ones are swallowed, so the
+/// archive writers can unwind quietly.
+struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+    gone: bool,
+}
+
+impl ChannelWriter {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() || self.gone {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK)));
+        if self.tx.blocking_send(Ok(chunk)).is_err() {
+            self.gone = true;
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "client went away"));
+        }
+        Ok(())
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.gone {
+            return Ok(data.len());
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
@@ -1,15 +1,25 @@
 // src/auth.rs
 use actix_web::{
+    cookie::{Cookie, SameSite},
     dev::Payload,
     error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
-    http::header,
-    FromRequest, HttpRequest,
+    http::{header, Method},
+    FromRequest, HttpMessage, HttpRequest,
 };
-use futures_util::future::{ready, Ready};
-use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use futures_util::future::LocalBoxFuture;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
 use serde_json::Value;
+use std::path::{Component, Path};
+use std::time::Duration;
 
-use crate::consts::{AuthMode, Config};
+use crate::consts::{AuthMode, Config, PATH_OBJECTS};
+use crate::error::ApiError;
+use crate::jwks::Freshness;
+use crate::tenants::{self, TENANT_DIR};
+use crate::AppState;
+
+/// NAMESPACE_BY_SUB: callers' keys live under `users/{sub}/`.
+pub const JAIL_DIR: &str = "users";
 
 /// Minimal authenticated user we might want later
 #[derive(Clone, Debug)]
@@ -18,6 +28,25 @@ pub struct AuthUser {
     pub scopes: Vec<String>,
     pub iss: Option<String>,
     pub aud: Vec<String>,
+    /// Token id and expiry, for revocation on logout.
+    pub jti: Option<String>,
+    pub exp: Option<u64>,
+    /// Holds a plain JWT_SCOPES_ADMIN scope: passes every class's scope check.
+    pub is_admin: bool,
+    /// The tenant acted for: the token's TENANT_CLAIM once verified, then
+    /// whatever the gate resolved (see `tenants::resolve`).
+    pub tenant: Option<String>,
+}
+
+impl AuthUser {
+    fn anonymous() -> Self {
+        Self { sub: None, scopes: vec![], iss: None, aud: vec![], jti: None, exp: None, is_admin: false, tenant: None }
+    }
+
+    /// Whether any credential (token, API key, Basic) identified the caller.
+    pub fn is_authenticated(&self) -> bool {
+        self.sub.is_some() || self.exp.is_some() || !self.scopes.is_empty()
+    }
 }
 
 /// Require write scopes (PUT/DELETE)
@@ -26,32 +55,51 @@ pub struct NeedWrite(pub AuthUser);
 pub struct NeedRead(pub AuthUser);
 /// Require list scopes (list endpoints)
 pub struct NeedList(pub AuthUser);
+/// Require admin scopes (bucket management); protected whenever auth is on
+pub struct NeedAdmin(pub AuthUser);
 
 // ---------- Extractor impls ----------
 
 impl FromRequest for NeedWrite {
     type Error = actix_web::Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
-        ready(auth_gate(req, RouteClass::Write).map(NeedWrite))
+        let req = req.clone();
+        Box::pin(async move { auth_gate(&req, RouteClass::Write).await.map(|u| NeedWrite(remember(&req, u))) })
     }
 }
 impl FromRequest for NeedRead {
     type Error = actix_web::Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
-        ready(auth_gate(req, RouteClass::Read).map(NeedRead))
+        let req = req.clone();
+        Box::pin(async move { auth_gate(&req, RouteClass::Read).await.map(|u| NeedRead(remember(&req, u))) })
     }
 }
 impl FromRequest for NeedList {
     type Error = actix_web::Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { auth_gate(&req, RouteClass::List).await.map(|u| NeedList(remember(&req, u))) })
+    }
+}
+impl FromRequest for NeedAdmin {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
-        ready(auth_gate(req, RouteClass::List).map(NeedList))
+        let req = req.clone();
+        Box::pin(async move { auth_gate(&req, RouteClass::Admin).await.map(|u| NeedAdmin(remember(&req, u))) })
     }
 }
 
 
+/// Stores the caller in request extensions, for the access log.
+fn remember(req: &HttpRequest, user: AuthUser) -> AuthUser {
+    req.extensions_mut().insert(user.clone());
+    user
+}
+
 // ---------- Core gate ----------
 
 #[derive(Copy, Clone)]
@@ -59,9 +107,20 @@ enum RouteClass {
     Write,
     Read,
     List,
+    Admin,
+}
+
+/// The caller, checked for `class`, with the tenant they act for.
+async fn auth_gate(req: &HttpRequest, class: RouteClass) -> Result<AuthUser, actix_web::Error> {
+    let mut user = check_class(req, class).await?;
+    let (Some(cfg), Some(state)) = (req.app_data::<actix_web::web::Data<Config>>(), req.app_data::<actix_web::web::Data<AppState>>()) else {
+        return Err(ErrorInternalServerError("app data not found"));
+    };
+    user.tenant = tenants::resolve(req, cfg, state, &user).await?;
+    Ok(user)
 }
 
-fn auth_gate(req: &HttpRequest, class: RouteClass) -> Result<AuthUser, actix_web::Error> {
+async fn check_class(req: &HttpRequest, class: RouteClass) -> Result<AuthUser, actix_web::Error> {
     use actix_web::web::Data;
     use std::ops::Deref;
 
@@ -73,35 +132,44 @@ fn auth_gate(req: &HttpRequest, class: RouteClass) -> Result<AuthUser, actix_web
 
     // global off → allow
     if matches!(cfg.auth_mode, AuthMode::Off) {
-        return Ok(AuthUser { sub: None, scopes: vec![], iss: None, aud: vec![] });
+        return Ok(AuthUser::anonymous());
     }
     // class not protected → allow
     let class_protected = match class {
         RouteClass::Write => cfg.auth_write,
         RouteClass::Read  => cfg.auth_read,
         RouteClass::List  => cfg.auth_list,
+        RouteClass::Admin => true,
     };
-    if !class_protected {
-        return Ok(AuthUser { sub: None, scopes: vec![], iss: None, aud: vec![] });
+    // a namespace or a tenant claim still needs to know who is asking on unprotected routes
+    let identify = (cfg.namespace_by_sub || cfg.tenant_claim.is_some()) && presents_credentials(req, &cfg);
+    if class_protected && !presents_credentials(req, &cfg) && public_read(req, &cfg, class) {
+        return Ok(AuthUser::anonymous());
+    }
+    if !class_protected && !identify {
+        return Ok(AuthUser::anonymous());
     }
 
-    // bearer
-    let token = bearer_token(req).map_err(|_| ErrorUnauthorized("missing or invalid Authorization header"))?;
-
-    // verify by mode
-    let user = match cfg.auth_mode {
-        AuthMode::JwtHs256 => verify_hs256(&cfg, &token)?,
-        AuthMode::JwtRs256 => return Err(ErrorInternalServerError("RS256 verifier not implemented yet")),
-        AuthMode::Off => unreachable!(),
-    };
+    let user = authenticate(req, &cfg).await?;
+    if cfg.namespace_by_sub && user.sub.is_none() && !user.is_admin {
+        return Err(ErrorUnauthorized("token has no sub to namespace by"));
+    }
+    if !class_protected {
+        return Ok(user);
+    }
 
-    // scope check
+    // scope check; an admin scope (bucket-bound ones on their bucket) covers every class
+    let bucket = req.match_info().get("bucket");
+    if !cfg.jwt_scopes_admin.is_empty() && require_any_scope(&cfg.jwt_scopes_admin, &user.scopes, bucket) {
+        return Ok(user);
+    }
     let required = match class {
         RouteClass::Write => &cfg.jwt_scopes_write,
         RouteClass::Read  => &cfg.jwt_scopes_read,
         RouteClass::List  => &cfg.jwt_scopes_list,
+        RouteClass::Admin => &cfg.jwt_scopes_admin,
     };
-    if !require_any_scope(required, &user.scopes) {
+    if !require_any_scope(required, &user.scopes, bucket) {
         return Err(ErrorForbidden("insufficient scope"));
     }
 
@@ -109,8 +177,259 @@ fn auth_gate(req: &HttpRequest, class: RouteClass) -> Result<AuthUser, actix_web
 }
 
 
+/// Who may call `POST /auth/introspect`: the INTROSPECTION_SECRET bearer, or
+/// a caller holding an admin scope.
+pub(crate) async fn require_introspector(req: &HttpRequest, cfg: &Config) -> Result<(), actix_web::Error> {
+    if let (Some(secret), Ok(presented)) = (&cfg.introspection_secret, bearer_token(req)) {
+        if same_secret(&presented, secret) {
+            return Ok(());
+        }
+    }
+    if cfg.auth_mode == AuthMode::Off {
+        return Ok(());
+    }
+    if !authenticate(req, cfg).await?.is_admin {
+        return Err(ErrorForbidden("insufficient scope"));
+    }
+    Ok(())
+}
+
+/// The holder of `token` if it is a live JWT this server accepts: verified
+/// like a bearer token (signature, claims, denylist). `None` outside the JWT modes.
+pub(crate) async fn introspect(cfg: &Config, state: &AppState, token: &str) -> Option<AuthUser> {
+    if !matches!(cfg.auth_mode, AuthMode::JwtHs256 | AuthMode::JwtRs256) {
+        return None;
+    }
+    verify_token(cfg, state, token).await.ok()
+}
+
+/// The caller of an S3 request whose SigV4 signature `routes::s3` already
+/// checked; `authenticate` takes it in place of a bearer token.
+#[derive(Clone)]
+pub(crate) struct SignedCaller(pub AuthUser);
+
+/// Verifies the request's bearer token for the configured mode (no scope
+/// check) and rejects revoked ones. Not for `AuthMode::Off`.
+pub(crate) async fn authenticate(req: &HttpRequest, cfg: &Config) -> Result<AuthUser, actix_web::Error> {
+    let mut user = caller(req, cfg).await?;
+    user.is_admin = is_admin(cfg, &user);
+    Ok(user)
+}
+
+/// `authenticate` without the admin flag.
+async fn caller(req: &HttpRequest, cfg: &Config) -> Result<AuthUser, actix_web::Error> {
+    if let Some(SignedCaller(user)) = req.extensions().get::<SignedCaller>() {
+        return Ok(user.clone());
+    }
+    let state = req
+        .app_data::<actix_web::web::Data<AppState>>()
+        .ok_or_else(|| ErrorInternalServerError("AppState not found"))?;
+    if cfg.auth_mode == AuthMode::Basic {
+        return verify_basic(req, cfg, state).await;
+    }
+
+    // bearer (api_key mode also takes `x-api-key`)
+    let token = match (&cfg.auth_mode, req.headers().get("x-api-key")) {
+        (AuthMode::ApiKey, Some(v)) => v.to_str().map(|s| s.trim().to_string()).map_err(|_| ErrorUnauthorized("invalid x-api-key header"))?,
+        // the header wins over a query token or session cookie
+        _ if !req.headers().contains_key(header::AUTHORIZATION) => match query_token(req, cfg) {
+            Some(token) => token,
+            None => cookie_token(req, cfg)?.ok_or_else(|| ErrorUnauthorized("missing or invalid Authorization header"))?,
+        },
+        _ => bearer_token(req).map_err(|_| ErrorUnauthorized("missing or invalid Authorization header"))?,
+    };
+
+    verify_token(cfg, state, &token).await
+}
+
+/// Verifies `token` (a JWT or an API key) for the configured mode and rejects revoked ones.
+async fn verify_token(cfg: &Config, state: &AppState, token: &str) -> Result<AuthUser, actix_web::Error> {
+    let user = match cfg.auth_mode {
+        AuthMode::JwtHs256 => verify_hs256(cfg, token)?,
+        AuthMode::ApiKey => verify_api_key(cfg, token)?,
+        AuthMode::JwtRs256 => verify_rs256(cfg, state, token).await?,
+        AuthMode::Basic => return Err(ErrorUnauthorized("basic mode takes no tokens")),
+        AuthMode::Off => return Err(ErrorInternalServerError("auth is off")),
+    };
+
+    if user.jti.as_deref().is_some_and(|jti| state.denylist.is_revoked(jti)) {
+        return Err(ErrorUnauthorized("token revoked"));
+    }
+    Ok(user)
+}
+
+/// The caller behind an S3 access key id and the secret its requests are
+/// signed with. In api_key mode the id is a key's name and the key is the
+/// secret; in the JWT modes the id is the token, which doubles as the secret.
+/// Not for `AuthMode::Off`.
+pub(crate) async fn s3_credentials(cfg: &Config, state: &AppState, access_key: &str) -> Result<(AuthUser, String), actix_web::Error> {
+    if cfg.auth_mode != AuthMode::ApiKey {
+        return Ok((verify_token(cfg, state, access_key).await?, access_key.to_string()));
+    }
+    let key = cfg.api_keys.iter().find(|k| k.name == access_key).ok_or_else(|| ErrorUnauthorized("unknown access key"))?;
+    let user = AuthUser { sub: Some(key.name.clone()), scopes: key.scopes.clone(), ..AuthUser::anonymous() };
+    Ok((user, key.key.clone()))
+}
+
+/// The root-relative prefix `user`'s keys live under: their tenant's tree
+/// (`tenants/{name}`), and within it (or the root) `users/{sub}` under
+/// NAMESPACE_BY_SUB. `None` when neither applies: namespacing off, anonymous
+/// callers (only on unprotected routes) and admin scopes, which see real
+/// keys. A `sub` that isn't one plain path component (`..`, `a/b`, empty) is
+/// refused rather than resolved.
+pub(crate) fn jail(cfg: &Config, user: &AuthUser) -> Result<Option<String>, ApiError> {
+    let tenant = user.tenant.as_ref().map(|t| format!("{TENANT_DIR}/{t}"));
+    if !cfg.namespace_by_sub || cfg.auth_mode == AuthMode::Off || user.is_admin {
+        return Ok(tenant);
+    }
+    let Some(sub) = &user.sub else { return Ok(tenant) };
+    let mut parts = Path::new(sub).components();
+    match (parts.next(), parts.next()) {
+        (Some(Component::Normal(c)), None) if c == sub.as_str() => Ok(Some(match tenant {
+            Some(t) => format!("{t}/{JAIL_DIR}/{sub}"),
+            None => format!("{JAIL_DIR}/{sub}"),
+        })),
+        _ => Err(ApiError::new(actix_web::http::StatusCode::FORBIDDEN, "invalid_subject", "token sub can't name a namespace")),
+    }
+}
+
+/// For routes that see across keys (search, stats, archives, events, trash,
+/// uploads): closed to namespaced callers and tenants.
+pub(crate) fn refuse_jailed(cfg: &Config, user: &AuthUser) -> Result<(), ApiError> {
+    match jail(cfg, user)? {
+        Some(_) => Err(ApiError::new(
+            actix_web::http::StatusCode::FORBIDDEN,
+            "namespaced_caller",
+            "not available to callers confined by NAMESPACE_BY_SUB or a tenant",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether `user` holds a plain (not bucket-bound) admin scope.
+fn is_admin(cfg: &Config, user: &AuthUser) -> bool {
+    !cfg.jwt_scopes_admin.is_empty() && require_any_scope(&cfg.jwt_scopes_admin, &user.scopes, None)
+}
+
 // ---------- Helpers ----------
 
+/// Whether the request carries anything `authenticate` would check.
+fn presents_credentials(req: &HttpRequest, cfg: &Config) -> bool {
+    req.headers().contains_key(header::AUTHORIZATION)
+        || req.headers().contains_key("x-api-key")
+        || req.extensions().get::<SignedCaller>().is_some()
+        || query_token(req, cfg).is_some()
+        || (cfg.auth_cookie && req.cookie(SESSION_COOKIE).is_some())
+}
+
+/// AUTH_COOKIE: the session cookie `/auth/login` sets, holding the JWT.
+pub const SESSION_COOKIE: &str = "bkt_session";
+/// Double-submit CSRF token: a script-readable cookie whose value must be
+/// echoed in `CSRF_HEADER` on cookie-authenticated writes.
+pub const CSRF_COOKIE: &str = "bkt_csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// The session cookie's token under AUTH_COOKIE. Anything but GET/HEAD/OPTIONS
+/// must also carry `CSRF_HEADER` matching the CSRF cookie, since browsers
+/// attach cookies to cross-site requests.
+fn cookie_token(req: &HttpRequest, cfg: &Config) -> Result<Option<String>, actix_web::Error> {
+    if !cfg.auth_cookie {
+        return Ok(None);
+    }
+    let Some(session) = req.cookie(SESSION_COOKIE) else { return Ok(None) };
+    if !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        let expected = req.cookie(CSRF_COOKIE);
+        let presented = req.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+        let ok = match (expected, presented) {
+            (Some(expected), Some(presented)) => !expected.value().is_empty() && same_secret(presented, expected.value()),
+            _ => false,
+        };
+        if !ok {
+            return Err(ApiError::new(
+                actix_web::http::StatusCode::FORBIDDEN,
+                "csrf_failed",
+                format!("cookie-authenticated {} needs the {CSRF_HEADER} header to match the {CSRF_COOKIE} cookie", req.method()),
+            )
+            .into());
+        }
+    }
+    Ok(Some(session.value().to_string()))
+}
+
+/// The session and CSRF cookies for a fresh login, lasting `ttl_secs`.
+pub(crate) fn session_cookies(cfg: &Config, token: &str, ttl_secs: u64) -> [Cookie<'static>; 2] {
+    let csrf = uuid::Uuid::new_v4().simple().to_string();
+    let max_age = actix_web::cookie::time::Duration::seconds(ttl_secs as i64);
+    [
+        session_cookie(cfg, SESSION_COOKIE, token.to_string(), true).max_age(max_age).finish(),
+        session_cookie(cfg, CSRF_COOKIE, csrf, false).max_age(max_age).finish(),
+    ]
+}
+
+/// Expired copies of the session cookies, for logout.
+pub(crate) fn cleared_session_cookies(cfg: &Config) -> [Cookie<'static>; 2] {
+    let gone = actix_web::cookie::time::Duration::ZERO;
+    [
+        session_cookie(cfg, SESSION_COOKIE, String::new(), true).max_age(gone).finish(),
+        session_cookie(cfg, CSRF_COOKIE, String::new(), false).max_age(gone).finish(),
+    ]
+}
+
+fn session_cookie(cfg: &Config, name: &'static str, value: String, http_only: bool) -> actix_web::cookie::CookieBuilder<'static> {
+    Cookie::build(name, value)
+        .path("/")
+        .http_only(http_only)
+        .same_site(SameSite::Lax)
+        .secure(cfg.scheme() == "https")
+}
+
+/// PUBLIC_READ_PREFIXES: a GET/HEAD of a key under a public prefix, or a
+/// listing whose `prefix` lies inside one. Prefixes are root-relative, so a
+/// named bucket's keys match as `<bucket>/<key>`; keys with `.`/`..`
+/// segments never match.
+fn public_read(req: &HttpRequest, cfg: &Config, class: RouteClass) -> bool {
+    if cfg.public_read_prefixes.is_empty() || !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return false;
+    }
+    let key = match class {
+        RouteClass::Read if is_object_route(req) => req.match_info().get("key").map(str::to_string),
+        RouteClass::List if req.match_pattern().is_some_and(|p| p.ends_with(&format!("/{PATH_OBJECTS}"))) => {
+            query_param(req, "prefix")
+        }
+        _ => None,
+    };
+    let Some(key) = key else { return false };
+    let key = match req.match_info().get("bucket") {
+        Some(bucket) => format!("{bucket}/{key}"),
+        None => key,
+    };
+    key.split('/').all(|seg| seg != "." && seg != "..") && cfg.public_read_prefixes.iter().any(|p| key.starts_with(p.as_str()))
+}
+
+/// `/objects/{key}` or `/b/{bucket}/objects/{key}`.
+fn is_object_route(req: &HttpRequest) -> bool {
+    req.match_info().get("key").is_some() && req.match_pattern().is_some_and(|p| p.contains(&format!("/{PATH_OBJECTS}/")))
+}
+
+fn query_param(req: &HttpRequest, name: &str) -> Option<String> {
+    actix_web::web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .ok()?
+        .into_inner()
+        .into_iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value)
+}
+
+/// ALLOW_QUERY_TOKEN: the `?access_token=` of an object GET/HEAD, so plain
+/// links can carry credentials. Never for writes or other routes. The access
+/// log records paths without their query, so it never sees the token.
+fn query_token(req: &HttpRequest, cfg: &Config) -> Option<String> {
+    if !cfg.allow_query_token || !matches!(*req.method(), Method::GET | Method::HEAD) || !is_object_route(req) {
+        return None;
+    }
+    query_param(req, "access_token").map(|token| token.trim().to_string()).filter(|token| !token.is_empty())
+}
+
 /// Pulls the Bearer token from Authorization header
 fn bearer_token(req: &HttpRequest) -> Result<String, ()> {
     let val = req.headers().get(header::AUTHORIZATION).ok_or(())?;
@@ -123,6 +442,63 @@ fn bearer_token(req: &HttpRequest) -> Result<String, ()> {
     }
 }
 
+/// Sent with every basic-mode 401 so browsers prompt for credentials.
+const BASIC_CHALLENGE: &str = "Basic realm=\"rust-buck3t\"";
+
+fn basic_challenge(message: &'static str) -> actix_web::Error {
+    let resp = actix_web::HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, BASIC_CHALLENGE))
+        .body(message);
+    actix_web::error::InternalError::from_response(message, resp).into()
+}
+
+/// Basic path: `Authorization: Basic base64(user:password)` checked against
+/// the user store; scopes come from the user record or BASIC_SCOPES.
+async fn verify_basic(req: &HttpRequest, cfg: &Config, state: &AppState) -> Result<AuthUser, actix_web::Error> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    let encoded = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.strip_prefix("Basic "))
+        .ok_or_else(|| basic_challenge("missing or invalid Authorization header"))?;
+    let decoded = STANDARD
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|b| String::from_utf8(b).ok())
+        .ok_or_else(|| basic_challenge("malformed basic credentials"))?;
+    let (username, password) = decoded.split_once(':').ok_or_else(|| basic_challenge("malformed basic credentials"))?;
+    let throttle = &state.login_throttle;
+    let attempt = crate::throttle::Attempt::new(req, cfg, username);
+    attempt.check(throttle)?;
+    let Some(scopes) = crate::routes::session::verify_basic(cfg, username, password).await? else {
+        attempt.failed(throttle, cfg).await?;
+        return Err(basic_challenge("invalid credentials"));
+    };
+    attempt.succeeded(throttle);
+    Ok(AuthUser { sub: Some(username.to_string()), scopes, ..AuthUser::anonymous() })
+}
+
+/// Compares secrets in constant time, via fixed-length digests.
+fn same_secret(presented: &str, expected: &str) -> bool {
+    use sha2::{Digest, Sha256};
+    let (a, b) = (Sha256::digest(presented.as_bytes()), Sha256::digest(expected.as_bytes()));
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// API key path: every configured key is compared (in constant time) so
+/// timing reveals neither which key nor how much matched.
+fn verify_api_key(cfg: &Config, presented: &str) -> Result<AuthUser, actix_web::Error> {
+    let mut found = None;
+    for k in &cfg.api_keys {
+        if same_secret(presented, &k.key) && found.is_none() {
+            found = Some(k);
+        }
+    }
+    let key = found.ok_or_else(|| ErrorUnauthorized("invalid api key"))?;
+    Ok(AuthUser { sub: Some(key.name.clone()), scopes: key.scopes.clone(), ..AuthUser::anonymous() })
+}
+
 /// HS256 verification path
 fn verify_hs256(cfg: &Config, token: &str) -> Result<AuthUser, actix_web::Error> {
     let secret = cfg
@@ -130,11 +506,7 @@ fn verify_hs256(cfg: &Config, token: &str) -> Result<AuthUser, actix_web::Error>
         .as_ref()
         .ok_or_else(|| ErrorInternalServerError("JWT_HS_SECRET not set"))?;
 
-    let mut validation = Validation::new(Algorithm::HS256);
-    // Enforce exp
-    validation.validate_exp = true;
-    // Pin algorithm
-    validation.algorithms = vec![Algorithm::HS256];
+    let validation = validation(cfg, Algorithm::HS256);
 
     // jsonwebtoken's built-in aud/iss is finicky across versions; do explicit checks below.
     let data = decode::<Value>(
@@ -144,9 +516,85 @@ fn verify_hs256(cfg: &Config, token: &str) -> Result<AuthUser, actix_web::Error>
     )
     .map_err(|_| ErrorUnauthorized("invalid token"))?;
 
-    let claims = data.claims;
+    check_claims(cfg, data.claims, true)
+}
+
+/// RS256 verification path: keys come from the embedded IdP (trusted as-is)
+/// or from the JWKS of an allowed issuer / configured JWKS_URLS.
+async fn verify_rs256(cfg: &Config, state: &AppState, token: &str) -> Result<AuthUser, actix_web::Error> {
+    let header = decode_header(token).map_err(|_| ErrorUnauthorized("invalid token"))?;
+    if header.alg != Algorithm::RS256 {
+        return Err(ErrorUnauthorized("invalid token"));
+    }
+    let kid = header.kid.ok_or_else(|| ErrorUnauthorized("kid missing"))?;
+
+    let validation = validation(cfg, Algorithm::RS256);
 
+    // embedded IdP: our own key, so its issuer needs no allow-listing
+    if let Some(key) = state.idp.as_ref().and_then(|idp| idp.decoding_key(&kid)) {
+        let data = decode::<Value>(token, &key, &validation)
+            .map_err(|_| ErrorUnauthorized("invalid token"))?;
+        return check_claims(cfg, data.claims, false);
+    }
+
+    // remote: look the kid up in the JWKS documents we're allowed to trust
+    let freshness = Freshness {
+        ttl: Duration::from_secs(cfg.jwks_ttl_secs),
+        refetch: Duration::from_secs(cfg.jwks_refetch_secs),
+    };
+    for url in jwks_urls_for(cfg, token) {
+        let key = state
+            .jwks
+            .key_for(&url, &kid, freshness)
+            .await
+            .map_err(ErrorUnauthorized)?;
+        if let Some(key) = key {
+            let data = decode::<Value>(token, &key, &validation)
+                .map_err(|_| ErrorUnauthorized("invalid token"))?;
+            return check_claims(cfg, data.claims, true);
+        }
+    }
+    Err(ErrorUnauthorized("unknown signing key"))
+}
+
+/// Pins `alg` and checks exp/nbf within JWT_LEEWAY_SECS; aud is checked
+/// explicitly in check_claims.
+fn validation(cfg: &Config, alg: Algorithm) -> Validation {
+    let mut validation = Validation::new(alg);
+    validation.algorithms = vec![alg];
+    validation.validate_exp = true;
+    validation.validate_nbf = true;
+    validation.validate_aud = false;
+    validation.leeway = cfg.jwt_leeway_secs;
+    validation
+}
 
+/// Explicit JWKS_URLS, else `{iss}/.well-known/jwks.json` for an allow-listed (unverified) iss.
+fn jwks_urls_for(cfg: &Config, token: &str) -> Vec<String> {
+    if !cfg.jwks_urls.is_empty() {
+        return cfg.jwks_urls.clone();
+    }
+    let Some(iss) = unverified_claims(token).and_then(|c| c.get("iss").and_then(|v| v.as_str()).map(String::from)) else {
+        return Vec::new();
+    };
+    if cfg.jwt_issuers.contains(&iss) {
+        vec![format!("{}/.well-known/jwks.json", iss.trim_end_matches('/'))]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Payload of a JWT without checking the signature — only for picking a key.
+fn unverified_claims(token: &str) -> Option<Value> {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    let payload = token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Checks shared by every verifier once the signature is good: exp, nbf and
+/// iat (each within JWT_LEEWAY_SECS), iss, aud, JWT_REQUIRED_CLAIMS.
+fn check_claims(cfg: &Config, claims: Value, enforce_issuers: bool) -> Result<AuthUser, actix_web::Error> {
     // Explicit exp enforcement (required)
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -158,12 +606,19 @@ fn verify_hs256(cfg: &Config, token: &str) -> Result<AuthUser, actix_web::Error>
         .and_then(|v| v.as_u64())
         .ok_or_else(|| ErrorUnauthorized("exp missing"))?;
 
-    if now >= exp {
+    let leeway = cfg.jwt_leeway_secs;
+    if now >= exp.saturating_add(leeway) {
         return Err(ErrorUnauthorized("token expired"));
-    }    
+    }
+    if claims.get("nbf").and_then(|v| v.as_u64()).is_some_and(|nbf| nbf > now.saturating_add(leeway)) {
+        return Err(ErrorUnauthorized("token not yet valid"));
+    }
+    if claims.get("iat").and_then(|v| v.as_u64()).is_some_and(|iat| iat > now.saturating_add(leeway)) {
+        return Err(ErrorUnauthorized("token issued in the future"));
+    }
 
     // iss allow-list (if configured)
-    if !cfg.jwt_issuers.is_empty() {
+    if enforce_issuers && !cfg.jwt_issuers.is_empty() {
         let iss = claims.get("iss").and_then(|v| v.as_str()).ok_or_else(|| ErrorUnauthorized("iss missing"))?;
         if !cfg.jwt_issuers.iter().any(|a| a == iss) {
             return Err(ErrorUnauthorized("issuer not allowed"));
@@ -177,14 +632,51 @@ fn verify_hs256(cfg: &Config, token: &str) -> Result<AuthUser, actix_web::Error>
         }
     }
 
+    check_required_claims(&cfg.jwt_required_claims, &claims).map_err(ErrorUnauthorized)?;
+
     // scopes
     let scopes = scopes_from_claims(&claims);
 
     let sub = claims.get("sub").and_then(|v| v.as_str()).map(|s| s.to_string());
     let iss = claims.get("iss").and_then(|v| v.as_str()).map(|s| s.to_string());
     let aud = aud_values(&claims);
+    let jti = claims.get("jti").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let tenant = cfg.tenant_claim.as_ref().and_then(|c| claims.get(c)).and_then(|v| v.as_str()).map(|s| s.to_string());
 
-    Ok(AuthUser { sub, scopes, iss, aud })
+    Ok(AuthUser { sub, scopes, iss, aud, jti, exp: Some(exp), is_admin: false, tenant })
+}
+
+/// JWT_REQUIRED_CLAIMS: each `name` must be present and non-empty, each
+/// `name=value` must equal `value` exactly.
+fn check_required_claims(required: &[String], claims: &Value) -> Result<(), String> {
+    for rule in required {
+        let (name, expected) = match rule.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (rule.trim(), None),
+        };
+        let value = claims.get(name);
+        let present = match value {
+            None | Some(Value::Null) => false,
+            Some(Value::String(s)) => !s.is_empty(),
+            Some(Value::Array(a)) => !a.is_empty(),
+            Some(Value::Object(o)) => !o.is_empty(),
+            Some(_) => true,
+        };
+        if !present {
+            return Err(format!("required claim {name:?} missing"));
+        }
+        if let Some(expected) = expected {
+            let actual = match value {
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+                None => String::new(),
+            };
+            if actual != expected {
+                return Err(format!("claim {name:?} must be {expected:?}"));
+            }
+        }
+    }
+    Ok(())
 }
 
 /// Parse scopes from `scope` (space-delimited) or `scopes` (array) or `scp` (space-delimited).
@@ -201,13 +693,63 @@ fn scopes_from_claims(claims: &Value) -> Vec<String> {
     Vec::new()
 }
 
-/// require any overlap between configured route scopes and token scopes.
-/// If `required` is empty, allow (treat as not needed).
-fn require_any_scope(required: &[String], token_scopes: &[String]) -> bool {
+/// require any overlap between configured route scopes and token scopes
+/// (see `scope_matches`). If `required` is empty, allow (treat as not needed).
+/// On a named bucket's routes `<scope>@<bucket>` counts too; a plain scope
+/// covers every bucket.
+fn require_any_scope(required: &[String], token_scopes: &[String], bucket: Option<&str>) -> bool {
     if required.is_empty() {
         return true;
     }
-    token_scopes.iter().any(|s| required.iter().any(|r| r == s))
+    token_scopes.iter().any(|s| {
+        let scoped = match (s.rsplit_once('@'), bucket) {
+            (Some((scope, b)), Some(bucket)) if b == bucket => Some(scope),
+            _ => None,
+        };
+        required.iter().any(|r| scope_matches(s, r) || scoped.is_some_and(|scoped| scope_matches(scoped, r)))
+    })
+}
+
+/// Whether holding `granted` entitles a caller to `requested`: equal, or
+/// `granted` ends in a `*` standing for the rest (`obj:*` covers `obj:write`;
+/// `obj:write` doesn't cover `obj:*`). A `@bucket` suffix only narrows.
+pub(crate) fn scope_covers(granted: &str, requested: &str) -> bool {
+    let requested = requested.rsplit_once('@').map_or(requested, |(scope, _)| scope);
+    let mut g = granted.split(':').peekable();
+    let mut r = requested.split(':');
+    loop {
+        match (g.next(), r.next()) {
+            (None, None) => return true,
+            (Some("*"), Some(_)) if g.peek().is_none() => return true,
+            (Some(a), Some(b)) if a == b => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Whether `scope` would pass an admin check (JWT_SCOPES_ADMIN), on any bucket.
+pub(crate) fn is_admin_scope(cfg: &Config, scope: &str) -> bool {
+    let scope = scope.rsplit_once('@').map_or(scope, |(scope, _)| scope);
+    cfg.jwt_scopes_admin.iter().any(|a| scope_matches(scope, a))
+}
+
+/// Whether a token scope and a required scope overlap, compared by
+/// `:`-separated segments. A `*` as the last segment on either side stands
+/// for one or more segments (`obj:*` covers `obj:write` and `obj:write:x`,
+/// not `obj`; a lone `*` covers everything). Anywhere else `*` is literal,
+/// and without one the scopes must be equal.
+fn scope_matches(granted: &str, required: &str) -> bool {
+    let mut g = granted.split(':').peekable();
+    let mut r = required.split(':').peekable();
+    loop {
+        match (g.next(), r.next()) {
+            (None, None) => return true,
+            (Some("*"), Some(_)) if g.peek().is_none() => return true,
+            (Some(_), Some("*")) if r.peek().is_none() => return true,
+            (Some(a), Some(b)) if a == b => {}
+            _ => return false,
+        }
+    }
 }
 
 /// Returns true if claims.aud matches expected (string or array)
@@ -227,3 +769,81 @@ fn aud_values(claims: &Value) -> Vec<String> {
         _ => vec![],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    fn hs256(claims: Value) -> Result<AuthUser, actix_web::Error> {
+        let mut cfg = Config::from_env();
+        cfg.jwt_hs_secret = Some("leeway-secret".into());
+        cfg.jwt_issuers.clear();
+        cfg.jwt_audience = None;
+        cfg.jwt_leeway_secs = 30;
+        let token = encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(b"leeway-secret")).unwrap();
+        verify_hs256(&cfg, &token)
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn exp_nbf_and_iat_get_the_leeway() {
+        let now = now();
+        assert!(hs256(serde_json::json!({ "sub": "x", "exp": now - 10 })).is_ok());
+        assert!(hs256(serde_json::json!({ "sub": "x", "exp": now - 60 })).is_err());
+        assert!(hs256(serde_json::json!({ "sub": "x", "exp": now + 600, "nbf": now + 10, "iat": now + 10 })).is_ok());
+        assert!(hs256(serde_json::json!({ "sub": "x", "exp": now + 600, "nbf": now + 60 })).is_err());
+        assert!(hs256(serde_json::json!({ "sub": "x", "exp": now + 600, "iat": now + 3600 })).is_err());
+    }
+
+    #[test]
+    fn scope_wildcards_match_by_segment() {
+        // exact match stays the default
+        assert!(scope_matches("obj:write", "obj:write"));
+        assert!(!scope_matches("obj:write:extra", "obj:write"));
+        assert!(!scope_matches("obj:write", "obj:write:extra"));
+        assert!(!scope_matches("obj:writer", "obj:write"));
+        assert!(!scope_matches("obj", "obj:write"));
+        // trailing wildcards in the token
+        assert!(scope_matches("obj:*", "obj:write"));
+        assert!(scope_matches("obj:*", "obj:write:extra"));
+        assert!(scope_matches("*", "bkt:admin"));
+        assert!(!scope_matches("obj:*", "obj"));
+        assert!(!scope_matches("obj:*", "objects:write"));
+        assert!(!scope_matches("obj*", "obj:write"));
+        assert!(!scope_matches("*:write", "obj:write"));
+        // and in the required scope
+        assert!(scope_matches("obj:read", "obj:*"));
+        assert!(!scope_matches("bkt:admin", "obj:*"));
+        assert!(scope_matches("anything", "*"));
+        assert!(scope_matches("obj:*", "obj:*"));
+
+        let required = vec!["obj:write".to_string()];
+        assert!(require_any_scope(&required, &["obj:*@photos".into()], Some("photos")));
+        assert!(!require_any_scope(&required, &["obj:*@photos".into()], Some("videos")));
+        assert!(!require_any_scope(&required, &["obj:read".into(), "bkt:*".into()], None));
+    }
+
+    #[test]
+    fn required_claims_must_be_present_and_match() {
+        let required: Vec<String> = vec!["sub".into(), "aud".into(), "token_use=access".into()];
+        let ok = serde_json::json!({ "sub": "x", "aud": ["api"], "token_use": "access" });
+        assert_eq!(check_required_claims(&required, &ok), Ok(()));
+        assert_eq!(check_required_claims(&[], &serde_json::json!({})), Ok(()));
+
+        let no_sub = serde_json::json!({ "aud": "api", "token_use": "access" });
+        assert_eq!(check_required_claims(&required, &no_sub), Err("required claim \"sub\" missing".into()));
+        let empty_sub = serde_json::json!({ "sub": "", "aud": "api", "token_use": "access" });
+        assert!(check_required_claims(&required, &empty_sub).is_err());
+        let empty_aud = serde_json::json!({ "sub": "x", "aud": [], "token_use": "access" });
+        assert!(check_required_claims(&required, &empty_aud).is_err());
+        let id_token = serde_json::json!({ "sub": "x", "aud": "api", "token_use": "id" });
+        assert_eq!(check_required_claims(&required, &id_token), Err("claim \"token_use\" must be \"access\"".into()));
+
+        let numeric: Vec<String> = vec!["ver=2".into()];
+        assert_eq!(check_required_claims(&numeric, &serde_json::json!({ "ver": 2 })), Ok(()));
+    }
+}
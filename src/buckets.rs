@@ -0,0 +1,104 @@
+// src/buckets.rs
+//
+// Named buckets: first-level namespaces, each a directory directly under the
+// root (`/b/{bucket}/objects/...`). The default bucket (`/objects/...`) is
+// the root itself, minus the named buckets' directories. Which directories
+// are buckets is recorded as marker files in `<root>/.buckets/`, so a bucket
+// is never confused with a default-bucket prefix of the same name.
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::meta::META_DIR;
+
+/// Registry directory (directly under the root): one empty file per bucket.
+pub const BUCKETS_DIR: &str = ".buckets";
+
+/// Whether `name` may name a bucket: 3–63 of `a-z 0-9 - _ .`, starting and
+/// ending with a letter or digit, no `..` (S3 rules, plus `_`).
+pub fn valid_name(name: &str) -> bool {
+    let b = name.as_bytes();
+    (3..=63).contains(&b.len())
+        && b.iter().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, b'-' | b'_' | b'.'))
+        && b[0].is_ascii_alphanumeric()
+        && b[b.len() - 1].is_ascii_alphanumeric()
+        && !name.contains("..")
+}
+
+#[derive(Default)]
+pub struct Buckets {
+    names: RwLock<BTreeSet<String>>,
+}
+
+impl Buckets {
+    /// Reads the registry under `root`; a missing registry means no buckets.
+    pub fn load(root: &Path) -> Self {
+        let names = std::fs::read_dir(root.join(BUCKETS_DIR))
+            .map(|rd| {
+                rd.flatten()
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|n| valid_name(n))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { names: RwLock::new(names) }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.read().unwrap().contains(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.names.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Registers `name` and creates its directory; false if it already was
+    /// a bucket. The caller validates the name.
+    pub fn create(&self, root: &Path, name: &str) -> io::Result<bool> {
+        if self.contains(name) {
+            return Ok(false);
+        }
+        std::fs::create_dir_all(root.join(name))?;
+        std::fs::create_dir_all(root.join(BUCKETS_DIR))?;
+        std::fs::write(root.join(BUCKETS_DIR).join(name), b"")?;
+        Ok(self.names.write().unwrap().insert(name.to_string()))
+    }
+
+    /// Unregisters `name` and removes its directory and sidecars, objects
+    /// included; the caller checks emptiness first.
+    pub fn remove(&self, root: &Path, name: &str) -> io::Result<()> {
+        for dir in [root.join(name), root.join(META_DIR).join(name)] {
+            match std::fs::remove_dir_all(&dir) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        self.names.write().unwrap().remove(name);
+        match std::fs::remove_file(root.join(BUCKETS_DIR).join(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether root-relative `key` is a named bucket's directory or lies
+    /// inside one, i.e. is out of the default bucket's reach.
+    pub fn owns(&self, key: &str) -> bool {
+        self.contains(key.split('/').next().unwrap_or(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_names() {
+        for ok in ["abc", "public-assets", "user_uploads", "v1.2", "a".repeat(63).as_str()] {
+            assert!(valid_name(ok), "{ok}");
+        }
+        for bad in ["ab", "", "...", "a..b", "-abc", "abc.", "Abc", "a/b", "a b", ".meta", "a".repeat(64).as_str()] {
+            assert!(!valid_name(bad), "{bad}");
+        }
+    }
+}
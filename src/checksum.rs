@@ -0,0 +1,98 @@
+// src/checksum.rs
+//
+// Client-declared digests on PUT: `x-bkt-checksum-sha256` and
+// `x-bkt-checksum-crc32c`, hex-encoded. The body is hashed as it is written
+// (after any gzip decoding, i.e. the bytes GET returns); every header sent
+// must match or the upload is refused. Verified digests go into the sidecar
+// and are replayed on GET/HEAD under the same header names.
+use actix_web::http::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+pub const SHA256_HEADER: &str = "x-bkt-checksum-sha256";
+pub const CRC32C_HEADER: &str = "x-bkt-checksum-crc32c";
+
+/// The algorithms understood, as (sidecar name, header, hex digits).
+pub const ALGORITHMS: &[(&str, &str, usize)] = &[("sha256", SHA256_HEADER, 64), ("crc32c", CRC32C_HEADER, 8)];
+
+#[derive(Debug)]
+pub enum ChecksumError {
+    /// Not hex of the algorithm's length; carries the header name.
+    Malformed(&'static str),
+    /// The body hashed to something else.
+    Mismatch { algorithm: &'static str, expected: String, actual: String },
+}
+
+/// Digests a PUT asked to be checked, lowercase hex by algorithm name.
+#[derive(Debug, Default)]
+pub struct Expected(BTreeMap<&'static str, String>);
+
+pub fn from_request(headers: &HeaderMap) -> Result<Expected, ChecksumError> {
+    let mut wanted = BTreeMap::new();
+    for &(name, header, digits) in ALGORITHMS {
+        let Some(v) = headers.get(header) else { continue };
+        let hex = v.to_str().map(|s| s.trim().to_ascii_lowercase()).map_err(|_| ChecksumError::Malformed(header))?;
+        if hex.len() != digits || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ChecksumError::Malformed(header));
+        }
+        wanted.insert(name, hex);
+    }
+    Ok(Expected(wanted))
+}
+
+impl Expected {
+    /// A hasher for the requested algorithms, plus SHA-256 when `sha256`
+    /// (dedup needs it either way).
+    pub fn hasher(&self, sha256: bool) -> Hasher {
+        Hasher {
+            sha256: (sha256 || self.0.contains_key("sha256")).then(Sha256::new),
+            crc32c: self.0.contains_key("crc32c").then_some(0),
+        }
+    }
+
+    /// Compares what was written against every declared digest; on success
+    /// the verified digests, ready for the sidecar.
+    pub fn verify(self, hasher: Hasher) -> Result<BTreeMap<String, String>, ChecksumError> {
+        let actual = hasher.finish();
+        for (algorithm, expected) in &self.0 {
+            let got = actual.get(*algorithm).cloned().unwrap_or_default();
+            if &got != expected {
+                return Err(ChecksumError::Mismatch { algorithm, expected: expected.clone(), actual: got });
+            }
+        }
+        Ok(actual)
+    }
+}
+
+/// Running digests of a body.
+pub struct Hasher {
+    sha256: Option<Sha256>,
+    crc32c: Option<u32>,
+}
+
+impl Hasher {
+    pub fn update(&mut self, chunk: &[u8]) {
+        if let Some(h) = &mut self.sha256 {
+            h.update(chunk);
+        }
+        if let Some(crc) = &mut self.crc32c {
+            *crc = crc32c::crc32c_append(*crc, chunk);
+        }
+    }
+
+    fn finish(self) -> BTreeMap<String, String> {
+        let mut out = BTreeMap::new();
+        if let Some(h) = self.sha256 {
+            out.insert("sha256".to_string(), hex::encode(h.finalize()));
+        }
+        if let Some(crc) = self.crc32c {
+            out.insert("crc32c".to_string(), format!("{crc:08x}"));
+        }
+        out
+    }
+}
+
+/// The header a stored digest is replayed under.
+pub fn header_for(algorithm: &str) -> Option<&'static str> {
+    ALGORITHMS.iter().find(|(name, _, _)| *name == algorithm).map(|(_, header, _)| *header)
+}
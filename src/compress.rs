@@ -0,0 +1,150 @@
+// src/compress.rs
+//
+// On-the-fly response compression for full-body GETs (COMPRESSION=1), and
+// decoding of gzip request bodies on PUT. Only text-like content types are
+// encoded; ranges and already-compressed formats are served as stored. Each
+// encoding gets its own ETag so caches never mix representations.
+use async_compression::tokio::bufread::{BrotliEncoder, GzipDecoder, GzipEncoder};
+use std::pin::Pin;
+use tokio::io::{AsyncBufRead, AsyncRead, BufReader};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// `Content-Encoding` token.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// The ETag of this encoding of an object: `W/"<tag>-gzip"` for `W/"<tag>"`.
+    pub fn etag(self, etag: &str) -> String {
+        let suffix = match self {
+            Encoding::Gzip => "-gzip",
+            Encoding::Brotli => "-br",
+        };
+        match etag.strip_suffix('"') {
+            Some(open) => format!("{open}{suffix}\""),
+            None => format!("{etag}{suffix}"),
+        }
+    }
+
+    /// Wraps `reader` in this encoder.
+    pub fn encode<R: AsyncRead + Send + 'static>(self, reader: R) -> Pin<Box<dyn AsyncRead + Send>> {
+        let reader = BufReader::new(reader);
+        match self {
+            Encoding::Gzip => Box::pin(GzipEncoder::new(reader)),
+            Encoding::Brotli => Box::pin(BrotliEncoder::new(reader)),
+        }
+    }
+}
+
+/// Decodes a gzip request body (`Content-Encoding: gzip` on PUT). Corrupt
+/// input reads as `InvalidData`, a truncated stream as `UnexpectedEof`.
+pub fn gunzip<R: AsyncBufRead>(reader: R) -> impl AsyncRead {
+    let mut decoder = GzipDecoder::new(reader);
+    decoder.multiple_members(true);
+    decoder
+}
+
+/// The encoding to use for an `Accept-Encoding` value: the highest q-value
+/// wins, brotli on a tie; `None` means identity.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+    let mut wildcard = None;
+    let mut listed = Vec::new();
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|v| v.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let enc = match coding.as_str() {
+            "br" => Encoding::Brotli,
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "*" => {
+                wildcard = Some(q);
+                continue;
+            }
+            _ => continue,
+        };
+        listed.push(enc);
+        consider(&mut best, enc, q);
+    }
+    // `*` covers whichever of ours the client didn't name
+    if let Some(q) = wildcard {
+        for enc in [Encoding::Brotli, Encoding::Gzip] {
+            if !listed.contains(&enc) {
+                consider(&mut best, enc, q);
+            }
+        }
+    }
+    best.map(|(enc, _)| enc)
+}
+
+fn consider(best: &mut Option<(Encoding, f32)>, enc: Encoding, q: f32) {
+    if q <= 0.0 {
+        return;
+    }
+    let better = match *best {
+        None => true,
+        Some((cur, cur_q)) => q > cur_q || (q == cur_q && enc == Encoding::Brotli && cur != Encoding::Brotli),
+    };
+    if better {
+        *best = Some((enc, q));
+    }
+}
+
+/// Whether a content type is worth compressing (text, JSON, XML, JS, SVG).
+/// Images, audio/video, archives and other binaries are already dense.
+pub fn compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    // a stream that must flush per event; never buffer it in an encoder
+    if mime == "text/event-stream" {
+        return false;
+    }
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json" | "application/javascript" | "application/xml" | "application/wasm" | "image/svg+xml"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_by_q_value() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("br;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0, br;q=0"), None);
+        assert_eq!(negotiate("identity, deflate"), None);
+        assert_eq!(negotiate("*;q=0.3, gzip;q=0.1"), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn varies_the_etag() {
+        assert_eq!(Encoding::Gzip.etag("W/\"5-10-0\""), "W/\"5-10-0-gzip\"");
+        assert_eq!(Encoding::Brotli.etag("W/\"5-10-0\""), "W/\"5-10-0-br\"");
+    }
+
+    #[test]
+    fn skips_dense_types() {
+        assert!(compressible("text/plain; charset=utf-8"));
+        assert!(compressible("application/json"));
+        assert!(!compressible("image/png"));
+        assert!(!compressible("application/zip"));
+        assert!(!compressible("text/event-stream"));
+    }
+}
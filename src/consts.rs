@@ -1,6 +1,13 @@
 // src/consts.rs
 
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::access_log::AccessLogFormat;
+use crate::content_types::ContentTypes;
+use crate::headers::{self, HeaderRule};
+use crate::keys::KeyRules;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -8,101 +15,744 @@ pub struct Config {
     pub port: u16,
     pub root_dir: String,
     pub max_upload_bytes: Option<u64>,
+    pub dedup: bool,                         // store identical PUT bodies once, as hard links to .blobs/ (DEDUP, default false)
+    pub reject_concurrent_writes: bool,      // 409 instead of waiting when a key is being written (REJECT_CONCURRENT_WRITES)
+    pub durable_writes: bool,                // fsync PUT bodies and the user store before answering (DURABLE_WRITES, default false)
+    pub require_content_length: bool,        // 411 for PUTs without Content-Length (REQUIRE_CONTENT_LENGTH, default false)
+    pub size_inputs: Vec<(&'static str, String, u64)>, // byte counts as written (`10MB`) and parsed, for the banner
+    pub max_object_count: Option<u64>,       // cap on stored objects (MAX_OBJECT_COUNT)
+    pub max_concurrent_uploads: Option<usize>,   // PUT bodies streamed at once (MAX_CONCURRENT_UPLOADS, default unlimited)
+    pub max_concurrent_downloads: Option<usize>, // GET bodies streamed at once (MAX_CONCURRENT_DOWNLOADS, default unlimited)
+    pub concurrency_wait_ms: u64,            // how long a transfer waits for a slot before 503 (default 100)
+    pub upload_rate_limit_bps: Option<u64>,  // all PUT bodies together, bytes/s (UPLOAD_RATE_LIMIT_BPS, default unlimited)
+    pub upload_rate_limit_per_upload_bps: Option<u64>, // each PUT body, bytes/s (UPLOAD_RATE_LIMIT_PER_UPLOAD_BPS)
+    pub download_rate_limit_bps: Option<u64>, // all GET/archive bodies together, bytes/s (DOWNLOAD_RATE_LIMIT_BPS)
+    pub upload_idle_timeout_secs: u64,       // 408 when a PUT body sends nothing this long (default 30, 0 = never)
+    pub upload_deadline_secs: u64,           // 408 when a PUT body takes longer than this overall (default 0 = never)
+    pub min_free_bytes: u64,                 // free space a PUT must leave on the volume, else 507 (MIN_FREE_BYTES, default 0)
+    // Defaults from timing 512 MiB GET/PUT over loopback (release build, one core):
+    // GET took 1.06s with 8 KiB reads, 0.47s with 64 KiB, 0.36s with 256 KiB and
+    // 0.42s with 1 MiB; PUT gained ~5-10% from a 1 MiB write buffer, and no more from 4 MiB.
+    pub stream_chunk_bytes: usize,           // file read size for GET bodies (STREAM_CHUNK_BYTES, default 256 KiB)
+    pub write_buffer_bytes: usize,           // buffer between a PUT body and its file (WRITE_BUFFER_BYTES, default 1 MiB)
+    pub archive_max_bytes: u64,              // cap on an archive download's content (default 10 GiB)
+    pub archive_max_entries: u64,            // cap on an archive download's entries (default 10000)
+    pub list_max_results: u64,               // cap on a buffered listing's entries (default 100000, 0 = none)
+    pub list_hide_dotfiles: bool,            // listings skip names starting with `.` unless `?hide_dotfiles=0` (default false)
+    pub list_exclude: Vec<String>,           // globs over keys listings leave out (LIST_EXCLUDE, CSV)
+    pub meta_cache_entries: usize,           // objects whose GET/HEAD headers are cached (default 10000, 0 = off)
+    pub meta_cache_ttl_secs: u64,            // how long a cached entry is trusted (default 60)
+    pub hot_cache_max_object_bytes: u64,     // GET bodies up to this size served from memory (default 0 = off)
+    pub hot_cache_total_bytes: u64,          // memory for those bodies in all (default 64 MiB)
+    pub delete_grace_secs: u64,              // defer DELETE removal by this long (default 0 = immediate)
+    pub stats_refresh_secs: u64,             // how long /stats results are reused (default 60)
+    pub expiry_sweep_secs: u64,              // how often expired objects are deleted (default 60)
+    pub trash_enabled: bool,                 // DELETE moves objects to .trash/ (TRASH_ENABLED, default false)
+    pub trash_retention_secs: u64,           // purge trash entries after this long (default 7 days, 0 = never)
+    pub resumable_expiry_secs: u64,          // drop resumable uploads idle this long (default 1 day, 0 = never)
+    pub shutdown_grace_secs: u64,            // time in-flight requests get on SIGTERM/SIGINT (default 30)
     pub auth_max_ttl_secs: u64,
+    pub auth_json_limit_bytes: usize,        // max JSON body on /auth/* (default 4096)
+    pub auth_user_db: String,                // dev user store (default "./auth/users.json")
+    pub strict_query: bool,                  // reject unknown query params (default false)
+    pub header_rules: Vec<HeaderRule>,       // per-prefix response headers (HEADER_RULES_FILE)
+    pub content_types: ContentTypes,         // extension → type overrides (CONTENT_TYPES: ext=type,... or a JSON file)
+    pub access_log: AccessLogFormat,         // "combined" (default), "json", "off"
+    pub store_gzip_uploads: bool,            // keep `Content-Encoding: gzip` PUT bodies compressed (default: decode)
+    pub buckets: Vec<String>,                // named buckets created at startup (BUCKETS, comma-separated)
+    pub ui_enabled: bool,                    // serve the browser UI at /ui (default false; needs the `ui` feature)
+    pub compression: bool,                   // gzip/br for text-like GET bodies (default false)
+    pub trust_proxy: bool,                   // take client IP from X-Forwarded-For (default false)
+    pub tls_cert_path: Option<String>,       // PEM chain; with TLS_KEY_PATH serves HTTPS
+    pub tls_key_path: Option<String>,        // PEM private key
+    pub webhook_url: Option<String>,         // POST object events here (WEBHOOK_URL)
+    pub webhook_secret: Option<String>,      // HMAC key for x-bucket-signature
+    pub webhook_queue_size: usize,           // pending events kept before dropping the oldest (default 1000)
+    pub key_rules: KeyRules,                 // key length/character limits (KEY_MAX_BYTES, KEY_SEGMENT_MAX_BYTES, ...)
+    pub s3_enabled: bool,                    // serve the S3-compatible subset at /{bucket}/{key} (default false)
+    pub s3_skip_signature: bool,             // dev: accept any SigV4 signature from a known access key (default false)
 
     // --- Auth config (config-only in this step) ---
-    pub auth_mode: AuthMode,                 // "jwt_rs256" (default), "jwt_hs256", "off"
+    pub auth_mode: AuthMode,                 // "jwt_rs256" (default), "jwt_hs256", "api_key", "basic", "off"
     pub auth_write: bool,                    // protect PUT/DELETE (default true)
     pub auth_read: bool,                     // protect GET/HEAD (default false)
     pub auth_list: bool,                     // protect listing (default false)
+    pub namespace_by_sub: bool,              // confine each caller to `users/{sub}/` (NAMESPACE_BY_SUB, default false)
+    pub tenant_header: Option<String>,       // request header naming the caller's tenant (TENANT_HEADER, e.g. x-bkt-tenant)
+    pub tenant_claim: Option<String>,        // token claim naming it; wins over the header (TENANT_CLAIM)
+    pub tenant_default: Option<String>,      // tenant for requests that name none (TENANT_DEFAULT; unset = 400)
+    pub tenant_auto_create: bool,            // a tenant's first write makes its directory (TENANT_AUTO_CREATE, default false)
+    pub signup_mode: SignupMode,             // "open" (default), "invite", "closed"
+    pub allow_query_token: bool,             // accept `?access_token=` on object GET/HEAD (default false)
+    pub auth_cookie: bool,                   // login sets an HttpOnly session cookie, accepted without a header (default false)
+    pub public_read_prefixes: Vec<String>,   // root-relative key prefixes anyone may GET/HEAD/list (PUBLIC_READ_PREFIXES, CSV)
+    pub health_details_admin: bool,          // `/healthz?verbose=1` needs admin scope (HEALTH_DETAILS_ADMIN, default false)
     pub jwt_scopes_write: Vec<String>,       // default ["obj:write"]
     pub jwt_scopes_read: Vec<String>,        // default ["obj:read"]
     pub jwt_scopes_list: Vec<String>,        // default ["obj:list"]
+    pub jwt_scopes_admin: Vec<String>,       // bucket management; default ["bkt:admin"]
+    pub basic_scopes: Vec<String>,           // basic mode: scopes for users without their own (default write+read+list)
+    pub login_max_failures: u32,             // failed logins per user/IP before a lockout (default 5; 0 = off)
+    pub login_window_secs: u64,              // failures count within this window (default 300)
+    pub login_lockout_secs: u64,             // lockout length (default 900)
+    pub login_failure_delay_ms: u64,         // pause added to every failed login (default 250)
     pub jwt_audience: Option<String>,        // optional
     // RS256
     pub jwt_issuers: Vec<String>,            // CSV allow-list
     pub jwks_urls: Vec<String>,              // CSV optional explicit URLs
     pub jwks_ttl_secs: u64,                  // default 300
+    pub jwks_refetch_secs: u64,              // an unknown kid refetches a URL's JWKS at most this often (default 30)
+    pub jwt_leeway_secs: u64,                // clock skew allowed on exp/nbf/iat (default 30)
+    pub jwt_required_claims: Vec<String>,    // CSV of claims a token must carry: `sub` or `token_use=access`
     // HS256
     pub jwt_hs_secret: Option<String>,       // required only in jwt_hs256 mode
+    pub introspection_secret: Option<String>, // bearer credential for POST /auth/introspect (besides admin scopes)
+    // API keys (api_key mode)
+    pub api_keys: Vec<ApiKey>,               // API_KEYS (CSV of name:key:scopes) + API_KEYS_FILE lines
     // Built-in IdP
     pub idp_embed: bool,                     // enable internal issuer (dev)
     pub idp_key_dir: String,                 // default "./keys"
+    pub idp_rotate_days: u64,                // new signing key when the current one is this old (0 = manual only)
+}
+
+/// A static API key and the scopes it grants.
+#[derive(Clone)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    pub scopes: Vec<String>,
+}
+
+// never print the key itself
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKey").field("name", &self.name).field("scopes", &self.scopes).finish_non_exhaustive()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AuthMode {
     JwtRs256,
     JwtHs256,
+    ApiKey,
+    Basic,
     Off,
 }
 
+impl AuthMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMode::JwtRs256 => "jwt_rs256",
+            AuthMode::JwtHs256 => "jwt_hs256",
+            AuthMode::ApiKey => "api_key",
+            AuthMode::Basic => "basic",
+            AuthMode::Off => "off",
+        }
+    }
+}
+
+impl std::str::FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "jwt_rs256" => Ok(AuthMode::JwtRs256),
+            "jwt_hs256" => Ok(AuthMode::JwtHs256),
+            "api_key" => Ok(AuthMode::ApiKey),
+            "basic" => Ok(AuthMode::Basic),
+            "off" => Ok(AuthMode::Off),
+            _ => Err("not one of jwt_rs256, jwt_hs256, api_key, basic, off".into()),
+        }
+    }
+}
+
+/// Who may call `/auth/signup` (SIGNUP_MODE).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignupMode {
+    /// Anyone who can reach the server.
+    Open,
+    /// Holders of a one-time code from `POST /auth/invites`.
+    Invite,
+    /// Nobody; signup answers 403.
+    Closed,
+}
+
+impl SignupMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignupMode::Open => "open",
+            SignupMode::Invite => "invite",
+            SignupMode::Closed => "closed",
+        }
+    }
+}
+
+impl std::str::FromStr for SignupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "open" => Ok(SignupMode::Open),
+            "invite" => Ok(SignupMode::Invite),
+            "closed" => Ok(SignupMode::Closed),
+            _ => Err("not one of open, invite, closed".into()),
+        }
+    }
+}
+
+/// Settings given on the command line; each one set replaces the value
+/// from the environment or config file (see `Config::merge`).
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub root_dir: Option<String>,
+    pub max_upload_bytes: Option<u64>,
+    pub max_object_count: Option<u64>,
+    pub auth_mode: Option<AuthMode>,
+}
+
 impl Config {
+    /// Reads the environment (and `.env`, and the `RUST_BUCKET_CONFIG`
+    /// file), falling back to defaults for anything unset or unparseable;
+    /// each fallback is printed as a warning. The binary uses
+    /// `try_from_env`, which refuses to start instead.
     pub fn from_env() -> Self {
         dotenvy::dotenv().ok();
+        let env: HashMap<String, String> = env::vars().collect();
+        let mut reader = EnvReader::new(env_file(&env).as_deref(), env);
+        let cfg = Self::read(&mut reader);
+        reader.print_warnings();
+        for problem in &reader.problems {
+            eprintln!("⚠️  {problem}");
+        }
+        cfg
+    }
+
+    /// Like `from_env`, but any unparseable value (a non-numeric `PORT`,
+    /// `MAX_UPLOAD_BYTES=10QB`, an unknown `AUTH_MODE`) or a `validate`
+    /// failure is an error listing every problem found.
+    pub fn try_from_env() -> Result<Self, ConfigError> {
+        dotenvy::dotenv().ok();
+        let env: HashMap<String, String> = env::vars().collect();
+        Self::from_sources(env_file(&env).as_deref(), &env)
+    }
+
+    /// Builds the config from an optional TOML file and a set of environment
+    /// variables; a variable wins over the file. File keys are the variable
+    /// names in lowercase (`max_upload_bytes`), with the auth settings in an
+    /// `[auth]` table (`[auth] mode = "api_key"` for `AUTH_MODE`). Unknown
+    /// keys are warnings, or problems with `RUST_BUCKET_CONFIG_STRICT=1`.
+    pub fn from_sources(file: Option<&Path>, env: &HashMap<String, String>) -> Result<Self, ConfigError> {
+        Self::resolve(file, env, &Overrides::default())
+    }
+
+    /// `from_sources` with command-line `overrides` on top, validated as
+    /// merged: CLI > environment > file > defaults.
+    pub fn resolve(file: Option<&Path>, env: &HashMap<String, String>, overrides: &Overrides) -> Result<Self, ConfigError> {
+        let mut reader = EnvReader::new(file, env.clone());
+        let cfg = Self::read(&mut reader).merge(overrides);
+        reader.print_warnings();
+        let mut problems = reader.problems;
+        if let Err(e) = cfg.validate() {
+            problems.extend(e.problems);
+        }
+        if problems.is_empty() { Ok(cfg) } else { Err(ConfigError { problems }) }
+    }
+
+    /// Applies the settings `overrides` sets, leaving the rest alone.
+    pub fn merge(mut self, overrides: &Overrides) -> Self {
+        let o = overrides.clone();
+        if let Some(host) = o.host {
+            self.host = host;
+        }
+        if let Some(port) = o.port {
+            self.port = port;
+        }
+        if let Some(root_dir) = o.root_dir {
+            self.root_dir = root_dir;
+        }
+        if o.max_upload_bytes.is_some() {
+            self.max_upload_bytes = o.max_upload_bytes;
+            self.size_inputs.retain(|(name, ..)| *name != "MAX_UPLOAD_BYTES");
+        }
+        if o.max_object_count.is_some() {
+            self.max_object_count = o.max_object_count;
+        }
+        if let Some(mode) = o.auth_mode {
+            self.auth_mode = mode;
+        }
+        self
+    }
+
+    /// The effective settings in config-file form (`--print-config`), with
+    /// secrets and API keys replaced by `<redacted>`.
+    pub fn to_toml_redacted(&self) -> String {
+        use toml::Value;
+        const REDACTED: &str = "<redacted>";
+        let int = |n: u64| Value::Integer(i64::try_from(n).unwrap_or(i64::MAX));
+        let list = |v: &[String]| Value::Array(v.iter().cloned().map(Value::String).collect());
+        let opt = |v: &Option<String>| v.clone().map(Value::String);
+        let secret = |v: &Option<String>| v.as_ref().map(|_| Value::String(REDACTED.into()));
+
+        let access_log = match self.access_log {
+            AccessLogFormat::Off => "off",
+            AccessLogFormat::Combined => "combined",
+            AccessLogFormat::Json => "json",
+        };
+        let top: Vec<(&str, Option<Value>)> = vec![
+            ("host", Some(Value::String(self.host.clone()))),
+            ("port", Some(int(self.port.into()))),
+            ("root_dir", Some(Value::String(self.root_dir.clone()))),
+            ("max_upload_bytes", self.max_upload_bytes.map(int)),
+            ("require_content_length", Some(Value::Boolean(self.require_content_length))),
+            ("durable_writes", Some(Value::Boolean(self.durable_writes))),
+            ("reject_concurrent_writes", Some(Value::Boolean(self.reject_concurrent_writes))),
+            ("dedup", Some(Value::Boolean(self.dedup))),
+            ("max_object_count", self.max_object_count.map(int)),
+            ("max_concurrent_uploads", self.max_concurrent_uploads.map(|n| int(n as u64))),
+            ("max_concurrent_downloads", self.max_concurrent_downloads.map(|n| int(n as u64))),
+            ("concurrency_wait_ms", Some(int(self.concurrency_wait_ms))),
+            ("upload_rate_limit_bps", self.upload_rate_limit_bps.map(int)),
+            ("upload_rate_limit_per_upload_bps", self.upload_rate_limit_per_upload_bps.map(int)),
+            ("download_rate_limit_bps", self.download_rate_limit_bps.map(int)),
+            ("upload_idle_timeout_secs", Some(int(self.upload_idle_timeout_secs))),
+            ("upload_deadline_secs", Some(int(self.upload_deadline_secs))),
+            ("min_free_bytes", Some(int(self.min_free_bytes))),
+            ("stream_chunk_bytes", Some(int(self.stream_chunk_bytes as u64))),
+            ("write_buffer_bytes", Some(int(self.write_buffer_bytes as u64))),
+            ("archive_max_bytes", Some(int(self.archive_max_bytes))),
+            ("archive_max_entries", Some(int(self.archive_max_entries))),
+            ("list_max_results", Some(int(self.list_max_results))),
+            ("list_hide_dotfiles", Some(Value::Boolean(self.list_hide_dotfiles))),
+            ("list_exclude", Some(list(&self.list_exclude))),
+            ("meta_cache_entries", Some(int(self.meta_cache_entries as u64))),
+            ("meta_cache_ttl_secs", Some(int(self.meta_cache_ttl_secs))),
+            ("hot_cache_max_object_bytes", Some(int(self.hot_cache_max_object_bytes))),
+            ("hot_cache_total_bytes", Some(int(self.hot_cache_total_bytes))),
+            ("delete_grace_secs", Some(int(self.delete_grace_secs))),
+            ("stats_refresh_secs", Some(int(self.stats_refresh_secs))),
+            ("expiry_sweep_secs", Some(int(self.expiry_sweep_secs))),
+            ("trash_enabled", Some(Value::Boolean(self.trash_enabled))),
+            ("trash_retention_secs", Some(int(self.trash_retention_secs))),
+            ("resumable_expiry_secs", Some(int(self.resumable_expiry_secs))),
+            ("shutdown_grace_secs", Some(int(self.shutdown_grace_secs))),
+            ("strict_query", Some(Value::Boolean(self.strict_query))),
+            ("content_types", (!self.content_types.is_empty()).then(|| Value::String(self.content_types.to_spec()))),
+            ("access_log", Some(Value::String(access_log.into()))),
+            ("store_gzip_uploads", Some(Value::Boolean(self.store_gzip_uploads))),
+            ("ui_enabled", Some(Value::Boolean(self.ui_enabled))),
+            ("buckets", Some(list(&self.buckets))),
+            ("compression", Some(Value::Boolean(self.compression))),
+            ("trust_proxy", Some(Value::Boolean(self.trust_proxy))),
+            ("tls_cert_path", opt(&self.tls_cert_path)),
+            ("tls_key_path", opt(&self.tls_key_path)),
+            ("webhook_url", opt(&self.webhook_url)),
+            ("webhook_secret", secret(&self.webhook_secret)),
+            ("webhook_queue_size", Some(int(self.webhook_queue_size as u64))),
+            ("key_max_bytes", Some(int(self.key_rules.max_bytes as u64))),
+            ("key_segment_max_bytes", Some(int(self.key_rules.segment_max_bytes as u64))),
+            ("key_forbidden_chars", Some(Value::String(self.key_rules.forbidden_chars.iter().collect()))),
+            ("key_portable_names", Some(Value::Boolean(self.key_rules.portable_names))),
+            ("blocked_prefixes", Some(list(&self.key_rules.blocked_prefixes))),
+            ("s3_enabled", Some(Value::Boolean(self.s3_enabled))),
+            ("s3_skip_signature", Some(Value::Boolean(self.s3_skip_signature))),
+        ];
+
+        let api_keys: Vec<String> =
+            self.api_keys.iter().map(|k| format!("{}:{REDACTED}:{}", k.name, k.scopes.join(" "))).collect();
+        let auth: Vec<(&str, Option<Value>)> = vec![
+            ("mode", Some(Value::String(self.auth_mode.as_str().into()))),
+            ("write", Some(Value::Boolean(self.auth_write))),
+            ("read", Some(Value::Boolean(self.auth_read))),
+            ("list", Some(Value::Boolean(self.auth_list))),
+            ("namespace_by_sub", Some(Value::Boolean(self.namespace_by_sub))),
+            ("tenant_header", opt(&self.tenant_header)),
+            ("tenant_claim", opt(&self.tenant_claim)),
+            ("tenant_default", opt(&self.tenant_default)),
+            ("tenant_auto_create", Some(Value::Boolean(self.tenant_auto_create))),
+            ("signup_mode", Some(Value::String(self.signup_mode.as_str().into()))),
+            ("allow_query_token", Some(Value::Boolean(self.allow_query_token))),
+            ("cookie", Some(Value::Boolean(self.auth_cookie))),
+            ("public_read_prefixes", Some(list(&self.public_read_prefixes))),
+            ("health_details_admin", Some(Value::Boolean(self.health_details_admin))),
+            ("max_ttl_secs", Some(int(self.auth_max_ttl_secs))),
+            ("json_limit_bytes", Some(int(self.auth_json_limit_bytes as u64))),
+            ("user_db", Some(Value::String(self.auth_user_db.clone()))),
+            ("scopes_write", Some(list(&self.jwt_scopes_write))),
+            ("scopes_read", Some(list(&self.jwt_scopes_read))),
+            ("scopes_list", Some(list(&self.jwt_scopes_list))),
+            ("scopes_admin", Some(list(&self.jwt_scopes_admin))),
+            ("basic_scopes", Some(list(&self.basic_scopes))),
+            ("login_max_failures", Some(int(self.login_max_failures as u64))),
+            ("login_window_secs", Some(int(self.login_window_secs))),
+            ("login_lockout_secs", Some(int(self.login_lockout_secs))),
+            ("login_failure_delay_ms", Some(int(self.login_failure_delay_ms))),
+            ("jwt_audience", opt(&self.jwt_audience)),
+            ("jwt_issuers", Some(list(&self.jwt_issuers))),
+            ("jwks_urls", Some(list(&self.jwks_urls))),
+            ("jwks_ttl_secs", Some(int(self.jwks_ttl_secs))),
+            ("jwks_refetch_secs", Some(int(self.jwks_refetch_secs))),
+            ("jwt_leeway_secs", Some(int(self.jwt_leeway_secs))),
+            ("jwt_required_claims", Some(list(&self.jwt_required_claims))),
+            ("jwt_hs_secret", secret(&self.jwt_hs_secret)),
+            ("introspection_secret", secret(&self.introspection_secret)),
+            ("api_keys", Some(list(&api_keys))),
+            ("idp_embed", Some(Value::Boolean(self.idp_embed))),
+            ("idp_key_dir", Some(Value::String(self.idp_key_dir.clone()))),
+            ("idp_rotate_days", Some(int(self.idp_rotate_days))),
+        ];
+
+        let table = |entries: Vec<(&str, Option<Value>)>| -> toml::Table {
+            entries.into_iter().filter_map(|(k, v)| Some((k.to_string(), v?))).collect()
+        };
+        let mut doc = table(top);
+        doc.insert("auth".into(), Value::Table(table(auth)));
+        let mut out = toml::to_string(&doc).unwrap_or_default();
+        if !self.header_rules.is_empty() {
+            out.insert_str(0, &format!("# header_rules_file: {} rule(s) loaded\n", self.header_rules.len()));
+        }
+        out
+    }
+
+    /// Checks that the settings can work together: a secret for HS256, a
+    /// trusted issuer for RS256, a root that isn't a file.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if self.auth_mode == AuthMode::JwtHs256 && self.jwt_hs_secret.is_none() {
+            problems.push("AUTH_MODE=jwt_hs256 requires JWT_HS_SECRET".to_string());
+        }
+        if self.auth_mode == AuthMode::JwtRs256 && self.jwt_issuers.is_empty() && self.jwks_urls.is_empty() && !self.idp_embed {
+            problems.push("AUTH_MODE=jwt_rs256 requires JWT_ISSUERS or JWKS_URLS, or IDP_EMBED=1".to_string());
+        }
+        if std::fs::metadata(&self.root_dir).is_ok_and(|m| !m.is_dir()) {
+            problems.push(format!("RUST_BUCKET_DIR={} exists but is not a directory", self.root_dir));
+        }
+        if problems.is_empty() { Ok(()) } else { Err(ConfigError { problems }) }
+    }
 
-        let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".into());
-        let port = env::var("PORT")
-            .ok()
-            .and_then(|s| s.parse::<u16>().ok())
+    fn read(env: &mut EnvReader) -> Self {
+        let host = env.var("HOST").unwrap_or_else(|| "0.0.0.0".into());
+        let port = env.num::<u16>("PORT")
             .unwrap_or(8080);
 
-        let root_dir = env::var("RUST_BUCKET_DIR").unwrap_or_else(|_| "data".into());
+        let root_dir = env.var("RUST_BUCKET_DIR").unwrap_or_else(|| "data".into());
+
+        let max_upload_bytes = env.size("MAX_UPLOAD_BYTES");
+        let require_content_length = env.flag("REQUIRE_CONTENT_LENGTH").unwrap_or(false);
+        let durable_writes = env.flag("DURABLE_WRITES").unwrap_or(false);
+        let reject_concurrent_writes = env.flag("REJECT_CONCURRENT_WRITES").unwrap_or(false);
+        let dedup = env.flag("DEDUP").unwrap_or(false);
+
+        let max_object_count = env.num::<u64>("MAX_OBJECT_COUNT");
+        let max_concurrent_uploads = env.num::<usize>("MAX_CONCURRENT_UPLOADS").filter(|n| *n > 0);
+        let max_concurrent_downloads = env.num::<usize>("MAX_CONCURRENT_DOWNLOADS").filter(|n| *n > 0);
+        let concurrency_wait_ms = env.num::<u64>("CONCURRENCY_WAIT_MS").unwrap_or(100);
+        let upload_rate_limit_bps = env.size("UPLOAD_RATE_LIMIT_BPS").filter(|n| *n > 0);
+        let upload_rate_limit_per_upload_bps = env.size("UPLOAD_RATE_LIMIT_PER_UPLOAD_BPS").filter(|n| *n > 0);
+        let download_rate_limit_bps = env.size("DOWNLOAD_RATE_LIMIT_BPS").filter(|n| *n > 0);
+        let upload_idle_timeout_secs = env.num::<u64>("UPLOAD_IDLE_TIMEOUT_SECS").unwrap_or(30);
+        let upload_deadline_secs = env.num::<u64>("UPLOAD_DEADLINE_SECS").unwrap_or(0);
+        let min_free_bytes = env.size("MIN_FREE_BYTES").unwrap_or(0);
+        let stream_chunk_bytes = env.buffer("STREAM_CHUNK_BYTES", 256 * 1024);
+        let write_buffer_bytes = env.buffer("WRITE_BUFFER_BYTES", 1024 * 1024);
 
-        let max_upload_bytes = env::var("MAX_UPLOAD_BYTES")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok());
+        let archive_max_bytes = env.size("ARCHIVE_MAX_BYTES")
+            .unwrap_or(10 * 1024 * 1024 * 1024);
+        let archive_max_entries = env.num::<u64>("ARCHIVE_MAX_ENTRIES")
+            .unwrap_or(10_000);
+        let list_max_results = env.num::<u64>("LIST_MAX_RESULTS")
+            .unwrap_or(100_000);
+        let list_hide_dotfiles = env.flag("LIST_HIDE_DOTFILES").unwrap_or(false);
+        let mut list_exclude = parse_csv(env.var("LIST_EXCLUDE")).unwrap_or_default();
+        list_exclude.retain(|pattern| match key_glob(pattern) {
+            Ok(_) => true,
+            Err(e) => {
+                env.problem(format!("LIST_EXCLUDE pattern {pattern:?} ignored: {e}"));
+                false
+            }
+        });
+        let meta_cache_entries = env.num::<usize>("META_CACHE_ENTRIES")
+            .unwrap_or(10_000);
+        let meta_cache_ttl_secs = env.num::<u64>("META_CACHE_TTL_SECS")
+            .unwrap_or(60);
+        let hot_cache_max_object_bytes = env.size("HOT_CACHE_MAX_OBJECT_BYTES").unwrap_or(0);
+        let hot_cache_total_bytes = env.size("HOT_CACHE_TOTAL_BYTES").unwrap_or(64 * 1024 * 1024);
 
-        let auth_max_ttl_secs = env::var("AUTH_MAX_TTL_SECS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
+        let delete_grace_secs = env.num::<u64>("DELETE_GRACE_SECS")
+            .unwrap_or(0);
+        let stats_refresh_secs = env.num::<u64>("STATS_REFRESH_SECS")
+            .unwrap_or(60);
+        let expiry_sweep_secs = env.num::<u64>("EXPIRY_SWEEP_SECS")
+            .filter(|n| *n > 0)
+            .unwrap_or(60);
+        let trash_enabled = env.flag("TRASH_ENABLED").unwrap_or(false);
+        let trash_retention_secs = env.num::<u64>("TRASH_RETENTION_SECS")
+            .unwrap_or(7 * 24 * 3600);
+        let resumable_expiry_secs = env.num::<u64>("RESUMABLE_EXPIRY_SECS")
+            .unwrap_or(24 * 3600);
+        let shutdown_grace_secs = env.num::<u64>("SHUTDOWN_GRACE_SECS")
+            .unwrap_or(30);
+
+        let auth_max_ttl_secs = env.num::<u64>("AUTH_MAX_TTL_SECS")
             .unwrap_or(900);
 
+        let auth_json_limit_bytes = env.size("AUTH_JSON_LIMIT_BYTES")
+            .map(|n| usize::try_from(n).unwrap_or(usize::MAX))
+            .unwrap_or(4096);
+
+        // Keep users out of the bucket.
+        let auth_user_db = env.var("AUTH_USER_DB").unwrap_or_else(|| "./auth/users.json".into());
+
+        let strict_query = env.flag("STRICT_QUERY").unwrap_or(false);
+
+        let header_rules = match env.var("HEADER_RULES_FILE").filter(|s| !s.trim().is_empty()) {
+            Some(path) => headers::load_rules(&path).unwrap_or_else(|e| {
+                env.problem(format!("HEADER_RULES_FILE ignored: {e}"));
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+
+        let content_types = match env.var("CONTENT_TYPES").filter(|s| !s.trim().is_empty()) {
+            Some(spec) => ContentTypes::parse(&spec).unwrap_or_else(|e| {
+                env.problem(format!("CONTENT_TYPES ignored: {e}"));
+                ContentTypes::default()
+            }),
+            None => ContentTypes::default(),
+        };
+
+        let access_log = match env.var("ACCESS_LOG").unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+            "off" | "0" | "false" => AccessLogFormat::Off,
+            "json" => AccessLogFormat::Json,
+            "combined" | "" => AccessLogFormat::Combined,
+            other => {
+                env.problem(format!("ACCESS_LOG={other:?} is not one of combined, json, off"));
+                AccessLogFormat::Combined
+            }
+        };
+        let store_gzip_uploads = env.flag("STORE_GZIP_UPLOADS").unwrap_or(false);
+        let ui_enabled = env.flag("UI_ENABLED").unwrap_or(false);
+        let s3_enabled = env.flag("S3_ENABLED").unwrap_or(false);
+        let s3_skip_signature = env.flag("S3_SKIP_SIGNATURE").unwrap_or(false);
+        let buckets = parse_csv(env.var("BUCKETS")).unwrap_or_default();
+        let compression = env.flag("COMPRESSION").unwrap_or(false);
+        let trust_proxy = env.flag("TRUST_PROXY").unwrap_or(false);
+        let tls_cert_path = env.var("TLS_CERT_PATH").filter(|s| !s.trim().is_empty());
+        let tls_key_path = env.var("TLS_KEY_PATH").filter(|s| !s.trim().is_empty());
+        let webhook_url = env.var("WEBHOOK_URL").filter(|s| !s.trim().is_empty());
+        let webhook_secret = env.var("WEBHOOK_SECRET").filter(|s| !s.trim().is_empty());
+        let webhook_queue_size = env.num::<usize>("WEBHOOK_QUEUE_SIZE")
+            .filter(|n| *n > 0)
+            .unwrap_or(1000);
+
+        let defaults = KeyRules::default();
+        let as_usize = |n: u64| usize::try_from(n).unwrap_or(usize::MAX);
+        let key_rules = KeyRules {
+            max_bytes: env.size("KEY_MAX_BYTES").filter(|n| *n > 0).map_or(defaults.max_bytes, as_usize),
+            segment_max_bytes: env.size("KEY_SEGMENT_MAX_BYTES").filter(|n| *n > 0).map_or(defaults.segment_max_bytes, as_usize),
+            forbidden_chars: env.var("KEY_FORBIDDEN_CHARS").map(|s| s.chars().collect()).unwrap_or_default(),
+            portable_names: env.flag("KEY_PORTABLE_NAMES").unwrap_or(false),
+            blocked_prefixes: parse_csv(env.var("BLOCKED_PREFIXES")).unwrap_or_default(),
+        };
+
         // --- Auth envs (config only; not enforced yet) ---
-        let auth_mode = parse_auth_mode(&env::var("AUTH_MODE").unwrap_or_else(|_| "jwt_rs256".into()));
-        let auth_write = parse_bool(env::var("AUTH_WRITE").ok()).unwrap_or(true);
-        let auth_read  = parse_bool(env::var("AUTH_READ").ok()).unwrap_or(false);
-        let auth_list  = parse_bool(env::var("AUTH_LIST").ok()).unwrap_or(false);
-
-        let jwt_scopes_write = parse_csv(env::var("JWT_SCOPES_WRITE").ok()).unwrap_or_else(|| vec!["obj:write".into()]);
-        let jwt_scopes_read  = parse_csv(env::var("JWT_SCOPES_READ").ok()).unwrap_or_else(|| vec!["obj:read".into()]);
-        let jwt_scopes_list  = parse_csv(env::var("JWT_SCOPES_LIST").ok()).unwrap_or_else(|| vec!["obj:list".into()]);
-
-        let jwt_audience = env::var("JWT_AUDIENCE").ok().filter(|s| !s.trim().is_empty());
-
-        let jwt_issuers = parse_csv(env::var("JWT_ISSUERS").ok()).unwrap_or_default();
-        let jwks_urls   = parse_csv(env::var("JWKS_URLS").ok()).unwrap_or_default();
-        let jwks_ttl_secs = env::var("JWKS_TTL_SECS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
+        let auth_mode = env.choice("AUTH_MODE", AuthMode::JwtRs256);
+        let auth_write = env.flag("AUTH_WRITE").unwrap_or(true);
+        let auth_read  = env.flag("AUTH_READ").unwrap_or(false);
+        let auth_list  = env.flag("AUTH_LIST").unwrap_or(false);
+        let namespace_by_sub = env.flag("NAMESPACE_BY_SUB").unwrap_or(false);
+        let tenant_header = match env.var("TENANT_HEADER").map(|h| h.trim().to_ascii_lowercase()).filter(|h| !h.is_empty()) {
+            Some(h) if actix_web::http::header::HeaderName::from_bytes(h.as_bytes()).is_err() => {
+                env.problem(format!("TENANT_HEADER={h:?} is not a header name"));
+                None
+            }
+            h => h,
+        };
+        let tenant_claim = env.var("TENANT_CLAIM").map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+        let tenant_default = match env.var("TENANT_DEFAULT").map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) {
+            Some(t) if !crate::tenants::valid_name(&t) => {
+                env.problem(format!("TENANT_DEFAULT={t:?} is not a valid tenant name"));
+                None
+            }
+            t => t,
+        };
+        let tenant_auto_create = env.flag("TENANT_AUTO_CREATE").unwrap_or(false);
+        let signup_mode = env.choice("SIGNUP_MODE", SignupMode::Open);
+        let allow_query_token = env.flag("ALLOW_QUERY_TOKEN").unwrap_or(false);
+        let auth_cookie = env.flag("AUTH_COOKIE").unwrap_or(false);
+        let public_read_prefixes = parse_csv(env.var("PUBLIC_READ_PREFIXES")).unwrap_or_default();
+        let health_details_admin = env.flag("HEALTH_DETAILS_ADMIN").unwrap_or(false);
+
+        let jwt_scopes_write = parse_csv(env.var("JWT_SCOPES_WRITE")).unwrap_or_else(|| vec!["obj:write".into()]);
+        let jwt_scopes_read  = parse_csv(env.var("JWT_SCOPES_READ")).unwrap_or_else(|| vec!["obj:read".into()]);
+        let jwt_scopes_list  = parse_csv(env.var("JWT_SCOPES_LIST")).unwrap_or_else(|| vec!["obj:list".into()]);
+        let jwt_scopes_admin = parse_csv(env.var("JWT_SCOPES_ADMIN")).unwrap_or_else(|| vec!["bkt:admin".into()]);
+        let basic_scopes = parse_csv(env.var("BASIC_SCOPES")).unwrap_or_else(|| {
+            jwt_scopes_write.iter().chain(&jwt_scopes_read).chain(&jwt_scopes_list).cloned().collect()
+        });
+        let login_max_failures = env.num::<u32>("LOGIN_MAX_FAILURES").unwrap_or(5);
+        let login_window_secs = env.num::<u64>("LOGIN_WINDOW_SECS").unwrap_or(300);
+        let login_lockout_secs = env.num::<u64>("LOGIN_LOCKOUT_SECS").unwrap_or(900);
+        let login_failure_delay_ms = env.num::<u64>("LOGIN_FAILURE_DELAY_MS").unwrap_or(250);
+
+        let jwt_audience = env.var("JWT_AUDIENCE").filter(|s| !s.trim().is_empty());
+
+        let jwt_issuers = parse_csv(env.var("JWT_ISSUERS")).unwrap_or_default();
+        let jwks_urls   = parse_csv(env.var("JWKS_URLS")).unwrap_or_default();
+        let jwks_ttl_secs = env.num::<u64>("JWKS_TTL_SECS")
             .unwrap_or(300);
+        let jwks_refetch_secs = env.num::<u64>("JWKS_REFETCH_SECS").unwrap_or(30);
+        let jwt_leeway_secs = env.num::<u64>("JWT_LEEWAY_SECS").unwrap_or(30);
+        let jwt_required_claims = parse_csv(env.var("JWT_REQUIRED_CLAIMS")).unwrap_or_default();
 
-        let jwt_hs_secret = env::var("JWT_HS_SECRET").ok().filter(|s| !s.trim().is_empty());
+        let jwt_hs_secret = env.var("JWT_HS_SECRET").filter(|s| !s.trim().is_empty());
+        let introspection_secret = env.var("INTROSPECTION_SECRET").filter(|s| !s.trim().is_empty());
 
-        let idp_embed = parse_bool(env::var("IDP_EMBED").ok()).unwrap_or(false);
-        let idp_key_dir = env::var("IDP_KEY_DIR").unwrap_or_else(|_| "./keys".into());
+        let mut api_keys = parse_api_keys(env.var("API_KEYS").as_deref().unwrap_or(""), ',');
+        if let Some(path) = env.var("API_KEYS_FILE").filter(|s| !s.trim().is_empty()) {
+            match std::fs::read_to_string(&path) {
+                Ok(raw) => api_keys.extend(parse_api_keys(&raw, '\n')),
+                Err(e) => env.problem(format!("API_KEYS_FILE ignored: {path}: {e}")),
+            }
+        }
+
+        let idp_embed = env.flag("IDP_EMBED").unwrap_or(false);
+        let idp_key_dir = env.var("IDP_KEY_DIR").unwrap_or_else(|| "./keys".into());
+        let idp_rotate_days = env.num::<u64>("IDP_ROTATE_DAYS").unwrap_or(0);
 
         Self {
             host,
             port,
             root_dir,
             max_upload_bytes,
+            require_content_length,
+            durable_writes,
+            reject_concurrent_writes,
+            dedup,
+            size_inputs: env.size_inputs.clone(),
+            max_object_count,
+            max_concurrent_uploads,
+            max_concurrent_downloads,
+            concurrency_wait_ms,
+            upload_rate_limit_bps,
+            upload_rate_limit_per_upload_bps,
+            download_rate_limit_bps,
+            upload_idle_timeout_secs,
+            upload_deadline_secs,
+            min_free_bytes,
+            stream_chunk_bytes,
+            write_buffer_bytes,
+            archive_max_bytes,
+            archive_max_entries,
+            list_max_results,
+            list_hide_dotfiles,
+            list_exclude,
+            meta_cache_entries,
+            meta_cache_ttl_secs,
+            hot_cache_max_object_bytes,
+            hot_cache_total_bytes,
+            delete_grace_secs,
+            stats_refresh_secs,
+            expiry_sweep_secs,
+            trash_enabled,
+            trash_retention_secs,
+            resumable_expiry_secs,
+            shutdown_grace_secs,
             auth_max_ttl_secs,
+            auth_json_limit_bytes,
+            auth_user_db,
+            strict_query,
+            header_rules,
+            content_types,
+            access_log,
+            store_gzip_uploads,
+            buckets,
+            ui_enabled,
+            compression,
+            trust_proxy,
+            tls_cert_path,
+            tls_key_path,
+            webhook_url,
+            webhook_secret,
+            webhook_queue_size,
+            key_rules,
+            s3_enabled,
+            s3_skip_signature,
             auth_mode,
             auth_write,
             auth_read,
             auth_list,
+            namespace_by_sub,
+            tenant_header,
+            tenant_claim,
+            tenant_default,
+            tenant_auto_create,
+            signup_mode,
+            allow_query_token,
+            auth_cookie,
+            public_read_prefixes,
+            health_details_admin,
             jwt_scopes_write,
             jwt_scopes_read,
             jwt_scopes_list,
+            jwt_scopes_admin,
+            basic_scopes,
+            login_max_failures,
+            login_window_secs,
+            login_lockout_secs,
+            login_failure_delay_ms,
             jwt_audience,
             jwt_issuers,
             jwks_urls,
             jwks_ttl_secs,
+            jwks_refetch_secs,
+            jwt_leeway_secs,
+            jwt_required_claims,
             jwt_hs_secret,
+            introspection_secret,
+            api_keys,
             idp_embed,
             idp_key_dir,
+            idp_rotate_days,
+        }
+    }
+
+    /// Space-delimited union of the configured write/read/list scopes.
+    pub fn default_scope(&self) -> String {
+        let mut s: Vec<String> = self
+            .jwt_scopes_write
+            .iter()
+            .chain(&self.jwt_scopes_read)
+            .chain(&self.jwt_scopes_list)
+            .cloned()
+            .collect();
+        if s.is_empty() {
+            return "obj:write obj:read obj:list".to_string();
         }
+        s.sort();
+        s.dedup();
+        s.join(" ")
+    }
+
+    /// Revoked `jti`s live next to the user store.
+    pub fn auth_denylist_path(&self) -> std::path::PathBuf {
+        std::path::Path::new(&self.auth_user_db).with_file_name("revoked.json")
+    }
+
+    /// Unspent invite codes live next to the user store too.
+    pub fn auth_invites_path(&self) -> std::path::PathBuf {
+        std::path::Path::new(&self.auth_user_db).with_file_name("invites.json")
+    }
+
+    /// "https" when TLS is configured, else "http".
+    pub fn scheme(&self) -> &'static str {
+        if self.tls_cert_path.is_some() { "https" } else { "http" }
+    }
+
+    /// `iss` used for tokens this server mints (login, embedded IdP).
+    pub fn idp_issuer(&self) -> String {
+        format!("{}://{}:{}", self.scheme(), self.host, self.port)
     }
 
     /// Prints an auth config banner and (importantly) reads scope fields,
@@ -111,6 +761,8 @@ impl Config {
         let mode_str = match self.auth_mode {
             AuthMode::JwtRs256 => "jwt_rs256",
             AuthMode::JwtHs256 => "jwt_hs256",
+            AuthMode::ApiKey => "api_key",
+            AuthMode::Basic => "basic",
             AuthMode::Off => "off",
         };
         println!("🔐 AUTH_MODE = {}", mode_str);
@@ -118,10 +770,54 @@ impl Config {
             "   • protected: write={} read={} list={}",
             self.auth_write, self.auth_read, self.auth_list
         );
+        if self.namespace_by_sub {
+            println!("   • namespace_by_sub: callers confined to users/{{sub}}/ (admin scopes exempt)");
+            if self.auth_mode == AuthMode::Off {
+                eprintln!("⚠️  NAMESPACE_BY_SUB=1 has no effect with AUTH_MODE=off");
+            }
+        }
+        if crate::tenants::enabled(self) {
+            let by: Vec<String> = [self.tenant_claim.as_ref().map(|c| format!("claim {c:?}")), self.tenant_header.clone()]
+                .into_iter()
+                .flatten()
+                .collect();
+            println!(
+                "   • tenants: by {}, confined to tenants/{{name}}/ (admin scopes exempt); default {}; auto-create {}",
+                by.join(", then "),
+                self.tenant_default.as_deref().unwrap_or("none (400)"),
+                self.tenant_auto_create
+            );
+        }
+        match self.signup_mode {
+            SignupMode::Open => println!("   • signup: open (anyone can create an account)"),
+            SignupMode::Invite => println!("   • signup: invite (one-time codes from POST /auth/invites)"),
+            SignupMode::Closed => println!("   • signup: closed"),
+        }
+        if self.allow_query_token {
+            println!("   • query tokens: ?access_token= accepted on object GET/HEAD (links carry credentials; share carefully)");
+        }
+        if !self.public_read_prefixes.is_empty() {
+            println!("   • public reads (no token needed): {}", self.public_read_prefixes.join(", "));
+        }
+        if self.health_details_admin {
+            println!("   • /healthz?verbose=1 needs admin scope");
+        }
+        if self.auth_cookie {
+            println!("   • session cookie: /auth/login sets bkt_session (HttpOnly, SameSite=Lax); writes need x-csrf-token");
+        }
         println!("   • scopes:");
         println!("     - write: {:?}", self.jwt_scopes_write);
         println!("     - read : {:?}", self.jwt_scopes_read);
         println!("     - list : {:?}", self.jwt_scopes_list);
+        println!("     - admin: {:?}", self.jwt_scopes_admin);
+        if self.login_max_failures > 0 {
+            println!(
+                "   • login lockout: {} failures in {}s locks for {}s",
+                self.login_max_failures, self.login_window_secs, self.login_lockout_secs
+            );
+        } else {
+            println!("   • login lockout: off");
+        }
         if let Some(aud) = &self.jwt_audience {
             println!("   • audience: {}", aud);
         }
@@ -131,10 +827,28 @@ impl Config {
         if !self.jwks_urls.is_empty() {
             println!("   • jwks_urls: {}", self.jwks_urls.join(", "));
         }
-        println!("   • jwks_ttl_secs: {}", self.jwks_ttl_secs);
+        println!("   • jwks_ttl_secs: {} (unknown kid refetch: {}s)", self.jwks_ttl_secs, self.jwks_refetch_secs);
+        println!("   • jwt_leeway_secs: {}", self.jwt_leeway_secs);
+        if !self.jwt_required_claims.is_empty() {
+            println!("   • required claims: {}", self.jwt_required_claims.join(", "));
+        }
         if matches!(self.auth_mode, AuthMode::JwtHs256) && self.jwt_hs_secret.is_none() {
             eprintln!("⚠️  AUTH_MODE=jwt_hs256 but JWT_HS_SECRET is not set");
         }
+        if matches!(self.auth_mode, AuthMode::ApiKey) {
+            println!("   • api keys loaded: {}", self.api_keys.len());
+            if self.api_keys.is_empty() {
+                eprintln!("⚠️  AUTH_MODE=api_key but no keys are configured (API_KEYS / API_KEYS_FILE)");
+            }
+        }
+        if matches!(self.auth_mode, AuthMode::Basic) {
+            println!("   • user store: {}", self.auth_user_db);
+            println!("   • default user scopes: {:?}", self.basic_scopes);
+            if self.tls_cert_path.is_none() {
+                eprintln!("⚠️⚠️⚠️  AUTH_MODE=basic WITHOUT TLS: every request carries a reusable password in cleartext.");
+                eprintln!("⚠️⚠️⚠️  Set TLS_CERT_PATH/TLS_KEY_PATH, or only run this behind a TLS-terminating proxy.");
+            }
+        }
         if matches!(self.auth_mode, AuthMode::JwtRs256) && self.jwt_issuers.is_empty() && !self.idp_embed {
             eprintln!("⚠️  AUTH_MODE=jwt_rs256 but JWT_ISSUERS is empty and IDP_EMBED=0; no issuers are permitted");
         }
@@ -143,19 +857,43 @@ impl Config {
                 "🪪 Built-in IdP enabled (dev):\n   • JWKS: /{}\n   • Token mint: /{}\n   • Key dir: {}",
                 PATH_JWKS, PATH_IDP_TOKEN, self.idp_key_dir
             );
-            println!("   • Suggested iss: http://{}:{}", host, port);
+            println!("   • Suggested iss: {}://{}:{}", self.scheme(), host, port);
+            match self.idp_rotate_days {
+                0 => println!("   • Key rotation: manual (POST /{})", PATH_IDP_ROTATE),
+                days => println!("   • Key rotation: every {days} days (and POST /{})", PATH_IDP_ROTATE),
+            }
         }
     }
 }
 
 // static constants
 pub(crate) const PATH_HEALTHZ: &str = "healthz";
+pub(crate) const PATH_READYZ: &str = "readyz";
 pub(crate) const PATH_OBJECTS: &str = "objects";
-// Built-in IdP/JWKS endpoints (used in a later step)
+pub(crate) const PATH_BUCKETS: &str = "b";
+pub(crate) const PATH_ARCHIVE: &str = "objects-archive";
+pub(crate) const PATH_ADMIN: &str = "admin";
+pub(crate) const PATH_METRICS: &str = "metrics";
+pub(crate) const PATH_EVENTS: &str = "events";
+pub(crate) const PATH_TRASH: &str = "trash";
+pub(crate) const PATH_UPLOADS: &str = "uploads";
+pub(crate) const PATH_STATS: &str = "stats";
+pub(crate) const PATH_SUMMARY: &str = "objects-summary";
+pub(crate) const PATH_SEARCH: &str = "objects-search";
+#[cfg(feature = "ui")]
+pub(crate) const PATH_UI: &str = "ui";
+// Built-in IdP/JWKS endpoints (IDP_EMBED=1)
 pub(crate) const PATH_JWKS: &str = ".well-known/jwks.json";
 pub(crate) const PATH_IDP_TOKEN: &str = "idp/token";
+pub(crate) const PATH_IDP_ROTATE: &str = "idp/rotate";
 
 // ---- helpers ----
+/// A glob over keys, as listings match them: `*` and `?` stay within a
+/// segment, `**` crosses them.
+pub(crate) fn key_glob(pattern: &str) -> Result<globset::Glob, globset::Error> {
+    globset::GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
 fn parse_csv(val: Option<String>) -> Option<Vec<String>> {
     val.map(|s| {
         s.split(',')
@@ -166,17 +904,446 @@ fn parse_csv(val: Option<String>) -> Option<Vec<String>> {
     })
 }
 
-fn parse_bool(val: Option<String>) -> Option<bool> {
-    val.map(|s| {
-        let t = s.trim().to_ascii_lowercase();
-        matches!(t.as_str(), "1" | "true" | "yes" | "on")
-    })
+/// `name:key:scopes` entries split by `sep`; scopes are space-delimited
+/// (they contain `:` themselves, so only the first two colons split).
+/// Blank entries and `#` comments are skipped; malformed ones are reported by name only.
+fn parse_api_keys(raw: &str, sep: char) -> Vec<ApiKey> {
+    let mut keys = Vec::new();
+    for entry in raw.split(sep).map(str::trim).filter(|e| !e.is_empty() && !e.starts_with('#')) {
+        let mut parts = entry.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(key), scopes) if !name.is_empty() && !key.is_empty() => keys.push(ApiKey {
+                name: name.to_string(),
+                key: key.to_string(),
+                scopes: scopes.unwrap_or("").split_whitespace().map(String::from).collect(),
+            }),
+            (name, _, _) => eprintln!("⚠️  ignoring malformed API key entry {:?}", name.unwrap_or("")),
+        }
+    }
+    keys
 }
 
-fn parse_auth_mode(s: &str) -> AuthMode {
-    match s.trim().to_ascii_lowercase().as_str() {
-        "jwt_hs256" => AuthMode::JwtHs256,
-        "off" => AuthMode::Off,
-        _ => AuthMode::JwtRs256,
+/// A byte count: digits with an optional fraction and unit, case-insensitive.
+/// SI units are powers of 1000 (`kB`, `MB`, `GB`, `TB`), binary ones powers
+/// of 1024 (`KiB`, `MiB`, `GiB`, `TiB`, and the bare `K`, `M`, `G`, `T`);
+/// `B` or nothing is bytes. Fractions round down: `1.5GiB` = 1610612736.
+pub fn parse_size(raw: &str) -> Result<u64, String> {
+    let s = raw.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = (&s[..split], s[split..].trim());
+    let mult: u128 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000_u128.pow(2),
+        "gb" => 1000_u128.pow(3),
+        "tb" => 1000_u128.pow(4),
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        _ => return Err(format!("unknown unit {unit:?} (use B, kB/MB/GB/TB or KiB/MiB/GiB/TiB)")),
+    };
+    let (whole, frac) = number.split_once('.').unwrap_or((number, ""));
+    if (whole.is_empty() && frac.is_empty()) || frac.contains('.') || frac.len() > 18 {
+        return Err("expected a number like 512, 10MB or 1.5GiB".into());
+    }
+    let parse = |d: &str| if d.is_empty() { Ok(0) } else { d.parse::<u128>().map_err(|_| "number too large".to_string()) };
+    let bytes = parse(whole)?
+        .checked_mul(mult)
+        .and_then(|b| b.checked_add(parse(frac).ok()? * mult / 10_u128.pow(frac.len() as u32)))
+        .and_then(|b| u64::try_from(b).ok());
+    bytes.ok_or_else(|| "larger than 2^64 bytes".into())
+}
+
+/// Every problem `Config::try_from_env` found, one per entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid configuration: {}", self.problems.join("; "))
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Where each setting lives in a config file: (variable, TOML key), with
+/// `auth.` keys in the `[auth]` table.
+const FILE_KEYS: &[(&str, &str)] = &[
+    ("HOST", "host"),
+    ("PORT", "port"),
+    ("RUST_BUCKET_DIR", "root_dir"),
+    ("MAX_UPLOAD_BYTES", "max_upload_bytes"),
+    ("REQUIRE_CONTENT_LENGTH", "require_content_length"),
+    ("DURABLE_WRITES", "durable_writes"),
+    ("REJECT_CONCURRENT_WRITES", "reject_concurrent_writes"),
+    ("DEDUP", "dedup"),
+    ("MAX_OBJECT_COUNT", "max_object_count"),
+    ("MAX_CONCURRENT_UPLOADS", "max_concurrent_uploads"),
+    ("MAX_CONCURRENT_DOWNLOADS", "max_concurrent_downloads"),
+    ("CONCURRENCY_WAIT_MS", "concurrency_wait_ms"),
+    ("UPLOAD_RATE_LIMIT_BPS", "upload_rate_limit_bps"),
+    ("UPLOAD_RATE_LIMIT_PER_UPLOAD_BPS", "upload_rate_limit_per_upload_bps"),
+    ("DOWNLOAD_RATE_LIMIT_BPS", "download_rate_limit_bps"),
+    ("UPLOAD_IDLE_TIMEOUT_SECS", "upload_idle_timeout_secs"),
+    ("UPLOAD_DEADLINE_SECS", "upload_deadline_secs"),
+    ("MIN_FREE_BYTES", "min_free_bytes"),
+    ("STREAM_CHUNK_BYTES", "stream_chunk_bytes"),
+    ("WRITE_BUFFER_BYTES", "write_buffer_bytes"),
+    ("ARCHIVE_MAX_BYTES", "archive_max_bytes"),
+    ("ARCHIVE_MAX_ENTRIES", "archive_max_entries"),
+    ("LIST_MAX_RESULTS", "list_max_results"),
+    ("LIST_HIDE_DOTFILES", "list_hide_dotfiles"),
+    ("LIST_EXCLUDE", "list_exclude"),
+    ("META_CACHE_ENTRIES", "meta_cache_entries"),
+    ("META_CACHE_TTL_SECS", "meta_cache_ttl_secs"),
+    ("HOT_CACHE_MAX_OBJECT_BYTES", "hot_cache_max_object_bytes"),
+    ("HOT_CACHE_TOTAL_BYTES", "hot_cache_total_bytes"),
+    ("DELETE_GRACE_SECS", "delete_grace_secs"),
+    ("STATS_REFRESH_SECS", "stats_refresh_secs"),
+    ("EXPIRY_SWEEP_SECS", "expiry_sweep_secs"),
+    ("TRASH_ENABLED", "trash_enabled"),
+    ("TRASH_RETENTION_SECS", "trash_retention_secs"),
+    ("RESUMABLE_EXPIRY_SECS", "resumable_expiry_secs"),
+    ("SHUTDOWN_GRACE_SECS", "shutdown_grace_secs"),
+    ("STRICT_QUERY", "strict_query"),
+    ("HEADER_RULES_FILE", "header_rules_file"),
+    ("CONTENT_TYPES", "content_types"),
+    ("ACCESS_LOG", "access_log"),
+    ("STORE_GZIP_UPLOADS", "store_gzip_uploads"),
+    ("UI_ENABLED", "ui_enabled"),
+    ("BUCKETS", "buckets"),
+    ("COMPRESSION", "compression"),
+    ("TRUST_PROXY", "trust_proxy"),
+    ("TLS_CERT_PATH", "tls_cert_path"),
+    ("TLS_KEY_PATH", "tls_key_path"),
+    ("WEBHOOK_URL", "webhook_url"),
+    ("WEBHOOK_SECRET", "webhook_secret"),
+    ("WEBHOOK_QUEUE_SIZE", "webhook_queue_size"),
+    ("KEY_MAX_BYTES", "key_max_bytes"),
+    ("KEY_SEGMENT_MAX_BYTES", "key_segment_max_bytes"),
+    ("KEY_FORBIDDEN_CHARS", "key_forbidden_chars"),
+    ("KEY_PORTABLE_NAMES", "key_portable_names"),
+    ("BLOCKED_PREFIXES", "blocked_prefixes"),
+    ("S3_ENABLED", "s3_enabled"),
+    ("S3_SKIP_SIGNATURE", "s3_skip_signature"),
+    ("AUTH_MODE", "auth.mode"),
+    ("AUTH_WRITE", "auth.write"),
+    ("AUTH_READ", "auth.read"),
+    ("AUTH_LIST", "auth.list"),
+    ("NAMESPACE_BY_SUB", "auth.namespace_by_sub"),
+    ("TENANT_HEADER", "auth.tenant_header"),
+    ("TENANT_CLAIM", "auth.tenant_claim"),
+    ("TENANT_DEFAULT", "auth.tenant_default"),
+    ("TENANT_AUTO_CREATE", "auth.tenant_auto_create"),
+    ("SIGNUP_MODE", "auth.signup_mode"),
+    ("ALLOW_QUERY_TOKEN", "auth.allow_query_token"),
+    ("AUTH_COOKIE", "auth.cookie"),
+    ("HEALTH_DETAILS_ADMIN", "auth.health_details_admin"),
+    ("PUBLIC_READ_PREFIXES", "auth.public_read_prefixes"),
+    ("AUTH_MAX_TTL_SECS", "auth.max_ttl_secs"),
+    ("AUTH_JSON_LIMIT_BYTES", "auth.json_limit_bytes"),
+    ("AUTH_USER_DB", "auth.user_db"),
+    ("JWT_SCOPES_WRITE", "auth.scopes_write"),
+    ("JWT_SCOPES_READ", "auth.scopes_read"),
+    ("JWT_SCOPES_LIST", "auth.scopes_list"),
+    ("JWT_SCOPES_ADMIN", "auth.scopes_admin"),
+    ("BASIC_SCOPES", "auth.basic_scopes"),
+    ("LOGIN_MAX_FAILURES", "auth.login_max_failures"),
+    ("LOGIN_WINDOW_SECS", "auth.login_window_secs"),
+    ("LOGIN_LOCKOUT_SECS", "auth.login_lockout_secs"),
+    ("LOGIN_FAILURE_DELAY_MS", "auth.login_failure_delay_ms"),
+    ("JWT_AUDIENCE", "auth.jwt_audience"),
+    ("JWT_ISSUERS", "auth.jwt_issuers"),
+    ("JWKS_URLS", "auth.jwks_urls"),
+    ("JWKS_TTL_SECS", "auth.jwks_ttl_secs"),
+    ("JWKS_REFETCH_SECS", "auth.jwks_refetch_secs"),
+    ("JWT_LEEWAY_SECS", "auth.jwt_leeway_secs"),
+    ("JWT_REQUIRED_CLAIMS", "auth.jwt_required_claims"),
+    ("JWT_HS_SECRET", "auth.jwt_hs_secret"),
+    ("INTROSPECTION_SECRET", "auth.introspection_secret"),
+    ("API_KEYS", "auth.api_keys"),
+    ("API_KEYS_FILE", "auth.api_keys_file"),
+    ("IDP_EMBED", "auth.idp_embed"),
+    ("IDP_KEY_DIR", "auth.idp_key_dir"),
+    ("IDP_ROTATE_DAYS", "auth.idp_rotate_days"),
+];
+
+/// The config file named by `RUST_BUCKET_CONFIG`, if any.
+pub fn env_file(env: &HashMap<String, String>) -> Option<PathBuf> {
+    env.get("RUST_BUCKET_CONFIG").filter(|s| !s.trim().is_empty()).map(PathBuf::from)
+}
+
+/// A TOML scalar (or array of scalars, comma-joined) as the string the
+/// matching environment variable would hold.
+fn toml_to_env(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Array(items) => items.iter().map(toml_to_env).collect::<Option<Vec<_>>>().map(|v| v.join(",")),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+/// Typed reads of environment variables, falling back to the config file,
+/// that note, rather than hide, values that don't parse. Unset and blank
+/// variables are `None`.
+#[derive(Default)]
+struct EnvReader {
+    env: HashMap<String, String>,
+    /// File values keyed by the variable they stand in for.
+    file: HashMap<&'static str, String>,
+    problems: Vec<String>,
+    warnings: Vec<String>,
+    size_inputs: Vec<(&'static str, String, u64)>,
+}
+
+impl EnvReader {
+    fn new(file: Option<&Path>, env: HashMap<String, String>) -> Self {
+        let mut reader = Self { env, ..Self::default() };
+        if let Some(path) = file {
+            reader.load_file(path);
+        }
+        reader
+    }
+
+    fn load_file(&mut self, path: &Path) {
+        let table = match std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|raw| {
+            raw.parse::<toml::Table>().map_err(|e| e.message().to_string())
+        }) {
+            Ok(table) => table,
+            Err(e) => return self.problem(format!("RUST_BUCKET_CONFIG={}: {e}", path.display())),
+        };
+        let strict = self.env.get("RUST_BUCKET_CONFIG_STRICT").is_some_and(|v| matches!(v.trim(), "1" | "true" | "yes" | "on"));
+
+        let mut entries: Vec<(String, &toml::Value)> = Vec::new();
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("auth", toml::Value::Table(auth)) => entries.extend(auth.iter().map(|(k, v)| (format!("auth.{k}"), v))),
+                _ => entries.push((key.clone(), value)),
+            }
+        }
+        for (key, value) in entries {
+            let Some(&(name, _)) = FILE_KEYS.iter().find(|(_, k)| *k == key) else {
+                let msg = format!("{}: unknown key {key:?}", path.display());
+                if strict { self.problem(msg) } else { self.warnings.push(msg) }
+                continue;
+            };
+            match toml_to_env(value) {
+                Some(v) => {
+                    self.file.insert(name, v);
+                }
+                None => self.problem(format!("{}: {key} must be a string, number, boolean or list", path.display())),
+            }
+        }
+    }
+
+    fn print_warnings(&self) {
+        for warning in &self.warnings {
+            eprintln!("⚠️  {warning}");
+        }
+    }
+
+    fn problem(&mut self, msg: String) {
+        self.problems.push(msg);
+    }
+
+    /// The variable if set, otherwise the file's value for it.
+    fn var(&self, name: &str) -> Option<String> {
+        self.env.get(name).or_else(|| self.file.get(name)).cloned()
+    }
+
+    /// How to name `name` in a problem: the variable, or the file key if
+    /// that's where the value came from.
+    fn label(&self, name: &str) -> String {
+        match FILE_KEYS.iter().find(|(n, _)| *n == name) {
+            Some((_, key)) if !self.env.contains_key(name) && self.file.contains_key(name) => format!("{key} (config file)"),
+            _ => name.to_string(),
+        }
+    }
+
+    /// A byte count, plain or with a unit (see `parse_size`).
+    fn size(&mut self, name: &'static str) -> Option<u64> {
+        let raw = self.var(name).filter(|s| !s.trim().is_empty())?;
+        match parse_size(&raw) {
+            Ok(n) => {
+                self.size_inputs.push((name, raw.trim().to_string(), n));
+                Some(n)
+            }
+            Err(e) => {
+                self.problem(format!("{}={raw:?} is not a valid size: {e}", self.label(name)));
+                None
+            }
+        }
+    }
+
+    /// An I/O buffer size: 4 KiB to 16 MiB, since every transfer in flight
+    /// holds one.
+    fn buffer(&mut self, name: &'static str, default: usize) -> usize {
+        match self.size(name) {
+            None => default,
+            Some(n) if (4 * 1024..=16 * 1024 * 1024).contains(&n) => n as usize,
+            Some(n) => {
+                self.problem(format!("{}={n} must be between 4KiB and 16MiB", self.label(name)));
+                default
+            }
+        }
+    }
+
+    fn num<T: std::str::FromStr>(&mut self, name: &str) -> Option<T> {
+        let raw = self.var(name).filter(|s| !s.trim().is_empty())?;
+        let parsed = raw.trim().parse::<T>().ok();
+        if parsed.is_none() {
+            self.problem(format!("{}={raw:?} is not a valid number", self.label(name)));
+        }
+        parsed
+    }
+
+    /// 1/true/yes/on or 0/false/no/off; anything else reads as false.
+    fn flag(&mut self, name: &str) -> Option<bool> {
+        let raw = self.var(name)?;
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "0" | "false" | "no" | "off" | "" => Some(false),
+            _ => {
+                self.problem(format!("{}={raw:?} is not a boolean (use 1/0, true/false, yes/no, on/off)", self.label(name)));
+                Some(false)
+            }
+        }
+    }
+
+    /// One of a fixed set of words (AUTH_MODE, SIGNUP_MODE), else `default`.
+    fn choice<T: std::str::FromStr<Err = String>>(&mut self, name: &str, default: T) -> T {
+        let Some(raw) = self.var(name) else { return default };
+        raw.parse().unwrap_or_else(|e| {
+            self.problem(format!("{}={raw:?} is {e}", self.label(name)));
+            default
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn sizes() {
+        for (raw, want) in [
+            ("0", 0),
+            ("1073741824", 1 << 30),
+            ("512K", 512 * 1024),
+            ("512k", 512 * 1024),
+            ("10MB", 10_000_000),
+            ("10mb", 10_000_000),
+            ("10 MiB", 10 << 20),
+            ("1.5GiB", 1_610_612_736),
+            ("1.5gb", 1_500_000_000),
+            ("2T", 2 << 40),
+            ("100b", 100),
+            (" 7 ", 7),
+            (".5K", 512),
+            ("1.0001kB", 1000),
+        ] {
+            assert_eq!(parse_size(raw), Ok(want), "{raw}");
+        }
+        for bad in ["", "MB", "ten", "10XB", "1.2.3G", "-5", "1e9", "10 M B", "99999999999999999999999T", "99999999999999999999999999999999999999T", "20000000000G"] {
+            assert!(parse_size(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn byte_settings_take_units() {
+        let vars = env(&[("AUTH_MODE", "off"), ("MAX_UPLOAD_BYTES", "10MB"), ("ARCHIVE_MAX_BYTES", "1GiB"), ("AUTH_JSON_LIMIT_BYTES", "8K")]);
+        let cfg = Config::resolve(None, &vars, &Overrides::default()).unwrap();
+        assert_eq!((cfg.max_upload_bytes, cfg.archive_max_bytes, cfg.auth_json_limit_bytes), (Some(10_000_000), 1 << 30, 8192));
+        assert_eq!(cfg.size_inputs[0], ("MAX_UPLOAD_BYTES", "10MB".to_string(), 10_000_000));
+        let vars = env(&[("AUTH_MODE", "off"), ("KEY_MAX_BYTES", "2K")]);
+        let cfg = Config::resolve(None, &vars, &Overrides::default()).unwrap();
+        assert!(cfg.size_inputs.contains(&("KEY_MAX_BYTES", "2K".to_string(), 2048)));
+
+        let err = Config::resolve(None, &env(&[("AUTH_MODE", "off"), ("MAX_UPLOAD_BYTES", "10XB")]), &Overrides::default()).unwrap_err();
+        assert_eq!(err.problems.len(), 1);
+        assert!(err.problems[0].starts_with("MAX_UPLOAD_BYTES=\"10XB\" is not a valid size"), "{err}");
+
+        let vars = env(&[("AUTH_MODE", "off"), ("STREAM_CHUNK_BYTES", "64K")]);
+        let cfg = Config::resolve(None, &vars, &Overrides::default()).unwrap();
+        assert_eq!((cfg.stream_chunk_bytes, cfg.write_buffer_bytes), (64 * 1024, 1024 * 1024));
+        let err = Config::resolve(None, &env(&[("AUTH_MODE", "off"), ("WRITE_BUFFER_BYTES", "0")]), &Overrides::default()).unwrap_err();
+        assert_eq!(err.problems, ["WRITE_BUFFER_BYTES=0 must be between 4KiB and 16MiB"]);
+        let vars = env(&[("AUTH_MODE", "off"), ("STREAM_CHUNK_BYTES", "4095"), ("WRITE_BUFFER_BYTES", "32M")]);
+        let err = Config::resolve(None, &vars, &Overrides::default()).unwrap_err();
+        assert_eq!(
+            err.problems,
+            ["STREAM_CHUNK_BYTES=4095 must be between 4KiB and 16MiB", "WRITE_BUFFER_BYTES=33554432 must be between 4KiB and 16MiB"]
+        );
+        let vars = env(&[("AUTH_MODE", "off"), ("STREAM_CHUNK_BYTES", "4K"), ("WRITE_BUFFER_BYTES", "16M")]);
+        let cfg = Config::resolve(None, &vars, &Overrides::default()).unwrap();
+        assert_eq!((cfg.stream_chunk_bytes, cfg.write_buffer_bytes), (4096, 16 << 20));
+    }
+
+    #[test]
+    fn cli_beats_env_beats_defaults() {
+        let base = env(&[("AUTH_MODE", "off")]);
+        let cfg = Config::resolve(None, &base, &Overrides::default()).unwrap();
+        assert_eq!((cfg.host.as_str(), cfg.port, cfg.max_upload_bytes), ("0.0.0.0", 8080, None));
+
+        let from_env = env(&[("AUTH_MODE", "off"), ("PORT", "9000"), ("HOST", "127.0.0.1"), ("MAX_UPLOAD_BYTES", "10")]);
+        let cli = Overrides { port: Some(9001), auth_mode: Some(AuthMode::ApiKey), ..Overrides::default() };
+        let cfg = Config::resolve(None, &from_env, &cli).unwrap();
+        assert_eq!((cfg.host.as_str(), cfg.port), ("127.0.0.1", 9001));
+        assert_eq!(cfg.max_upload_bytes, Some(10));
+        assert_eq!(cfg.auth_mode, AuthMode::ApiKey);
+
+        // unset overrides leave every field alone
+        let cfg = Config::resolve(None, &from_env, &Overrides::default()).unwrap();
+        let merged = cfg.clone().merge(&Overrides::default());
+        assert_eq!(merged.to_toml_redacted(), cfg.to_toml_redacted());
+        let merged = cfg.merge(&Overrides { root_dir: Some("/srv/x".into()), max_object_count: Some(5), ..Overrides::default() });
+        assert_eq!((merged.root_dir.as_str(), merged.max_object_count, merged.port), ("/srv/x", Some(5), 9000));
+    }
+
+    #[test]
+    fn overrides_are_validated_as_merged() {
+        // rs256 without issuers is fixed by --auth-mode off, and caused by --auth-mode jwt_hs256
+        let rs = env(&[("AUTH_MODE", "jwt_rs256")]);
+        assert!(Config::resolve(None, &rs, &Overrides::default()).is_err());
+        assert!(Config::resolve(None, &rs, &Overrides { auth_mode: Some(AuthMode::Off), ..Overrides::default() }).is_ok());
+        let off = env(&[("AUTH_MODE", "off")]);
+        let err = Config::resolve(None, &off, &Overrides { auth_mode: Some(AuthMode::JwtHs256), ..Overrides::default() }).unwrap_err();
+        assert_eq!(err.problems, ["AUTH_MODE=jwt_hs256 requires JWT_HS_SECRET"]);
+    }
+
+    #[test]
+    fn printed_config_reads_back_and_hides_secrets() {
+        let vars = env(&[
+            ("AUTH_MODE", "jwt_hs256"),
+            ("JWT_HS_SECRET", "hs-secret"),
+            ("WEBHOOK_URL", "http://hook"),
+            ("WEBHOOK_SECRET", "wh-secret"),
+            ("API_KEYS", "ci:ci-key:obj:write obj:read"),
+            ("BUCKETS", "a-b,c-d"),
+            ("ACCESS_LOG", "json"),
+        ]);
+        let cfg = Config::resolve(None, &vars, &Overrides::default()).unwrap();
+        let printed = cfg.to_toml_redacted();
+        for secret in ["hs-secret", "wh-secret", "ci-key"] {
+            assert!(!printed.contains(secret), "{secret} leaked: {printed}");
+        }
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("printed.toml");
+        std::fs::write(&file, &printed).unwrap();
+        let again = Config::from_sources(Some(&file), &HashMap::new()).unwrap();
+        assert_eq!(again.to_toml_redacted(), printed);
+        assert_eq!(again.buckets, ["a-b", "c-d"]);
+        assert_eq!(again.api_keys[0].scopes, ["obj:write", "obj:read"]);
     }
 }
@@ -0,0 +1,113 @@
+// src/content_types.rs
+//
+// The Content-Type an object is served with, from its key's extension:
+// mime_guess's table, with the operator's CONTENT_TYPES entries on top. A
+// Content-Type stored with the object (its custom headers) still replaces
+// whatever is guessed here.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use mime_guess::mime;
+
+/// Extension → type entries that win over mime_guess (CONTENT_TYPES).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContentTypes {
+    overrides: BTreeMap<String, String>,
+}
+
+impl ContentTypes {
+    /// Reads CONTENT_TYPES: `ext=type` pairs separated by commas, or the
+    /// path of a JSON object mapping extensions to types.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let pairs: Vec<(String, String)> = if spec.contains('=') {
+            spec.split(',')
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| {
+                    p.split_once('=')
+                        .map(|(e, t)| (e.to_string(), t.to_string()))
+                        .ok_or_else(|| format!("{:?} is not ext=type", p.trim()))
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            let raw = std::fs::read_to_string(spec.trim()).map_err(|e| format!("{}: {e}", spec.trim()))?;
+            serde_json::from_str::<BTreeMap<String, String>>(&raw)
+                .map_err(|e| format!("{}: {e}", spec.trim()))?
+                .into_iter()
+                .collect()
+        };
+
+        let mut overrides = BTreeMap::new();
+        for (ext, ctype) in pairs {
+            let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+            let ctype = ctype.trim();
+            if ext.is_empty() {
+                return Err(format!("empty extension for {ctype:?}"));
+            }
+            if ctype.parse::<mime::Mime>().is_err() {
+                return Err(format!("{ctype:?} (for .{ext}) is not a media type"));
+            }
+            overrides.insert(ext, ctype.to_string());
+        }
+        Ok(Self { overrides })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    /// The entries as CONTENT_TYPES would spell them.
+    pub fn to_spec(&self) -> String {
+        self.overrides.iter().map(|(e, t)| format!("{e}={t}")).collect::<Vec<_>>().join(",")
+    }
+
+    /// The type to serve `key` as: an override, else mime_guess's first
+    /// guess (text types as UTF-8), else `application/octet-stream`.
+    pub fn guess(&self, key: &str) -> String {
+        let Some(ext) = Path::new(key).extension().and_then(|s| s.to_str()).map(|s| s.to_ascii_lowercase()) else {
+            return mime::APPLICATION_OCTET_STREAM.to_string();
+        };
+        if let Some(ctype) = self.overrides.get(&ext) {
+            return ctype.clone();
+        }
+        match mime_guess::from_ext(&ext).first() {
+            Some(m) if m.type_() == mime::TEXT && m.get_param(mime::CHARSET).is_none() => format!("{m}; charset=utf-8"),
+            Some(m) => m.to_string(),
+            None => mime::APPLICATION_OCTET_STREAM.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_then_mime_guess_then_octet_stream() {
+        let types = ContentTypes::parse("md=text/markdown; charset=utf-8, .PNG=image/x-custom").unwrap();
+        assert_eq!(types.guess("docs/README.md"), "text/markdown; charset=utf-8");
+        assert_eq!(types.guess("logo.png"), "image/x-custom");
+
+        let none = ContentTypes::default();
+        assert_eq!(none.guess("logo.PNG"), "image/png");
+        assert_eq!(none.guess("a/b.webm"), "video/webm");
+        assert_eq!(none.guess("notes.txt"), "text/plain; charset=utf-8");
+        assert_eq!(none.guess("data.json"), "application/json");
+        assert_eq!(none.guess("blob.zzzunknown"), "application/octet-stream");
+        assert_eq!(none.guess("Makefile"), "application/octet-stream");
+
+        assert_eq!(types.to_spec(), "md=text/markdown; charset=utf-8,png=image/x-custom");
+        assert_eq!(ContentTypes::parse(&types.to_spec()), Ok(types));
+        assert!(ContentTypes::parse("md=not a type").is_err());
+        assert!(ContentTypes::parse("md=text/plain,oops").is_err());
+    }
+
+    #[test]
+    fn json_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("types.json");
+        std::fs::write(&path, r#"{ "log": "text/plain", "wasm": "application/wasm" }"#).unwrap();
+        let types = ContentTypes::parse(path.to_str().unwrap()).unwrap();
+        assert_eq!(types.guess("app.log"), "text/plain");
+        assert!(ContentTypes::parse(dir.path().join("missing.json").to_str().unwrap()).is_err());
+    }
+}
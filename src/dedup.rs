@@ -0,0 +1,157 @@
+// src/dedup.rs
+//
+// Content-addressed storage (DEDUP=1): a PUT body is kept once under
+// `<root>/.blobs/<2 hex>/<sha256>` and the object's path is a hard link to
+// it, so identical uploads share one copy. GET streams the key's file like
+// any other. The link count is the reference count: a blob only the store
+// links to is garbage. Deletes release their blob right away; a pass at
+// startup and after bulk removals (trash purges, bucket deletes) collects
+// whatever a crash or a tree removal left behind. Every write replaces a
+// key's link by rename, so a shared inode is never changed in place.
+// Writers other than PUT (form and archive uploads) store plain files.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::fs;
+
+use crate::consts::Config;
+use crate::meta::ObjectMeta;
+use crate::storage::{self, TempUpload, TMP_DIR};
+use crate::AppState;
+
+/// Directory (directly under the root) holding the blobs.
+pub const BLOBS_DIR: &str = ".blobs";
+
+#[derive(Default)]
+pub struct Dedup {
+    /// Set by `start`; until then nothing touches the blob store.
+    enabled: AtomicBool,
+}
+
+impl Dedup {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+}
+
+/// Where the blob with SHA-256 `sha` (lowercase hex) lives.
+pub fn blob_path(root: &Path, sha: &str) -> PathBuf {
+    root.join(BLOBS_DIR).join(&sha[..2]).join(sha)
+}
+
+/// The blob an object's sidecar says it links to.
+pub fn blob_of(meta: &ObjectMeta) -> Option<&str> {
+    meta.checksums.get("sha256").map(String::as_str)
+}
+
+/// Lands a finished upload at `dest` through the store: the temp file
+/// becomes the blob unless that content is stored already, then `dest` is
+/// (re)linked to the blob.
+pub async fn store(state: &AppState, tmp: TempUpload, sha: &str, dest: &Path, durable: bool) -> io::Result<()> {
+    let blob = blob_path(&state.root, sha);
+    let _guard = state.locks.lock(&blob).await;
+    if fs::metadata(&blob).await.is_ok() {
+        drop(tmp);
+    } else {
+        if let Some(dir) = blob.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        tmp.persist(&blob, durable).await?;
+    }
+
+    let link = state.root.join(TMP_DIR).join(format!("{}.link", uuid::Uuid::new_v4()));
+    fs::hard_link(&blob, &link).await?;
+    let renamed = fs::rename(&link, dest).await;
+    // renaming onto another link of the same inode succeeds without moving anything
+    let _ = fs::remove_file(&link).await;
+    renamed?;
+    if durable {
+        if let Some(dir) = dest.parent() {
+            storage::sync_dir(dir).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Drops a key's claim on blob `sha` once its link is gone: the blob goes
+/// when nothing but the store links to it.
+pub async fn release(state: &AppState, sha: &str) -> io::Result<()> {
+    if sha.len() < 2 || !sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(());
+    }
+    let blob = blob_path(&state.root, sha);
+    let _guard = state.locks.lock(&blob).await;
+    match fs::metadata(&blob).await {
+        Ok(m) if links(&m) <= 1 => fs::remove_file(&blob).await,
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes every unreferenced blob; returns how many went.
+pub async fn collect(state: &AppState) -> io::Result<usize> {
+    let mut removed = 0;
+    let mut dirs = match fs::read_dir(state.root.join(BLOBS_DIR)).await {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    while let Some(dir) = dirs.next_entry().await? {
+        let Ok(mut blobs) = fs::read_dir(dir.path()).await else { continue };
+        while let Some(blob) = blobs.next_entry().await? {
+            let Some(sha) = blob.file_name().to_str().map(str::to_string) else { continue };
+            let before = fs::metadata(blob.path()).await.is_ok();
+            release(state, &sha).await?;
+            if before && fs::metadata(blob.path()).await.is_err() {
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// `collect` in the background when dedup is on; for callers that just
+/// removed whole trees.
+pub fn collect_later(state: &AppState) {
+    if !state.dedup.enabled() {
+        return;
+    }
+    let state = state.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = collect(&state).await {
+            eprintln!("⚠️  blob collection failed: {e}");
+        }
+    });
+}
+
+/// Turns dedup on when DEDUP is set and repairs the store once. Call once
+/// per process, from inside the actix runtime.
+pub fn start(state: AppState, cfg: &Config) {
+    if !cfg.dedup {
+        return;
+    }
+    if !cfg!(unix) {
+        eprintln!("⚠️  DEDUP needs hard-link counts; unreferenced blobs are never collected on this platform");
+    }
+    state.dedup.enabled.store(true, Ordering::Relaxed);
+    actix_web::rt::spawn(async move {
+        match collect(&state).await {
+            Ok(0) => {}
+            Ok(n) => println!("🧹 removed {n} unreferenced blob(s)"),
+            Err(e) => eprintln!("⚠️  blob collection failed: {e}"),
+        }
+    });
+}
+
+#[cfg(unix)]
+fn links(meta: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink()
+}
+
+// without a link count, never treat a blob as unreferenced
+#[cfg(not(unix))]
+fn links(_meta: &std::fs::Metadata) -> u64 {
+    u64::MAX
+}
@@ -0,0 +1,52 @@
+// src/error.rs
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Structured error response: `{"error": "<code>", "message": "...", ...extra}`.
+/// `error` is a stable machine-readable code; extra fields name the offending input.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    extra: Map<String, Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into(), extra: Map::new() }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    /// Adds an extra field to the body (e.g. `param`, `accepted`).
+    pub fn with(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.extra.insert(field.to_string(), value.into());
+        self
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = Map::new();
+        body.insert("error".into(), Value::from(self.code));
+        body.insert("message".into(), Value::from(self.message.clone()));
+        for (k, v) in &self.extra {
+            body.insert(k.clone(), v.clone());
+        }
+        HttpResponse::build(self.status).json(Value::Object(body))
+    }
+}
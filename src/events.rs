@@ -0,0 +1,51 @@
+// src/events.rs
+//
+// Object change events. Handlers publish once; subscribers are the SSE
+// stream (`GET /events`, via a broadcast channel) and the webhook queue.
+use serde::Serialize;
+use std::path::Path;
+use tokio::sync::broadcast;
+
+use crate::pending::now_secs;
+use crate::storage::{key_of, make_etag};
+use crate::AppState;
+
+/// Events buffered per SSE subscriber; one that falls further behind is disconnected.
+pub const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Event {
+    /// `object.created`, `object.overwritten`, `object.appended`, `object.copied`,
+    /// `object.deleted` or `object.restored`.
+    pub event: &'static str,
+    pub key: String,
+    pub size: u64,
+    pub etag: String,
+    pub timestamp: u64,
+}
+
+pub fn channel() -> broadcast::Sender<Event> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// True when someone would receive a published event, so callers can skip building it.
+pub fn wanted(state: &AppState) -> bool {
+    state.webhooks.enabled() || state.events.receiver_count() > 0
+}
+
+pub fn publish(state: &AppState, event: Event) {
+    // no subscribers is fine
+    let _ = state.events.send(event.clone());
+    state.webhooks.emit(event);
+}
+
+/// Publishes a change event for the object at `path`, as described by `meta`.
+pub fn notify(state: &AppState, event: &'static str, path: &Path, meta: &std::fs::Metadata) {
+    publish(state, Event {
+        event,
+        key: key_of(&state.root, path),
+        size: meta.len(),
+        etag: make_etag(meta),
+        timestamp: now_secs(),
+    });
+}
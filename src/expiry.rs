@@ -0,0 +1,140 @@
+// src/expiry.rs
+//
+// Object expiry: a PUT with `x-bkt-expires-in: <secs>` or
+// `x-bkt-expires-at: <unix>` records `expires_at` in the sidecar and here,
+// and a sweeper (every EXPIRY_SWEEP_SECS) deletes objects once it passes.
+// In between, an expired object already reads as 404 and is left out of
+// listings.
+use actix_web::http::header::HeaderMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::consts::Config;
+use crate::events::notify;
+use crate::meta::{self, ObjectMeta};
+use crate::pending::now_secs;
+use crate::trash;
+use crate::AppState;
+
+pub const EXPIRES_IN_HEADER: &str = "x-bkt-expires-in";
+pub const EXPIRES_AT_HEADER: &str = "x-bkt-expires-at";
+
+#[derive(Debug)]
+pub enum ExpiryError {
+    /// Both headers were sent.
+    Conflict,
+    /// Not a positive integer; carries the header name.
+    Invalid(&'static str),
+    /// `x-bkt-expires-at` is not in the future.
+    InPast,
+}
+
+/// The expiry requested by a PUT, as a unix time.
+pub fn from_request(headers: &HeaderMap, now: u64) -> Result<Option<u64>, ExpiryError> {
+    let read = |name: &'static str| -> Result<Option<u64>, ExpiryError> {
+        match headers.get(name) {
+            None => Ok(None),
+            Some(v) => v
+                .to_str()
+                .ok()
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .filter(|n| *n > 0)
+                .map(Some)
+                .ok_or(ExpiryError::Invalid(name)),
+        }
+    };
+    match (read(EXPIRES_IN_HEADER)?, read(EXPIRES_AT_HEADER)?) {
+        (Some(_), Some(_)) => Err(ExpiryError::Conflict),
+        (Some(secs), None) => Ok(Some(now.saturating_add(secs))),
+        (None, Some(at)) if at <= now => Err(ExpiryError::InPast),
+        (None, at) => Ok(at),
+    }
+}
+
+/// Keys with an expiry, and when.
+#[derive(Default)]
+pub struct Expiries {
+    entries: Mutex<HashMap<String, u64>>,
+}
+
+impl Expiries {
+    /// Rebuilds the index from sidecars carrying `expires_at` (see `meta::scan`);
+    /// held objects are left out until their hold is released.
+    pub fn from_sidecars(sidecars: &[(String, ObjectMeta)]) -> Self {
+        let entries = sidecars.iter().filter(|(_, m)| !m.legal_hold).filter_map(|(key, m)| Some((key.clone(), m.expires_at?))).collect();
+        Self { entries: Mutex::new(entries) }
+    }
+
+    pub fn set(&self, key: &str, at: u64) {
+        self.entries.lock().unwrap().insert(key.to_string(), at);
+    }
+
+    pub fn clear(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    pub fn get(&self, key: &str) -> Option<u64> {
+        self.entries.lock().unwrap().get(key).copied()
+    }
+
+    pub fn is_expired(&self, key: &str, now: u64) -> bool {
+        self.get(key).is_some_and(|at| at <= now)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns every key expired at `now`.
+    fn take_due(&self, now: u64) -> Vec<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let due: Vec<String> = entries.iter().filter(|(_, at)| **at <= now).map(|(k, _)| k.clone()).collect();
+        for k in &due {
+            entries.remove(k);
+        }
+        due
+    }
+}
+
+/// Deletes `key` if its sidecar still says it has expired (an overwrite may
+/// have replaced it since). Called by the sweeper and by reads that hit it first.
+pub async fn expire(state: &AppState, key: &str) {
+    let path = state.root.join(key);
+    let now = now_secs();
+    match meta::load(&state.root, &path).await {
+        Ok(m) if m.expires_at.is_some_and(|at| at <= now) && !m.legal_hold => {}
+        _ => return,
+    }
+    let Ok(current) = tokio::fs::metadata(&path).await else {
+        state.expiries.clear(key);
+        let _ = meta::remove(&state.root, &path).await;
+        return;
+    };
+    match trash::discard(state, &path).await {
+        Ok(()) => notify(state, "object.expired", &path, &current),
+        Err(e) if tokio::fs::metadata(&path).await.is_ok() => {
+            eprintln!("⚠️  expiring {key} failed, will retry: {e}");
+            state.expiries.set(key, now);
+        }
+        Err(e) => eprintln!("⚠️  expired {key}: sidecar not cleaned up: {e}"),
+    }
+}
+
+/// Starts the expiry sweeper. Call once per process, from inside the actix runtime.
+pub fn spawn_sweeper(state: AppState, cfg: &Config) {
+    let every = Duration::from_secs(cfg.expiry_sweep_secs.max(1));
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(every);
+        loop {
+            tick.tick().await;
+            for key in state.expiries.take_due(now_secs()) {
+                expire(&state, &key).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,144 @@
+// src/headers.rs
+//
+// Custom response headers: per-object ones supplied on PUT as
+// `x-bucket-header-<Name>` and global per-prefix rules (HEADER_RULES_FILE).
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Request header prefix carrying a per-object response header on PUT.
+pub const OBJECT_HEADER_PREFIX: &str = "x-bucket-header-";
+
+/// Request/response header prefix carrying user metadata (`x-bkt-meta-<name>`).
+pub const USER_META_PREFIX: &str = "x-bkt-meta-";
+
+/// Cap on the combined size (names + values) of one object's custom headers.
+pub const MAX_OBJECT_HEADER_BYTES: usize = 4096;
+
+/// Response headers an object may set for itself. Anything that changes how
+/// the body is framed or interpreted (Content-Type/Length/Encoding, Set-Cookie…)
+/// is deliberately absent.
+const ALLOWED: &[&str] = &[
+    "Cache-Control",
+    "Content-Language",
+    "Content-Security-Policy",
+    "Content-Security-Policy-Report-Only",
+    "Cross-Origin-Resource-Policy",
+    "Link",
+    "Permissions-Policy",
+    "Referrer-Policy",
+    "X-Content-Type-Options",
+    "X-Frame-Options",
+    "X-Robots-Tag",
+];
+
+/// Headers applied to every object under `prefix`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HeaderRule {
+    pub prefix: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Why a custom header was refused, with the offending name.
+#[derive(Debug)]
+pub enum HeaderError {
+    NotAllowed(String),
+    InvalidValue(String),
+    TooLarge(usize),
+}
+
+/// Canonical spelling of an allow-listed header name, case-insensitively.
+pub fn allowed_name(name: &str) -> Option<&'static str> {
+    ALLOWED.iter().copied().find(|a| a.eq_ignore_ascii_case(name))
+}
+
+/// Visible ASCII, space and tab only; CR/LF (header injection) and other controls are refused.
+fn valid_value(v: &str) -> bool {
+    v.bytes().all(|b| b == b'\t' || (b' '..=b'~').contains(&b))
+}
+
+fn valid_name(n: &str) -> bool {
+    !n.is_empty() && n.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// Collects `x-bucket-header-*` pairs from a PUT, validating names, values and total size.
+pub fn from_request(headers: &actix_web::http::header::HeaderMap) -> Result<BTreeMap<String, String>, HeaderError> {
+    let mut out = BTreeMap::new();
+    let mut size = 0;
+    for (name, value) in headers.iter() {
+        let Some(custom) = name.as_str().strip_prefix(OBJECT_HEADER_PREFIX) else { continue };
+        let canonical = allowed_name(custom).ok_or_else(|| HeaderError::NotAllowed(custom.to_string()))?;
+        let value = value
+            .to_str()
+            .ok()
+            .filter(|v| valid_value(v))
+            .ok_or_else(|| HeaderError::InvalidValue(canonical.to_string()))?;
+        size += canonical.len() + value.len();
+        if size > MAX_OBJECT_HEADER_BYTES {
+            return Err(HeaderError::TooLarge(MAX_OBJECT_HEADER_BYTES));
+        }
+        out.insert(canonical.to_string(), value.trim().to_string());
+    }
+    Ok(out)
+}
+
+/// Collects `x-bkt-meta-*` pairs, names lowercased; an empty value is kept
+/// (the caller reads it as "remove"). Same value rules and size cap as
+/// custom headers.
+pub fn user_meta_from_request(headers: &actix_web::http::header::HeaderMap) -> Result<BTreeMap<String, String>, HeaderError> {
+    let mut out = BTreeMap::new();
+    let mut size = 0;
+    for (name, value) in headers.iter() {
+        let Some(custom) = name.as_str().strip_prefix(USER_META_PREFIX) else { continue };
+        if !valid_name(custom) {
+            return Err(HeaderError::NotAllowed(name.as_str().to_string()));
+        }
+        let value = value
+            .to_str()
+            .ok()
+            .filter(|v| valid_value(v))
+            .ok_or_else(|| HeaderError::InvalidValue(name.as_str().to_string()))?;
+        size += custom.len() + value.len();
+        if size > MAX_OBJECT_HEADER_BYTES {
+            return Err(HeaderError::TooLarge(MAX_OBJECT_HEADER_BYTES));
+        }
+        out.insert(custom.to_ascii_lowercase(), value.trim().to_string());
+    }
+    Ok(out)
+}
+
+/// Reads rules from a JSON file (`[{"prefix": "docs/", "headers": {"X-Robots-Tag": "noindex"}}]`).
+/// Rule names aren't allow-listed (operators may set anything), but must be syntactically valid.
+pub fn load_rules(path: &str) -> Result<Vec<HeaderRule>, String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut rules: Vec<HeaderRule> = serde_json::from_str(&raw).map_err(|e| format!("{path}: {e}"))?;
+    for rule in &rules {
+        for (name, value) in &rule.headers {
+            if !valid_name(name) || !valid_value(value) {
+                return Err(format!("{path}: invalid header {name:?} for prefix {:?}", rule.prefix));
+            }
+        }
+    }
+    // shortest prefix first, so more specific rules override when merged in order
+    rules.sort_by_key(|r| r.prefix.len());
+    Ok(rules)
+}
+
+/// Headers for `key`: matching prefix rules (most specific last), then the object's own.
+pub fn merged(rules: &[HeaderRule], key: &str, object: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    let mut out: Vec<(String, String)> = Vec::new();
+    let mut set = |name: &str, value: &str| {
+        match out.iter_mut().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            Some(slot) => slot.1 = value.to_string(),
+            None => out.push((name.to_string(), value.to_string())),
+        }
+    };
+    for rule in rules.iter().filter(|r| key.starts_with(&r.prefix)) {
+        for (n, v) in &rule.headers {
+            set(n, v);
+        }
+    }
+    for (n, v) in object {
+        set(n, v);
+    }
+    out
+}
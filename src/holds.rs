@@ -0,0 +1,47 @@
+// src/holds.rs
+//
+// Legal holds: `PUT /objects/{key}?hold` (admin) sets `legal_hold` in the
+// sidecar and `DELETE /objects/{key}?hold` clears it. Unlike retention
+// there's no end time: until released, the object can't be deleted or
+// overwritten (423 Locked) and doesn't expire. Held keys are indexed here,
+// rebuilt from sidecars at startup, for those checks and `held=1` listings.
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::meta::ObjectMeta;
+
+/// Response header marking a held object on GET/HEAD.
+pub const LEGAL_HOLD_HEADER: &str = "x-bkt-legal-hold";
+
+#[derive(Default)]
+pub struct Holds {
+    keys: Mutex<HashSet<String>>,
+}
+
+impl Holds {
+    /// Rebuilds the index from sidecars carrying `legal_hold` (see `meta::scan`).
+    pub fn from_sidecars(sidecars: &[(String, ObjectMeta)]) -> Self {
+        let keys = sidecars.iter().filter(|(_, m)| m.legal_hold).map(|(key, _)| key.clone()).collect();
+        Self { keys: Mutex::new(keys) }
+    }
+
+    pub fn set(&self, key: &str) {
+        self.keys.lock().unwrap().insert(key.to_string());
+    }
+
+    pub fn clear(&self, key: &str) {
+        self.keys.lock().unwrap().remove(key);
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.lock().unwrap().contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
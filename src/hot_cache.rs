@@ -0,0 +1,142 @@
+// src/hot_cache.rs
+//
+// Bytes of small, often-read objects, so a GET for one is served from
+// memory instead of opening and streaming the file. Off unless
+// HOT_CACHE_MAX_OBJECT_BYTES is set; objects up to that size are kept in an
+// LRU bounded by HOT_CACHE_TOTAL_BYTES. Like the metadata cache, an entry
+// is used only while the file's size and mtime still match, and writes
+// through the server drop it.
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use actix_web::web::Bytes;
+
+use crate::meta_cache::Lru;
+
+struct Hot {
+    bytes: Bytes,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Default)]
+pub struct HotCache {
+    /// Largest object kept; 0 turns the cache off.
+    max_object: u64,
+    /// Bytes kept in all.
+    budget: u64,
+    lru: Mutex<Lru<Arc<Hot>>>,
+    /// Bumped by every `forget`, so a read that raced a write isn't cached.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl HotCache {
+    pub fn new(max_object: u64, budget: u64) -> Self {
+        Self { max_object, budget, ..Self::default() }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.max_object > 0 && self.budget > 0
+    }
+
+    /// The bytes of the object at `path`, whose stat is `file`: from memory
+    /// while size and mtime still match, else read and kept. `None` for
+    /// objects over the size limit, or one that changed while being read
+    /// (the caller streams it from disk instead).
+    pub async fn get(&self, path: &Path, file: &std::fs::Metadata) -> io::Result<Option<Bytes>> {
+        if !self.enabled() || file.len() > self.max_object {
+            return Ok(None);
+        }
+        let modified = file.modified().ok();
+        let generation = self.generation.load(Ordering::Acquire);
+        let hit = self.lru.lock().unwrap().get(path).filter(|h| h.bytes.len() as u64 == file.len() && h.modified == modified);
+        if let Some(hit) = hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(hit.bytes.clone()));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let bytes = Bytes::from(tokio::fs::read(path).await?);
+        // a write that landed between the stat and the read
+        let after = tokio::fs::metadata(path).await?;
+        if bytes.len() as u64 != file.len() || after.len() != file.len() || after.modified().ok() != modified {
+            return Ok(None);
+        }
+        let mut lru = self.lru.lock().unwrap();
+        if self.generation.load(Ordering::Acquire) == generation {
+            let hot = Arc::new(Hot { bytes: bytes.clone(), modified });
+            lru.insert(path.to_path_buf(), hot, bytes.len() as u64, self.budget);
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Drops the entry for `path`; call after any change to the object.
+    pub fn forget(&self, path: &Path) {
+        if !self.enabled() {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::Release);
+        lru.remove(path);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Object bytes held in memory.
+    pub fn bytes(&self) -> u64 {
+        self.lru.lock().unwrap().weight()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn keeps_small_objects_within_the_budget() {
+        let td = tempfile::tempdir().unwrap();
+        let (a, b, big) = (td.path().join("a"), td.path().join("b"), td.path().join("big"));
+        std::fs::write(&a, "aaaa").unwrap();
+        std::fs::write(&b, "bbbb").unwrap();
+        std::fs::write(&big, "0123456789").unwrap();
+        let stat = |p: &Path| std::fs::metadata(p).unwrap();
+        let cache = HotCache::new(8, 6);
+
+        assert_eq!(cache.get(&a, &stat(&a)).await.unwrap().unwrap(), "aaaa");
+        assert_eq!(cache.get(&a, &stat(&a)).await.unwrap().unwrap(), "aaaa");
+        assert_eq!((cache.hits(), cache.misses(), cache.bytes()), (1, 1, 4));
+
+        // over the per-object limit: not served from memory at all
+        assert!(cache.get(&big, &stat(&big)).await.unwrap().is_none());
+
+        // b doesn't fit beside a, which goes
+        cache.get(&b, &stat(&b)).await.unwrap();
+        assert_eq!((cache.len(), cache.bytes()), (1, 4));
+
+        // a changed file is read again
+        std::fs::write(&b, "bb").unwrap();
+        assert_eq!(cache.get(&b, &stat(&b)).await.unwrap().unwrap(), "bb");
+        assert_eq!(cache.misses(), 3);
+
+        cache.forget(&b);
+        assert!(cache.is_empty());
+        assert!(HotCache::default().get(&a, &stat(&a)).await.unwrap().is_none());
+    }
+}
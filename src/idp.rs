@@ -0,0 +1,239 @@
+// src/idp.rs
+//
+// Built-in dev identity provider: RSA keypairs persisted under IDP_KEY_DIR
+// (`<kid>.pem`), published as a JWKS and used to mint RS256 tokens.
+//
+// The newest key signs. Rotation (`POST /idp/rotate`, or IDP_ROTATE_DAYS)
+// adds a key; the ones it supersedes stay in the JWKS for a grace window so
+// outstanding tokens still verify, then their files are deleted.
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::consts::Config;
+use crate::pending::now_secs;
+use crate::AppState;
+
+const KEY_BITS: usize = 2048;
+/// How often IDP_ROTATE_DAYS and the grace window are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A signing key with its public half in JWK form.
+pub struct IdpKey {
+    pub kid: String,
+    /// When the key was generated (its file's mtime), unix seconds.
+    pub created: u64,
+    pub jwk: Jwk,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub alg: String,
+    pub kid: String,
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+pub struct Idp {
+    dir: PathBuf,
+    /// Oldest first; the last one signs.
+    keys: RwLock<Vec<Arc<IdpKey>>>,
+}
+
+impl Idp {
+    /// Loads every `<kid>.pem` in `dir`, generating a key on first start.
+    pub fn load_or_generate(dir: &Path) -> io::Result<Self> {
+        let mut keys = Vec::new();
+        for (path, created) in find_pems(dir)? {
+            let pem = std::fs::read_to_string(&path)?;
+            let private = RsaPrivateKey::from_pkcs8_pem(&pem)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            keys.push(Arc::new(key_from_private(&private, created)?));
+        }
+        let idp = Self { dir: dir.to_path_buf(), keys: RwLock::new(keys) };
+        if idp.keys.read().unwrap().is_empty() {
+            idp.rotate()?;
+        }
+        Ok(idp)
+    }
+
+    /// The newest key: the one new tokens are signed with.
+    pub fn signing_key(&self) -> Arc<IdpKey> {
+        self.keys.read().unwrap().last().expect("an IdP always has a key").clone()
+    }
+
+    /// Every key still accepted, newest first.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet { keys: self.keys.read().unwrap().iter().rev().map(|k| k.jwk.clone()).collect() }
+    }
+
+    /// Public key for `kid`, if this IdP issued it and it's still accepted.
+    pub fn decoding_key(&self, kid: &str) -> Option<DecodingKey> {
+        self.keys.read().unwrap().iter().find(|k| k.kid == kid).map(|k| k.decoding.clone())
+    }
+
+    /// Generates and persists a new signing key; returns its `kid`. The keys
+    /// it supersedes keep verifying until `prune` drops them.
+    pub fn rotate(&self) -> io::Result<String> {
+        let private = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, KEY_BITS)
+            .map_err(io::Error::other)?;
+        let key = key_from_private(&private, now_secs())?;
+        let pem = private
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(io::Error::other)?;
+        create_private_dir(&self.dir)?;
+        write_private(&self.dir.join(format!("{}.pem", key.kid)), pem.as_bytes())?;
+        let kid = key.kid.clone();
+        self.keys.write().unwrap().push(Arc::new(key));
+        Ok(kid)
+    }
+
+    /// Drops (and deletes the files of) keys superseded more than `grace`
+    /// seconds ago. Returns how many went.
+    pub fn prune(&self, grace: u64, now: u64) -> io::Result<usize> {
+        let expired: Vec<Arc<IdpKey>> = {
+            let mut keys = self.keys.write().unwrap();
+            // a key is superseded when the next one was created; the newest never is
+            let cut = keys.windows(2).take_while(|pair| now >= pair[1].created.saturating_add(grace)).count();
+            keys.drain(..cut).collect()
+        };
+        for key in &expired {
+            match std::fs::remove_file(self.dir.join(format!("{}.pem", key.kid))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(expired.len())
+    }
+
+    /// Rotates if the signing key is older than IDP_ROTATE_DAYS, then prunes
+    /// keys past the grace window.
+    pub fn maintain(&self, cfg: &Config) -> io::Result<()> {
+        let now = now_secs();
+        if cfg.idp_rotate_days > 0 && now >= self.signing_key().created.saturating_add(cfg.idp_rotate_days * 86_400) {
+            let kid = self.rotate()?;
+            println!("🔑 IdP signing key rotated (kid {kid})");
+        }
+        self.prune(grace_secs(cfg), now)?;
+        Ok(())
+    }
+}
+
+/// How long a superseded key keeps verifying: the longest token the IdP
+/// mints (AUTH_MAX_TTL_SECS) plus clock leeway.
+pub fn grace_secs(cfg: &Config) -> u64 {
+    cfg.auth_max_ttl_secs.saturating_add(cfg.jwt_leeway_secs)
+}
+
+/// Starts the background task that runs `Idp::maintain` hourly. Call once
+/// per process, from inside the actix runtime.
+pub fn spawn_rotator(state: AppState, cfg: &Config) {
+    let Some(idp) = state.idp.clone() else { return };
+    let cfg = cfg.clone();
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(CHECK_INTERVAL);
+        loop {
+            tick.tick().await;
+            let idp = idp.clone();
+            let cfg = cfg.clone();
+            // key generation is CPU-heavy
+            match actix_web::web::block(move || idp.maintain(&cfg)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("⚠️  IdP key maintenance failed: {e}"),
+                Err(e) => eprintln!("⚠️  IdP key maintenance failed: {e}"),
+            }
+        }
+    });
+}
+
+fn key_from_private(private: &RsaPrivateKey, created: u64) -> io::Result<IdpKey> {
+    let n = URL_SAFE_NO_PAD.encode(private.n().to_bytes_be());
+    let e = URL_SAFE_NO_PAD.encode(private.e().to_bytes_be());
+    let kid = thumbprint(&n, &e);
+    let pem = private
+        .to_pkcs8_pem(LineEnding::LF)
+        .map_err(io::Error::other)?;
+    let encoding = EncodingKey::from_rsa_pem(pem.as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let decoding = DecodingKey::from_rsa_components(&n, &e)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let jwk = Jwk {
+        kty: "RSA".into(),
+        use_: "sig".into(),
+        alg: "RS256".into(),
+        kid: kid.clone(),
+        n,
+        e,
+    };
+    Ok(IdpKey { kid, created, jwk, encoding, decoding })
+}
+
+/// RFC 7638 JWK thumbprint, used as the `kid`.
+fn thumbprint(n: &str, e: &str) -> String {
+    let canonical = format!(r#"{{"e":"{e}","kty":"RSA","n":"{n}"}}"#);
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// The `.pem` files in `dir` with their creation times, oldest first.
+fn find_pems(dir: &Path) -> io::Result<Vec<(PathBuf, u64)>> {
+    let rd = match std::fs::read_dir(dir) {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut pems = Vec::new();
+    for entry in rd.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|x| x.to_str()) != Some("pem") {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        pems.push((modified, path));
+    }
+    pems.sort();
+    Ok(pems
+        .into_iter()
+        .map(|(modified, path)| (path, modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)))
+        .collect())
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Writes a private key readable by the owner only.
+fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    opts.open(path)?.write_all(bytes)
+}
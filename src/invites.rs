@@ -0,0 +1,92 @@
+// src/invites.rs
+//
+// One-time signup codes for SIGNUP_MODE=invite, minted by `POST /auth/invites`
+// and persisted as a JSON map `{sha256(code): expires}` next to the user
+// store. Only digests are kept, so the file can't be replayed as codes. A
+// code is removed when a signup consumes it or once it expires.
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
+
+use crate::pending::now_secs;
+
+#[derive(Default)]
+pub struct Invites {
+    /// Backing file; `None` keeps the codes in memory only.
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, u64>>,
+    /// Serialises file writes so an older snapshot can't land last.
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+fn digest(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().as_bytes()))
+}
+
+impl Invites {
+    /// Loads `path` if it exists, discarding already-expired codes.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut entries: HashMap<String, u64> = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let now = now_secs();
+        entries.retain(|_, exp| *exp > now);
+        Ok(Self { path: Some(path.to_path_buf()), entries: Mutex::new(entries), write_lock: Default::default() })
+    }
+
+    /// A fresh code valid for `ttl_secs`, with its expiry.
+    pub async fn mint(&self, ttl_secs: u64) -> io::Result<(String, u64)> {
+        let code = uuid::Uuid::new_v4().simple().to_string();
+        let expires = now_secs() + ttl_secs;
+        let _writing = self.write_lock.lock().await;
+        self.entries.lock().unwrap().insert(digest(&code), expires);
+        self.persist().await?;
+        Ok((code, expires))
+    }
+
+    /// Whether `code` is live, without using it up.
+    pub fn is_valid(&self, code: &str) -> bool {
+        self.entries.lock().unwrap().get(&digest(code)).is_some_and(|exp| *exp > now_secs())
+    }
+
+    /// Uses up `code`; `false` if it is unknown, spent or expired.
+    pub async fn consume(&self, code: &str) -> io::Result<bool> {
+        let _writing = self.write_lock.lock().await;
+        let found = self.entries.lock().unwrap().remove(&digest(code)).is_some_and(|exp| exp > now_secs());
+        if found {
+            self.persist().await?;
+        }
+        Ok(found)
+    }
+
+    /// Prunes expired codes and writes the rest. Call with `write_lock` held.
+    async fn persist(&self) -> io::Result<()> {
+        let snapshot = {
+            let mut entries = self.entries.lock().unwrap();
+            let now = now_secs();
+            entries.retain(|_, exp| *exp > now);
+            serde_json::to_vec(&*entries).map_err(io::Error::other)?
+        };
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // write-then-rename so a crash never leaves a truncated list
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, snapshot).await?;
+        tokio::fs::rename(&tmp, path).await
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
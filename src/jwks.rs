@@ -0,0 +1,90 @@
+// src/jwks.rs
+//
+// Remote JWKS documents for RS256 verification, cached per URL for
+// JWKS_TTL_SECS. A `kid` the cached copy doesn't know triggers an early
+// refetch (at most once per JWKS_REFETCH_SECS per URL), so keys rotated in
+// upstream verify before the TTL runs out.
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::DecodingKey;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One URL's document, as decoding keys by kid.
+struct Entry {
+    fetched: Instant,
+    /// Last fetch started, successful or not; paces unknown-kid refetches.
+    attempted: Instant,
+    keys: HashMap<String, DecodingKey>,
+}
+
+#[derive(Default)]
+pub struct JwksCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+/// How long a cached document is trusted, and how soon an unknown kid may
+/// refetch it.
+#[derive(Clone, Copy)]
+pub struct Freshness {
+    pub ttl: Duration,
+    pub refetch: Duration,
+}
+
+impl JwksCache {
+    /// Decoding key for `kid` from the JWKS at `url`, fetching if the cached
+    /// copy is stale or (rate-limited) doesn't know `kid`.
+    pub async fn key_for(&self, url: &str, kid: &str, freshness: Freshness) -> Result<Option<DecodingKey>, String> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(entry) = entries.get_mut(url).filter(|e| e.fetched.elapsed() < freshness.ttl) {
+                if let Some(key) = entry.keys.get(kid) {
+                    return Ok(Some(key.clone()));
+                }
+                if entry.attempted.elapsed() < freshness.refetch {
+                    return Ok(None);
+                }
+                // claim the refetch so concurrent requests don't all go
+                entry.attempted = Instant::now();
+            }
+        }
+
+        let set = fetch(url).await;
+        let mut entries = self.entries.lock().unwrap();
+        let set = match set {
+            Ok(set) => set,
+            Err(e) => {
+                if let Some(entry) = entries.get_mut(url) {
+                    entry.attempted = Instant::now();
+                }
+                return Err(e);
+            }
+        };
+        let keys = decoding_keys(&set);
+        let found = keys.get(kid).cloned();
+        let now = Instant::now();
+        entries.insert(url.to_string(), Entry { fetched: now, attempted: now, keys });
+        Ok(found)
+    }
+}
+
+/// The set's usable keys by kid; keys without a kid or that don't parse are skipped.
+fn decoding_keys(set: &JwkSet) -> HashMap<String, DecodingKey> {
+    set.keys
+        .iter()
+        .filter_map(|jwk| Some((jwk.common.key_id.clone()?, DecodingKey::from_jwk(jwk).ok()?)))
+        .collect()
+}
+
+async fn fetch(url: &str) -> Result<JwkSet, String> {
+    let resp = reqwest::Client::new()
+        .get(url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| format!("jwks fetch {url}: {e}"))?;
+    if !resp.status().is_success() {
+        return Err(format!("jwks fetch {url}: HTTP {}", resp.status()));
+    }
+    resp.json::<JwkSet>().await.map_err(|e| format!("jwks parse {url}: {e}"))
+}
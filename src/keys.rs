@@ -0,0 +1,165 @@
+// src/keys.rs
+//
+// What an object key may look like, beyond `resolve_key`'s path checks:
+// length caps, no control characters, an optional character blacklist,
+// only names every common filesystem can store (optional, but always on under
+// Windows, which can't store the others), and no keys under a reserved prefix.
+
+/// Limits on keys (KEY_MAX_BYTES, KEY_SEGMENT_MAX_BYTES, KEY_FORBIDDEN_CHARS,
+/// KEY_PORTABLE_NAMES, BLOCKED_PREFIXES).
+#[derive(Clone, Debug)]
+pub struct KeyRules {
+    pub max_bytes: usize,
+    pub segment_max_bytes: usize,
+    pub forbidden_chars: Vec<char>,
+    pub portable_names: bool,
+    /// Refused on top of the server's own directories (`.meta`, `.trash`, ...).
+    pub blocked_prefixes: Vec<String>,
+}
+
+impl Default for KeyRules {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024,
+            segment_max_bytes: 255,
+            forbidden_chars: Vec::new(),
+            portable_names: false,
+            blocked_prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Which rule a key broke.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyError {
+    TooLong(usize),
+    SegmentTooLong(usize),
+    ControlChar,
+    ForbiddenChar(char),
+    NotPortable(String),
+    /// Under a reserved prefix: a server directory or a BLOCKED_PREFIXES entry.
+    Blocked(String),
+}
+
+impl KeyError {
+    /// The rule's name, as reported to clients.
+    pub fn rule(&self) -> &'static str {
+        match self {
+            KeyError::TooLong(_) => "max_bytes",
+            KeyError::SegmentTooLong(_) => "segment_max_bytes",
+            KeyError::ControlChar => "control_chars",
+            KeyError::ForbiddenChar(_) => "forbidden_chars",
+            KeyError::NotPortable(_) => "portable_names",
+            KeyError::Blocked(_) => "blocked_prefixes",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            KeyError::TooLong(max) => format!("key is longer than {max} bytes"),
+            KeyError::SegmentTooLong(max) => format!("a key segment is longer than {max} bytes"),
+            KeyError::ControlChar => "key contains control characters".into(),
+            KeyError::ForbiddenChar(c) => format!("key contains forbidden character {c:?}"),
+            KeyError::NotPortable(seg) => format!("key segment {seg:?} can't be stored on every filesystem"),
+            KeyError::Blocked(prefix) => format!("keys under {prefix:?} are reserved"),
+        }
+    }
+}
+
+/// Characters Windows refuses in file names.
+const NON_PORTABLE_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+impl KeyRules {
+    /// Checks `key` (or a listing prefix: an empty last segment is fine).
+    pub fn check(&self, key: &str) -> Result<(), KeyError> {
+        if key.len() > self.max_bytes {
+            return Err(KeyError::TooLong(self.max_bytes));
+        }
+        if key.chars().any(char::is_control) {
+            return Err(KeyError::ControlChar);
+        }
+        if let Some(c) = key.chars().find(|c| self.forbidden_chars.contains(c)) {
+            return Err(KeyError::ForbiddenChar(c));
+        }
+        for seg in key.split('/') {
+            if seg.len() > self.segment_max_bytes {
+                return Err(KeyError::SegmentTooLong(self.segment_max_bytes));
+            }
+            if (self.portable_names || cfg!(windows)) && !seg.is_empty() && !portable(seg) {
+                return Err(KeyError::NotPortable(seg.to_string()));
+            }
+        }
+        match self.blocked(key) {
+            Some(prefix) => Err(KeyError::Blocked(prefix)),
+            None => Ok(()),
+        }
+    }
+
+    /// The reserved prefix `key` falls under, if any: a first segment naming
+    /// one of the server's directories, or a BLOCKED_PREFIXES entry.
+    pub fn blocked(&self, key: &str) -> Option<String> {
+        let first = key.split('/').next().unwrap_or(key);
+        if crate::storage::is_internal(std::ffi::OsStr::new(first)) {
+            return Some(format!("{first}/"));
+        }
+        self.blocked_prefixes.iter().find(|p| key.starts_with(p.as_str())).cloned()
+    }
+}
+
+/// Whether `seg` is a legal file name on Windows as well as Unix.
+fn portable(seg: &str) -> bool {
+    let stem = seg.split('.').next().unwrap_or(seg).trim_end();
+    !seg.contains(NON_PORTABLE_CHARS)
+        && !seg.ends_with(['.', ' '])
+        && !RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let rules = KeyRules::default();
+        for ok in ["a.txt", "dir/sub/file", "ünïcode/名前", "CON", "a/", &"x".repeat(255)] {
+            assert_eq!(rules.check(ok), Ok(()), "{ok}");
+        }
+        assert_eq!(rules.check(&"a/".repeat(600)), Err(KeyError::TooLong(1024)));
+        assert_eq!(rules.check(&"x".repeat(256)), Err(KeyError::SegmentTooLong(255)));
+        assert_eq!(rules.check("a\nb"), Err(KeyError::ControlChar));
+        assert_eq!(rules.check("a\u{7f}"), Err(KeyError::ControlChar));
+
+        let strict = KeyRules { forbidden_chars: vec!['#', '%'], portable_names: true, ..KeyRules::default() };
+        assert_eq!(strict.check("a#b"), Err(KeyError::ForbiddenChar('#')));
+        for bad in ["CON", "docs/nul.txt", "Com1", "lpt9.tar.gz", "a?b", "trailing.", "space ", "a:b"] {
+            assert!(matches!(strict.check(bad), Err(KeyError::NotPortable(_))), "{bad}");
+        }
+        for ok in ["console", "CONS.txt", "COM10", "a/b.txt", "prefix/"] {
+            assert_eq!(strict.check(ok), Ok(()), "{ok}");
+        }
+
+        let blocked = KeyRules { blocked_prefixes: vec!["private/".into()], ..KeyRules::default() };
+        assert_eq!(blocked.check(".meta/a.txt.json"), Err(KeyError::Blocked(".meta/".into())));
+        assert_eq!(blocked.check(".trash"), Err(KeyError::Blocked(".trash/".into())));
+        assert_eq!(blocked.check("private/a.txt"), Err(KeyError::Blocked("private/".into())));
+        for ok in [".metadata/a", "a/.meta/b", "private", "privateer/a"] {
+            assert_eq!(blocked.check(ok), Ok(()), "{ok}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_refuses_names_it_cant_store() {
+        let rules = KeyRules::default();
+        for bad in ["CON", "docs/aux.txt", "a:b", "a\\b", "q?.txt", "trailing."] {
+            assert!(matches!(rules.check(bad), Err(KeyError::NotPortable(_))), "{bad}");
+        }
+        assert_eq!(rules.check("docs/console.txt"), Ok(()));
+    }
+}
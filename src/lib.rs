@@ -6,14 +6,232 @@ use actix_web::{
     body::MessageBody,
     Error,
 };
+pub mod access_log;
+pub mod archive;
+pub mod buckets;
+pub mod checksum;
 pub mod consts; 
+pub mod copy;
+pub mod dedup;
 pub mod auth;
+pub mod compress;
+pub mod content_types;
+pub mod error;
+pub mod events;
+pub mod expiry;
+pub mod headers;
+pub mod holds;
+pub mod hot_cache;
+pub mod idp;
+pub mod invites;
+pub mod jwks;
+pub mod keys;
+pub mod limits;
+pub mod locks;
+pub mod meta;
+pub mod meta_cache;
+pub mod pending;
+pub mod resumable;
+pub mod retention;
+pub mod revocation;
+pub mod s3;
+pub mod search;
+pub mod selftest;
+pub mod shutdown;
+pub mod stats;
+pub mod storage;
+pub mod tenants;
+pub mod throttle;
+pub mod tls;
+pub mod trash;
+pub mod uploads;
+pub mod webhooks;
 mod routes;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub root: PathBuf,
+    pub uploads: Arc<uploads::UploadRegistry>,
+    /// One writer at a time per object path.
+    pub locks: Arc<locks::KeyLocks>,
+    /// Set when a write fails with a read-only/permission error; cleared by the next successful write.
+    pub read_only: Arc<AtomicBool>,
+    /// Embedded dev IdP keys (IDP_EMBED=1), loaded once before the server starts.
+    pub idp: Option<Arc<idp::Idp>>,
+    pub jwks: Arc<jwks::JwksCache>,
+    /// Objects under `root`, for MAX_OBJECT_COUNT.
+    pub objects: Arc<storage::ObjectCount>,
+    /// Deferred deletes awaiting the sweeper (DELETE_GRACE_SECS).
+    pub pending: Arc<pending::PendingDeletes>,
+    /// Objects with an expiry (x-bkt-expires-*), for the expiry sweeper.
+    pub expiries: Arc<expiry::Expiries>,
+    /// Objects under a legal hold.
+    pub holds: Arc<holds::Holds>,
+    /// `jti`s revoked by logout.
+    pub denylist: Arc<revocation::Denylist>,
+    /// Unspent signup codes (SIGNUP_MODE=invite).
+    pub invites: Arc<invites::Invites>,
+    /// Soft-delete settings (TRASH_ENABLED).
+    pub trash: Arc<trash::Trash>,
+    /// Content-addressed blob store (DEDUP).
+    pub dedup: Arc<dedup::Dedup>,
+    /// Object change feed for `GET /events` subscribers.
+    pub events: tokio::sync::broadcast::Sender<events::Event>,
+    /// Object event queue for WEBHOOK_URL.
+    pub webhooks: Arc<webhooks::Webhooks>,
+    /// Named buckets (directories under `root`); the root itself is the default bucket.
+    pub buckets: Arc<buckets::Buckets>,
+    /// Cached `/stats` walk.
+    pub stats: Arc<stats::StatsCache>,
+    /// Cached `/objects-summary` results for large prefixes.
+    pub summaries: Arc<stats::SummaryCache>,
+    /// Cached `/readyz` result.
+    pub ready: Arc<storage::ReadyCache>,
+    /// Requests being handled, for draining on shutdown.
+    pub in_flight: Arc<shutdown::InFlight>,
+    /// Failed logins per username and client address.
+    pub login_throttle: Arc<throttle::LoginThrottle>,
+    /// PUT bodies being streamed in (MAX_CONCURRENT_UPLOADS).
+    pub upload_slots: Arc<limits::Limiter>,
+    /// GET bodies being streamed out (MAX_CONCURRENT_DOWNLOADS).
+    pub download_slots: Arc<limits::Limiter>,
+    /// Aggregate PUT body bandwidth (UPLOAD_RATE_LIMIT_BPS).
+    pub upload_rate: Arc<limits::RateLimit>,
+    /// Aggregate GET body bandwidth (DOWNLOAD_RATE_LIMIT_BPS).
+    pub download_rate: Arc<limits::RateLimit>,
+    /// Object bytes served, per second.
+    pub downloaded: Arc<limits::Meter>,
+    /// GET and archive bodies being streamed out, limited or not.
+    pub downloads_in_flight: Arc<AtomicUsize>,
+    /// When the state was built, for the uptime in `/healthz?verbose=1`.
+    pub started: std::time::Instant,
+    /// Sidecars, ETags and types of recently read objects (META_CACHE_ENTRIES).
+    pub meta_cache: Arc<meta_cache::MetaCache>,
+    /// Bytes of small, often-read objects (HOT_CACHE_MAX_OBJECT_BYTES).
+    pub hot_cache: Arc<hot_cache::HotCache>,
+    /// LIST_EXCLUDE as one matcher, built once rather than per listing.
+    pub list_exclude: Arc<globset::GlobSet>,
+}
+
+impl AppState {
+    /// Scans `root` once to seed the object count, pending deletes, expiries and holds.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let objects = storage::ObjectCount::new(storage::count_objects(&root));
+        let sidecars = meta::scan(&root);
+        let pending = pending::PendingDeletes::from_sidecars(&sidecars);
+        let expiries = expiry::Expiries::from_sidecars(&sidecars);
+        let holds = holds::Holds::from_sidecars(&sidecars);
+        let buckets = buckets::Buckets::load(&root);
+        Self {
+            root,
+            uploads: Arc::new(uploads::UploadRegistry::default()),
+            locks: Arc::new(locks::KeyLocks::default()),
+            read_only: Arc::new(AtomicBool::new(false)),
+            idp: None,
+            jwks: Arc::new(jwks::JwksCache::default()),
+            objects: Arc::new(objects),
+            pending: Arc::new(pending),
+            expiries: Arc::new(expiries),
+            holds: Arc::new(holds),
+            denylist: Arc::new(revocation::Denylist::default()),
+            invites: Arc::new(invites::Invites::default()),
+            trash: Arc::new(trash::Trash::default()),
+            dedup: Arc::new(dedup::Dedup::default()),
+            events: events::channel(),
+            webhooks: Arc::new(webhooks::Webhooks::default()),
+            buckets: Arc::new(buckets),
+            stats: Arc::new(stats::StatsCache::default()),
+            summaries: Arc::new(stats::SummaryCache::default()),
+            ready: Arc::new(storage::ReadyCache::default()),
+            in_flight: Arc::new(shutdown::InFlight::default()),
+            login_throttle: Arc::new(throttle::LoginThrottle::default()),
+            upload_slots: Arc::new(limits::Limiter::default()),
+            download_slots: Arc::new(limits::Limiter::default()),
+            upload_rate: Arc::new(limits::RateLimit::default()),
+            download_rate: Arc::new(limits::RateLimit::default()),
+            downloaded: Arc::new(limits::Meter::default()),
+            downloads_in_flight: Arc::new(AtomicUsize::new(0)),
+            started: std::time::Instant::now(),
+            meta_cache: Arc::new(meta_cache::MetaCache::default()),
+            hot_cache: Arc::new(hot_cache::HotCache::default()),
+            list_exclude: Arc::new(globset::GlobSet::empty()),
+        }
+    }
+
+    /// Loads auth state from disk: the embedded IdP keys (created if missing,
+    /// rotated if due) when `cfg.idp_embed` is set, the revoked-token list and the
+    /// unspent invite codes.
+    pub fn with_auth(mut self, cfg: &consts::Config) -> std::io::Result<Self> {
+        if cfg.idp_embed {
+            let idp = idp::Idp::load_or_generate(std::path::Path::new(&cfg.idp_key_dir))?;
+            idp.maintain(cfg)?;
+            self.idp = Some(Arc::new(idp));
+        }
+        self.denylist = Arc::new(revocation::Denylist::load(&cfg.auth_denylist_path())?);
+        self.invites = Arc::new(invites::Invites::load(&cfg.auth_invites_path())?);
+        Ok(self)
+    }
+
+    /// Creates the buckets named in `cfg.buckets` that don't exist yet.
+    pub fn with_buckets(self, cfg: &consts::Config) -> std::io::Result<Self> {
+        for name in &cfg.buckets {
+            if !buckets::valid_name(name) {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("invalid bucket name {name:?} in BUCKETS")));
+            }
+            self.buckets.create(&self.root, name)?;
+        }
+        Ok(self)
+    }
+
+    /// Sizes the transfer slots from MAX_CONCURRENT_UPLOADS and
+    /// MAX_CONCURRENT_DOWNLOADS, and the shared bandwidth caps from
+    /// UPLOAD_RATE_LIMIT_BPS and DOWNLOAD_RATE_LIMIT_BPS; without this,
+    /// transfers are unlimited.
+    pub fn with_limits(mut self, cfg: &consts::Config) -> Self {
+        self.upload_slots = Arc::new(limits::Limiter::new(cfg.max_concurrent_uploads));
+        self.download_slots = Arc::new(limits::Limiter::new(cfg.max_concurrent_downloads));
+        self.upload_rate = Arc::new(limits::RateLimit::new(cfg.upload_rate_limit_bps));
+        self.download_rate = Arc::new(limits::RateLimit::new(cfg.download_rate_limit_bps));
+        self
+    }
+
+    /// Sizes the GET/HEAD metadata cache from META_CACHE_ENTRIES and
+    /// META_CACHE_TTL_SECS, and the small-object cache from
+    /// HOT_CACHE_MAX_OBJECT_BYTES and HOT_CACHE_TOTAL_BYTES; without this,
+    /// every read goes to disk.
+    pub fn with_caches(mut self, cfg: &consts::Config) -> Self {
+        let ttl = std::time::Duration::from_secs(cfg.meta_cache_ttl_secs);
+        self.meta_cache = Arc::new(meta_cache::MetaCache::new(cfg.meta_cache_entries, ttl));
+        self.hot_cache = Arc::new(hot_cache::HotCache::new(cfg.hot_cache_max_object_bytes, cfg.hot_cache_total_bytes));
+        self
+    }
+
+    /// Compiles LIST_EXCLUDE (the patterns were checked by `Config::resolve`);
+    /// without this, listings exclude nothing.
+    pub fn with_listing(mut self, cfg: &consts::Config) -> Self {
+        let mut set = globset::GlobSetBuilder::new();
+        for glob in cfg.list_exclude.iter().filter_map(|p| consts::key_glob(p).ok()) {
+            set.add(glob);
+        }
+        self.list_exclude = Arc::new(set.build().unwrap_or_default());
+        self
+    }
+
+    /// Drops what the read caches hold for the object at `path`; call after
+    /// any change to it or its sidecar.
+    pub fn forget_cached(&self, path: &std::path::Path) {
+        self.meta_cache.forget(path);
+        self.hot_cache.forget(path);
+    }
+
+    /// Whether `key` reads as present at `now`: not pending deletion, not expired.
+    pub fn is_visible(&self, key: &str, now: u64) -> bool {
+        !self.pending.is_pending(key) && !self.expiries.is_expired(key, now)
+    }
 }
 
 pub fn app(
@@ -28,12 +246,40 @@ pub fn app(
         Error = Error,
     >,
 > {
+    let auth_json_limit = cfg.auth_json_limit_bytes;
+    let ui_enabled = cfg.ui_enabled;
+    let s3_enabled = cfg.s3_enabled;
     App::new()
+        .wrap(actix_web::middleware::from_fn(shutdown::track))
+        .wrap(actix_web::middleware::from_fn(access_log::log))
         .app_data(web::Data::new(state))
         .app_data(web::Data::new(cfg))
         .configure(routes::health::init)
         .configure(routes::objects::init)
-        .configure(routes::session::init)
+        .configure(routes::archive::init)
+        .configure(routes::buckets::init)
+        .configure(|c| routes::session::init(c, auth_json_limit))
+        .configure(routes::idp::init)
+        .configure(routes::admin::init)
+        .configure(routes::metrics::init)
+        .configure(routes::events::init)
+        .configure(routes::trash::init)
+        .configure(routes::resumable::init)
+        .configure(routes::stats::init)
+        .configure(routes::search::init)
+        .configure(|c| {
+            #[cfg(feature = "ui")]
+            if ui_enabled {
+                routes::ui::init(c);
+            }
+            #[cfg(not(feature = "ui"))]
+            let _ = (c, ui_enabled);
+        })
+        .configure(|c| {
+            if s3_enabled {
+                routes::s3::init(c);
+            }
+        })
 }
 
 #[cfg(test)]
@@ -43,7 +289,7 @@ mod tests {
 
     #[actix_web::test]
     async fn app_builds_and_healthz_works() {
-        let state = AppState { root: PathBuf::from("/tmp") };
+        let state = AppState::new("/tmp");
         let cfg = consts::Config::from_env();
         let app = test::init_service(app(state, cfg)).await;
 
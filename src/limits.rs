@@ -0,0 +1,308 @@
+// src/limits.rs
+//
+// Limits on object transfers. Concurrency: MAX_CONCURRENT_UPLOADS bounds PUT
+// bodies being streamed to disk, MAX_CONCURRENT_DOWNLOADS (optional,
+// independent) bounds GET bodies being streamed out. A request that can't get
+// a slot within CONCURRENCY_WAIT_MS is answered 503 `too_busy` with
+// Retry-After instead of queueing behind the burst. Bandwidth:
+// UPLOAD_RATE_LIMIT_BPS caps all PUT bodies together and
+// UPLOAD_RATE_LIMIT_PER_UPLOAD_BPS each one, by pausing between reads;
+// DOWNLOAD_RATE_LIMIT_BPS caps everything served, and a client may slow its
+// own download further with `x-bkt-max-rate`, by pacing the body's chunks.
+// Time: a PUT body that goes quiet for UPLOAD_IDLE_TIMEOUT_SECS, or is still
+// arriving after UPLOAD_DEADLINE_SECS, is abandoned with 408.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::web::Bytes;
+use actix_web::{http::header, http::StatusCode, HttpRequest, ResponseError};
+use futures_util::{Stream, StreamExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::consts::{parse_size, Config};
+use crate::error::ApiError;
+use crate::pending::now_secs;
+use crate::AppState;
+
+/// A client's own cap on its download, in bytes per second (`512K`, `1MB`, ...).
+pub const MAX_RATE_HEADER: &str = "x-bkt-max-rate";
+
+/// What a turned-away client is told to wait, in seconds.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// One transfer direction's slots; unlimited by default.
+#[derive(Default)]
+pub struct Limiter {
+    slots: Option<(Arc<Semaphore>, usize)>,
+}
+
+impl Limiter {
+    /// `None` (or 0) means no cap.
+    pub fn new(limit: Option<usize>) -> Self {
+        Self { slots: limit.filter(|n| *n > 0).map(|n| (Arc::new(Semaphore::new(n)), n)) }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.slots.as_ref().map(|(_, n)| *n)
+    }
+
+    /// Transfers holding a slot right now (always 0 when unlimited).
+    pub fn in_flight(&self) -> usize {
+        self.slots.as_ref().map_or(0, |(sem, n)| n - sem.available_permits())
+    }
+
+    /// A slot for one transfer, waiting at most `wait`. `Ok(None)` when
+    /// unlimited; the 503 when every slot stays taken.
+    pub async fn acquire(&self, wait: Duration, what: &'static str) -> Result<Option<OwnedSemaphorePermit>, actix_web::Error> {
+        let Some((sem, limit)) = &self.slots else { return Ok(None) };
+        let permit = match sem.clone().try_acquire_owned() {
+            Ok(permit) => Ok(permit),
+            Err(_) => match actix_web::rt::time::timeout(wait, sem.clone().acquire_owned()).await {
+                Ok(Ok(permit)) => Ok(permit),
+                // the semaphore is never closed; a timeout is the only way here
+                _ => Err(too_busy(what, *limit)),
+            },
+        }?;
+        Ok(Some(permit))
+    }
+}
+
+/// The 503 for a transfer that found every slot taken, with Retry-After.
+fn too_busy(what: &'static str, limit: usize) -> actix_web::Error {
+    let err = ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "too_busy", format!("too many concurrent {what}; try again shortly"))
+        .with("limit", limit);
+    let mut resp = err.error_response();
+    resp.headers_mut().insert(header::RETRY_AFTER, header::HeaderValue::from(RETRY_AFTER_SECS));
+    actix_web::error::InternalError::from_response(err, resp).into()
+}
+
+/// A download about to stream: its MAX_CONCURRENT_DOWNLOADS slot, the cap
+/// the client asked for and its place in the in-flight count.
+pub struct Download {
+    slot: Option<OwnedSemaphorePermit>,
+    own_rate: Arc<RateLimit>,
+    counted: Counted,
+}
+
+/// Counts one download in `AppState::downloads_in_flight` until dropped.
+struct Counted(Arc<AtomicUsize>);
+
+impl Counted {
+    fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count.clone())
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Download {
+    /// Reads `x-bkt-max-rate` (400 `invalid_max_rate` if it isn't a size)
+    /// and takes a download slot (503 if none frees up in time).
+    pub async fn start(state: &AppState, cfg: &Config, req: &HttpRequest) -> Result<Self, actix_web::Error> {
+        let own = match req.headers().get(MAX_RATE_HEADER) {
+            None => None,
+            Some(v) => Some(v.to_str().ok().and_then(|s| parse_size(s.trim()).ok()).filter(|n| *n > 0).ok_or_else(|| {
+                ApiError::bad_request("invalid_max_rate", "x-bkt-max-rate must be a positive byte count per second")
+            })?),
+        };
+        let slot = state.download_slots.acquire(Duration::from_millis(cfg.concurrency_wait_ms), "downloads").await?;
+        Ok(Self { slot, own_rate: Arc::new(RateLimit::new(own)), counted: Counted::new(&state.downloads_in_flight) })
+    }
+
+    /// `body` paced to DOWNLOAD_RATE_LIMIT_BPS and the client's cap, counted
+    /// in `AppState::downloaded`, and holding the slot until it is finished
+    /// or dropped.
+    pub fn body<S, E>(self, state: &AppState, body: S) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        let (global, meter) = (state.download_rate.clone(), state.downloaded.clone());
+        let Download { slot, own_rate, counted } = self;
+        body.then(move |chunk| {
+            let _held = (&slot, &counted);
+            let (global, own_rate, meter) = (global.clone(), own_rate.clone(), meter.clone());
+            async move {
+                if let Ok(bytes) = &chunk {
+                    global.take(bytes.len()).await;
+                    own_rate.take(bytes.len()).await;
+                    meter.add(bytes.len() as u64, now_secs());
+                }
+                chunk
+            }
+        })
+    }
+}
+
+/// A token bucket over bytes, refilled at `rate` per second and holding at
+/// most one second's worth. Unlimited by default.
+#[derive(Default)]
+pub struct RateLimit {
+    bucket: Option<(f64, Mutex<(f64, Instant)>)>,
+}
+
+impl RateLimit {
+    /// `None` (or 0) means no cap.
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        let bucket = bytes_per_sec.filter(|n| *n > 0).map(|n| (n as f64, Mutex::new((n as f64, Instant::now()))));
+        Self { bucket }
+    }
+
+    /// Takes `n` bytes' worth of tokens, going into debt if there aren't
+    /// enough, and returns how long the caller must wait for that debt to be
+    /// repaid. Concurrent callers queue up behind one another's debt, so the
+    /// aggregate rate holds however many share the bucket.
+    fn reserve(&self, n: usize, now: Instant) -> Duration {
+        let Some((rate, state)) = &self.bucket else { return Duration::ZERO };
+        let mut state = state.lock().unwrap();
+        let (tokens, last) = &mut *state;
+        *tokens = (*tokens + now.saturating_duration_since(*last).as_secs_f64() * rate).min(*rate);
+        *last = now.max(*last);
+        *tokens -= n as f64;
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / rate)
+        }
+    }
+
+    /// Waits until `n` more bytes fit under the cap.
+    pub async fn take(&self, n: usize) {
+        let wait = self.reserve(n, Instant::now());
+        if !wait.is_zero() {
+            actix_web::rt::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The clock on one upload body: UPLOAD_IDLE_TIMEOUT_SECS between reads and
+/// UPLOAD_DEADLINE_SECS overall. Pauses the server imposes itself (rate
+/// limits) push the deadline back and never count as idle.
+pub struct BodyClock {
+    idle: Option<Duration>,
+    deadline: Option<tokio::time::Instant>,
+}
+
+impl BodyClock {
+    pub fn start(cfg: &Config) -> Self {
+        let now = tokio::time::Instant::now();
+        Self {
+            idle: (cfg.upload_idle_timeout_secs > 0).then(|| Duration::from_secs(cfg.upload_idle_timeout_secs)),
+            deadline: (cfg.upload_deadline_secs > 0).then(|| now + Duration::from_secs(cfg.upload_deadline_secs)),
+        }
+    }
+
+    /// Awaits the next read of the body, or the 408 once it has been quiet
+    /// too long or the deadline passes.
+    pub async fn read<T>(&self, read: impl std::future::Future<Output = T>) -> Result<T, actix_web::Error> {
+        let idle_until = self.idle.map(|d| tokio::time::Instant::now() + d);
+        let limit = match (idle_until, self.deadline) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => match a.or(b) {
+                Some(limit) => limit,
+                None => return Ok(read.await),
+            },
+        };
+        tokio::time::timeout_at(limit, read).await.map_err(|_| {
+            let err = if self.deadline == Some(limit) {
+                ApiError::new(StatusCode::REQUEST_TIMEOUT, "upload_deadline", "upload took longer than UPLOAD_DEADLINE_SECS")
+            } else {
+                ApiError::new(StatusCode::REQUEST_TIMEOUT, "body_timeout", "no upload data arrived within UPLOAD_IDLE_TIMEOUT_SECS")
+            };
+            err.into()
+        })
+    }
+
+    /// Runs a pause the server chose, moving the deadline back by its length.
+    pub async fn pause(&mut self, pause: impl std::future::Future<Output = ()>) {
+        let started = tokio::time::Instant::now();
+        pause.await;
+        if let Some(deadline) = &mut self.deadline {
+            *deadline += started.elapsed();
+        }
+    }
+}
+
+/// Bytes moved per wall-clock second.
+#[derive(Default)]
+pub struct Meter {
+    /// (second, bytes in it, bytes in the second before)
+    seconds: Mutex<(u64, u64, u64)>,
+}
+
+impl Meter {
+    pub fn add(&self, n: u64, now: u64) {
+        let mut s = self.seconds.lock().unwrap();
+        Self::roll(&mut s, now);
+        s.1 += n;
+    }
+
+    /// Bytes in the last complete second.
+    pub fn per_sec(&self, now: u64) -> u64 {
+        let mut s = self.seconds.lock().unwrap();
+        Self::roll(&mut s, now);
+        s.2
+    }
+
+    fn roll(s: &mut (u64, u64, u64), now: u64) {
+        match now.checked_sub(s.0) {
+            Some(0) | None => {}
+            Some(1) => *s = (now, 0, s.1),
+            Some(_) => *s = (now, 0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn turns_away_once_every_slot_is_taken() {
+        let wait = Duration::from_millis(10);
+        let limiter = Limiter::new(Some(2));
+        let a = limiter.acquire(wait, "uploads").await.unwrap();
+        let _b = limiter.acquire(wait, "uploads").await.unwrap();
+        assert_eq!(limiter.in_flight(), 2);
+        let err = limiter.acquire(wait, "uploads").await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        drop(a);
+        assert!(limiter.acquire(wait, "uploads").await.unwrap().is_some());
+
+        let unlimited = Limiter::new(Some(0));
+        assert!(unlimited.acquire(wait, "downloads").await.unwrap().is_none());
+        assert_eq!((unlimited.limit(), unlimited.in_flight()), (None, 0));
+    }
+
+    #[test]
+    fn rate_limit_spends_a_second_of_burst_then_paces() {
+        let rate = RateLimit::new(Some(1000));
+        let t0 = Instant::now();
+        assert_eq!(rate.reserve(1000, t0), Duration::ZERO);
+        assert_eq!(rate.reserve(500, t0), Duration::from_millis(500));
+        // a second caller queues behind the first one's debt
+        assert_eq!(rate.reserve(500, t0), Duration::from_secs(1));
+        // refills at the rate, never past one second's worth
+        assert_eq!(rate.reserve(0, t0 + Duration::from_secs(1)), Duration::ZERO);
+        assert_eq!(rate.reserve(1000, t0 + Duration::from_secs(10)), Duration::ZERO);
+        assert_eq!(rate.reserve(100, t0 + Duration::from_secs(10)), Duration::from_millis(100));
+        assert_eq!(RateLimit::new(None).reserve(usize::MAX, t0), Duration::ZERO);
+    }
+
+    #[test]
+    fn meter_reports_the_last_complete_second() {
+        let meter = Meter::default();
+        meter.add(100, 10);
+        meter.add(50, 10);
+        assert_eq!(meter.per_sec(10), 0);
+        meter.add(7, 11);
+        assert_eq!(meter.per_sec(11), 150);
+        assert_eq!(meter.per_sec(12), 7);
+        assert_eq!(meter.per_sec(14), 0);
+    }
+}
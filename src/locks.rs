@@ -0,0 +1,93 @@
+// src/locks.rs
+//
+// One writer per key: PUTs (and DELETEs) of the same object take turns, so
+// conditional checks and the final rename see a key nobody else is changing.
+// Readers don't lock; the temp-file + rename in `put_object` already means
+// they see either the old object or the new one, never a mix.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OwnedMutexGuard;
+
+type Slot = Arc<tokio::sync::Mutex<()>>;
+
+/// Per-path async mutexes, created on first use and dropped once nobody
+/// holds or waits for them.
+#[derive(Default)]
+pub struct KeyLocks {
+    slots: Mutex<HashMap<PathBuf, Slot>>,
+}
+
+/// Exclusive use of one key until dropped.
+pub struct KeyGuard<'a> {
+    locks: &'a KeyLocks,
+    path: PathBuf,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl KeyLocks {
+    fn slot(&self, path: &Path) -> Slot {
+        self.slots.lock().unwrap().entry(path.to_path_buf()).or_default().clone()
+    }
+
+    /// Waits until `path` is free.
+    pub async fn lock(&self, path: &Path) -> KeyGuard<'_> {
+        let guard = self.slot(path).lock_owned().await;
+        KeyGuard { locks: self, path: path.to_path_buf(), guard: Some(guard) }
+    }
+
+    /// `None` if another writer holds `path`.
+    pub fn try_lock(&self, path: &Path) -> Option<KeyGuard<'_>> {
+        let guard = self.slot(path).try_lock_owned().ok();
+        let held = KeyGuard { locks: self, path: path.to_path_buf(), guard };
+        // dropping an empty guard still tidies the slot made for it
+        held.guard.is_some().then_some(held)
+    }
+
+    /// Keys with a writer or a waiter.
+    pub fn len(&self) -> usize {
+        self.slots.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for KeyGuard<'_> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        let mut slots = self.locks.slots.lock().unwrap();
+        // the map's own reference is the last one: no holder, no waiter
+        if slots.get(&self.path).is_some_and(|s| Arc::strong_count(s) == 1) {
+            slots.remove(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn one_writer_per_key_and_idle_slots_go_away() {
+        let locks = KeyLocks::default();
+        let a = Path::new("/r/a");
+        let held = locks.lock(a).await;
+        assert!(locks.try_lock(a).is_none());
+        assert!(locks.try_lock(Path::new("/r/b")).is_some());
+        assert_eq!(locks.len(), 1);
+        drop(held);
+        assert!(locks.is_empty());
+
+        let held = locks.lock(a).await;
+        let waiter = async { locks.lock(a).await };
+        tokio::pin!(waiter);
+        assert!(futures_util::poll!(&mut waiter).is_pending());
+        drop(held);
+        let second = waiter.await;
+        assert_eq!(locks.len(), 1);
+        drop(second);
+        assert!(locks.is_empty());
+    }
+}
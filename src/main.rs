@@ -1,20 +1,92 @@
 // src/main.rs
 use actix_web::HttpServer;
-use std::path::PathBuf;
+use clap::Parser;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use rust_buck3t::consts::Config;
+use rust_buck3t::consts::{self, AuthMode, Config, Overrides};
 use rust_buck3t::{app, AppState};
 
-fn banner(cfg: &Config, state_root: &PathBuf) {
+/// Flags override the environment and config file; everything else is
+/// configured through those (see the README).
+#[derive(Parser)]
+#[command(version, about = "A small S3-ish object store")]
+struct Cli {
+    /// Config file (TOML); overrides RUST_BUCKET_CONFIG
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Address to bind (HOST)
+    #[arg(long)]
+    host: Option<String>,
+    /// Port to bind (PORT)
+    #[arg(long)]
+    port: Option<u16>,
+    /// Storage root (RUST_BUCKET_DIR)
+    #[arg(long, value_name = "DIR")]
+    root: Option<String>,
+    /// Largest accepted upload: bytes or 512K, 10MB, 1.5GiB (MAX_UPLOAD_BYTES)
+    #[arg(long, value_name = "SIZE", value_parser = consts::parse_size)]
+    max_upload_bytes: Option<u64>,
+    /// Cap on stored objects (MAX_OBJECT_COUNT)
+    #[arg(long, value_name = "N")]
+    max_object_count: Option<u64>,
+    /// jwt_rs256, jwt_hs256, api_key, basic or off (AUTH_MODE)
+    #[arg(long, value_name = "MODE")]
+    auth_mode: Option<AuthMode>,
+    /// Print the effective configuration, secrets redacted, and exit
+    #[arg(long)]
+    print_config: bool,
+    /// Run the self-test against a temp root and exit
+    #[arg(long)]
+    self_test: bool,
+}
+
+impl Cli {
+    fn overrides(&self) -> Overrides {
+        Overrides {
+            host: self.host.clone(),
+            port: self.port,
+            root_dir: self.root.clone(),
+            max_upload_bytes: self.max_upload_bytes,
+            max_object_count: self.max_object_count,
+            auth_mode: self.auth_mode.clone(),
+        }
+    }
+}
+
+fn banner(cfg: &Config, state_root: &Path) {
+    // byte counts echo what was written next to what it means
+    let size = |raw: &str, bytes: u64| match raw.parse::<u64>() {
+        Ok(_) => format!("{bytes} bytes"),
+        Err(_) => format!("{raw} ({bytes} bytes)"),
+    };
     if let Some(limit) = cfg.max_upload_bytes {
-        println!("📦 MAX_UPLOAD_BYTES = {} bytes", limit);
+        // a --max-upload-bytes override drops the variable's entry
+        let shown = match cfg.size_inputs.iter().find(|(n, ..)| *n == "MAX_UPLOAD_BYTES") {
+            Some((_, raw, bytes)) => size(raw, *bytes),
+            None => format!("{limit} bytes"),
+        };
+        println!("📦 MAX_UPLOAD_BYTES = {shown}");
     } else {
         println!("📦 MAX_UPLOAD_BYTES not set (no upload size limit)");
     }
+    for (name, raw, bytes) in cfg.size_inputs.iter().filter(|(n, ..)| *n != "MAX_UPLOAD_BYTES") {
+        println!("📏 {name} = {}", size(raw, *bytes));
+    }
+    if let Some(max) = cfg.max_object_count {
+        println!("🗃️  MAX_OBJECT_COUNT = {}", max);
+    }
+    if let Some(max) = cfg.max_concurrent_uploads {
+        println!("🚦 MAX_CONCURRENT_UPLOADS = {} (503 after waiting {}ms)", max, cfg.concurrency_wait_ms);
+    }
+    if let Some(max) = cfg.max_concurrent_downloads {
+        println!("🚦 MAX_CONCURRENT_DOWNLOADS = {} (503 after waiting {}ms)", max, cfg.concurrency_wait_ms);
+    }
     println!("📂 RUST_BUCKET_DIR = {}", cfg.root_dir);
     println!("   • auth_max_ttl_secs: {}s", cfg.auth_max_ttl_secs);
     println!(
-        "🚀 rust-buck3t on http://{}:{}  (root = {})",
+        "🚀 rust-buck3t on {}://{}:{}  (root = {})",
+        cfg.scheme(),
         cfg.host,
         cfg.port,
         state_root.display()
@@ -24,24 +96,127 @@ fn banner(cfg: &Config, state_root: &PathBuf) {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let cfg = Config::from_env();
+    let cli = Cli::parse();
+    dotenvy::dotenv().ok();
+    let env: HashMap<String, String> = std::env::vars().collect();
+    let file = cli.config.clone().or_else(|| consts::env_file(&env));
+    let loaded = Config::resolve(file.as_deref(), &env, &cli.overrides());
+    let cfg = loaded.unwrap_or_else(|e| {
+        for problem in &e.problems {
+            eprintln!("❌ {problem}");
+        }
+        eprintln!("❌ refusing to start with an invalid configuration");
+        std::process::exit(1);
+    });
+
+    if cli.print_config {
+        print!("{}", cfg.to_toml_redacted());
+        return Ok(());
+    }
+
+    if cli.self_test {
+        println!("🩺 rust-buck3t self-test");
+        let report = rust_buck3t::selftest::run(&cfg).await;
+        report.print();
+        std::process::exit(if report.ok { 0 } else { 1 });
+    }
+
+    // fail before anything else starts if the cert/key can't be used
+    let tls = rust_buck3t::tls::server_config(&cfg).unwrap_or_else(|e| {
+        eprintln!("❌ TLS setup failed: {e}");
+        std::process::exit(1);
+    });
 
     tokio::fs::create_dir_all(&cfg.root_dir).await?;
-    let state = AppState { root: PathBuf::from(&cfg.root_dir) };
+    let state = AppState::new(&cfg.root_dir).with_auth(&cfg)?.with_buckets(&cfg)?.with_limits(&cfg).with_caches(&cfg).with_listing(&cfg);
 
     banner(&cfg, &state.root);
+    let buckets = state.buckets.names();
+    if !buckets.is_empty() {
+        println!("🪣 buckets: {} (at /b/{{bucket}}/objects)", buckets.join(", "));
+    }
+    if let Err(e) = rust_buck3t::storage::probe_writable(&state.root).await {
+        if rust_buck3t::storage::is_read_only_error(&e) {
+            state.read_only.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        eprintln!("⚠️  RUST_BUCKET_DIR is not writable ({}); PUT/DELETE will fail until it is", e);
+    }
+
+    if cfg.delete_grace_secs > 0 {
+        println!("🗑️  DELETE_GRACE_SECS = {} (deletes are deferred; undo with POST ?undelete)", cfg.delete_grace_secs);
+    }
+    rust_buck3t::pending::spawn_sweeper(state.clone());
+    rust_buck3t::expiry::spawn_sweeper(state.clone(), &cfg);
+    if let Some(url) = &cfg.webhook_url {
+        println!(
+            "🪝 WEBHOOK_URL = {} ({})",
+            url,
+            if cfg.webhook_secret.is_some() { "signed" } else { "unsigned; set WEBHOOK_SECRET to sign" }
+        );
+    }
+    rust_buck3t::webhooks::spawn_sender(state.clone(), &cfg);
+    if cfg.trash_enabled {
+        match cfg.trash_retention_secs {
+            0 => println!("🗑️  TRASH_ENABLED (kept until purged via DELETE /trash)"),
+            secs => println!("🗑️  TRASH_ENABLED (TRASH_RETENTION_SECS = {})", secs),
+        }
+    }
+    rust_buck3t::trash::spawn_purger(state.clone(), &cfg);
+    if cfg.dedup {
+        println!("🔗 DEDUP (identical uploads share one copy under .blobs/)");
+    }
+    rust_buck3t::dedup::start(state.clone(), &cfg);
+    if cfg.list_hide_dotfiles {
+        println!("🙈 LIST_HIDE_DOTFILES (listings skip names starting with '.'; ?hide_dotfiles=0 shows them)");
+    }
+    if !cfg.list_exclude.is_empty() {
+        println!("🙈 LIST_EXCLUDE = {}", cfg.list_exclude.join(", "));
+    }
+    rust_buck3t::resumable::spawn_sweeper(state.clone(), &cfg);
+    rust_buck3t::s3::spawn_sweeper(state.clone(), &cfg);
+    rust_buck3t::throttle::spawn_pruner(state.clone(), &cfg);
+    rust_buck3t::idp::spawn_rotator(state.clone(), &cfg);
+    if cfg.ui_enabled {
+        if cfg!(feature = "ui") {
+            println!("🖥️  UI_ENABLED (browser UI at /ui)");
+        } else {
+            eprintln!("⚠️  UI_ENABLED is set but this build has no `ui` feature; /ui is not served");
+        }
+    }
+    if cfg.s3_enabled {
+        println!("🔌 S3_ENABLED (S3-compatible subset at /{{bucket}}/{{key}})");
+        if cfg.s3_skip_signature {
+            eprintln!("⚠️  S3_SKIP_SIGNATURE is set: SigV4 signatures are not checked (dev only)");
+        }
+    }
+    rust_buck3t::storage::clear_tmp(&state.root);
 
     // prepare separate values for the closure and for bind()
     let cfg_for_server = cfg.clone();
     let state_for_server = state.clone();
     let bind_host = cfg.host.clone();
     let bind_port = cfg.port;
+    let grace = cfg.shutdown_grace_secs;
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         // use the cloned copies inside the closure
         app(state_for_server.clone(), cfg_for_server.clone())
     })
-    .bind((bind_host.as_str(), bind_port))?
-    .run()
-    .await
+    // SIGINT would otherwise force-stop; both signals drain via shutdown::on_signal
+    .disable_signals()
+    .shutdown_timeout(grace);
+    let server = match tls {
+        Some(tls) => server.bind_rustls_0_23((bind_host.as_str(), bind_port), tls)?,
+        None => server.bind((bind_host.as_str(), bind_port))?,
+    };
+    let server = server.run();
+    actix_web::rt::spawn(rust_buck3t::shutdown::on_signal(server.handle(), state.clone(), grace));
+    server.await?;
+
+    println!(
+        "👋 shutdown complete: {} request(s) drained, {} cut off",
+        state.in_flight.drained(),
+        state.in_flight.cut_off()
+    );
+    Ok(())
 }
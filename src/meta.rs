@@ -0,0 +1,150 @@
+// src/meta.rs
+//
+// Per-object sidecar metadata, stored as JSON under `<root>/.meta/<key>.json`.
+// The sidecar tree is hidden from listings and not addressable as a key.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Directory (directly under the root) holding sidecars.
+pub const META_DIR: &str = ".meta";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct ObjectMeta {
+    /// Custom response headers replayed on GET/HEAD.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Unix time after which a deferred DELETE removes the object (DELETE_GRACE_SECS).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_after: Option<u64>,
+    /// Unix time after which the object reads as absent and is swept (x-bkt-expires-*).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Encoding the stored bytes are in (`gzip`, with STORE_GZIP_UPLOADS); replayed as `Content-Encoding`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    /// Digests verified on upload (`sha256`, `crc32c`), lowercase hex; replayed as `x-bkt-checksum-*`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Content-Type set by a metadata update; served instead of the guessed one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// User metadata (`x-bkt-meta-<name>`, names lowercase); replayed on GET/HEAD.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub user: BTreeMap<String, String>,
+    /// Unix time before which the object can't be overwritten, deleted or edited (`retention`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retain_until: Option<u64>,
+    /// Under a legal hold (`holds`): no delete, overwrite or expiry until released.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub legal_hold: bool,
+    /// Bumped by each metadata update, so the ETag changes with the metadata.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl ObjectMeta {
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.delete_after.is_none() && self.expires_at.is_none() && self.content_encoding.is_none()
+            && self.checksums.is_empty() && self.content_type.is_none() && self.user.is_empty() && self.revision == 0
+            && self.retain_until.is_none() && !self.legal_hold
+    }
+
+    /// The object's ETag: its size and mtime, plus the metadata revision once it has one.
+    pub fn etag(&self, file: &std::fs::Metadata) -> String {
+        let base = crate::storage::make_etag(file);
+        if self.revision == 0 {
+            return base;
+        }
+        format!("{}-r{}\"", base.trim_end_matches('"'), self.revision)
+    }
+}
+
+/// Every readable sidecar of a live object under `root`, as (key, meta).
+/// Used at startup to rebuild the in-memory schedules; trashed objects are skipped.
+pub fn scan(root: &Path) -> Vec<(String, ObjectMeta)> {
+    let meta_root = root.join(META_DIR);
+    let mut out = Vec::new();
+    let mut stack = vec![meta_root.clone()];
+    while let Some(dir) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&dir) else { continue };
+        for entry in rd.flatten() {
+            let p = entry.path();
+            if dir == meta_root && crate::storage::is_internal(&entry.file_name()) {
+                continue;
+            }
+            if p.is_dir() {
+                stack.push(p);
+                continue;
+            }
+            let Some(m) = std::fs::read(&p).ok().and_then(|b| serde_json::from_slice::<ObjectMeta>(&b).ok()) else {
+                continue;
+            };
+            let rel = crate::storage::key_of(&meta_root, &p);
+            if let Some(key) = rel.strip_suffix(".json") {
+                out.push((key.to_string(), m));
+            }
+        }
+    }
+    out
+}
+
+/// Sidecar location for the object stored at `path` (a resolved key under `root`).
+pub fn sidecar_path(root: &Path, path: &Path) -> Option<PathBuf> {
+    let rel = path.strip_prefix(root).ok()?;
+    let mut name = rel.as_os_str().to_owned();
+    name.push(".json");
+    Some(root.join(META_DIR).join(name))
+}
+
+/// The object's sidecar, or the default when it has none.
+pub async fn load(root: &Path, path: &Path) -> io::Result<ObjectMeta> {
+    let Some(side) = sidecar_path(root, path) else { return Ok(ObjectMeta::default()) };
+    match fs::read(&side).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ObjectMeta::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// `load` for blocking code.
+pub fn load_blocking(root: &Path, path: &Path) -> io::Result<ObjectMeta> {
+    let Some(side) = sidecar_path(root, path) else { return Ok(ObjectMeta::default()) };
+    match std::fs::read(&side) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ObjectMeta::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the sidecar (atomically, through `TMP_DIR`), or removes it when `meta` is empty.
+pub async fn store(root: &Path, path: &Path, meta: &ObjectMeta) -> io::Result<()> {
+    if meta.is_empty() {
+        return remove(root, path).await;
+    }
+    let Some(side) = sidecar_path(root, path) else { return Ok(()) };
+    if let Some(parent) = side.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let bytes = serde_json::to_vec(meta).map_err(io::Error::other)?;
+    let (tmp, mut file) = crate::storage::TempUpload::create(root).await?;
+    file.write_all(&bytes).await?;
+    file.flush().await?;
+    drop(file);
+    tmp.persist(&side, false).await
+}
+
+pub async fn remove(root: &Path, path: &Path) -> io::Result<()> {
+    let Some(side) = sidecar_path(root, path) else { return Ok(()) };
+    match fs::remove_file(&side).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
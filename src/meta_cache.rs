@@ -0,0 +1,227 @@
+// src/meta_cache.rs
+//
+// What GET/HEAD send besides the bytes (the sidecar, the ETag and the
+// Content-Type), kept for recently read objects so a hot object costs one
+// stat per request rather than a stat, a sidecar read and a parse. Entries
+// are keyed by resolved path and used only while the file's size and mtime
+// are still the ones they were built from, so edits made behind the
+// server's back show up at once; writes through the server drop the entry.
+// META_CACHE_ENTRIES bounds it (least recently used go first) and
+// META_CACHE_TTL_SECS caps how long an entry is trusted.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::meta::{self, ObjectMeta};
+
+/// An object's sidecar and headers, as of one size and mtime.
+pub struct Cached {
+    pub meta: ObjectMeta,
+    pub etag: String,
+    /// The stored type, else the guess from the key.
+    pub content_type: String,
+    size: u64,
+    modified: Option<SystemTime>,
+    at: Instant,
+}
+
+impl Cached {
+    fn fresh(&self, file: &std::fs::Metadata, ttl: Duration, now: Instant) -> bool {
+        self.size == file.len() && self.modified == file.modified().ok() && now.duration_since(self.at) < ttl
+    }
+}
+
+/// Values by path within a total weight, the least recently used going
+/// first when something new doesn't fit.
+pub(crate) struct Lru<V> {
+    /// Value, last use and weight.
+    entries: HashMap<PathBuf, (V, u64, u64)>,
+    /// Last use → path, oldest first.
+    order: BTreeMap<u64, PathBuf>,
+    tick: u64,
+    weight: u64,
+}
+
+impl<V> Default for Lru<V> {
+    fn default() -> Self {
+        Self { entries: HashMap::new(), order: BTreeMap::new(), tick: 0, weight: 0 }
+    }
+}
+
+impl<V: Clone> Lru<V> {
+    pub(crate) fn get(&mut self, path: &Path) -> Option<V> {
+        let (value, used, _) = self.entries.get_mut(path)?;
+        self.order.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.order.insert(self.tick, path.to_path_buf());
+        Some(value.clone())
+    }
+
+    /// Keeps `value` under `path`, evicting until the total fits `budget`;
+    /// a value heavier than the whole budget isn't kept.
+    pub(crate) fn insert(&mut self, path: PathBuf, value: V, weight: u64, budget: u64) {
+        self.remove(&path);
+        if weight > budget {
+            return;
+        }
+        while self.weight + weight > budget {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            if let Some((_, _, w)) = self.entries.remove(&oldest) {
+                self.weight -= w;
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, path.clone());
+        self.entries.insert(path, (value, self.tick, weight));
+        self.weight += weight;
+    }
+
+    pub(crate) fn remove(&mut self, path: &Path) {
+        if let Some((_, used, w)) = self.entries.remove(path) {
+            self.order.remove(&used);
+            self.weight -= w;
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The summed weight of what is kept.
+    pub(crate) fn weight(&self) -> u64 {
+        self.weight
+    }
+}
+
+#[derive(Default)]
+pub struct MetaCache {
+    /// Most entries kept; 0 turns the cache off.
+    capacity: usize,
+    ttl: Duration,
+    lru: Mutex<Lru<Arc<Cached>>>,
+    /// Bumped by every `forget`, so a read that raced a write isn't cached.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl MetaCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self { capacity, ttl, ..Self::default() }
+    }
+
+    /// The headers of the object at `path`, whose stat is `file`: the cached
+    /// ones while size and mtime still match, else freshly read. `guess`
+    /// gives the Content-Type when the sidecar stores none.
+    pub async fn get(&self, root: &Path, path: &Path, file: &std::fs::Metadata, guess: impl FnOnce() -> String) -> Arc<Cached> {
+        let now = Instant::now();
+        let generation = self.generation.load(Ordering::Acquire);
+        if self.capacity > 0 {
+            let hit = self.lru.lock().unwrap().get(path).filter(|c| c.fresh(file, self.ttl, now));
+            if let Some(hit) = hit {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return hit;
+            }
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        let meta = meta::load(root, path).await.unwrap_or_else(|e| {
+            eprintln!("⚠️  unreadable sidecar for {}: {e}", path.display());
+            ObjectMeta::default()
+        });
+        let entry = Arc::new(Cached {
+            etag: meta.etag(file),
+            content_type: meta.content_type.clone().unwrap_or_else(guess),
+            meta,
+            size: file.len(),
+            modified: file.modified().ok(),
+            at: now,
+        });
+        if self.capacity > 0 {
+            let mut lru = self.lru.lock().unwrap();
+            // checked under the lock `forget` takes, so a write can't slip in between
+            if self.generation.load(Ordering::Acquire) == generation {
+                lru.insert(path.to_path_buf(), entry.clone(), 1, self.capacity as u64);
+            }
+        }
+        entry
+    }
+
+    /// Drops the entry for `path`; call after any change to the object or its sidecar.
+    pub fn forget(&self, path: &Path) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::Release);
+        lru.remove(path);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn hits_until_the_file_changes_and_evicts_the_least_recent() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        let (a, b, c) = (root.join("a.txt"), root.join("b.txt"), root.join("c.txt"));
+        for p in [&a, &b, &c] {
+            std::fs::write(p, "one").unwrap();
+        }
+        let stat = |p: &Path| std::fs::metadata(p).unwrap();
+        let cache = MetaCache::new(2, Duration::from_secs(60));
+        let guess = || "text/plain".to_string();
+
+        cache.get(root, &a, &stat(&a), guess).await;
+        let first = cache.get(root, &a, &stat(&a), guess).await;
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(first.content_type, "text/plain");
+
+        // a different size is a miss, and the new etag replaces the old
+        std::fs::write(&a, "three").unwrap();
+        let changed = cache.get(root, &a, &stat(&a), guess).await;
+        assert_ne!(changed.etag, first.etag);
+        assert_eq!(cache.misses(), 2);
+
+        // b, then c: a was used longest ago and goes
+        cache.get(root, &b, &stat(&b), guess).await;
+        cache.get(root, &c, &stat(&c), guess).await;
+        assert_eq!(cache.len(), 2);
+        cache.get(root, &c, &stat(&c), guess).await;
+        assert_eq!(cache.hits(), 2);
+        cache.get(root, &a, &stat(&a), guess).await;
+        assert_eq!(cache.misses(), 5);
+
+        cache.forget(&a);
+        assert_eq!(cache.len(), 1);
+
+        let off = MetaCache::new(0, Duration::from_secs(60));
+        off.get(root, &a, &stat(&a), guess).await;
+        assert!(off.is_empty());
+        assert_eq!((off.hits(), off.misses()), (0, 0));
+    }
+}
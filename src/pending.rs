@@ -0,0 +1,116 @@
+// src/pending.rs
+//
+// Deferred deletion (DELETE_GRACE_SECS): deleted keys are only flagged, in
+// their sidecar and here, and a sweeper removes them once the window passes.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::meta::{self, ObjectMeta};
+use crate::trash;
+use crate::AppState;
+
+/// How often the sweeper looks for due deletions.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keys awaiting removal, with the unix time they become due.
+#[derive(Default)]
+pub struct PendingDeletes {
+    entries: Mutex<HashMap<String, u64>>,
+}
+
+impl PendingDeletes {
+    /// Rebuilds the queue from sidecars flagged `delete_after` (see
+    /// `meta::scan`), so pending deletes survive a restart.
+    pub fn from_sidecars(sidecars: &[(String, ObjectMeta)]) -> Self {
+        let entries = sidecars.iter().filter_map(|(key, m)| Some((key.clone(), m.delete_after?))).collect();
+        Self { entries: Mutex::new(entries) }
+    }
+
+    pub fn schedule(&self, key: &str, due: u64) {
+        self.entries.lock().unwrap().insert(key.to_string(), due);
+    }
+
+    /// Drops `key` from the queue; false if it wasn't pending.
+    pub fn cancel(&self, key: &str) -> bool {
+        self.entries.lock().unwrap().remove(key).is_some()
+    }
+
+    pub fn is_pending(&self, key: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pending keys starting with `prefix`, sorted.
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .entries
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Removes and returns every key due at `now`.
+    fn take_due(&self, now: u64) -> Vec<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let due: Vec<String> = entries.iter().filter(|(_, d)| **d <= now).map(|(k, _)| k.clone()).collect();
+        for k in &due {
+            entries.remove(k);
+        }
+        due
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Starts the background task that carries out due deletions. Call once per
+/// process, from inside the actix runtime.
+pub fn spawn_sweeper(state: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            for key in state.pending.take_due(now_secs()) {
+                purge(&state, &key).await;
+            }
+        }
+    });
+}
+
+/// Removes a due object, unless an overwrite cleared its flag in the meantime.
+/// Waits out a writer of the key, so a body that just landed isn't taken
+/// for the flagged one.
+async fn purge(state: &AppState, key: &str) {
+    let path = state.root.join(key);
+    let _writer = state.locks.lock(&path).await;
+    match meta::load(&state.root, &path).await {
+        Ok(m) if m.delete_after.is_some() => {}
+        _ => return,
+    }
+    match trash::discard(state, &path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            // the object is gone already; don't leave its sidecar behind
+            let _ = meta::remove(&state.root, &path).await;
+        }
+        Err(e) if tokio::fs::metadata(&path).await.is_ok() => {
+            eprintln!("⚠️  deferred delete of {key} failed, will retry: {e}");
+            state.pending.schedule(key, now_secs() + SWEEP_INTERVAL.as_secs());
+        }
+        Err(e) => eprintln!("⚠️  deferred delete of {key}: sidecar not cleaned up: {e}"),
+    }
+}
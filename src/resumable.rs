@@ -0,0 +1,160 @@
+// src/resumable.rs
+//
+// Resumable uploads (tus-style): `POST /uploads` opens one for a key and a
+// declared length, `PATCH /uploads/{id}` appends at `Upload-Offset`, and
+// once the staging file holds the whole length it is renamed onto the key.
+// Each upload is `<root>/.uploads/<id>.part` (the bytes so far; its length
+// is the offset) plus `<id>.json` (the record below), so uploads survive a
+// restart. One untouched for RESUMABLE_EXPIRY_SECS is removed.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::fs;
+
+use crate::consts::Config;
+use crate::pending::now_secs;
+use crate::AppState;
+
+/// Directory (directly under the root) holding uploads in progress.
+pub const RESUMABLE_DIR: &str = ".uploads";
+
+/// How long a staging file or record may lack its other half.
+const ORPHAN_GRACE_SECS: u64 = 60;
+
+/// What an upload is for.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Resumable {
+    /// Root-relative target key.
+    pub key: String,
+    /// Declared total length (`Upload-Length`).
+    pub length: u64,
+    pub created_at: u64,
+}
+
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// The upload's staging file and record, if `id` is well-formed.
+fn paths(root: &Path, id: &str) -> Option<(PathBuf, PathBuf)> {
+    if id.len() != 32 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let dir = root.join(RESUMABLE_DIR);
+    Some((dir.join(format!("{id}.part")), dir.join(format!("{id}.json"))))
+}
+
+/// The staging file bytes are appended to.
+pub fn part_path(root: &Path, id: &str) -> Option<PathBuf> {
+    paths(root, id).map(|(part, _)| part)
+}
+
+/// Records a new upload with an empty staging file.
+pub async fn create(root: &Path, id: &str, upload: &Resumable) -> io::Result<()> {
+    let (part, record) = paths(root, id).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad upload id"))?;
+    fs::create_dir_all(root.join(RESUMABLE_DIR)).await?;
+    fs::write(&part, b"").await?;
+    let json = serde_json::to_vec(upload).map_err(io::Error::other)?;
+    if let Err(e) = fs::write(&record, json).await {
+        let _ = fs::remove_file(&part).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// The upload and its current offset; `None` if unknown, finished or expired.
+pub async fn load(root: &Path, id: &str, expiry_secs: u64) -> io::Result<Option<(Resumable, u64)>> {
+    let Some((part, record)) = paths(root, id) else { return Ok(None) };
+    let raw = match fs::read(&record).await {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let upload: Resumable = serde_json::from_slice(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let staged = match fs::metadata(&part).await {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if expired(&staged, expiry_secs) {
+        // the sweeper would take it shortly anyway
+        remove(root, id).await;
+        return Ok(None);
+    }
+    Ok(Some((upload, staged.len())))
+}
+
+/// When an upload last touched at `modified` expires (seconds).
+pub fn expires_at(modified: u64, expiry_secs: u64) -> u64 {
+    modified.saturating_add(expiry_secs)
+}
+
+/// Last activity on the staging file (seconds).
+pub fn touched(staged: &std::fs::Metadata) -> u64 {
+    staged.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs())
+}
+
+fn expired(staged: &std::fs::Metadata, expiry_secs: u64) -> bool {
+    expiry_secs > 0 && expires_at(touched(staged), expiry_secs) <= now_secs()
+}
+
+/// Forgets an upload and its bytes.
+pub async fn remove(root: &Path, id: &str) {
+    if let Some((part, record)) = paths(root, id) {
+        let _ = fs::remove_file(&record).await;
+        let _ = fs::remove_file(&part).await;
+    }
+}
+
+/// Removes uploads idle for longer than `expiry_secs`, and halves left
+/// without the other by a crash; returns how many went.
+pub async fn sweep(root: &Path, expiry_secs: u64) -> io::Result<usize> {
+    let mut entries = match fs::read_dir(root.join(RESUMABLE_DIR)).await {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut ids = BTreeSet::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if let Some(id) = name.to_str().and_then(|n| n.strip_suffix(".json").or_else(|| n.strip_suffix(".part"))) {
+            ids.insert(id.to_string());
+        }
+    }
+    let mut removed = 0;
+    for id in ids {
+        let Some((part, record)) = paths(root, &id) else { continue };
+        let stale = match (fs::metadata(&part).await, fs::metadata(&record).await) {
+            (Ok(staged), Ok(_)) => expired(&staged, expiry_secs),
+            // `create` writes the two a moment apart
+            (Ok(half), Err(_)) | (Err(_), Ok(half)) => expired(&half, ORPHAN_GRACE_SECS),
+            (Err(_), Err(_)) => false,
+        };
+        if stale {
+            remove(root, &id).await;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Expires idle uploads in the background. Call once per process, from
+/// inside the actix runtime.
+pub fn spawn_sweeper(state: AppState, cfg: &Config) {
+    let expiry = cfg.resumable_expiry_secs;
+    if expiry == 0 {
+        return;
+    }
+    let every = Duration::from_secs((expiry / 2).clamp(1, 60));
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(every);
+        loop {
+            tick.tick().await;
+            if let Err(e) = sweep(&state.root, expiry).await {
+                eprintln!("⚠️  resumable upload sweep failed: {e}");
+            }
+        }
+    });
+}
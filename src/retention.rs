@@ -0,0 +1,42 @@
+// src/retention.rs
+//
+// Object retention (WORM): `x-bkt-retain-until: <unix>` on a PUT, or on a
+// metadata update, records `retain_until` in the sidecar. Until then the
+// object can't be overwritten, deleted or otherwise edited, whatever the
+// token's scopes; the time may be pushed later but never earlier. Named
+// buckets holding such objects can't be force-deleted either.
+use actix_web::http::header::HeaderMap;
+use std::path::Path;
+
+use crate::meta::{self, ObjectMeta};
+
+pub const RETAIN_UNTIL_HEADER: &str = "x-bkt-retain-until";
+
+#[derive(Debug)]
+pub enum RetentionError {
+    /// Not a positive integer.
+    Invalid,
+    /// Not in the future.
+    InPast,
+}
+
+/// The retain-until time a request asks for, as a unix time.
+pub fn from_request(headers: &HeaderMap, now: u64) -> Result<Option<u64>, RetentionError> {
+    let Some(v) = headers.get(RETAIN_UNTIL_HEADER) else { return Ok(None) };
+    let at = v.to_str().ok().and_then(|s| s.trim().parse::<u64>().ok()).ok_or(RetentionError::Invalid)?;
+    if at <= now {
+        return Err(RetentionError::InPast);
+    }
+    Ok(Some(at))
+}
+
+/// When the object stops being locked, while it still is.
+pub fn locked_until(object: &ObjectMeta, now: u64) -> Option<u64> {
+    object.retain_until.filter(|&at| now < at)
+}
+
+/// `locked_until` for the object at `path`, read from its sidecar (blocking).
+/// An unreadable sidecar is an error rather than "unlocked".
+pub fn locked_at(root: &Path, path: &Path, now: u64) -> std::io::Result<Option<u64>> {
+    Ok(locked_until(&meta::load_blocking(root, path)?, now))
+}
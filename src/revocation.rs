@@ -0,0 +1,69 @@
+// src/revocation.rs
+//
+// Revoked token ids (`jti`) from `POST /auth/logout`, persisted as a small
+// JSON map `{jti: exp}` next to the user store. Entries are dropped once
+// their token would have expired anyway.
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+pub struct Denylist {
+    /// Backing file; `None` keeps the list in memory only.
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, u64>>,
+    /// Serialises file writes so an older snapshot can't land last.
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl Denylist {
+    /// Loads `path` if it exists, discarding already-expired entries.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut entries: HashMap<String, u64> = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let now = now_secs();
+        entries.retain(|_, exp| *exp > now);
+        Ok(Self { path: Some(path.to_path_buf()), entries: Mutex::new(entries), write_lock: Default::default() })
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(jti)
+    }
+
+    /// Adds `jti` (valid until `exp`), prunes expired entries and persists.
+    pub async fn revoke(&self, jti: &str, exp: u64) -> io::Result<()> {
+        let _writing = self.write_lock.lock().await;
+        let snapshot = {
+            let mut entries = self.entries.lock().unwrap();
+            let now = now_secs();
+            entries.retain(|_, e| *e > now);
+            entries.insert(jti.to_string(), exp);
+            serde_json::to_vec(&*entries).map_err(io::Error::other)?
+        };
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // write-then-rename so a crash never leaves a truncated list
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, snapshot).await?;
+        tokio::fs::rename(&tmp, path).await
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
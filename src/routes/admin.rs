@@ -0,0 +1,53 @@
+// src/routes/admin.rs
+use actix_web::{web, HttpResponse, Result};
+
+use crate::AppState;
+use crate::auth::NeedAdmin;
+use crate::consts::{Config, PATH_ADMIN};
+use crate::selftest;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope(format!("/{}", PATH_ADMIN).as_str())
+            .route("/uploads", web::get().to(list_uploads))
+            .route("/uploads/{id}", web::delete().to(cancel_upload))
+            .route("/self-test", web::post().to(self_test)),
+    );
+}
+
+async fn list_uploads(
+    _auth: NeedAdmin,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    println!("→ GET /{}/uploads", PATH_ADMIN);
+    Ok(HttpResponse::Ok().json(state.uploads.list()))
+}
+
+/// Aborts an in-flight PUT; its handler removes the partial file.
+async fn cancel_upload(
+    _auth: NeedAdmin,
+    state: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<HttpResponse> {
+    let id = id.into_inner();
+    println!("→ DELETE /{}/uploads/{}", PATH_ADMIN, id);
+    if state.uploads.cancel(id) {
+        Ok(HttpResponse::Accepted().finish())
+    } else {
+        Err(actix_web::error::ErrorNotFound("no such upload"))
+    }
+}
+
+/// Runs the `--self-test` sequence in-process; 503 if any step failed.
+async fn self_test(
+    _auth: NeedAdmin,
+    cfg: web::Data<Config>,
+) -> Result<HttpResponse> {
+    println!("→ POST /{}/self-test", PATH_ADMIN);
+    let report = selftest::run(&cfg).await;
+    Ok(if report.ok {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    })
+}
@@ -0,0 +1,170 @@
+// src/routes/archive.rs
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Result};
+use futures_util::StreamExt;
+use std::sync::atomic::Ordering;
+use tokio::io::AsyncWriteExt;
+
+use crate::archive::{self, ExtractError, Format, Limits};
+use crate::auth::{self, NeedList, NeedRead, NeedWrite};
+use crate::consts::{Config, PATH_ARCHIVE};
+use crate::error::ApiError;
+use crate::limits;
+use crate::pending::now_secs;
+use crate::routes::objects::resolve_key;
+use crate::routes::query::QueryParams;
+use crate::storage;
+use crate::AppState;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource(format!("/{}", PATH_ARCHIVE).as_str())
+            .route(web::get().to(download))
+            .route(web::post().to(upload)),
+    );
+}
+
+/// Streams every object under `?prefix=` as `?format=zip|tar|tar.gz` (default zip).
+/// A prefix with no objects is a 404 `no_objects`, not an empty archive.
+async fn download(
+    auth: NeedList,
+    _read: NeedRead,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_ARCHIVE);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["prefix", "format"])?;
+    let format = match q.str("format") {
+        None => Format::Zip,
+        Some(f) => Format::parse(f).ok_or_else(|| {
+            ApiError::new(StatusCode::BAD_REQUEST, "invalid_format", "format must be zip, tar or tar.gz")
+        })?,
+    };
+    let prefix = q.str("prefix").unwrap_or("");
+    let base = if prefix.trim_matches('/').is_empty() {
+        state.root.clone()
+    } else {
+        resolve_key(&state.root, prefix)
+            .filter(|p| !state.buckets.owns(&storage::key_of(&state.root, p)))
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("invalid prefix"))?
+    };
+
+    let st = state.get_ref().clone();
+    let entries = web::block(move || archive::collect(&st, &base, now_secs()))
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if entries.is_empty() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "no_objects", "no objects under prefix").into());
+    }
+    let bytes: u64 = entries.iter().map(|e| e.size).sum();
+    if entries.len() as u64 > cfg.archive_max_entries || bytes > cfg.archive_max_bytes {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "archive_too_large", "prefix exceeds the archive limits; narrow it")
+            .with("entries", entries.len())
+            .with("bytes", bytes)
+            .with("max_entries", cfg.archive_max_entries)
+            .with("max_bytes", cfg.archive_max_bytes)
+            .into());
+    }
+
+    let download = limits::Download::start(&state, &cfg, &req).await?;
+    let rx = archive::spawn(format, entries);
+    let body = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    let body = download.body(&state, body);
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .append_header(("Content-Disposition", format!("attachment; filename=\"{}.{}\"", archive_name(prefix), format.extension())))
+        .streaming(body))
+}
+
+/// Expands a `?format=tar|tar.gz|zip` body (default tar) under `?prefix=`.
+/// 200 with `{created, overwritten, skipped}`; an error partway returns the
+/// same lists alongside the error code, covering what was written before it.
+async fn upload(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    mut body: web::Payload,
+) -> Result<HttpResponse> {
+    println!("→ POST /{}", PATH_ARCHIVE);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["prefix", "format"])?;
+    let format = match q.str("format") {
+        None => Format::Tar,
+        Some(f) => Format::parse(f).ok_or_else(|| {
+            ApiError::new(StatusCode::BAD_REQUEST, "invalid_format", "format must be zip, tar or tar.gz")
+        })?,
+    };
+    let prefix = q.str("prefix").unwrap_or("").to_string();
+    if !prefix.trim_matches('/').is_empty() && resolve_key(&state.root, &prefix).is_none() {
+        return Err(actix_web::error::ErrorBadRequest("invalid prefix"));
+    }
+
+    // spool first: zip needs its central directory, at the end of the body
+    let upload = state.uploads.begin(&format!("{PATH_ARCHIVE}/{prefix}"), auth.0.sub.clone(), None);
+    let (spool, mut file) = storage::TempUpload::create(&state.root)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut received: u64 = 0;
+    loop {
+        let chunk = tokio::select! {
+            chunk = body.next() => chunk,
+            _ = upload.cancelled().cancelled() => {
+                return Err(actix_web::error::ErrorConflict("upload cancelled"));
+            }
+        };
+        let Some(chunk) = chunk else { break };
+        let bytes = chunk.map_err(actix_web::error::ErrorBadRequest)?;
+        received += bytes.len() as u64;
+        upload.add(bytes.len() as u64);
+        if cfg.max_upload_bytes.is_some_and(|limit| received > limit) {
+            return Err(actix_web::error::ErrorPayloadTooLarge("upload too large"));
+        }
+        file.write_all(&bytes).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+    file.flush().await.map_err(actix_web::error::ErrorInternalServerError)?;
+    drop(file);
+
+    let st = state.get_ref().clone();
+    let limits = Limits { max_bytes: cfg.max_upload_bytes, max_objects: cfg.max_object_count };
+    let (summary, res) = web::block(move || {
+        let out = archive::extract(&st, spool.path(), format, &prefix, limits);
+        drop(spool);
+        out
+    })
+    .await?;
+    let err = match res {
+        Ok(()) => return Ok(HttpResponse::Ok().json(summary)),
+        Err(ExtractError::TooLarge) => {
+            ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "upload_too_large", "extracted size exceeds the upload limit")
+        }
+        Err(ExtractError::CountExceeded) => {
+            ApiError::new(StatusCode::INSUFFICIENT_STORAGE, "object_count_exceeded", "object count limit reached")
+        }
+        Err(ExtractError::Invalid(msg)) => ApiError::new(StatusCode::BAD_REQUEST, "invalid_archive", msg),
+        Err(ExtractError::Io(e)) if storage::is_read_only_error(&e) => {
+            state.read_only.store(true, Ordering::Relaxed);
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "storage_read_only", "storage is not accepting writes")
+        }
+        Err(ExtractError::Io(e)) => ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "extract_failed", e.to_string()),
+    };
+    Err(err
+        .with("created", summary.created)
+        .with("overwritten", summary.overwritten)
+        .with("skipped", serde_json::to_value(&summary.skipped).unwrap_or_default())
+        .into())
+}
+
+/// `photos/2024/` → `photos-2024`; the whole bucket is `bucket`.
+fn archive_name(prefix: &str) -> String {
+    let name: String = prefix
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    if name.is_empty() { "bucket".into() } else { name }
+}
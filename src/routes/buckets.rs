@@ -0,0 +1,158 @@
+// src/routes/buckets.rs
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::auth::NeedAdmin;
+use crate::buckets;
+use crate::dedup;
+use crate::consts::PATH_BUCKETS;
+use crate::error::ApiError;
+use crate::pending::now_secs;
+use crate::retention;
+use crate::routes::query::QueryParams;
+use crate::stats;
+use crate::storage::key_of;
+use crate::AppState;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(format!("/{}", PATH_BUCKETS).as_str()).route(web::get().to(list_buckets)))
+        .service(
+            web::resource(format!("/{}/{{bucket}}", PATH_BUCKETS).as_str())
+                .route(web::put().to(create_bucket))
+                .route(web::delete().to(delete_bucket)),
+        );
+}
+
+#[derive(Serialize)]
+struct BucketInfo {
+    name: String,
+    objects: u64,
+    bytes: u64,
+}
+
+/// `GET /b`: named buckets with their object counts and sizes (a live walk).
+async fn list_buckets(_auth: NeedAdmin, state: web::Data<AppState>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_BUCKETS);
+    let st = state.get_ref().clone();
+    let list = web::block(move || {
+        st.buckets
+            .names()
+            .into_iter()
+            .map(|name| {
+                let totals = stats::dir_totals(&st.root.join(&name));
+                BucketInfo { name, objects: totals.objects, bytes: totals.bytes }
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// `PUT /b/{bucket}`: 201, or 409 if the bucket exists or the default
+/// bucket already holds keys under that name.
+pub(crate) async fn create_bucket(_auth: NeedAdmin, state: web::Data<AppState>, name: web::Path<String>) -> Result<HttpResponse> {
+    let name = name.into_inner();
+    println!("→ PUT /{}/{}", PATH_BUCKETS, name);
+    if !buckets::valid_name(&name) {
+        return Err(ApiError::bad_request(
+            "invalid_bucket_name",
+            "bucket names are 3-63 of a-z 0-9 - _ . starting and ending with a letter or digit",
+        )
+        .with("bucket", name)
+        .into());
+    }
+    if state.buckets.contains(&name) {
+        return Err(ApiError::new(StatusCode::CONFLICT, "bucket_exists", "bucket already exists").with("bucket", name).into());
+    }
+    if state.root.join(&name).exists() {
+        return Err(ApiError::new(StatusCode::CONFLICT, "name_in_use", "the default bucket has keys under this name")
+            .with("bucket", name)
+            .into());
+    }
+    let st = state.get_ref().clone();
+    let bucket = name.clone();
+    web::block(move || st.buckets.create(&st.root, &bucket))
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Created().json(serde_json::json!({ "name": name })))
+}
+
+/// `DELETE /b/{bucket}[?force=1]`: 204; a bucket holding objects is a 409
+/// unless forced, which deletes them outright (no trash, no events), and a
+/// 423 `legal_hold` or 403 `object_locked` while any is held or under retention.
+pub(crate) async fn delete_bucket(
+    _auth: NeedAdmin,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    let name = name.into_inner();
+    println!("→ DELETE /{}/{}", PATH_BUCKETS, name);
+    let force = QueryParams::parse(&req, &["force"])?.bool("force")?.unwrap_or(false);
+    if !state.buckets.contains(&name) {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "no_such_bucket", "bucket does not exist").with("bucket", name).into());
+    }
+
+    let st = state.get_ref().clone();
+    let bucket = name.clone();
+    let keys = web::block(move || object_keys(&st.root, &st.root.join(&bucket))).await?;
+    if !keys.is_empty() && !force {
+        return Err(ApiError::new(StatusCode::CONFLICT, "bucket_not_empty", "bucket still holds objects; delete them or pass force=1")
+            .with("bucket", name)
+            .with("objects", keys.len())
+            .into());
+    }
+    // held objects and those under retention outlive a forced delete too
+    let held = keys.iter().filter(|key| state.holds.contains(key)).count();
+    if held > 0 {
+        return Err(ApiError::new(StatusCode::LOCKED, "legal_hold", "bucket holds objects under a legal hold")
+            .with("bucket", name)
+            .with("held", held)
+            .into());
+    }
+    let st = state.get_ref().clone();
+    let candidates = keys.clone();
+    let locked = web::block(move || {
+        let now = now_secs();
+        candidates.iter().try_fold(0usize, |n, key| {
+            Ok::<_, std::io::Error>(n + usize::from(retention::locked_at(&st.root, &st.root.join(key), now)?.is_some()))
+        })
+    })
+    .await?
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    if locked > 0 {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "object_locked", "bucket holds objects under retention")
+            .with("bucket", name)
+            .with("locked", locked)
+            .into());
+    }
+    let st = state.get_ref().clone();
+    web::block(move || st.buckets.remove(&st.root, &name))
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    for key in &keys {
+        state.pending.cancel(key);
+        state.expiries.clear(key);
+        state.objects.decrement();
+    }
+    dedup::collect_later(&state);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Root-relative keys of the files under `dir` (blocking).
+fn object_keys(root: &Path, dir: &Path) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&d) else { continue };
+        for entry in rd.flatten() {
+            match entry.file_type() {
+                Ok(ft) if ft.is_dir() => stack.push(entry.path()),
+                Ok(ft) if ft.is_file() => keys.push(key_of(root, &entry.path())),
+                _ => {}
+            }
+        }
+    }
+    keys
+}
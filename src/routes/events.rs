@@ -0,0 +1,81 @@
+// src/routes/events.rs
+//
+// `GET /events`: a server-sent events feed of object changes, optionally
+// limited to keys starting with `prefix`. A subscriber that falls
+// `events::CHANNEL_CAPACITY` events behind is disconnected, not buffered.
+use actix_web::{web, web::Bytes, HttpRequest, HttpResponse, Result};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::auth::{self, NeedList};
+use crate::consts::{Config, PATH_EVENTS};
+use crate::events::Event;
+use crate::routes::query::QueryParams;
+use crate::AppState;
+
+/// Comment frames on an idle stream keep proxies from timing it out.
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route(format!("/{}", PATH_EVENTS).as_str(), web::get().to(events));
+}
+
+struct Feed {
+    rx: broadcast::Receiver<Event>,
+    prefix: String,
+    heartbeat: actix_web::rt::time::Interval,
+    stopping: CancellationToken,
+    closed: bool,
+}
+
+async fn events(auth: NeedList, req: HttpRequest, state: web::Data<AppState>, cfg: web::Data<Config>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_EVENTS);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["prefix"])?;
+    let feed = Feed {
+        rx: state.events.subscribe(),
+        prefix: q.str("prefix").unwrap_or("").trim_start_matches('/').to_string(),
+        // the first tick fires at once, so headers go out on connect
+        heartbeat: actix_web::rt::time::interval(HEARTBEAT),
+        stopping: state.in_flight.stopping(),
+        closed: false,
+    };
+    let body = futures_util::stream::unfold(feed, |mut feed| async move {
+        next_frame(&mut feed).await.map(|frame| (Ok::<_, actix_web::Error>(frame), feed))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("cache-control", "no-cache"))
+        // nginx: don't buffer the stream
+        .insert_header(("x-accel-buffering", "no"))
+        .streaming(body))
+}
+
+/// The next SSE frame, or `None` to end the stream.
+async fn next_frame(feed: &mut Feed) -> Option<Bytes> {
+    if feed.closed {
+        return None;
+    }
+    loop {
+        tokio::select! {
+            _ = feed.stopping.cancelled() => return None,
+            _ = feed.heartbeat.tick() => return Some(Bytes::from_static(b": keepalive\n\n")),
+            msg = feed.rx.recv() => match msg {
+                Ok(event) if event.key.starts_with(&feed.prefix) => return Some(frame(&event)),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    // tell the client why, then hang up; it can reconnect and re-list
+                    feed.closed = true;
+                    return Some(Bytes::from(format!("event: lagged\ndata: {{\"missed\":{missed}}}\n\n")));
+                }
+                Err(RecvError::Closed) => return None,
+            },
+        }
+    }
+}
+
+fn frame(event: &Event) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event.event, data))
+}
@@ -1,11 +1,78 @@
-use actix_web::{web, HttpResponse};
-use crate::consts::PATH_HEALTHZ;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Result};
+use std::sync::atomic::Ordering;
+
+use crate::auth::NeedAdmin;
+use crate::AppState;
+use crate::consts::{Config, PATH_HEALTHZ, PATH_READYZ};
+use crate::storage;
 
 pub(crate) fn init(cfg: &mut web::ServiceConfig) {
     cfg.route(format!("/{}", PATH_HEALTHZ).as_str(), web::get().to(healthz));
+    cfg.route(format!("/{}", PATH_READYZ).as_str(), web::get().to(readyz));
+}
+
+#[derive(serde::Deserialize)]
+struct HealthQuery {
+    deep: Option<u8>,
+    verbose: Option<u8>,
 }
 
-async fn healthz() -> HttpResponse {
+/// Plain liveness by default; `?deep=1` also reports degraded storage (503
+/// when read-only), and `?verbose=1` adds server diagnostics on top (admin
+/// scope with HEALTH_DETAILS_ADMIN).
+async fn healthz(req: HttpRequest, state: web::Data<AppState>, cfg: web::Data<Config>, q: web::Query<HealthQuery>) -> Result<HttpResponse> {
     println!("→ /{} endpoint hit", PATH_HEALTHZ);
-    HttpResponse::Ok().body("ok")
+    let verbose = q.verbose.unwrap_or(0) != 0;
+    if q.deep.unwrap_or(0) == 0 && !verbose {
+        return Ok(HttpResponse::Ok().body("ok"));
+    }
+    if verbose && cfg.health_details_admin {
+        NeedAdmin::extract(&req).await?;
+    }
+    let read_only = state.read_only.load(Ordering::Relaxed);
+    let mut body = serde_json::json!({
+        "status": if read_only { "degraded" } else { "ok" },
+        "storage_read_only": read_only,
+        "objects": { "count": state.objects.get(), "limit": cfg.max_object_count },
+    });
+    if verbose {
+        let writable = storage::check_ready(&state.root).await;
+        let root = tokio::fs::canonicalize(&state.root).await.unwrap_or_else(|_| state.root.clone());
+        body["version"] = env!("CARGO_PKG_VERSION").into();
+        body["uptime_secs"] = state.started.elapsed().as_secs().into();
+        body["root"] = root.display().to_string().into();
+        body["root_writable"] = writable.is_ok().into();
+        if let Err(e) = writable {
+            body["root_error"] = e.message.into();
+        }
+        body["free_bytes"] = storage::free_bytes(&state.root).into();
+        body["auth_mode"] = cfg.auth_mode.as_str().into();
+        body["in_flight"] = serde_json::json!({
+            "requests": state.in_flight.active(),
+            "uploads": state.uploads.in_flight(),
+            "downloads": state.downloads_in_flight.load(Ordering::Relaxed),
+        });
+    }
+    Ok(if read_only {
+        HttpResponse::ServiceUnavailable().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    })
+}
+
+/// Readiness: the root exists, is a directory and takes writes (cached briefly).
+/// Free space is reported alongside, with `low_space` once it is under
+/// MIN_FREE_BYTES; that warns but doesn't fail the probe, as reads still work.
+async fn readyz(state: web::Data<AppState>, cfg: web::Data<Config>) -> HttpResponse {
+    let free = storage::free_bytes(&state.root);
+    let low_space = free.is_some_and(|f| f < cfg.min_free_bytes);
+    match state.ready.check(&state.root).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({ "status": "ready", "free_bytes": free, "low_space": low_space })),
+        Err(e) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "status": "not_ready",
+            "reason": e.reason,
+            "message": e.message,
+            "free_bytes": free,
+        })),
+    }
 }
@@ -0,0 +1,100 @@
+// src/routes/idp.rs
+use actix_web::{web, HttpResponse, Result};
+use jsonwebtoken::{encode, Algorithm, Header};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::auth::NeedAdmin;
+use crate::consts::{Config, PATH_IDP_ROTATE, PATH_IDP_TOKEN, PATH_JWKS};
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route(format!("/{}", PATH_JWKS).as_str(), web::get().to(jwks))
+        .route(format!("/{}", PATH_IDP_TOKEN).as_str(), web::post().to(token))
+        .route(format!("/{}", PATH_IDP_ROTATE).as_str(), web::post().to(rotate));
+}
+
+#[derive(Deserialize)]
+struct TokenReq {
+    sub: String,
+    /// Optional: space-delimited scopes (default: all configured)
+    scope: Option<String>,
+    /// Optional: token TTL seconds (default 900, clamped by AUTH_MAX_TTL_SECS)
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Claims {
+    sub: String,
+    scope: String,
+    exp: u64,
+    iat: u64,
+    iss: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aud: Option<String>,
+    jti: String,
+}
+
+#[derive(Serialize)]
+struct TokenResp {
+    access_token: String,
+    token_type: String, // "Bearer"
+    expires_in: u64,
+}
+
+async fn jwks(state: web::Data<AppState>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_JWKS);
+    let idp = state.idp.as_ref().ok_or_else(|| actix_web::error::ErrorNotFound("IdP disabled"))?;
+    Ok(HttpResponse::Ok().json(idp.jwks()))
+}
+
+/// Switches signing to a fresh key (admin scope); the old one keeps verifying
+/// for the grace window.
+async fn rotate(_auth: NeedAdmin, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let idp = state.idp.clone().ok_or_else(|| actix_web::error::ErrorNotFound("IdP disabled"))?;
+    let kid = web::block(move || idp.rotate())
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    println!("🔑 IdP signing key rotated (kid {kid})");
+    Ok(HttpResponse::Created().json(serde_json::json!({ "kid": kid })))
+}
+
+/// Dev-only: mints an RS256 token for whatever `sub`/scope is asked for.
+async fn token(
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    req: web::Json<TokenReq>,
+) -> Result<HttpResponse> {
+    println!("→ POST /{}", PATH_IDP_TOKEN);
+    let idp = state.idp.as_ref().ok_or_else(|| actix_web::error::ErrorNotFound("IdP disabled"))?;
+    let key = idp.signing_key();
+
+    let ttl = req.ttl_secs.unwrap_or(900).min(cfg.auth_max_ttl_secs);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .as_secs();
+
+    let claims = Claims {
+        sub: req.sub.clone(),
+        scope: req.scope.clone().unwrap_or_else(|| cfg.default_scope()),
+        exp: now + ttl,
+        iat: now,
+        iss: cfg.idp_issuer(),
+        aud: cfg.jwt_audience.clone(),
+        jti: uuid::Uuid::new_v4().to_string(),
+    };
+
+    let mut header = Header::new(Algorithm::RS256);
+    header.typ = Some("JWT".into());
+    header.kid = Some(key.kid.clone());
+
+    let token = encode(&header, &claims, &key.encoding)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(TokenResp {
+        access_token: token,
+        token_type: "Bearer".into(),
+        expires_in: ttl,
+    }))
+}
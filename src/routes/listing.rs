@@ -0,0 +1,120 @@
+// src/routes/listing.rs
+//
+// HTML rendering of `GET /objects` for browsers (`Accept: text/html` or
+// `?format=html`). Key names are untrusted: every one is escaped for HTML
+// and percent-encoded in links.
+use actix_web::{http::header, HttpRequest};
+use std::fmt::Write;
+use std::time::{Duration, UNIX_EPOCH};
+
+
+/// One file row: the key, its size and mtime (unix seconds).
+pub(crate) struct Row<'a> {
+    pub key: &'a str,
+    pub size: u64,
+    pub modified: u64,
+}
+
+/// Whether the client ranks `text/html` above `application/json`. A bare
+/// `*/*` (curl, most HTTP libraries) is a tie, which stays JSON.
+pub(crate) fn prefers_html(req: &HttpRequest) -> bool {
+    let Some(accept) = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()) else { return false };
+    let (mut html, mut json, mut any) = (None, None, 0.0f32);
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let mime = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|v| v.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match mime.as_str() {
+            "text/html" => html = Some(q),
+            "application/json" => json = Some(q),
+            "*/*" => any = q,
+            _ => {}
+        }
+    }
+    html.unwrap_or(any) > json.unwrap_or(any)
+}
+
+/// The listing page for `prefix` (a directory key, `""` for the root) of
+/// the bucket whose listing lives at `objects` (`/objects`, `/b/x/objects`).
+pub(crate) fn render(objects: &str, prefix: &str, dirs: &[String], files: &[Row]) -> String {
+    let base = match prefix.trim_matches('/') {
+        "" => String::new(),
+        p => format!("{p}/"),
+    };
+    let title = format!("/{base}");
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!doctype html>\n<html><head><meta charset=\"utf-8\"><title>Index of {t}</title>\
+         <style>body{{font:14px system-ui,sans-serif;margin:1.5rem}}td,th{{padding:.2rem .8rem;text-align:left}}td.n{{text-align:right}}</style>\
+         </head><body>\n<h1>Index of {t}</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n",
+        t = escape(&title)
+    );
+    if !base.is_empty() {
+        let parent = match base.trim_end_matches('/').rsplit_once('/') {
+            Some((p, _)) => p,
+            None => "",
+        };
+        let _ = writeln!(html, "<tr><td><a href=\"{}\">../</a></td><td></td><td></td></tr>", escape(&dir_href(objects, parent)));
+    }
+    for dir in dirs {
+        let name = format!("{}/", dir.strip_prefix(&base).unwrap_or(dir));
+        let _ = writeln!(html, "<tr><td><a href=\"{}\">{}</a></td><td></td><td></td></tr>", escape(&dir_href(objects, dir)), escape(&name));
+    }
+    for f in files {
+        let name = f.key.strip_prefix(&base).unwrap_or(f.key);
+        let modified = actix_web::http::header::HttpDate::from(UNIX_EPOCH + Duration::from_secs(f.modified));
+        let _ = writeln!(
+            html,
+            "<tr><td><a href=\"{}/{}?download=0\">{}</a></td><td class=\"n\">{}</td><td>{}</td></tr>",
+            objects,
+            escape(&encode(f.key)),
+            escape(name),
+            f.size,
+            modified
+        );
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+fn dir_href(objects: &str, prefix: &str) -> String {
+    if prefix.is_empty() {
+        format!("{objects}?format=html")
+    } else {
+        format!("{objects}?prefix={}/&format=html", encode(prefix))
+    }
+}
+
+/// Escapes text for element content and quoted attributes.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Percent-encodes a key for a URL path or query value, keeping `/`.
+pub(crate) fn encode(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for b in key.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(b as char),
+            _ => {
+                let _ = write!(out, "%{b:02X}");
+            }
+        }
+    }
+    out
+}
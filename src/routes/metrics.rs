@@ -0,0 +1,74 @@
+// src/routes/metrics.rs
+use actix_web::{web, HttpResponse};
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use crate::AppState;
+use crate::consts::{Config, PATH_METRICS};
+use crate::pending::now_secs;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route(format!("/{}", PATH_METRICS).as_str(), web::get().to(metrics));
+}
+
+/// Prometheus text exposition of the server's gauges.
+async fn metrics(state: web::Data<AppState>, cfg: web::Data<Config>) -> HttpResponse {
+    let mut out = String::new();
+    gauge(&mut out, "bucket_uploads_in_flight", "Uploads currently streaming", state.uploads.in_flight() as u64);
+    gauge(&mut out, "bucket_upload_slots_in_use", "PUT bodies holding a MAX_CONCURRENT_UPLOADS slot", state.upload_slots.in_flight() as u64);
+    if let Some(limit) = state.upload_slots.limit() {
+        gauge(&mut out, "bucket_upload_slots_limit", "MAX_CONCURRENT_UPLOADS", limit as u64);
+    }
+    gauge(&mut out, "bucket_download_slots_in_use", "GET bodies holding a MAX_CONCURRENT_DOWNLOADS slot", state.download_slots.in_flight() as u64);
+    if let Some(limit) = state.download_slots.limit() {
+        gauge(&mut out, "bucket_download_slots_limit", "MAX_CONCURRENT_DOWNLOADS", limit as u64);
+    }
+    gauge(&mut out, "bucket_upload_bytes_per_second", "Aggregate receive rate of in-flight uploads", state.uploads.bytes_per_sec());
+    gauge(&mut out, "bucket_storage_read_only", "1 if the last write failed because storage is read-only", state.read_only.load(Ordering::Relaxed) as u64);
+    gauge(&mut out, "bucket_download_bytes_per_second", "Object bytes served in the last second", state.downloaded.per_sec(now_secs()));
+    if let Some(limit) = cfg.download_rate_limit_bps {
+        gauge(&mut out, "bucket_download_rate_limit_bytes_per_second", "DOWNLOAD_RATE_LIMIT_BPS", limit);
+    }
+    if state.meta_cache.capacity() > 0 {
+        gauge(&mut out, "bucket_meta_cache_entries", "Objects with cached GET/HEAD headers", state.meta_cache.len() as u64);
+        counter(&mut out, "bucket_meta_cache_hits_total", "GET/HEAD answered from the metadata cache", state.meta_cache.hits());
+        counter(&mut out, "bucket_meta_cache_misses_total", "GET/HEAD that had to load the sidecar", state.meta_cache.misses());
+    }
+    if state.hot_cache.enabled() {
+        gauge(&mut out, "bucket_hot_cache_entries", "Objects whose bytes are held in memory", state.hot_cache.len() as u64);
+        gauge(&mut out, "bucket_hot_cache_bytes", "Object bytes held in memory (HOT_CACHE_TOTAL_BYTES)", state.hot_cache.bytes());
+        counter(&mut out, "bucket_hot_cache_hits_total", "GET bodies served from memory", state.hot_cache.hits());
+        counter(&mut out, "bucket_hot_cache_misses_total", "Small GET bodies that had to be read from disk", state.hot_cache.misses());
+    }
+    gauge(&mut out, "bucket_pending_deletes", "Deleted objects waiting out DELETE_GRACE_SECS", state.pending.len() as u64);
+    gauge(&mut out, "bucket_objects", "Objects currently stored", state.objects.get());
+    gauge(&mut out, "bucket_expiring_objects", "Objects with an expiry set", state.expiries.len() as u64);
+    if let Some(free) = crate::storage::free_bytes(&state.root) {
+        gauge(&mut out, "bucket_free_bytes", "Free space on the storage volume", free);
+    }
+    if let Some(limit) = cfg.max_object_count {
+        gauge(&mut out, "bucket_objects_limit", "MAX_OBJECT_COUNT", limit);
+    }
+    if state.webhooks.enabled() {
+        let hooks = &state.webhooks;
+        gauge(&mut out, "bucket_webhook_queue", "Webhook events waiting for delivery", hooks.queued() as u64);
+        counter(&mut out, "bucket_webhook_delivered_total", "Webhook events delivered", hooks.delivered());
+        counter(&mut out, "bucket_webhook_failed_total", "Webhook events abandoned after retries", hooks.failed());
+        counter(&mut out, "bucket_webhook_dropped_total", "Webhook events evicted from a full queue", hooks.dropped());
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    let _ = writeln!(out, "{name} {value}");
+}
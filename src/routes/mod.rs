@@ -1,5 +1,20 @@
 // routes/mod.rs 
 
+pub(crate) mod admin;
+pub(crate) mod archive;
+pub(crate) mod buckets;
+pub(crate) mod events;
 pub(crate) mod health;
+pub(crate) mod idp;
+pub(crate) mod listing;
+pub(crate) mod metrics;
 pub(crate) mod objects;
+pub(crate) mod query;
+pub(crate) mod resumable;
+pub(crate) mod s3;
+pub(crate) mod search;
 pub(crate) mod session;
+pub(crate) mod stats;
+pub(crate) mod trash;
+#[cfg(feature = "ui")]
+pub(crate) mod ui;
@@ -0,0 +1,65 @@
+// src/routes/query.rs
+//
+// Explicit query-string handling for object routes: duplicates are rejected,
+// booleans accept a fixed set of spellings, and STRICT_QUERY rejects unknown names.
+use actix_web::{web, HttpRequest};
+use std::collections::HashMap;
+
+use crate::consts::Config;
+use crate::error::ApiError;
+
+pub(crate) struct QueryParams {
+    values: HashMap<String, String>,
+}
+
+impl QueryParams {
+    /// Parses the request's query string, allowing only names in `accepted` when strict.
+    pub(crate) fn parse(req: &HttpRequest, accepted: &[&str]) -> Result<Self, ApiError> {
+        let strict = req
+            .app_data::<web::Data<Config>>()
+            .map(|c| c.strict_query)
+            .unwrap_or(false);
+
+        let pairs = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map_err(|_| ApiError::bad_request("invalid_query", "malformed query string"))?
+            .into_inner();
+
+        let mut values = HashMap::new();
+        for (name, value) in pairs {
+            if strict && !accepted.contains(&name.as_str()) {
+                return Err(ApiError::bad_request("unknown_query_param", format!("unknown query parameter `{name}`"))
+                    .with("param", name)
+                    .with("accepted", accepted.to_vec()));
+            }
+            if values.contains_key(&name) {
+                return Err(ApiError::bad_request("duplicate_query_param", format!("query parameter `{name}` given more than once"))
+                    .with("param", name));
+            }
+            values.insert(name, value);
+        }
+        Ok(Self { values })
+    }
+
+    pub(crate) fn str(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|s| s.as_str())
+    }
+
+    /// A non-negative integer.
+    pub(crate) fn u64(&self, name: &str) -> Result<Option<u64>, ApiError> {
+        let Some(raw) = self.str(name) else { return Ok(None) };
+        raw.trim().parse().map(Some).map_err(|_| {
+            ApiError::bad_request("invalid_query_param", format!("`{name}` must be a non-negative integer")).with("param", name)
+        })
+    }
+
+    /// Boolean params accept `1`/`0`/`true`/`false` (case-insensitive).
+    pub(crate) fn bool(&self, name: &str) -> Result<Option<bool>, ApiError> {
+        let Some(raw) = self.str(name) else { return Ok(None) };
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" => Ok(Some(true)),
+            "0" | "false" => Ok(Some(false)),
+            _ => Err(ApiError::bad_request("invalid_query_param", format!("`{name}` must be one of 1, 0, true, false"))
+                .with("param", name)),
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// src/routes/resumable.rs
+//
+// Resumable uploads, tus-style (see `crate::resumable`): create with
+// `Upload-Length`, append with `Upload-Offset`, ask for the offset with HEAD,
+// abandon with DELETE. Under `/uploads` for the default bucket and
+// `/b/{bucket}/uploads` for named ones.
+use actix_web::{http::header, http::header::HttpDate, http::StatusCode, web, HttpRequest, HttpResponse, HttpResponseBuilder, Result};
+use futures_util::StreamExt;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::auth::NeedWrite;
+use crate::consts::{Config, PATH_BUCKETS, PATH_UPLOADS};
+use crate::error::ApiError;
+use crate::meta::ObjectMeta;
+use crate::pending::now_secs;
+use crate::resumable::{self, Resumable};
+use crate::routes::objects::{self, check_key, durable, write_error, Bucket};
+use crate::routes::listing;
+use crate::routes::query::QueryParams;
+use crate::storage::{key_of, make_etag};
+use crate::AppState;
+
+const TUS_RESUMABLE: &str = "Tus-Resumable";
+const TUS_VERSION: &str = "1.0.0";
+const UPLOAD_LENGTH: &str = "Upload-Length";
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+const UPLOAD_EXPIRES: &str = "Upload-Expires";
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    for base in [format!("/{}", PATH_UPLOADS), format!("/{}/{{bucket}}/{}", PATH_BUCKETS, PATH_UPLOADS)] {
+        cfg.service(web::resource(base.as_str()).route(web::post().to(create_upload))).service(
+            web::resource(format!("{base}/{{id}}").as_str())
+                .route(web::head().to(upload_offset))
+                .route(web::patch().to(append_upload))
+                .route(web::delete().to(terminate_upload)),
+        );
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct IdParam {
+    id: String,
+}
+
+/// What POST answers with.
+#[derive(serde::Serialize)]
+struct UploadStatus {
+    id: String,
+    key: String,
+    length: u64,
+    offset: u64,
+    /// Absent with RESUMABLE_EXPIRY_SECS=0.
+    expires_at: Option<u64>,
+    /// True when a zero-length upload landed straight away.
+    completed: bool,
+}
+
+fn no_such_upload(id: &str) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "no_such_upload", "no upload in progress with this id").with("id", id)
+}
+
+/// A non-negative integer upload header; 400 `code` otherwise.
+fn number_header(req: &HttpRequest, name: &'static str, code: &'static str) -> Result<u64, ApiError> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .ok_or_else(|| ApiError::bad_request(code, format!("`{name}` must be a non-negative integer")).with("header", name))
+}
+
+fn tus(mut resp: HttpResponseBuilder) -> HttpResponseBuilder {
+    resp.insert_header((TUS_RESUMABLE, TUS_VERSION)).insert_header((header::CACHE_CONTROL, "no-store"));
+    resp
+}
+
+/// When an upload last touched at `touched` goes, for `Upload-Expires`.
+fn expires_at(cfg: &Config, touched: u64) -> Option<u64> {
+    (cfg.resumable_expiry_secs > 0).then(|| resumable::expires_at(touched, cfg.resumable_expiry_secs))
+}
+
+fn expires_header(resp: &mut HttpResponseBuilder, at: Option<u64>) {
+    if let Some(at) = at {
+        resp.insert_header((UPLOAD_EXPIRES, HttpDate::from(UNIX_EPOCH + Duration::from_secs(at))));
+    }
+}
+
+/// The upload `id` names, when it's in this bucket; 404 otherwise.
+async fn find(state: &AppState, cfg: &Config, bucket: &Bucket, id: &str) -> Result<(Resumable, u64)> {
+    let found = resumable::load(&state.root, id, cfg.resumable_expiry_secs)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match found {
+        Some((upload, offset)) if bucket.holds(state, &upload.key) => Ok((upload, offset)),
+        _ => Err(no_such_upload(id).into()),
+    }
+}
+
+/// Lands a complete upload on its key and forgets it; the stored ETag.
+async fn complete(req: &HttpRequest, state: &AppState, cfg: &Config, id: &str, upload: &Resumable) -> Result<String> {
+    let part = resumable::part_path(&state.root, id).ok_or_else(|| no_such_upload(id))?;
+    // the key was checked at creation; resolve again in case a link was planted since
+    let path = objects::resolve_key(&state.root, &upload.key)
+        .ok_or_else(|| ApiError::bad_request("invalid_key", "the upload's key can no longer be written"))?;
+    let (stored, _) = objects::land(state, cfg, &part, &path, durable(req, cfg), ObjectMeta::default()).await?;
+    resumable::remove(&state.root, id).await;
+    Ok(make_etag(&stored))
+}
+
+/// `POST /uploads?key=...` with `Upload-Length`: opens an upload (201 with
+/// its URL in Location). A length over MAX_UPLOAD_BYTES is refused here,
+/// before any bytes are sent; a zero length lands on the key at once.
+async fn create_upload(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+) -> Result<HttpResponse> {
+    println!("→ POST /{}", PATH_UPLOADS);
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["key"])?;
+    let key = q.str("key").ok_or_else(|| ApiError::bad_request("missing_key", "`key` query parameter is required"))?.to_string();
+    check_key(&cfg, &key)?;
+    let path = bucket.resolve(&state, &key).ok_or_else(|| actix_web::error::ErrorBadRequest("invalid key"))?;
+    let length = number_header(&req, UPLOAD_LENGTH, "invalid_upload_length")?;
+    if let Some(limit) = cfg.max_upload_bytes.filter(|limit| length > *limit) {
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", "Upload-Length exceeds MAX_UPLOAD_BYTES")
+            .with("limit", limit)
+            .with("upload_length", length)
+            .into());
+    }
+
+    let id = resumable::new_id();
+    let upload = Resumable { key: key_of(&state.root, &path), length, created_at: now_secs() };
+    resumable::create(&state.root, &id, &upload).await.map_err(|e| write_error(&state, e))?;
+
+    let mut resp = tus(HttpResponse::Created());
+    let completed = length == 0;
+    if completed {
+        let etag = complete(&req, &state, &cfg, &id, &upload).await?;
+        resp.insert_header((header::LOCATION, format!("{}/{}", bucket.objects_path(), listing::encode(&key))))
+            .insert_header((header::ETAG, etag));
+    } else {
+        resp.insert_header((header::LOCATION, format!("{}/{}", req.uri().path().trim_end_matches('/'), id)));
+    }
+    let expires_at = expires_at(&cfg, upload.created_at).filter(|_| !completed);
+    expires_header(&mut resp, expires_at);
+    resp.insert_header((UPLOAD_OFFSET, "0"));
+    Ok(resp.json(UploadStatus { id, key, length, offset: 0, expires_at, completed }))
+}
+
+/// `HEAD /uploads/{id}`: how much has arrived (`Upload-Offset`) of how much
+/// (`Upload-Length`), so a client can resume after a dropped connection.
+async fn upload_offset(
+    auth: NeedWrite,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    id: web::Path<IdParam>,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let id = id.into_inner().id;
+    println!("→ HEAD /{}/{}", PATH_UPLOADS, id);
+    let (upload, offset) = find(&state, &cfg, &bucket, &id).await?;
+    let staged = match resumable::part_path(&state.root, &id) {
+        Some(part) => fs::metadata(part).await.ok(),
+        None => None,
+    };
+    let touched = staged.map_or(upload.created_at, |m| resumable::touched(&m));
+    let mut resp = tus(HttpResponse::Ok());
+    resp.insert_header((UPLOAD_OFFSET, offset.to_string())).insert_header((UPLOAD_LENGTH, upload.length.to_string()));
+    expires_header(&mut resp, expires_at(&cfg, touched));
+    Ok(resp.finish())
+}
+
+/// `PATCH /uploads/{id}` with `Upload-Offset`: appends the body, where the
+/// offset must be what has arrived so far (409 `offset_mismatch` with
+/// `current_offset` otherwise) and the total may not pass `Upload-Length`.
+/// Bytes received before a dropped connection are kept, so the client
+/// resumes from the new offset. The append that reaches the length moves
+/// the upload onto its key (204 with the object's ETag and Location).
+async fn append_upload(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    id: web::Path<IdParam>,
+    mut body: web::Payload,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let id = id.into_inner().id;
+    println!("→ PATCH /{}/{}", PATH_UPLOADS, id);
+    let offset = number_header(&req, UPLOAD_OFFSET, "invalid_upload_offset")?;
+    let part = resumable::part_path(&state.root, &id).ok_or_else(|| no_such_upload(&id))?;
+    // one append per upload at a time; the offset is read under the lock
+    let _guard = state.locks.lock(&part).await;
+    let (upload, current) = find(&state, &cfg, &bucket, &id).await?;
+    if offset != current {
+        return Err(ApiError::new(StatusCode::CONFLICT, "offset_mismatch", "Upload-Offset is not the upload's current offset")
+            .with("current_offset", current)
+            .with("offset", offset)
+            .into());
+    }
+    let too_long = |size: u64| {
+        ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "upload_length_exceeded", "the body runs past Upload-Length")
+            .with("upload_length", upload.length)
+            .with("size", size)
+    };
+    let declared = req.headers().get(header::CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok());
+    if let Some(len) = declared.filter(|len| current.saturating_add(*len) > upload.length) {
+        return Err(too_long(current.saturating_add(len)).into());
+    }
+
+    let mut file = fs::OpenOptions::new().append(true).open(&part).await.map_err(|e| write_error(&state, e))?;
+    let mut received: u64 = 0;
+    let appended: Result<()> = async {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            if current + received + chunk.len() as u64 > upload.length {
+                return Err(too_long(current + received + chunk.len() as u64).into());
+            }
+            file.write_all(&chunk).await.map_err(|e| write_error(&state, e))?;
+            received += chunk.len() as u64;
+        }
+        Ok(())
+    }
+    .await;
+    file.flush().await.map_err(|e| write_error(&state, e))?;
+    if durable(&req, &cfg) {
+        file.sync_all().await.map_err(|e| write_error(&state, e))?;
+    }
+    drop(file);
+    // what arrived stays, whether or not the body did
+    appended?;
+
+    let offset = current + received;
+    let mut resp = tus(HttpResponse::NoContent());
+    resp.insert_header((UPLOAD_OFFSET, offset.to_string()));
+    if offset == upload.length {
+        let etag = complete(&req, &state, &cfg, &id, &upload).await?;
+        resp.insert_header((header::ETAG, etag))
+            .insert_header((header::LOCATION, format!("{}/{}", bucket.objects_path(), listing::encode(bucket.api_key(&upload.key)))));
+    } else {
+        expires_header(&mut resp, expires_at(&cfg, now_secs()));
+    }
+    Ok(resp.finish())
+}
+
+/// `DELETE /uploads/{id}`: abandons the upload and its bytes.
+async fn terminate_upload(
+    auth: NeedWrite,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    id: web::Path<IdParam>,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let id = id.into_inner().id;
+    println!("→ DELETE /{}/{}", PATH_UPLOADS, id);
+    let part = resumable::part_path(&state.root, &id).ok_or_else(|| no_such_upload(&id))?;
+    let _guard = state.locks.lock(&part).await;
+    find(&state, &cfg, &bucket, &id).await?;
+    resumable::remove(&state.root, &id).await;
+    Ok(tus(HttpResponse::NoContent()).finish())
+}
@@ -0,0 +1,779 @@
+// src/routes/s3.rs
+//
+// The S3-compatible subset (S3_ENABLED): path-style `/{bucket}/{key}` object
+// PUT/GET/HEAD/DELETE, ListObjects (V1 and V2), ListBuckets and bucket
+// create/HEAD/DELETE over the named buckets, answering in S3's XML, with
+// AWS SigV4 `Authorization` headers checked against the configured auth mode.
+// `layer` translates requests and responses; objects are served by the
+// regular handlers in `routes::objects`; multipart uploads are staged here
+// (`s3::MULTIPART_DIR`) and landed like resumable ones. Presigned URLs,
+// CopyObject, versioning, ACLs and other subresources answer 501.
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::PayloadError,
+    http::header::{self, HeaderName, HeaderValue},
+    http::{Method, StatusCode, Uri},
+    middleware::{self, Next},
+    guard, web, Error, HttpMessage, HttpRequest, HttpResponse, Result,
+};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine as _};
+use futures_util::{Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::pin::Pin;
+use tokio::io::AsyncWriteExt;
+
+use crate::auth::{self, AuthUser, NeedAdmin, NeedList, NeedWrite, SignedCaller};
+use crate::checksum::{CRC32C_HEADER, SHA256_HEADER};
+use crate::consts::{AuthMode, Config};
+use crate::error::ApiError;
+use crate::headers::{self, USER_META_PREFIX};
+use crate::meta::ObjectMeta;
+use crate::pending::now_secs;
+use crate::resumable;
+use crate::routes::buckets;
+use crate::routes::objects::{self, Bucket, DeclaredType};
+use crate::s3::{self, xml_escape};
+use crate::storage::{self, key_of, make_etag};
+use crate::AppState;
+
+const XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+const AMZ_META_PREFIX: &str = "x-amz-meta-";
+
+/// Query parameters an object request may carry: GET's `response-*`
+/// overrides and the multipart ones. The SDKs' `x-id` is dropped.
+const OBJECT_PARAMS: &[&str] = &[
+    "response-content-type", "response-content-disposition", "response-cache-control",
+    "uploads", "uploadId", "partNumber",
+];
+
+/// What `GET /{bucket}` understands: the listing's parameters and `location`.
+const LIST_PARAMS: &[&str] = &[
+    "list-type", "prefix", "delimiter", "max-keys", "continuation-token", "start-after", "marker",
+    "encoding-type", "fetch-owner", "location", "x-id",
+];
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    // registered last: anything the native routes didn't take is S3's
+    cfg.service(
+        web::scope("")
+            .wrap(middleware::from_fn(layer))
+            .service(web::resource("/").route(web::get().to(list_buckets)))
+            .service(
+                web::resource(["/{bucket}", "/{bucket}/"])
+                    .route(web::head().to(head_bucket))
+                    .route(web::get().to(list_objects))
+                    .route(web::put().to(buckets::create_bucket))
+                    .route(web::delete().to(buckets::delete_bucket)),
+            )
+            .service(
+                web::resource("/{bucket}/{key:.+}")
+                    .route(web::post().guard(with_param("uploads")).to(create_multipart))
+                    .route(web::post().guard(with_param("uploadId")).to(complete_multipart))
+                    .route(web::put().guard(with_param("uploadId")).to(upload_part))
+                    .route(web::delete().guard(with_param("uploadId")).to(abort_multipart))
+                    .route(web::put().to(objects::put_object))
+                    .route(web::head().to(objects::head_object))
+                    .route(web::get().to(objects::get_object))
+                    .route(web::delete().to(objects::delete_object)),
+            ),
+    );
+}
+
+/// Matches requests whose query names `param`.
+fn with_param(param: &'static str) -> impl guard::Guard {
+    guard::fn_guard(move |ctx| query_names(ctx.head().uri.query().unwrap_or("")).any(|n| n == param))
+}
+
+/// The (decoded) parameter names in a raw query string.
+fn query_names(query: &str) -> impl Iterator<Item = String> + '_ {
+    query.split('&').filter(|p| !p.is_empty()).map(|p| s3::percent_decode(p.split('=').next().unwrap_or(""), true))
+}
+
+/// An error `layer` answers before the request reaches a handler.
+struct Refusal {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl Refusal {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    fn denied(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", message)
+    }
+
+    fn not_implemented(what: &str) -> Self {
+        Self::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", format!("{what} is not supported"))
+    }
+}
+
+/// An S3 `<Error>` response; HEAD answers carry no body.
+fn error_response(status: StatusCode, code: &str, message: &str, resource: &str, head: bool) -> HttpResponse {
+    let mut resp = HttpResponse::build(status);
+    if head {
+        return resp.finish();
+    }
+    resp.content_type("application/xml").body(s3::error_xml(code, message, resource))
+}
+
+fn xml(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/xml")
+        .body(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{body}"))
+}
+
+/// Translates S3 requests for the native handlers and their answers back.
+async fn layer(mut req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let cfg = req.app_data::<web::Data<Config>>().cloned().ok_or_else(|| actix_web::error::ErrorInternalServerError("Config not found"))?;
+    let state = req.app_data::<web::Data<AppState>>().cloned().ok_or_else(|| actix_web::error::ErrorInternalServerError("AppState not found"))?;
+    let head = req.method() == Method::HEAD;
+    let resource = req.path().to_string();
+    let object = object_path(&resource);
+
+    let prepared = match verify_signature(&req, &cfg, &state).await {
+        Ok(()) if object => translate_request(&mut req),
+        other => other,
+    };
+    if let Err(r) = prepared {
+        return Ok(req.into_response(error_response(r.status, r.code, &r.message, &resource, head)));
+    }
+
+    let method = req.method().clone();
+    let res = next.call(req).await?.map_into_boxed_body();
+    let status = res.status();
+    if status.is_client_error() || status.is_server_error() {
+        return Ok(translate_error(res, object, &method, &resource, head).await);
+    }
+    if !object {
+        return Ok(match (method, status) {
+            // CreateBucket answers 200 with the bucket's Location
+            (Method::PUT, StatusCode::CREATED) => {
+                let (http_req, _) = res.into_parts();
+                ServiceResponse::new(http_req, HttpResponse::Ok().insert_header((header::LOCATION, resource)).finish())
+            }
+            _ => res,
+        });
+    }
+    Ok(translate_response(res, &state, &cfg, &method).await)
+}
+
+/// Whether `path` names an object (`/{bucket}/{key}`) rather than a bucket or the root.
+fn object_path(path: &str) -> bool {
+    path.trim_start_matches('/').split_once('/').is_some_and(|(_, key)| !key.is_empty())
+}
+
+/// Checks an `Authorization: AWS4-HMAC-SHA256 ...` header and leaves the
+/// caller for `auth::authenticate`. Requests without one go on as anonymous,
+/// for the routes' own auth checks to judge. With auth off nothing is checked.
+async fn verify_signature(req: &ServiceRequest, cfg: &Config, state: &AppState) -> Result<(), Refusal> {
+    if cfg.auth_mode == AuthMode::Off {
+        return Ok(());
+    }
+    if req.query_string().contains("X-Amz-Credential=") {
+        return Err(Refusal::denied("presigned URLs are not supported"));
+    }
+    let Some(value) = req.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) else { return Ok(()) };
+    if value.starts_with("AWS ") {
+        return Err(Refusal::denied("only AWS Signature Version 4 is supported"));
+    }
+    if !value.starts_with("AWS4-") {
+        return Ok(());
+    }
+    let malformed = || Refusal::new(StatusCode::BAD_REQUEST, "AuthorizationHeaderMalformed", "malformed AWS4-HMAC-SHA256 Authorization header");
+    let parsed = s3::Authorization::parse(value).ok_or_else(malformed)?;
+    let amz_date = req.headers().get("x-amz-date").and_then(|v| v.to_str().ok()).unwrap_or("");
+    let signed_at = s3::parse_amz_date(amz_date).ok_or_else(|| Refusal::denied("x-amz-date is missing or malformed"))?;
+    if !amz_date.starts_with(&parsed.date) {
+        return Err(malformed());
+    }
+    if now_secs().abs_diff(signed_at) > s3::MAX_SKEW_SECS {
+        return Err(Refusal::new(StatusCode::FORBIDDEN, "RequestTimeTooSkewed", "x-amz-date is too far from the server's time"));
+    }
+    let (user, secret) = auth::s3_credentials(cfg, state, &parsed.access_key)
+        .await
+        .map_err(|_| Refusal::new(StatusCode::FORBIDDEN, "InvalidAccessKeyId", "the access key id is not known"))?;
+    if !cfg.s3_skip_signature {
+        let payload_hash = req.headers().get("x-amz-content-sha256").and_then(|v| v.to_str().ok()).unwrap_or(s3::UNSIGNED_PAYLOAD);
+        let canonical = s3::canonical_request(
+            req.method().as_str(),
+            req.uri().path(),
+            req.query_string(),
+            req.headers(),
+            &parsed.signed_headers,
+            payload_hash,
+        );
+        let expected = s3::signature(&secret, &parsed, amz_date, &canonical);
+        if !s3::signatures_match(&expected, &parsed.signature) {
+            return Err(Refusal::new(StatusCode::FORBIDDEN, "SignatureDoesNotMatch", "the request signature does not match"));
+        }
+    }
+    req.extensions_mut().insert(SignedCaller(user));
+    Ok(())
+}
+
+/// Rewrites an object request into the native API's terms: query, metadata
+/// and checksum headers, and an `aws-chunked` body.
+fn translate_request(req: &mut ServiceRequest) -> Result<(), Refusal> {
+    // keep what the routes understand, refuse other subresources (versionId, tagging, ...)
+    let mut kept = Vec::new();
+    for (pair, name) in req.query_string().split('&').filter(|p| !p.is_empty()).zip(query_names(req.query_string())) {
+        if OBJECT_PARAMS.contains(&name.as_str()) {
+            kept.push(pair.to_string());
+        } else if name != "x-id" && !name.starts_with("X-Amz-") {
+            return Err(Refusal::not_implemented(&format!("`{name}`")));
+        }
+    }
+    // only the multipart calls themselves; ListParts and part GETs aren't served
+    let has = |param: &str| query_names(req.query_string()).any(|n| n == param);
+    let multipart = match *req.method() {
+        Method::POST => true,
+        Method::PUT | Method::DELETE => has("uploadId"),
+        _ => false,
+    };
+    if (has("uploads") || has("uploadId") || has("partNumber")) && !multipart {
+        return Err(Refusal::not_implemented("this multipart request"));
+    }
+    let path = req.uri().path().to_string();
+    let uri = if kept.is_empty() { path } else { format!("{path}?{}", kept.join("&")) };
+    req.head_mut().uri = uri.parse::<Uri>().map_err(|_| Refusal::new(StatusCode::BAD_REQUEST, "InvalidURI", "invalid request URI"))?;
+
+    if req.method() != Method::PUT && req.method() != Method::POST {
+        return Ok(());
+    }
+    if req.headers().contains_key("x-amz-copy-source") {
+        return Err(Refusal::not_implemented("CopyObject"));
+    }
+    let mut renamed = Vec::new();
+    for (name, value) in req.headers().iter() {
+        if let Some(field) = name.as_str().strip_prefix(AMZ_META_PREFIX) {
+            renamed.push((format!("{USER_META_PREFIX}{field}"), value.clone()));
+        }
+    }
+    let declared = req.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let payload_hash = req.headers().get("x-amz-content-sha256").and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+    let crc32c = req.headers().get("x-amz-checksum-crc32c").and_then(|v| v.to_str().ok()).map(str::to_string);
+    let encodings: Vec<String> = req
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').map(|e| e.trim().to_string()).filter(|e| !e.is_empty()).collect())
+        .unwrap_or_default();
+    let decoded_length = req.headers().get("x-amz-decoded-content-length").cloned();
+
+    let headers = req.headers_mut();
+    for (name, value) in renamed {
+        if let Ok(name) = HeaderName::try_from(name) {
+            headers.insert(name, value);
+        }
+    }
+    if payload_hash.len() == 64 && payload_hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        headers.insert(HeaderName::from_static(SHA256_HEADER), HeaderValue::from_str(&payload_hash).expect("hex is a valid header value"));
+    }
+    if let Some(crc) = crc32c.and_then(|v| STANDARD.decode(v.trim()).ok()).filter(|b| b.len() == 4) {
+        headers.insert(HeaderName::from_static(CRC32C_HEADER), HeaderValue::from_str(&hex::encode(crc)).expect("hex is a valid header value"));
+    }
+    let chunked = encodings.iter().any(|e| e.eq_ignore_ascii_case("aws-chunked")) || payload_hash.starts_with("STREAMING-");
+    if chunked {
+        let rest: Vec<&str> = encodings.iter().map(String::as_str).filter(|e| !e.eq_ignore_ascii_case("aws-chunked")).collect();
+        match HeaderValue::from_str(&rest.join(", ")) {
+            Ok(v) if !rest.is_empty() => headers.insert(header::CONTENT_ENCODING, v),
+            _ => headers.remove(header::CONTENT_ENCODING),
+        };
+        match decoded_length {
+            Some(len) => headers.insert(header::CONTENT_LENGTH, len),
+            None => headers.remove(header::CONTENT_LENGTH),
+        };
+        let body = s3::decode_chunked(req.take_payload(), || PayloadError::EncodingCorrupted);
+        let body: Pin<Box<dyn Stream<Item = Result<web::Bytes, PayloadError>>>> = Box::pin(body);
+        req.set_payload(Payload::from(body));
+    }
+    if let Some(t) = declared.filter(|t| t.parse::<mime_guess::mime::Mime>().is_ok()) {
+        req.extensions_mut().insert(DeclaredType(t));
+    }
+    Ok(())
+}
+
+/// Object answers as S3 gives them: PUT (an object or a part) is a bare 200
+/// with the ETag, DELETE always 204, GET/HEAD gain Last-Modified and
+/// `x-amz-meta-*`; the multipart POSTs answer for themselves. ETags are
+/// strong, as S3 clients compare them byte for byte.
+async fn translate_response(mut res: ServiceResponse<BoxBody>, state: &AppState, cfg: &Config, method: &Method) -> ServiceResponse<BoxBody> {
+    let etag = res
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|e| e.trim_start_matches("W/").to_string());
+    match *method {
+        Method::PUT => {
+            let (http_req, _) = res.into_parts();
+            let mut resp = HttpResponse::Ok();
+            if let Some(etag) = etag {
+                resp.insert_header((header::ETAG, etag));
+            }
+            ServiceResponse::new(http_req, resp.finish())
+        }
+        Method::DELETE => {
+            let (http_req, _) = res.into_parts();
+            ServiceResponse::new(http_req, HttpResponse::NoContent().finish())
+        }
+        Method::POST => res,
+        _ => {
+            // the handler left its caller behind; a namespaced one's key lies in their jail
+            let jail = res.request().extensions().get::<AuthUser>().and_then(|u| auth::jail(cfg, u).ok().flatten());
+            let modified = match (res.request().match_info().get("bucket"), res.request().match_info().get("key")) {
+                (Some(b), Some(k)) => match objects::resolve_key(&state.root, &match jail {
+                    Some(j) => format!("{b}/{j}/{k}"),
+                    None => format!("{b}/{k}"),
+                }) {
+                    Some(path) => tokio::fs::metadata(path).await.ok().and_then(|m| m.modified().ok()),
+                    None => None,
+                },
+                _ => None,
+            };
+            let headers = res.headers_mut();
+            if let Some(etag) = etag.and_then(|e| HeaderValue::from_str(&e).ok()) {
+                headers.insert(header::ETAG, etag);
+            }
+            if let Some(modified) = modified.filter(|_| !headers.contains_key(header::LAST_MODIFIED)) {
+                if let Ok(v) = HeaderValue::from_str(&header::HttpDate::from(modified).to_string()) {
+                    headers.insert(header::LAST_MODIFIED, v);
+                }
+            }
+            let user: Vec<(String, HeaderValue)> = headers
+                .iter()
+                .filter_map(|(n, v)| n.as_str().strip_prefix(USER_META_PREFIX).map(|f| (format!("{AMZ_META_PREFIX}{f}"), v.clone())))
+                .collect();
+            for (name, value) in user {
+                if let Ok(name) = HeaderName::try_from(name) {
+                    headers.insert(name, value);
+                }
+            }
+            res
+        }
+    }
+}
+
+/// Replaces a native error body (`{"error": code, "message": ...}` or plain
+/// text) with S3's XML. A DELETE of a missing key succeeds, as on S3.
+async fn translate_error(res: ServiceResponse<BoxBody>, object: bool, method: &Method, resource: &str, head: bool) -> ServiceResponse<BoxBody> {
+    let (http_req, res) = res.into_parts();
+    let status = res.status();
+    let xml = res.headers().get(header::CONTENT_TYPE).is_some_and(|v| v.as_bytes().starts_with(b"application/xml"));
+    let body = actix_web::body::to_bytes(res.into_body()).await.unwrap_or_default();
+    let parsed: Option<serde_json::Value> = serde_json::from_slice(&body).ok();
+    let native = parsed.as_ref().and_then(|v| v["error"].as_str()).unwrap_or("");
+    if object && *method == Method::DELETE && status == StatusCode::NOT_FOUND && !matches!(native, "no_such_bucket" | "NoSuchUpload") {
+        return ServiceResponse::new(http_req, HttpResponse::NoContent().finish());
+    }
+    if xml {
+        let resp = HttpResponse::build(status).content_type("application/xml").body(body);
+        return ServiceResponse::new(http_req, resp);
+    }
+    let message = match parsed.as_ref().and_then(|v| v["message"].as_str()) {
+        Some(m) => m.to_string(),
+        None => String::from_utf8_lossy(&body).into_owned(),
+    };
+    let (status, code) = s3_error_code(status, native);
+    ServiceResponse::new(http_req, error_response(status, code, &message, resource, head))
+}
+
+/// The S3 status and code for a native error. Codes the S3 handlers raise
+/// themselves are already S3's.
+fn s3_error_code(status: StatusCode, native: &str) -> (StatusCode, &str) {
+    if native.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return (status, native);
+    }
+    match (native, status.as_u16()) {
+        ("no_such_bucket", _) => (status, "NoSuchBucket"),
+        ("bucket_exists", _) => (status, "BucketAlreadyOwnedByYou"),
+        ("name_in_use", _) => (status, "BucketAlreadyExists"),
+        ("invalid_bucket_name", _) => (status, "InvalidBucketName"),
+        ("bucket_not_empty", _) => (status, "BucketNotEmpty"),
+        ("checksum_mismatch", _) => (StatusCode::BAD_REQUEST, "BadDigest"),
+        ("incomplete_body", _) => (status, "IncompleteBody"),
+        ("is_prefix", _) => (StatusCode::NOT_FOUND, "NoSuchKey"),
+        (_, 401 | 403 | 423) => (StatusCode::FORBIDDEN, "AccessDenied"),
+        (_, 404) => (status, "NoSuchKey"),
+        (_, 405) => (status, "MethodNotAllowed"),
+        (_, 411) => (status, "MissingContentLength"),
+        (_, 412) => (status, "PreconditionFailed"),
+        (_, 413) => (status, "EntityTooLarge"),
+        (_, 416) => (status, "InvalidRange"),
+        (_, 501) => (status, "NotImplemented"),
+        (_, 503) => (status, "ServiceUnavailable"),
+        (_, 400..=499) => (status, "InvalidArgument"),
+        _ => (status, "InternalError"),
+    }
+}
+
+/// `GET /`: ListBuckets, the named buckets (admin, like `GET /b`).
+async fn list_buckets(_auth: NeedAdmin, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let st = state.get_ref().clone();
+    let buckets = web::block(move || {
+        st.buckets
+            .names()
+            .into_iter()
+            .map(|name| {
+                let created = std::fs::metadata(st.root.join(&name))
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                (name, created)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    let mut body = format!("<ListAllMyBucketsResult xmlns=\"{XMLNS}\"><Owner><ID>rust-buck3t</ID><DisplayName>rust-buck3t</DisplayName></Owner><Buckets>");
+    for (name, created) in buckets {
+        body.push_str(&format!(
+            "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+            xml_escape(&name),
+            s3::iso8601(created)
+        ));
+    }
+    body.push_str("</Buckets></ListAllMyBucketsResult>");
+    Ok(xml(body))
+}
+
+/// `HEAD /{bucket}`: 200 if the bucket exists.
+async fn head_bucket(_auth: NeedList, _bucket: Bucket) -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+fn invalid_argument(message: impl Into<String>) -> ApiError {
+    ApiError::bad_request("InvalidArgument", message)
+}
+
+/// A listing entry: an object, or a common prefix standing for every key
+/// that continues past the delimiter.
+enum Entry<'a> {
+    Object(&'a objects::ListedObject),
+    Prefix(String),
+}
+
+impl Entry<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Entry::Object(o) => &o.key,
+            Entry::Prefix(p) => p,
+        }
+    }
+}
+
+/// `GET /{bucket}`: ListObjectsV2 (`list-type=2`) or V1, and `?location`.
+async fn list_objects(
+    auth: NeedList,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let pairs = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .map_err(|_| invalid_argument("malformed query string"))?
+        .into_inner();
+    let mut q = BTreeMap::new();
+    for (name, value) in pairs {
+        if !LIST_PARAMS.contains(&name.as_str()) {
+            return Err(ApiError::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", format!("`{name}` is not supported")).into());
+        }
+        q.insert(name, value);
+    }
+    if q.contains_key("location") {
+        // empty means us-east-1, which any region a client signs for is fine with
+        return Ok(xml(format!("<LocationConstraint xmlns=\"{XMLNS}\"/>")));
+    }
+    let v2 = match q.get("list-type").map(String::as_str) {
+        None => false,
+        Some("2") => true,
+        Some(_) => return Err(invalid_argument("list-type must be 2").into()),
+    };
+    let prefix = q.get("prefix").cloned().unwrap_or_default();
+    let delimiter = q.get("delimiter").cloned().unwrap_or_default();
+    let max_keys = match q.get("max-keys") {
+        None => 1000,
+        Some(v) => v.parse::<usize>().map_err(|_| invalid_argument("max-keys must be a non-negative integer"))?.min(1000),
+    };
+    let url = match q.get("encoding-type").map(String::as_str) {
+        None => false,
+        Some("url") => true,
+        Some(_) => return Err(invalid_argument("encoding-type must be url").into()),
+    };
+    // continuation tokens are the last name returned, opaque to clients
+    let token = match q.get("continuation-token").filter(|_| v2) {
+        None => None,
+        Some(t) => Some(
+            URL_SAFE_NO_PAD
+                .decode(t)
+                .ok()
+                .and_then(|b| String::from_utf8(b).ok())
+                .ok_or_else(|| invalid_argument("invalid continuation-token"))?,
+        ),
+    };
+    let start = if v2 { q.get("start-after") } else { q.get("marker") };
+    let after = token.clone().or_else(|| start.cloned()).unwrap_or_default();
+
+    let objects = objects::list_all(&state, &cfg.key_rules, &bucket, &prefix)
+        .await
+        .ok_or_else(|| invalid_argument("invalid prefix"))?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut entries: Vec<Entry> = Vec::new();
+    for o in &objects {
+        let rest = &o.key[prefix.len()..];
+        match rest.find(delimiter.as_str()).filter(|_| !delimiter.is_empty()) {
+            Some(i) => {
+                let common = &o.key[..prefix.len() + i + delimiter.len()];
+                if !matches!(entries.last(), Some(Entry::Prefix(p)) if p == common) {
+                    entries.push(Entry::Prefix(common.to_string()));
+                }
+            }
+            None => entries.push(Entry::Object(o)),
+        }
+    }
+    let mut remaining = entries.iter().filter(|e| after.is_empty() || e.name() > after.as_str()).peekable();
+    let page: Vec<&Entry> = remaining.by_ref().take(max_keys).collect();
+    let truncated = max_keys > 0 && remaining.peek().is_some();
+    let next = page.last().map(|e| e.name().to_string()).filter(|_| truncated);
+
+    let enc = |s: &str| xml_escape(&if url { s3::uri_encode(s, true) } else { s.to_string() });
+    let mut body = format!("<ListBucketResult xmlns=\"{XMLNS}\">");
+    body.push_str(&format!("<Name>{}</Name>", xml_escape(req.match_info().get("bucket").unwrap_or(""))));
+    body.push_str(&format!("<Prefix>{}</Prefix>", enc(&prefix)));
+    if !delimiter.is_empty() {
+        body.push_str(&format!("<Delimiter>{}</Delimiter>", enc(&delimiter)));
+    }
+    body.push_str(&format!("<MaxKeys>{max_keys}</MaxKeys><IsTruncated>{truncated}</IsTruncated>"));
+    if v2 {
+        body.push_str(&format!("<KeyCount>{}</KeyCount>", page.len()));
+        if let Some(t) = q.get("continuation-token") {
+            body.push_str(&format!("<ContinuationToken>{}</ContinuationToken>", xml_escape(t)));
+        }
+        if let Some(next) = &next {
+            body.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", URL_SAFE_NO_PAD.encode(next)));
+        }
+        if let Some(s) = start {
+            body.push_str(&format!("<StartAfter>{}</StartAfter>", enc(s)));
+        }
+    } else {
+        body.push_str(&format!("<Marker>{}</Marker>", enc(start.map(String::as_str).unwrap_or(""))));
+        if let Some(next) = &next {
+            body.push_str(&format!("<NextMarker>{}</NextMarker>", enc(next)));
+        }
+    }
+    if url {
+        body.push_str("<EncodingType>url</EncodingType>");
+    }
+    for entry in &page {
+        match entry {
+            Entry::Object(o) => body.push_str(&format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                enc(&o.key),
+                s3::iso8601(o.modified),
+                xml_escape(o.etag.trim_start_matches("W/")),
+                o.size
+            )),
+            Entry::Prefix(p) => body.push_str(&format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", enc(p))),
+        }
+    }
+    body.push_str("</ListBucketResult>");
+    Ok(xml(body))
+}
+
+/// The upload (and its id) a multipart request names, if it's for this key.
+async fn named_upload(req: &HttpRequest, state: &AppState, storage_key: &str) -> Result<(String, s3::MultipartUpload)> {
+    let id = query_value(req, "uploadId").unwrap_or_default();
+    let upload = s3::load_upload(&state.root, &id).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    match upload {
+        Some(upload) if upload.key == storage_key => Ok((id, upload)),
+        _ => Err(ApiError::new(StatusCode::NOT_FOUND, "NoSuchUpload", "the multipart upload does not exist").into()),
+    }
+}
+
+fn query_value(req: &HttpRequest, name: &str) -> Option<String> {
+    web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .ok()?
+        .into_inner()
+        .into_iter()
+        .find_map(|(n, v)| (n == name).then_some(v))
+}
+
+/// The object path for `key` in `bucket`, as the object handlers resolve it.
+fn object_target(state: &AppState, cfg: &Config, bucket: &Bucket, key: &str) -> Result<PathBuf> {
+    objects::check_key(cfg, key)?;
+    Ok(bucket.resolve(state, key).ok_or_else(|| invalid_argument("invalid key"))?)
+}
+
+/// `POST /{bucket}/{key}?uploads`: CreateMultipartUpload. Content-Type and
+/// `x-amz-meta-*` are taken now and applied when the upload completes.
+async fn create_multipart(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    key: web::Path<objects::KeyParam>,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let path = object_target(&state, &cfg, &bucket, &key.key)?;
+    let mut user = headers::user_meta_from_request(req.headers()).map_err(|_| invalid_argument("invalid x-amz-meta-* header"))?;
+    user.retain(|_, v| !v.is_empty());
+    let upload = s3::MultipartUpload {
+        key: key_of(&state.root, &path),
+        content_type: req.extensions().get::<DeclaredType>().map(|t| t.0.clone()),
+        user,
+        created_at: now_secs(),
+    };
+    let id = resumable::new_id();
+    s3::create_upload(&state.root, &id, &upload).await.map_err(|e| objects::write_error(&state, e))?;
+    Ok(xml(format!(
+        "<InitiateMultipartUploadResult xmlns=\"{XMLNS}\"><Bucket>{}</Bucket><Key>{}</Key><UploadId>{id}</UploadId></InitiateMultipartUploadResult>",
+        xml_escape(req.match_info().get("bucket").unwrap_or("")),
+        xml_escape(&key.key)
+    )))
+}
+
+/// `PUT /{bucket}/{key}?partNumber=N&uploadId=ID`: UploadPart. A part
+/// replaces any earlier one with its number; its ETag is its SHA-256.
+async fn upload_part(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    key: web::Path<objects::KeyParam>,
+    mut body: web::Payload,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let path = object_target(&state, &cfg, &bucket, &key.key)?;
+    let (id, _) = named_upload(&req, &state, &key_of(&state.root, &path)).await?;
+    let dest = query_value(&req, "partNumber")
+        .and_then(|n| n.parse::<u32>().ok())
+        .and_then(|n| s3::part_path(&state.root, &id, n))
+        .ok_or_else(|| invalid_argument(format!("partNumber must be 1 to {}", s3::MAX_PART_NUMBER)))?;
+    let declared = req.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
+
+    let (tmp, mut file) = storage::TempUpload::create(&state.root).await.map_err(|e| objects::write_error(&state, e))?;
+    let mut hasher = Sha256::new();
+    let mut received: u64 = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if cfg.max_upload_bytes.is_some_and(|limit| received > limit) {
+            return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "EntityTooLarge", "part exceeds MAX_UPLOAD_BYTES").into());
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(|e| objects::write_error(&state, e))?;
+    }
+    if declared.is_some_and(|len| received < len) {
+        return Err(ApiError::bad_request("IncompleteBody", "request body ended before Content-Length bytes").into());
+    }
+    let sha = hex::encode(hasher.finalize());
+    if req.headers().get(SHA256_HEADER).is_some_and(|v| !v.as_bytes().eq_ignore_ascii_case(sha.as_bytes())) {
+        return Err(ApiError::bad_request("BadDigest", "body does not match x-amz-content-sha256").into());
+    }
+    file.flush().await.map_err(|e| objects::write_error(&state, e))?;
+    drop(file);
+    tmp.persist(&dest, false).await.map_err(|e| objects::write_error(&state, e))?;
+    Ok(HttpResponse::Ok().insert_header((header::ETAG, format!("\"{sha}\""))).finish())
+}
+
+/// `POST /{bucket}/{key}?uploadId=ID`: CompleteMultipartUpload. The parts
+/// the body lists (by `<PartNumber>`, ascending) are joined and landed as
+/// one object; the upload is then gone.
+async fn complete_multipart(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    key: web::Path<objects::KeyParam>,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let path = object_target(&state, &cfg, &bucket, &key.key)?;
+    let (id, upload) = named_upload(&req, &state, &key_of(&state.root, &path)).await?;
+    let numbers = part_numbers(&String::from_utf8_lossy(&body))
+        .ok_or_else(|| ApiError::bad_request("MalformedXML", "the body does not list the parts to complete"))?;
+    if numbers.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(ApiError::bad_request("InvalidPartOrder", "parts must be listed in ascending order").into());
+    }
+    let mut parts = Vec::with_capacity(numbers.len());
+    let mut total: u64 = 0;
+    for n in numbers {
+        let part = s3::part_path(&state.root, &id, n)
+            .ok_or_else(|| ApiError::bad_request("InvalidPart", format!("part {n} is out of range")))?;
+        let meta = tokio::fs::metadata(&part)
+            .await
+            .map_err(|_| ApiError::bad_request("InvalidPart", format!("part {n} was not uploaded")))?;
+        total += meta.len();
+        parts.push(part);
+    }
+    if cfg.max_upload_bytes.is_some_and(|limit| total > limit) {
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "EntityTooLarge", "the parts together exceed MAX_UPLOAD_BYTES").into());
+    }
+
+    let durable = objects::durable(&req, &cfg);
+    let root = state.root.clone();
+    let staged = web::block(move || {
+        let (tmp, mut file) = storage::TempUpload::create_blocking(&root)?;
+        for part in &parts {
+            std::io::copy(&mut std::fs::File::open(part)?, &mut file)?;
+        }
+        if durable {
+            file.sync_all()?;
+        }
+        Ok::<_, std::io::Error>(tmp)
+    })
+    .await?
+    .map_err(|e| objects::write_error(&state, e))?;
+    let object = ObjectMeta { content_type: upload.content_type, user: upload.user, ..Default::default() };
+    // `land` renames the staged file away; dropping the guard only cleans up after a failure
+    let (stored, _) = objects::land(&state, &cfg, staged.path(), &path, durable, object).await?;
+    drop(staged);
+    s3::remove_upload(&state.root, &id).await;
+    Ok(xml(format!(
+        "<CompleteMultipartUploadResult xmlns=\"{XMLNS}\"><Location>{}</Location><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+        xml_escape(req.path()),
+        xml_escape(req.match_info().get("bucket").unwrap_or("")),
+        xml_escape(&key.key),
+        xml_escape(make_etag(&stored).trim_start_matches("W/"))
+    )))
+}
+
+/// The `<PartNumber>`s of a CompleteMultipartUpload body, in order; `None`
+/// if there are none or one isn't a number.
+fn part_numbers(body: &str) -> Option<Vec<u32>> {
+    let mut numbers = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("<PartNumber>") {
+        rest = &rest[start + "<PartNumber>".len()..];
+        let end = rest.find("</PartNumber>")?;
+        numbers.push(rest[..end].trim().parse().ok()?);
+        rest = &rest[end..];
+    }
+    (!numbers.is_empty()).then_some(numbers)
+}
+
+/// `DELETE /{bucket}/{key}?uploadId=ID`: AbortMultipartUpload.
+async fn abort_multipart(
+    auth: NeedWrite,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    bucket: Bucket,
+    key: web::Path<objects::KeyParam>,
+) -> Result<HttpResponse> {
+    let bucket = bucket.jailed(&cfg, &auth.0)?;
+    let path = object_target(&state, &cfg, &bucket, &key.key)?;
+    let (id, _) = named_upload(&req, &state, &key_of(&state.root, &path)).await?;
+    s3::remove_upload(&state.root, &id).await;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,57 @@
+// src/routes/search.rs
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+
+use crate::auth::{self, NeedList};
+use crate::consts::{Config, PATH_SEARCH};
+use crate::error::ApiError;
+use crate::routes::query::QueryParams;
+use crate::search::{self, Matcher, PatternError, MAX_PATTERN_BYTES};
+use crate::AppState;
+
+/// Results per page without `limit`, and the most one may ask for.
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route(format!("/{}", PATH_SEARCH).as_str(), web::get().to(search_keys));
+}
+
+/// `?q=` with `mode=substring` (default), `glob` or `regex` over whole keys,
+/// `ignore_case=1`, `limit` (default 100, at most 1000) and `after=<key>`
+/// from the previous page's `next`.
+async fn search_keys(auth: NeedList, req: HttpRequest, state: web::Data<AppState>, cfg: web::Data<Config>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_SEARCH);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["q", "mode", "ignore_case", "limit", "after"])?;
+    let pattern = q.str("q").filter(|p| !p.is_empty()).ok_or_else(|| {
+        ApiError::bad_request("missing_query", "`q` is required").with("param", "q")
+    })?;
+    let ignore_case = q.bool("ignore_case")?.unwrap_or(false);
+    let matcher = match Matcher::new(q.str("mode").unwrap_or("substring"), pattern, ignore_case) {
+        None => {
+            return Err(ApiError::bad_request("invalid_query_param", "`mode` must be substring, glob or regex")
+                .with("param", "mode")
+                .into())
+        }
+        Some(Err(PatternError::TooLong)) => {
+            return Err(ApiError::bad_request("pattern_too_long", "the search pattern is too long")
+                .with("limit", MAX_PATTERN_BYTES)
+                .into())
+        }
+        Some(Err(PatternError::Invalid(why))) => return Err(ApiError::bad_request("invalid_pattern", why).into()),
+        Some(Ok(m)) => m,
+    };
+    let limit = q.u64("limit")?.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::bad_request("invalid_query_param", format!("`limit` must be 1 to {MAX_LIMIT}"))
+            .with("param", "limit")
+            .into());
+    }
+    let after = q.str("after").map(str::to_string);
+
+    let st = state.get_ref().clone();
+    let page = web::block(move || search::search(&st, &matcher, after.as_deref(), limit as usize))
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(page))
+}
@@ -1,40 +1,125 @@
 // src/routes/session.rs
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, Either, HttpRequest, HttpResponse, Result};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
-use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use std::path::{Path, PathBuf};
 
-use crate::AppState;
-use crate::consts::{Config, AuthMode};
+use crate::{auth, storage, throttle, AppState};
+use crate::auth::NeedAdmin;
+use crate::consts::{Config, AuthMode, SignupMode};
+use crate::error::ApiError;
 
-pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+/// Caps applied before any credential work (hashing, file I/O).
+const MAX_USERNAME_LEN: usize = 64;
+const MAX_PASSWORD_LEN: usize = 256;
+/// Invite lifetime when `POST /auth/invites` doesn't ask for one.
+const DEFAULT_INVITE_TTL_SECS: u64 = 7 * 24 * 3600;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig, json_limit: usize) {
     cfg.service(
         web::scope("/auth")
+            .app_data(json_config(json_limit))
             .route("/signup", web::post().to(signup))
+            .route("/invites", web::post().to(mint_invite))
+            .route("/introspect", web::post().to(introspect))
             .route("/login",  web::post().to(login))
             .route("/logout", web::post().to(logout)),
     );
 }
 
+/// Small JSON bodies only, and only with `Content-Type: application/json`.
+fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .content_type(|mime| mime.essence_str() == "application/json")
+        .content_type_required(true)
+        .error_handler(|err, _req| {
+            use actix_web::error::JsonPayloadError;
+            use actix_web::http::StatusCode;
+            let api = match &err {
+                JsonPayloadError::ContentType => ApiError::new(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "unsupported_media_type",
+                    "Content-Type must be application/json",
+                ),
+                JsonPayloadError::Overflow { limit } | JsonPayloadError::OverflowKnownLength { limit, .. } => {
+                    ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", format!("body exceeds {limit} bytes"))
+                        .with("limit", *limit)
+                }
+                _ => ApiError::bad_request("invalid_json", err.to_string()),
+            };
+            api.into()
+        })
+}
+
+fn check_credential_lengths(username: &str, password: &str) -> Result<(), ApiError> {
+    if username.len() > MAX_USERNAME_LEN {
+        return Err(ApiError::bad_request("field_too_long", format!("username exceeds {MAX_USERNAME_LEN} bytes"))
+            .with("param", "username"));
+    }
+    if password.len() > MAX_PASSWORD_LEN {
+        return Err(ApiError::bad_request("field_too_long", format!("password exceeds {MAX_PASSWORD_LEN} bytes"))
+            .with("param", "password"));
+    }
+    Ok(())
+}
+
 /* ---------- storage (dev-only, JSON file) ---------- */
 
 #[derive(Serialize, Deserialize, Clone)]
 struct StoredUser {
     username: String,
-    // NOTE: dev-only — plaintext to keep deps minimal.
-    // Replace with argon2/bcrypt before prod.
-    password: String,
+    /// argon2id PHC string (`$argon2id$v=19$...`), salt included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_hash: Option<String>,
+    /// Legacy plaintext from older files. Kept until the user's next login
+    /// replaces it with a hash; never set for new records.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    /// Scopes granted in basic mode (BASIC_SCOPES when absent) and the most a
+    /// login may ask for (the configured write/read/list sets when absent).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scopes: Option<Vec<String>>,
+}
+
+enum Verdict {
+    Valid,
+    /// Matched a legacy plaintext entry; the caller should store a hash.
+    ValidLegacy,
+    Invalid,
 }
 
-fn users_path() -> PathBuf {
-    // Keep users out of the bucket. Override with AUTH_USER_DB if you like.
-    // Defaults to ./auth/users.json
-    let p = std::env::var("AUTH_USER_DB").unwrap_or_else(|_| "./auth/users.json".into());
-    PathBuf::from(p)
+/// argon2id with a fresh random salt. CPU-heavy: call through `web::block`.
+fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
 }
 
-async fn load_users(path: &PathBuf) -> Result<Vec<StoredUser>> {
+/// Checks `password` against the stored hash, or the legacy plaintext. CPU-heavy too.
+fn verify_password(user: &StoredUser, password: &str) -> Verdict {
+    if let Some(hash) = &user.password_hash {
+        let ok = PasswordHash::new(hash)
+            .map(|h| Argon2::default().verify_password(password.as_bytes(), &h).is_ok())
+            .unwrap_or(false);
+        return if ok { Verdict::Valid } else { Verdict::Invalid };
+    }
+    match &user.password {
+        Some(plain) if plain == password => Verdict::ValidLegacy,
+        _ => Verdict::Invalid,
+    }
+}
+
+async fn hash_blocking(password: String) -> Result<String> {
+    web::block(move || hash_password(&password))
+        .await?
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))
+}
+
+async fn load_users(path: &Path) -> Result<Vec<StoredUser>> {
     match fs::read(path).await {
         Ok(bytes) => {
             let users: Vec<StoredUser> = serde_json::from_slice(&bytes)
@@ -46,7 +131,9 @@ async fn load_users(path: &PathBuf) -> Result<Vec<StoredUser>> {
     }
 }
 
-async fn save_users(path: &PathBuf, users: &[StoredUser]) -> Result<()> {
+/// Writes the user store; `durable` (DURABLE_WRITES) fsyncs the file and
+/// its directory before returning.
+async fn save_users(path: &Path, users: &[StoredUser], durable: bool) -> Result<()> {
     let bytes = serde_json::to_vec_pretty(users)
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -56,8 +143,18 @@ async fn save_users(path: &PathBuf, users: &[StoredUser]) -> Result<()> {
             .map_err(actix_web::error::ErrorInternalServerError)?;
     }
 
-    fs::write(path, bytes).await
+    let mut file = fs::File::create(path).await
         .map_err(actix_web::error::ErrorInternalServerError)?;
+    file.write_all(&bytes).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    file.flush().await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if durable {
+        file.sync_all().await.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(dir) = path.parent() {
+            storage::sync_dir(dir).await.map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+    }
     Ok(())
 }
 
@@ -67,6 +164,41 @@ async fn save_users(path: &PathBuf, users: &[StoredUser]) -> Result<()> {
 struct SignupReq {
     username: String,
     password: String,
+    /// One-time code; required under SIGNUP_MODE=invite.
+    invite: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InviteReq {
+    /// Optional: seconds until the code expires (default 7 days)
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct IntrospectReq {
+    token: String,
+}
+
+/// RFC 7662 response: only `active` for a token that isn't.
+#[derive(Serialize)]
+struct IntrospectResp {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aud: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct InviteResp {
+    code: String,
+    expires_at: u64,
 }
 
 #[derive(Deserialize)]
@@ -97,35 +229,63 @@ struct Claims {
     iss: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     aud: Option<String>,
+    jti: String,                // revocable via /auth/logout
 }
 
 /* ---------- handlers ---------- */
 
 async fn signup(
-    _state: web::Data<AppState>, // unused here now
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
     req: web::Json<SignupReq>,
 ) -> Result<HttpResponse> {
-    let path = users_path();
-    let mut users = load_users(&path).await?;
-
-    if users.iter().any(|u| u.username == req.username) {
+    use actix_web::http::StatusCode;
+    check_credential_lengths(&req.username, &req.password)?;
+    let invite_refused = || ApiError::new(StatusCode::FORBIDDEN, "invalid_invite", "invite code is unknown, used or expired");
+    match cfg.signup_mode {
+        SignupMode::Open => {}
+        SignupMode::Closed => {
+            return Err(ApiError::new(StatusCode::FORBIDDEN, "signup_closed", "signup is disabled on this server").into());
+        }
+        SignupMode::Invite => match req.invite.as_deref() {
+            None => return Err(ApiError::new(StatusCode::FORBIDDEN, "invite_required", "signup needs an invite code").into()),
+            // checked again (and used up) once the account is about to be written
+            Some(code) if !state.invites.is_valid(code) => return Err(invite_refused().into()),
+            Some(_) => {}
+        },
+    }
+    let path = PathBuf::from(&cfg.auth_user_db);
+    if load_users(&path).await?.iter().any(|u| u.username == req.username) {
         return Err(actix_web::error::ErrorConflict("username already exists"));
     }
 
-    users.push(StoredUser {
-        username: req.username.clone(),
-        password: req.password.clone(),
-    });
+    let SignupReq { username, password, invite } = req.into_inner();
+    let password_hash = hash_blocking(password).await?;
 
-    save_users(&path, &users).await?;
+    // re-read: hashing yields the worker, so another signup may have landed meanwhile
+    let mut users = load_users(&path).await?;
+    if users.iter().any(|u| u.username == username) {
+        return Err(actix_web::error::ErrorConflict("username already exists"));
+    }
+    if cfg.signup_mode == SignupMode::Invite {
+        let code = invite.unwrap_or_default();
+        if !state.invites.consume(&code).await.map_err(actix_web::error::ErrorInternalServerError)? {
+            return Err(invite_refused().into());
+        }
+    }
+    users.push(StoredUser { username, password_hash: Some(password_hash), password: None, scopes: None });
+
+    save_users(&path, &users, cfg.durable_writes).await?;
     Ok(HttpResponse::Created().finish())
 }
 
 async fn login(
-    _state: web::Data<AppState>, // not needed for user storage anymore
+    http: HttpRequest,
+    state: web::Data<AppState>,
     cfg: web::Data<Config>,
     req: web::Json<LoginReq>,
 ) -> Result<HttpResponse> {
+    check_credential_lengths(&req.username, &req.password)?;
     if !matches!(cfg.auth_mode, AuthMode::JwtHs256) {
         return Err(actix_web::error::ErrorBadRequest("login available only in HS256 mode"));
     }
@@ -133,30 +293,39 @@ async fn login(
         .ok_or_else(|| actix_web::error::ErrorInternalServerError("JWT_HS_SECRET not set"))?
         .clone();
 
+    let attempt = throttle::Attempt::new(&http, &cfg, &req.username);
+    attempt.check(&state.login_throttle)?;
+
     // verify credentials
-    let path = users_path();
+    let path = PathBuf::from(&cfg.auth_user_db);
     let users = load_users(&path).await?;
-    let Some(user) = users.into_iter().find(|u| u.username == req.username) else {
-        return Err(actix_web::error::ErrorUnauthorized("invalid credentials"));
+    let user = users.into_iter().find(|u| u.username == req.username);
+    let verdict = match user {
+        Some(user) => {
+            let candidate = req.password.clone();
+            let (user, verdict) = web::block(move || {
+                let verdict = verify_password(&user, &candidate);
+                (user, verdict)
+            })
+            .await?;
+            Some((user, verdict))
+        }
+        None => None,
     };
-    if user.password != req.password {
-        return Err(actix_web::error::ErrorUnauthorized("invalid credentials"));
-    }
-
-    // scopes: requested or default to the configured sets
-    let scope = req.scope.clone().unwrap_or_else(|| {
-        let mut s = Vec::new();
-        if !cfg.jwt_scopes_write.is_empty() { s.extend(cfg.jwt_scopes_write.clone()); }
-        if !cfg.jwt_scopes_read.is_empty()  { s.extend(cfg.jwt_scopes_read.clone()); }
-        if !cfg.jwt_scopes_list.is_empty()  { s.extend(cfg.jwt_scopes_list.clone()); }
-        if s.is_empty() {
-            "obj:write obj:read obj:list".to_string()
-        } else {
-            s.sort();
-            s.dedup();
-            s.join(" ")
+    let user = match verdict {
+        Some((user, Verdict::Valid)) => user,
+        Some((user, Verdict::ValidLegacy)) => {
+            upgrade_legacy(&path, &user.username, req.password.clone(), cfg.durable_writes).await?;
+            user
+        }
+        Some((_, Verdict::Invalid)) | None => {
+            attempt.failed(&state.login_throttle, &cfg).await?;
+            return Err(actix_web::error::ErrorUnauthorized("invalid credentials"));
         }
-    });
+    };
+    attempt.succeeded(&state.login_throttle);
+
+    let scope = granted_scope(&cfg, user.scopes.as_deref(), req.scope.as_deref())?;
 
     // NEW: clamp requested TTL to a server-side max (default 15 min)
     let ttl = req.ttl_secs.unwrap_or(900).min(cfg.auth_max_ttl_secs);
@@ -167,25 +336,158 @@ async fn login(
         + std::time::Duration::from_secs(ttl))
         .duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as usize;
 
-    let iss = Some(format!("http://{}:{}", cfg.host, cfg.port));
+    let iss = Some(cfg.idp_issuer());
     let aud = cfg.jwt_audience.clone();
 
     let mut header = Header::new(Algorithm::HS256);
     header.typ = Some("JWT".into());
 
-    let claims = Claims { sub: user.username, scope, exp, iss, aud };
+    let jti = uuid::Uuid::new_v4().to_string();
+    let claims = Claims { sub: user.username, scope, exp, iss, aud, jti };
 
     let token = encode(&header, &claims, &EncodingKey::from_secret(secret.as_bytes()))
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    Ok(HttpResponse::Ok().json(TokenResp {
+    let mut resp = HttpResponse::Ok();
+    if cfg.auth_cookie {
+        for cookie in auth::session_cookies(&cfg, &token, ttl) {
+            resp.cookie(cookie);
+        }
+    }
+    Ok(resp.json(TokenResp {
         access_token: token,
         token_type: "Bearer".into(),
         expires_in: ttl,
     }))
 }
 
-async fn logout() -> Result<HttpResponse> {
-    // Stateless: client should delete token; server doesn't track sessions.
-    Ok(HttpResponse::NoContent().finish())
+/// The scopes a login gets: those requested (all by default) that the user's
+/// record allows, else that the configured write/read/list sets do. An admin
+/// scope only when the record lists it as such, so signing up can't buy one.
+/// 403 when none of the requested scopes is allowed.
+fn granted_scope(cfg: &Config, stored: Option<&[String]>, requested: Option<&str>) -> Result<String, ApiError> {
+    use actix_web::http::StatusCode;
+    let defaults = cfg.default_scope();
+    let allowed: Vec<&str> = match stored {
+        Some(scopes) => scopes.iter().map(String::as_str).collect(),
+        None => defaults.split_whitespace().collect(),
+    };
+    let listed = |r: &str| stored.is_some_and(|s| s.iter().any(|s| s == r));
+    let granted: Vec<&str> = requested
+        .map_or_else(|| allowed.clone(), |r| r.split_whitespace().filter(|r| allowed.iter().any(|a| auth::scope_covers(a, r))).collect())
+        .into_iter()
+        .filter(|r| !auth::is_admin_scope(cfg, r) || listed(r))
+        .collect();
+    if granted.is_empty() {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "scope_not_granted", "none of the requested scopes is granted to this user")
+            .with("scope", requested.unwrap_or_default()));
+    }
+    Ok(granted.join(" "))
+}
+
+/// Basic mode: checks `username`/`password` against the user store and
+/// returns the user's scopes, or `None` for bad credentials. Upgrades a
+/// legacy plaintext entry like a login does.
+pub(crate) async fn verify_basic(cfg: &Config, username: &str, password: &str) -> Result<Option<Vec<String>>> {
+    if check_credential_lengths(username, password).is_err() {
+        return Ok(None);
+    }
+    let path = PathBuf::from(&cfg.auth_user_db);
+    let Some(user) = load_users(&path).await?.into_iter().find(|u| u.username == username) else {
+        return Ok(None);
+    };
+    let candidate = password.to_string();
+    let (user, verdict) = web::block(move || {
+        let verdict = verify_password(&user, &candidate);
+        (user, verdict)
+    })
+    .await?;
+    match verdict {
+        Verdict::Valid => {}
+        Verdict::ValidLegacy => upgrade_legacy(&path, &user.username, password.to_string(), cfg.durable_writes).await?,
+        Verdict::Invalid => return Ok(None),
+    }
+    Ok(Some(user.scopes.unwrap_or_else(|| cfg.basic_scopes.clone())))
+}
+
+/// Replaces a legacy plaintext entry with an argon2id hash after a successful login.
+async fn upgrade_legacy(path: &Path, username: &str, password: String, durable: bool) -> Result<()> {
+    let hash = hash_blocking(password).await?;
+    let mut users = load_users(path).await?;
+    if let Some(u) = users.iter_mut().find(|u| u.username == username && u.password_hash.is_none()) {
+        u.password_hash = Some(hash);
+        u.password = None;
+        save_users(path, &users, durable).await?;
+    }
+    Ok(())
+}
+
+/// Mints a one-time signup code (admin scope).
+async fn mint_invite(
+    _auth: NeedAdmin,
+    state: web::Data<AppState>,
+    req: Option<web::Json<InviteReq>>,
+) -> Result<HttpResponse> {
+    let ttl = req.and_then(|r| r.ttl_secs).unwrap_or(DEFAULT_INVITE_TTL_SECS);
+    let (code, expires_at) = state.invites.mint(ttl).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Created().json(InviteResp { code, expires_at }))
+}
+
+/// Reports whether a token is live, and for whom (admin scope or
+/// INTROSPECTION_SECRET). Bad tokens are `{"active": false}`, never an error.
+async fn introspect(
+    http: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    req: Either<web::Json<IntrospectReq>, web::Form<IntrospectReq>>,
+) -> Result<HttpResponse> {
+    auth::require_introspector(&http, &cfg).await?;
+    let token = match req {
+        Either::Left(json) => json.into_inner().token,
+        Either::Right(form) => form.into_inner().token,
+    };
+    let resp = match auth::introspect(&cfg, &state, token.trim()).await {
+        Some(user) => IntrospectResp {
+            active: true,
+            sub: user.sub,
+            scope: Some(user.scopes.join(" ")),
+            exp: user.exp,
+            iss: user.iss,
+            aud: match user.aud.len() {
+                0 => None,
+                1 => Some(user.aud[0].clone().into()),
+                _ => Some(user.aud.into()),
+            },
+        },
+        None => IntrospectResp { active: false, sub: None, scope: None, exp: None, iss: None, aud: None },
+    };
+    Ok(HttpResponse::Ok().insert_header((actix_web::http::header::CACHE_CONTROL, "no-store")).json(resp))
+}
+
+/// Revokes the presented token's `jti` until it expires, and clears the
+/// session cookies under AUTH_COOKIE.
+async fn logout(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+) -> Result<HttpResponse> {
+    if matches!(cfg.auth_mode, AuthMode::Off) {
+        return Ok(HttpResponse::NoContent().finish());
+    }
+    let user = auth::authenticate(&req, &cfg).await?;
+    let (Some(jti), Some(exp)) = (user.jti, user.exp) else {
+        return Err(ApiError::bad_request("jti_missing", "token carries no jti and cannot be revoked").into());
+    };
+    state
+        .denylist
+        .revoke(&jti, exp)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut resp = HttpResponse::NoContent();
+    if cfg.auth_cookie {
+        for cookie in auth::cleared_session_cookies(&cfg) {
+            resp.cookie(cookie);
+        }
+    }
+    Ok(resp.finish())
 }
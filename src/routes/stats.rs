@@ -0,0 +1,92 @@
+// src/routes/stats.rs
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Result};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::{self, NeedList, NeedWrite};
+use crate::consts::{Config, PATH_STATS, PATH_SUMMARY};
+use crate::error::ApiError;
+use crate::pending::now_secs;
+use crate::routes::objects::{check_key, resolve_key};
+use crate::routes::query::QueryParams;
+use crate::stats;
+use crate::storage::{free_bytes, key_of};
+use crate::AppState;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route(format!("/{}", PATH_STATS).as_str(), web::get().to(stats))
+        .route(format!("/{}", PATH_SUMMARY).as_str(), web::get().to(summary));
+}
+
+/// Bucket size and shape, cached for STATS_REFRESH_SECS; `?refresh=1`
+/// (write scope, like the admin routes) recomputes now.
+async fn stats(auth: NeedList, req: HttpRequest, state: web::Data<AppState>, cfg: web::Data<Config>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_STATS);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["refresh"])?;
+    let refresh = q.bool("refresh")?.unwrap_or(false);
+    if refresh {
+        NeedWrite::extract(&req).await?;
+    }
+    let stats = state
+        .stats
+        .get(&state.root, Duration::from_secs(cfg.stats_refresh_secs), refresh)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut body = serde_json::to_value(&*stats).map_err(actix_web::error::ErrorInternalServerError)?;
+    body["object_limit"] = cfg.max_object_count.into();
+    // live, not cached with the walk
+    body["download_bytes_per_second"] = state.downloaded.per_sec(now_secs()).into();
+    body["free_bytes"] = free_bytes(&state.root).into();
+    body["min_free_bytes"] = cfg.min_free_bytes.into();
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Object count, bytes, largest object and newest mtime under `?prefix=`
+/// (a string prefix, as in listings), optionally `group_by=first_level`.
+/// Large prefixes are served from a cache for STATS_REFRESH_SECS (see
+/// `generated_at`); `?refresh=1` (write scope) recomputes.
+async fn summary(auth: NeedList, req: HttpRequest, state: web::Data<AppState>, cfg: web::Data<Config>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_SUMMARY);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let q = QueryParams::parse(&req, &["prefix", "group_by", "refresh"])?;
+    let group = match q.str("group_by") {
+        None => false,
+        Some("first_level") => true,
+        Some(_) => {
+            return Err(ApiError::bad_request("invalid_query_param", "`group_by` must be first_level").with("param", "group_by").into())
+        }
+    };
+    let refresh = q.bool("refresh")?.unwrap_or(false);
+    if refresh {
+        NeedWrite::extract(&req).await?;
+    }
+
+    // the walk starts at the deepest directory that can hold a match
+    let raw = q.str("prefix").unwrap_or("");
+    let (prefix, base) = if raw.trim_matches('/').is_empty() {
+        (String::new(), state.root.clone())
+    } else {
+        check_key(&cfg, raw)?;
+        let path = resolve_key(&state.root, raw)
+            .filter(|p| !state.buckets.owns(&key_of(&state.root, p)))
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("invalid prefix"))?;
+        let key = key_of(&state.root, &path);
+        if raw.ends_with('/') {
+            (format!("{key}/"), path)
+        } else {
+            let parent = path.parent().map(Path::to_path_buf).unwrap_or_else(|| state.root.clone());
+            (key, parent)
+        }
+    };
+
+    let max_age = Duration::from_secs(cfg.stats_refresh_secs);
+    if let Some(cached) = state.summaries.get(&prefix, group, max_age).filter(|_| !refresh) {
+        return Ok(HttpResponse::Ok().json(&*cached));
+    }
+    let st = state.get_ref().clone();
+    let summary = Arc::new(web::block(move || stats::summarize(&st, &base, &prefix, group)).await?);
+    state.summaries.put(group, summary.clone());
+    Ok(HttpResponse::Ok().json(&*summary))
+}
@@ -0,0 +1,87 @@
+// src/routes/trash.rs
+//
+// Trash management (TRASH_ENABLED): list, restore, purge.
+use actix_web::{http::StatusCode, web, HttpResponse, Result};
+use tokio::fs;
+
+use crate::auth::{self, NeedList, NeedWrite};
+use crate::consts::{Config, PATH_TRASH};
+use crate::error::ApiError;
+use crate::events::notify;
+use crate::trash::{self, RestoreError};
+use crate::AppState;
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource(format!("/{}", PATH_TRASH).as_str())
+            .route(web::get().to(list_trash))
+            .route(web::delete().to(empty_trash)),
+    )
+    .service(web::resource(format!("/{}/{{id}}", PATH_TRASH).as_str()).route(web::delete().to(purge_entry)))
+    .service(web::resource(format!("/{}/{{id}}/restore", PATH_TRASH).as_str()).route(web::post().to(restore_entry)));
+}
+
+fn not_in_trash(id: String) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "not_in_trash", "no trash entry with this id").with("id", id)
+}
+
+async fn list_trash(auth: NeedList, state: web::Data<AppState>, cfg: web::Data<Config>) -> Result<HttpResponse> {
+    println!("→ GET /{}", PATH_TRASH);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let entries = trash::list(&state).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// `POST /trash/{id}/restore`: 409 if the original key has been reused since.
+async fn restore_entry(
+    auth: NeedWrite,
+    state: web::Data<AppState>,
+    cfg: web::Data<Config>,
+    id: web::Path<String>,
+) -> Result<HttpResponse> {
+    println!("→ POST /{}/{}/restore", PATH_TRASH, id);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let id = id.into_inner();
+    match trash::restore(&state, &id, cfg.max_object_count).await {
+        Ok(key) => {
+            let path = state.root.join(&key);
+            if let Ok(m) = fs::metadata(&path).await {
+                notify(&state, "object.restored", &path, &m);
+            }
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "id": id, "key": key })))
+        }
+        Err(RestoreError::NotFound) => Err(not_in_trash(id).into()),
+        Err(RestoreError::Occupied(key)) => {
+            Err(ApiError::new(StatusCode::CONFLICT, "key_occupied", "an object already exists at the original key")
+                .with("key", key)
+                .into())
+        }
+        Err(RestoreError::CountExceeded) => Err(ApiError::new(
+            StatusCode::INSUFFICIENT_STORAGE,
+            "object_count_exceeded",
+            "object count limit reached",
+        )
+        .with("limit", cfg.max_object_count.unwrap_or(0))
+        .with("count", state.objects.get())
+        .into()),
+        Err(RestoreError::Io(e)) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn purge_entry(auth: NeedWrite, state: web::Data<AppState>, cfg: web::Data<Config>, id: web::Path<String>) -> Result<HttpResponse> {
+    println!("→ DELETE /{}/{}", PATH_TRASH, id);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let id = id.into_inner();
+    match trash::purge(&state, &id).await {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Err(not_in_trash(id).into()),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn empty_trash(auth: NeedWrite, state: web::Data<AppState>, cfg: web::Data<Config>) -> Result<HttpResponse> {
+    println!("→ DELETE /{}", PATH_TRASH);
+    auth::refuse_jailed(&cfg, &auth.0)?;
+    let purged = trash::purge_all(&state, None).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "purged": purged })))
+}
@@ -0,0 +1,27 @@
+// src/routes/ui.rs
+//
+// The built-in browser UI (cargo feature `ui`, served when UI_ENABLED=1): a
+// single self-contained page that talks to the regular API with the
+// caller's token, so it grants nothing the API wouldn't.
+use actix_web::{http::header, web, HttpResponse};
+
+use crate::consts::PATH_UI;
+
+const INDEX_HTML: &str = include_str!("../../ui/index.html");
+
+pub(crate) fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route(format!("/{}", PATH_UI).as_str(), web::get().to(index))
+        .route(format!("/{}/", PATH_UI).as_str(), web::get().to(index));
+}
+
+async fn index() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // inline script/style only; nothing may load from elsewhere
+        .insert_header((
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'self'; script-src 'unsafe-inline'; style-src 'unsafe-inline'; img-src 'self' data: blob:; frame-ancestors 'none'",
+        ))
+        .body(INDEX_HTML)
+}
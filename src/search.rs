@@ -0,0 +1,143 @@
+// src/search.rs
+//
+// Key search for `GET /objects-search`: a substring, glob or regex matched
+// against whole keys (forward slashes, root-relative) over the default
+// bucket. The walk visits each directory's entries in name order, so
+// results come in segment-wise key order (`a/z` before `a.txt` and `ab`)
+// and a page ends at a key the next request resumes after; subtrees wholly
+// before that key aren't opened again.
+use serde::Serialize;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::pending::now_secs;
+use crate::storage::{is_internal, key_of};
+use crate::AppState;
+
+/// Longest pattern accepted, in bytes.
+pub const MAX_PATTERN_BYTES: usize = 256;
+/// Ceiling on a compiled regex, so a pattern can't balloon the program.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+pub enum Matcher {
+    Substring { needle: String, ignore_case: bool },
+    Glob(globset::GlobMatcher),
+    Regex(regex::Regex),
+}
+
+#[derive(Debug)]
+pub enum PatternError {
+    TooLong,
+    /// Carries the parser's message.
+    Invalid(String),
+}
+
+impl Matcher {
+    /// Builds a matcher for `mode` (`substring`, `glob` or `regex`); `None` for an unknown mode.
+    pub fn new(mode: &str, pattern: &str, ignore_case: bool) -> Option<Result<Self, PatternError>> {
+        if pattern.len() > MAX_PATTERN_BYTES {
+            return Some(Err(PatternError::TooLong));
+        }
+        let invalid = |e: &dyn std::fmt::Display| PatternError::Invalid(e.to_string());
+        Some(match mode {
+            "substring" => {
+                let needle = if ignore_case { pattern.to_lowercase() } else { pattern.to_string() };
+                Ok(Self::Substring { needle, ignore_case })
+            }
+            "glob" => globset::GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .case_insensitive(ignore_case)
+                .build()
+                .map(|g| Self::Glob(g.compile_matcher()))
+                .map_err(|e| invalid(&e)),
+            "regex" => regex::RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .size_limit(REGEX_SIZE_LIMIT)
+                .dfa_size_limit(REGEX_SIZE_LIMIT)
+                .build()
+                .map(Self::Regex)
+                .map_err(|e| invalid(&e)),
+            _ => return None,
+        })
+    }
+
+    pub fn is_match(&self, key: &str) -> bool {
+        match self {
+            Self::Substring { needle, ignore_case: true } => key.to_lowercase().contains(needle.as_str()),
+            Self::Substring { needle, ignore_case: false } => key.contains(needle.as_str()),
+            Self::Glob(g) => g.is_match(key),
+            Self::Regex(r) => r.is_match(key),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hit {
+    pub key: String,
+    pub size: u64,
+    pub modified: u64,
+}
+
+/// One page of results.
+#[derive(Serialize, Debug)]
+pub struct Page {
+    pub results: Vec<Hit>,
+    /// Pass as `after` for the next page; absent on the last one.
+    pub next: Option<String>,
+}
+
+/// Up to `limit` visible objects matching `matcher` that come after key
+/// `after` (blocking; run it off the async workers). Named buckets are
+/// not searched.
+pub fn search(state: &AppState, matcher: &Matcher, after: Option<&str>, limit: usize) -> std::io::Result<Page> {
+    let root = state.root.as_path();
+    let after: Option<Vec<&str>> = after.map(|a| a.split('/').collect());
+    let now = now_secs();
+    let mut results = Vec::new();
+    let mut more = false;
+    // entries still to visit, the next one last: a directory's contents are
+    // pushed in its place, so keys come out in order
+    let mut pending = children(root, root)?;
+    while let Some(entry) = pending.pop() {
+        let key = key_of(root, &entry.path());
+        if state.buckets.owns(&key) {
+            continue;
+        }
+        let Ok(ft) = entry.file_type() else { continue };
+        let segments: Vec<&str> = key.split('/').collect();
+        if ft.is_dir() {
+            // nothing under it comes after the cursor
+            if after.as_ref().is_some_and(|a| segments.as_slice() < &a[..a.len().min(segments.len())]) {
+                continue;
+            }
+            pending.extend(children(root, &entry.path())?);
+            continue;
+        }
+        if !ft.is_file() || after.as_ref().is_some_and(|a| segments <= *a) {
+            continue;
+        }
+        if !matcher.is_match(&key) || !state.is_visible(&key, now) {
+            continue;
+        }
+        if results.len() == limit {
+            more = true;
+            break;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        let modified = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs());
+        results.push(Hit { key, size: meta.len(), modified });
+    }
+    let next = if more { results.last().map(|h| h.key.clone()) } else { None };
+    Ok(Page { results, next })
+}
+
+/// `dir`'s entries in reverse name order (for popping), less the server's own.
+fn children(root: &Path, dir: &Path) -> std::io::Result<Vec<std::fs::DirEntry>> {
+    let mut entries: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(rd) => rd.flatten().filter(|e| dir != root || !is_internal(&e.file_name())).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    entries.sort_by_key(|e| std::cmp::Reverse(e.file_name()));
+    Ok(entries)
+}
@@ -0,0 +1,215 @@
+// src/selftest.rs
+//
+// Scripted smoke test of a full request cycle, run in-process against a
+// throwaway root: `rust-buck3t --self-test` and `POST /admin/self-test`.
+use actix_web::{http::header, http::StatusCode, test};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::consts::{AuthMode, Config, PATH_IDP_TOKEN};
+use crate::{app, AppState};
+
+const PROBE_KEY: &str = "selftest/probe.txt";
+const PROBE_BODY: &[u8] = b"rust-buck3t self-test";
+
+#[derive(Serialize)]
+pub struct StepResult {
+    pub step: &'static str,
+    pub ok: bool,
+    pub micros: u128,
+    pub detail: String,
+}
+
+#[derive(Serialize)]
+pub struct SelfTestReport {
+    pub ok: bool,
+    pub steps: Vec<StepResult>,
+}
+
+impl SelfTestReport {
+    fn record(&mut self, step: &'static str, started: Instant, res: Result<String, String>) {
+        let micros = started.elapsed().as_micros();
+        let (ok, detail) = match res {
+            Ok(d) => (true, d),
+            Err(d) => (false, d),
+        };
+        self.ok &= ok;
+        self.steps.push(StepResult { step, ok, micros, detail });
+    }
+
+    /// Human-readable report, one line per step.
+    pub fn print(&self) {
+        for s in &self.steps {
+            let mark = if s.ok { "✔" } else { "✘" };
+            println!("  {} {:<8} {:>9.3} ms  {}", mark, s.step, s.micros as f64 / 1000.0, s.detail);
+        }
+        println!("{}", if self.ok { "✅ self-test passed" } else { "❌ self-test failed" });
+    }
+}
+
+/// Runs the sequence with `cfg` (auth, limits) against a fresh temp root.
+pub async fn run(cfg: &Config) -> SelfTestReport {
+    let mut report = SelfTestReport { ok: true, steps: Vec::new() };
+
+    let root = scratch_dir();
+    let started = Instant::now();
+    if let Err(e) = tokio::fs::create_dir_all(&root).await {
+        report.record("setup", started, Err(format!("create {}: {e}", root.display())));
+        return report;
+    }
+    report.record("setup", started, Ok(format!("root {}", root.display())));
+
+    let state = match AppState::new(&root).with_auth(cfg) {
+        Ok(state) => state,
+        Err(e) => {
+            report.record("setup", started, Err(format!("auth state: {e}")));
+            return report;
+        }
+    };
+    let svc = test::init_service(app(state, cfg.clone())).await;
+
+    // health
+    let started = Instant::now();
+    let resp = test::call_service(&svc, test::TestRequest::get().uri("/healthz").to_request()).await;
+    report.record("health", started, expect(resp.status(), StatusCode::OK));
+
+    // token
+    let started = Instant::now();
+    let token = if matches!(cfg.auth_mode, AuthMode::JwtRs256) && cfg.idp_embed {
+        let req = test::TestRequest::post()
+            .uri(&format!("/{PATH_IDP_TOKEN}"))
+            .set_json(serde_json::json!({ "sub": "self-test", "ttl_secs": 60 }))
+            .to_request();
+        let resp = test::call_service(&svc, req).await;
+        let status = resp.status();
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap_or_default();
+        expect(status, StatusCode::OK).and_then(|_| match body["access_token"].as_str() {
+            Some(t) => Ok(Some((t.to_string(), "minted RS256 token via embedded IdP"))),
+            None => Err("no access_token in IdP response".into()),
+        })
+    } else if matches!(cfg.auth_mode, AuthMode::ApiKey) {
+        match cfg.api_keys.first() {
+            Some(k) => Ok(Some((k.key.clone(), "using first configured API key"))),
+            None => Err("AUTH_MODE=api_key but no keys are configured".into()),
+        }
+    } else {
+        mint_hs256(cfg).map(|t| t.map(|t| (t, "minted HS256 token")))
+    };
+    report.record(
+        "token",
+        started,
+        match &token {
+            Ok(Some((_, how))) => Ok(how.to_string()),
+            Ok(None) => Ok("skipped (no local minting for this auth mode)".into()),
+            Err(e) => Err(e.clone()),
+        },
+    );
+    let bearer = token.ok().flatten().map(|(t, _)| format!("Bearer {t}"));
+    let with_auth = |r: test::TestRequest| match &bearer {
+        Some(b) => r.insert_header((header::AUTHORIZATION, b.as_str())),
+        None => r,
+    };
+    let object_uri = format!("/objects/{PROBE_KEY}");
+
+    // put
+    let started = Instant::now();
+    let req = with_auth(test::TestRequest::put().uri(&object_uri).set_payload(PROBE_BODY)).to_request();
+    let resp = test::call_service(&svc, req).await;
+    report.record("put", started, expect(resp.status(), StatusCode::CREATED));
+
+    // head
+    let started = Instant::now();
+    let req = with_auth(test::TestRequest::default().method(actix_web::http::Method::HEAD).uri(&object_uri)).to_request();
+    let resp = test::call_service(&svc, req).await;
+    let res = expect(resp.status(), StatusCode::OK).and_then(|_| {
+        resp.headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|e| format!("etag {e}"))
+            .ok_or_else(|| "missing ETag".to_string())
+    });
+    report.record("head", started, res);
+
+    // get
+    let started = Instant::now();
+    let req = with_auth(test::TestRequest::get().uri(&object_uri)).to_request();
+    let resp = test::call_service(&svc, req).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    let res = expect(status, StatusCode::OK).and_then(|_| {
+        if body.as_ref() == PROBE_BODY { Ok(format!("{} bytes", body.len())) } else { Err("body mismatch".into()) }
+    });
+    report.record("get", started, res);
+
+    // range
+    let started = Instant::now();
+    let req = with_auth(test::TestRequest::get().uri(&object_uri).insert_header((header::RANGE, "bytes=0-3"))).to_request();
+    let resp = test::call_service(&svc, req).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    let res = expect(status, StatusCode::PARTIAL_CONTENT).and_then(|_| {
+        if body.as_ref() == &PROBE_BODY[..4] { Ok("bytes=0-3".into()) } else { Err("range body mismatch".into()) }
+    });
+    report.record("range", started, res);
+
+    // list
+    let started = Instant::now();
+    let req = with_auth(test::TestRequest::get().uri("/objects?prefix=selftest")).to_request();
+    let resp = test::call_service(&svc, req).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    let res = expect(status, StatusCode::OK).and_then(|_| {
+        let listed: Vec<serde_json::Value> = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+        if listed.iter().any(|o| o.get("key").and_then(|k| k.as_str()) == Some(PROBE_KEY)) {
+            Ok(format!("{} entries", listed.len()))
+        } else {
+            Err("probe key not listed".into())
+        }
+    });
+    report.record("list", started, res);
+
+    // delete
+    let started = Instant::now();
+    let req = with_auth(test::TestRequest::delete().uri(&object_uri)).to_request();
+    let resp = test::call_service(&svc, req).await;
+    let want = if cfg.delete_grace_secs > 0 { StatusCode::ACCEPTED } else { StatusCode::NO_CONTENT };
+    report.record("delete", started, expect(resp.status(), want));
+
+    let _ = tokio::fs::remove_dir_all(&root).await;
+    report
+}
+
+fn expect(got: StatusCode, want: StatusCode) -> Result<String, String> {
+    if got == want {
+        Ok(format!("{}", got.as_u16()))
+    } else {
+        Err(format!("expected {}, got {}", want.as_u16(), got.as_u16()))
+    }
+}
+
+fn scratch_dir() -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    std::env::temp_dir().join(format!("rust-buck3t-selftest-{}-{}", std::process::id(), nanos))
+}
+
+/// A short-lived HS256 token carrying every configured scope, when in HS256 mode.
+fn mint_hs256(cfg: &Config) -> Result<Option<String>, String> {
+    if !matches!(cfg.auth_mode, AuthMode::JwtHs256) {
+        return Ok(None);
+    }
+    let secret = cfg.jwt_hs_secret.as_ref().ok_or("JWT_HS_SECRET not set")?;
+
+    let exp = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs() + 60;
+    let mut claims = serde_json::json!({ "sub": "self-test", "scope": cfg.default_scope(), "exp": exp });
+    if let Some(iss) = cfg.jwt_issuers.first() {
+        claims["iss"] = iss.clone().into();
+    }
+    if let Some(aud) = &cfg.jwt_audience {
+        claims["aud"] = aud.clone().into();
+    }
+    encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret.as_bytes()))
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,109 @@
+// src/shutdown.rs
+//
+// SIGTERM/SIGINT: stop accepting connections, give in-flight requests
+// SHUTDOWN_GRACE_SECS to finish, then exit. Uploads cut off at the limit
+// only ever wrote to a temp file (see `storage::TempUpload`).
+use actix_web::{
+    body::MessageBody,
+    dev::{ServerHandle, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio_util::sync::CancellationToken;
+
+use crate::AppState;
+
+/// Requests being handled, and how they fared once shutdown began.
+#[derive(Default)]
+pub struct InFlight {
+    active: AtomicUsize,
+    draining: AtomicBool,
+    at_signal: AtomicUsize,
+    drained: AtomicUsize,
+    /// Cancelled when shutdown begins, so open-ended streams can end early.
+    stopping: CancellationToken,
+}
+
+impl InFlight {
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Requests that completed after shutdown began.
+    pub fn drained(&self) -> usize {
+        self.drained.load(Ordering::Relaxed)
+    }
+
+    /// Requests running at the signal that never completed.
+    pub fn cut_off(&self) -> usize {
+        self.at_signal.load(Ordering::Relaxed).saturating_sub(self.drained())
+    }
+
+    pub fn stopping(&self) -> CancellationToken {
+        self.stopping.clone()
+    }
+
+    /// Marks the start of shutdown; returns the number of requests still running.
+    pub fn begin_drain(&self) -> usize {
+        self.draining.store(true, Ordering::Relaxed);
+        self.stopping.cancel();
+        let n = self.active();
+        self.at_signal.store(n, Ordering::Relaxed);
+        n
+    }
+}
+
+/// Decrements on drop, so a request dropped mid-flight is still accounted for.
+struct Tracked<'a> {
+    counts: &'a InFlight,
+    finished: bool,
+}
+
+impl Drop for Tracked<'_> {
+    fn drop(&mut self) {
+        self.counts.active.fetch_sub(1, Ordering::Relaxed);
+        if self.finished && self.counts.draining.load(Ordering::Relaxed) {
+            self.counts.drained.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Middleware for `App::wrap(from_fn(..))`; counts requests in `AppState::in_flight`.
+pub async fn track(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    state.in_flight.active.fetch_add(1, Ordering::Relaxed);
+    let mut tracked = Tracked { counts: &state.in_flight, finished: false };
+    let res = next.call(req).await;
+    tracked.finished = true;
+    res
+}
+
+/// Resolves on SIGINT or SIGTERM with the signal's name.
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).expect("install SIGTERM handler");
+        tokio::select! {
+            _ = actix_web::rt::signal::ctrl_c() => "SIGINT",
+            _ = term.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = actix_web::rt::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+/// Waits for a signal, then stops the server gracefully; in-flight requests
+/// get the server's `shutdown_timeout` to finish.
+pub async fn on_signal(handle: ServerHandle, state: AppState, grace_secs: u64) {
+    let sig = signal().await;
+    let n = state.in_flight.begin_drain();
+    println!("🛑 {sig}: no longer accepting connections; draining {n} in-flight request(s) (up to {grace_secs}s)");
+    handle.stop(true).await;
+}
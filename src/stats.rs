@@ -0,0 +1,251 @@
+// src/stats.rs
+//
+// Bucket statistics for `GET /stats`: a full walk of the root, cached for
+// STATS_REFRESH_SECS so large buckets aren't re-walked on every request.
+// `GET /objects-summary` does the same for one prefix; only summaries of
+// large prefixes are cached.
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::pending::now_secs;
+use crate::storage::{is_internal, key_of};
+use crate::trash::TRASH_DIR;
+use crate::AppState;
+
+/// Summaries counting at least this many objects are cached.
+pub const SUMMARY_CACHE_MIN_OBJECTS: u64 = 10_000;
+/// Cached summaries kept at most; the oldest goes first.
+const SUMMARY_CACHE_ENTRIES: usize = 64;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Totals {
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Largest {
+    pub key: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Stats {
+    pub objects: u64,
+    pub bytes: u64,
+    pub largest: Option<Largest>,
+    /// Newest object mtime (unix seconds).
+    pub last_modified: Option<u64>,
+    /// Per top-level prefix (`"dir/"`); objects directly under the root are under `""`.
+    pub prefixes: BTreeMap<String, Totals>,
+    /// Space held by trashed objects (TRASH_ENABLED), not counted above.
+    pub trash: Totals,
+    pub generated_at: u64,
+}
+
+/// Walks `root` (blocking; run it off the async workers).
+pub fn compute(root: &Path) -> Stats {
+    let mut stats = Stats { generated_at: now_secs(), ..Default::default() };
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&dir) else { continue };
+        for entry in rd.flatten() {
+            if dir == root && is_internal(&entry.file_name()) {
+                continue;
+            }
+            let Ok(ft) = entry.file_type() else { continue };
+            if ft.is_dir() {
+                stack.push(entry.path());
+                continue;
+            }
+            let Some(meta) = entry.metadata().ok().filter(|_| ft.is_file()) else { continue };
+            let key = key_of(root, &entry.path());
+            let size = meta.len();
+            stats.objects += 1;
+            stats.bytes += size;
+            if stats.largest.as_ref().is_none_or(|l| size > l.size) {
+                stats.largest = Some(Largest { key: key.clone(), size });
+            }
+            let mtime = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+            stats.last_modified = stats.last_modified.max(mtime);
+            let prefix = key.split_once('/').map(|(top, _)| format!("{top}/")).unwrap_or_default();
+            let group = stats.prefixes.entry(prefix).or_default();
+            group.objects += 1;
+            group.bytes += size;
+        }
+    }
+    stats.trash = dir_totals(&root.join(TRASH_DIR));
+    stats
+}
+
+/// Files and bytes anywhere under `dir` (blocking).
+pub fn dir_totals(dir: &Path) -> Totals {
+    let mut totals = Totals::default();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&d) else { continue };
+        for entry in rd.flatten() {
+            match entry.file_type() {
+                Ok(ft) if ft.is_dir() => stack.push(entry.path()),
+                Ok(ft) if ft.is_file() => {
+                    totals.objects += 1;
+                    totals.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                }
+                _ => {}
+            }
+        }
+    }
+    totals
+}
+
+/// The last `compute` result and when it was taken.
+#[derive(Default)]
+pub struct StatsCache(tokio::sync::Mutex<Option<(Instant, Arc<Stats>)>>);
+
+impl StatsCache {
+    /// Cached stats unless older than `max_age` or `force`d; concurrent callers share one walk.
+    pub async fn get(&self, root: &Path, max_age: Duration, force: bool) -> std::io::Result<Arc<Stats>> {
+        let mut cached = self.0.lock().await;
+        if let Some((at, stats)) = cached.as_ref() {
+            if !force && at.elapsed() < max_age {
+                return Ok(stats.clone());
+            }
+        }
+        let root = root.to_path_buf();
+        let stats = Arc::new(actix_web::web::block(move || compute(&root)).await.map_err(std::io::Error::other)?);
+        *cached = Some((Instant::now(), stats.clone()));
+        Ok(stats)
+    }
+}
+
+/// Usage under one prefix, as served by `GET /objects-summary`.
+#[derive(Serialize, Clone, Debug)]
+pub struct Summary {
+    pub prefix: String,
+    pub object_count: u64,
+    pub total_bytes: u64,
+    pub largest_object: Option<Largest>,
+    /// Newest object mtime (unix seconds).
+    pub last_modified: Option<u64>,
+    /// With `group_by=first_level`: per immediate child prefix
+    /// (`<prefix><name>/`); objects directly under the prefix count under
+    /// the prefix itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<BTreeMap<String, Totals>>,
+    pub generated_at: u64,
+}
+
+/// Totals the visible objects whose keys start with `prefix`, walking from
+/// `base` (the deepest directory that can hold them) and keeping only the
+/// running sums (blocking; run it off the async workers). Named buckets are
+/// left out, as in the default bucket's listing.
+pub fn summarize(state: &AppState, base: &Path, prefix: &str, group: bool) -> Summary {
+    let root = state.root.as_path();
+    let now = now_secs();
+    let mut summary = Summary {
+        prefix: prefix.to_string(),
+        object_count: 0,
+        total_bytes: 0,
+        largest_object: None,
+        last_modified: None,
+        groups: group.then(BTreeMap::new),
+        generated_at: now,
+    };
+    let mut stack = vec![base.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&dir) else { continue };
+        for entry in rd.flatten() {
+            if dir == root && is_internal(&entry.file_name()) {
+                continue;
+            }
+            let key = key_of(root, &entry.path());
+            if state.buckets.owns(&key) {
+                continue;
+            }
+            let Ok(ft) = entry.file_type() else { continue };
+            if ft.is_dir() {
+                if format!("{key}/").starts_with(prefix) || prefix.starts_with(&format!("{key}/")) {
+                    stack.push(entry.path());
+                }
+                continue;
+            }
+            if !ft.is_file() || !key.starts_with(prefix) || !state.is_visible(&key, now) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else { continue };
+            let size = meta.len();
+            summary.object_count += 1;
+            summary.total_bytes += size;
+            if summary.largest_object.as_ref().is_none_or(|l| size > l.size) {
+                summary.largest_object = Some(Largest { key: key.clone(), size });
+            }
+            let mtime = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+            summary.last_modified = summary.last_modified.max(mtime);
+            if let Some(groups) = &mut summary.groups {
+                let totals = groups.entry(first_level(&key, prefix)).or_default();
+                totals.objects += 1;
+                totals.bytes += size;
+            }
+        }
+    }
+    summary
+}
+
+/// The immediate child prefix of `prefix` holding `key`, or the prefix
+/// itself for a key directly under it.
+fn first_level(key: &str, prefix: &str) -> String {
+    let rest = &key[prefix.len()..];
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let start = key.len() - rest.len();
+    match rest.find('/') {
+        Some(i) => key[..start + i + 1].to_string(),
+        None => prefix.to_string(),
+    }
+}
+
+/// Summaries of large prefixes, by (prefix, grouped), with when each was taken.
+type Summaries = HashMap<(String, bool), (Instant, Arc<Summary>)>;
+
+#[derive(Default)]
+pub struct SummaryCache(Mutex<Summaries>);
+
+impl SummaryCache {
+    /// A cached summary younger than `max_age`.
+    pub fn get(&self, prefix: &str, group: bool, max_age: Duration) -> Option<Arc<Summary>> {
+        let cached = self.0.lock().unwrap();
+        let (at, summary) = cached.get(&(prefix.to_string(), group))?;
+        (at.elapsed() < max_age).then(|| summary.clone())
+    }
+
+    /// Keeps `summary` if its prefix is large enough to be worth it.
+    pub fn put(&self, group: bool, summary: Arc<Summary>) {
+        if summary.object_count < SUMMARY_CACHE_MIN_OBJECTS {
+            return;
+        }
+        let mut cached = self.0.lock().unwrap();
+        if cached.len() >= SUMMARY_CACHE_ENTRIES {
+            if let Some(oldest) = cached.iter().min_by_key(|(_, (at, _))| *at).map(|(k, _)| k.clone()) {
+                cached.remove(&oldest);
+            }
+        }
+        cached.insert((summary.prefix.clone(), group), (Instant::now(), summary));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_level_groups() {
+        assert_eq!(first_level("b/2024/jan/x.tar", "b/2024/"), "b/2024/jan/");
+        assert_eq!(first_level("b/2024/top.txt", "b/2024/"), "b/2024/");
+        assert_eq!(first_level("b/2024/jan/x.tar", "b/2024"), "b/2024/jan/");
+        assert_eq!(first_level("b/2024x/y", "b/2024"), "b/2024x/");
+        assert_eq!(first_level("a.txt", ""), "");
+        assert_eq!(first_level("a/b/c.txt", ""), "a/");
+    }
+}
@@ -0,0 +1,317 @@
+// src/storage.rs
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::buckets::BUCKETS_DIR;
+use crate::dedup::BLOBS_DIR;
+use crate::meta::META_DIR;
+use crate::resumable::RESUMABLE_DIR;
+use crate::s3::MULTIPART_DIR;
+use crate::trash::TRASH_DIR;
+
+/// Name of the scratch file used to probe writability of the root.
+const PROBE_NAME: &str = ".rust-buck3t-probe";
+
+/// Directory (directly under the root) where PUT bodies are streamed before
+/// being renamed into place, so a partial upload is never visible as an object.
+pub const TMP_DIR: &str = ".tmp";
+
+/// True for root-level names owned by the server rather than by any key.
+pub fn is_internal(name: &OsStr) -> bool {
+    [META_DIR, TMP_DIR, TRASH_DIR, BUCKETS_DIR, BLOBS_DIR, RESUMABLE_DIR, MULTIPART_DIR, PROBE_NAME].iter().any(|n| name == *n)
+}
+
+/// True if any existing component of `rel` under `root` is a symlink.
+/// Components past the first missing one can't be links yet. The root
+/// itself may be a link; it's configured, not uploaded.
+pub fn through_symlink(root: &Path, rel: &Path) -> bool {
+    let mut path = root.to_path_buf();
+    for comp in rel.components() {
+        path.push(comp);
+        match std::fs::symlink_metadata(&path) {
+            Ok(m) if m.file_type().is_symlink() => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// True for errors meaning "this volume won't take writes": EROFS, or the
+/// permission errors you get from a root whose write bits were removed.
+pub fn is_read_only_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ReadOnlyFilesystem | io::ErrorKind::PermissionDenied
+    )
+}
+
+/// Creates and removes a probe file in `root`.
+pub async fn probe_writable(root: &Path) -> io::Result<()> {
+    let probe = root.join(PROBE_NAME);
+    tokio::fs::write(&probe, b"").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+/// Normalised key ('/'-separated, relative to the root) of a resolved path.
+/// Joined component by component, so the platform's separators become `/`
+/// while a `\` inside a Unix file name stays part of the key.
+pub fn key_of(root: &Path, path: &Path) -> String {
+    let mut key = String::new();
+    for comp in path.strip_prefix(root).unwrap_or(path).components() {
+        if !key.is_empty() && !key.ends_with('/') {
+            key.push('/');
+        }
+        key.push_str(&comp.as_os_str().to_string_lossy());
+    }
+    key
+}
+
+/// Weak ETag from size and mtime.
+pub fn make_etag(meta: &std::fs::Metadata) -> String {
+    let len = meta.len();
+    let ts = meta.modified().ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| (d.as_secs(), d.subsec_nanos()))
+        .unwrap_or((0, 0));
+    format!("W/\"{}-{}-{}\"", len, ts.0, ts.1)
+}
+
+/// Bytes unprivileged writers can still use on the volume holding `root`;
+/// `None` where the platform can't say or the call fails.
+#[cfg(target_os = "linux")]
+pub fn free_bytes(root: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(root.as_os_str().as_bytes()).ok()?;
+    // SAFETY: all-zero is a valid `statvfs`, and `path` is NUL-terminated
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::statvfs(path.as_ptr(), &mut st) };
+    (rc == 0).then(|| st.f_bavail as u64 * st.f_frsize as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn free_bytes(_root: &Path) -> Option<u64> {
+    None
+}
+
+/// How long a `/readyz` result is reused before the disk is checked again.
+const READY_TTL: Duration = Duration::from_secs(1);
+
+/// Why the root can't take traffic, as reported by `/readyz`.
+#[derive(Clone, Debug)]
+pub struct NotReady {
+    pub reason: &'static str,
+    pub message: String,
+}
+
+/// Checks that `root` exists, is a directory and accepts writes.
+pub async fn check_ready(root: &Path) -> Result<(), NotReady> {
+    let fail = |reason, detail: String| NotReady { reason, message: format!("{}: {detail}", root.display()) };
+    match tokio::fs::metadata(root).await {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(fail("root_missing", "does not exist".into())),
+        Err(e) => return Err(fail("root_unreadable", e.to_string())),
+        Ok(m) if !m.is_dir() => return Err(fail("root_not_directory", "is not a directory".into())),
+        Ok(_) => {}
+    }
+    probe_writable(root).await.map_err(|e| fail("root_not_writable", e.to_string()))
+}
+
+/// `check_ready` behind a short cache, so probe storms hit the disk once per `READY_TTL`.
+#[derive(Default)]
+pub struct ReadyCache(tokio::sync::Mutex<Option<(Instant, Result<(), NotReady>)>>);
+
+impl ReadyCache {
+    pub async fn check(&self, root: &Path) -> Result<(), NotReady> {
+        // held across the check so concurrent probes wait for one result
+        let mut cached = self.0.lock().await;
+        if let Some((at, result)) = cached.as_ref() {
+            if at.elapsed() < READY_TTL {
+                return result.clone();
+            }
+        }
+        let result = check_ready(root).await;
+        *cached = Some((Instant::now(), result.clone()));
+        result
+    }
+}
+
+/// Number of stored objects, kept incrementally by PUT/DELETE after a startup scan.
+pub struct ObjectCount(AtomicU64);
+
+impl ObjectCount {
+    pub fn new(n: u64) -> Self {
+        Self(AtomicU64::new(n))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Claims a slot for a new key unless that would exceed `limit`.
+    /// The slot is returned on drop unless the reservation is committed.
+    pub fn try_reserve(&self, limit: Option<u64>) -> Option<CountReservation<'_>> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| match limit {
+                Some(max) if n >= max => None,
+                _ => Some(n + 1),
+            })
+            .ok()
+            .map(|_| CountReservation { count: self, committed: false })
+    }
+
+    pub fn decrement(&self) {
+        let _ = self.0.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    }
+}
+
+pub struct CountReservation<'a> {
+    count: &'a ObjectCount,
+    committed: bool,
+}
+
+impl CountReservation<'_> {
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for CountReservation<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.count.decrement();
+        }
+    }
+}
+
+/// Counts regular files under `root`, skipping the server's own entries.
+/// Unreadable directories are skipped rather than failing startup.
+pub fn count_objects(root: &Path) -> u64 {
+    let mut count = 0;
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&dir) else { continue };
+        for entry in rd.flatten() {
+            if dir == root && is_internal(&entry.file_name()) {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(ft) if ft.is_dir() => stack.push(entry.path()),
+                Ok(ft) if ft.is_file() => count += 1,
+                _ => {}
+            }
+        }
+    }
+    count
+}
+
+/// A PUT body being written under `TMP_DIR`. Removed on drop (failed,
+/// cancelled or aborted upload) unless `persist` moved it into place.
+pub struct TempUpload {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TempUpload {
+    pub async fn create(root: &Path) -> io::Result<(Self, tokio::fs::File)> {
+        let dir = root.join(TMP_DIR);
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(format!("{}.part", uuid::Uuid::new_v4()));
+        let file = tokio::fs::File::create(&path).await?;
+        Ok((Self { path, persisted: false }, file))
+    }
+
+    /// A temp path for a file the caller makes itself (a link, a copy);
+    /// removed on drop like an upload.
+    pub fn reserve(root: &Path) -> io::Result<Self> {
+        let dir = root.join(TMP_DIR);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { path: dir.join(format!("{}.part", uuid::Uuid::new_v4())), persisted: false })
+    }
+
+    /// Atomically replaces `dest` with the finished upload; `durable` also
+    /// fsyncs `dest`'s directory so the rename survives a power cut (the
+    /// caller syncs the file itself before this).
+    pub async fn persist(mut self, dest: &Path, durable: bool) -> io::Result<()> {
+        tokio::fs::rename(&self.path, dest).await?;
+        self.persisted = true;
+        if durable {
+            if let Some(dir) = dest.parent() {
+                sync_dir(dir).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `create` for blocking code.
+    pub fn create_blocking(root: &Path) -> io::Result<(Self, std::fs::File)> {
+        let dir = root.join(TMP_DIR);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.part", uuid::Uuid::new_v4()));
+        let file = std::fs::File::create(&path)?;
+        Ok((Self { path, persisted: false }, file))
+    }
+
+    /// `persist` for blocking code.
+    pub fn persist_blocking(mut self, dest: &Path) -> io::Result<()> {
+        std::fs::rename(&self.path, dest)?;
+        self.persisted = true;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempUpload {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Flushes a directory's entries (creates, renames) to disk. A no-op where
+/// directories can't be opened as files.
+pub async fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    tokio::fs::File::open(dir).await?.sync_all().await?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Removes partial uploads left behind by a crash or forced shutdown.
+pub fn clear_tmp(root: &Path) {
+    let _ = std::fs::remove_dir_all(root.join(TMP_DIR));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_use_forward_slashes() {
+        let root = Path::new("root");
+        assert_eq!(key_of(root, &root.join("a").join("b.txt")), "a/b.txt");
+        assert_eq!(key_of(root, &root.join("a/b/")), "a/b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_backslash_is_part_of_a_unix_name() {
+        let root = Path::new("/srv/root");
+        assert_eq!(key_of(root, &root.join("a\\b.txt")), "a\\b.txt");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_separators_become_slashes() {
+        let root = Path::new(r"C:\srv\root");
+        assert_eq!(key_of(root, &root.join(r"a\b/c.txt")), "a/b/c.txt");
+    }
+}
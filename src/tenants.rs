@@ -0,0 +1,91 @@
+// src/tenants.rs
+//
+// Tenants: isolated trees under `tenants/{name}/` in one root. With
+// TENANT_HEADER or TENANT_CLAIM set, every request through the auth gate acts
+// for a tenant and is jailed to its tree the way NAMESPACE_BY_SUB jails a
+// caller. The token's claim names it when the token carries one, so a client
+// can't pick another tenant by header; otherwise the header does, else
+// TENANT_DEFAULT. With TENANT_CLAIM set, an authenticated caller whose
+// credential names no tenant (a token without the claim, an API key, Basic)
+// may not name one by header either. Admin scopes are exempt and see every tenant's real keys.
+// A tenant's directory is made by its first write under TENANT_AUTO_CREATE;
+// without it, the directory must already exist.
+use actix_web::http::StatusCode;
+use actix_web::HttpRequest;
+
+use crate::auth::AuthUser;
+use crate::consts::Config;
+use crate::error::ApiError;
+use crate::routes::objects::Bucket;
+use crate::AppState;
+
+/// Tenants' trees live under `tenants/{name}/`.
+pub const TENANT_DIR: &str = "tenants";
+const MAX_NAME_BYTES: usize = 64;
+
+pub fn enabled(cfg: &Config) -> bool {
+    cfg.tenant_header.is_some() || cfg.tenant_claim.is_some()
+}
+
+/// `[A-Za-z0-9][A-Za-z0-9_-]*`, at most 64 bytes: one plain directory name.
+pub fn valid_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_BYTES
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The tenant `user` acts for: their token's claim, else the header, else
+/// TENANT_DEFAULT. `None` with tenancy off and for admin scopes. 403 for a
+/// header from an authenticated caller without the claim when TENANT_CLAIM
+/// is set; 400 when there is none or it is badly named; 404 for one without
+/// a directory in the bucket the request addresses, unless TENANT_AUTO_CREATE.
+pub(crate) async fn resolve(req: &HttpRequest, cfg: &Config, state: &AppState, user: &AuthUser) -> Result<Option<String>, ApiError> {
+    if !enabled(cfg) || user.is_admin {
+        return Ok(None);
+    }
+    let header = match cfg.tenant_header.as_deref().and_then(|h| req.headers().get(h)) {
+        Some(v) => Some(
+            v.to_str()
+                .map_err(|_| ApiError::bad_request("invalid_tenant", "tenant header is not a valid name"))?
+                .trim()
+                .to_string(),
+        ),
+        None => None,
+    };
+    if header.is_some() && user.tenant.is_none() && cfg.tenant_claim.is_some() && user.is_authenticated() {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "tenant_not_in_credentials", "the tenant must come from the token's claim")
+            .with("claim", cfg.tenant_claim.clone().unwrap_or_default()));
+    }
+    let Some(name) = user.tenant.clone().or(header).or_else(|| cfg.tenant_default.clone()) else {
+        return Err(ApiError::bad_request("tenant_required", "the request names no tenant"));
+    };
+    if !valid_name(&name) {
+        return Err(ApiError::bad_request("invalid_tenant", "tenant names are letters, digits, `_` and `-`").with("tenant", name));
+    }
+    if !cfg.tenant_auto_create {
+        // where the jail will put it: `/b/{bucket}` routes are jailed inside the bucket
+        let base = match Bucket::name_of(req)? {
+            Some(bucket) => state.root.join(bucket),
+            None => state.root.clone(),
+        };
+        if !tokio::fs::metadata(base.join(TENANT_DIR).join(&name)).await.is_ok_and(|m| m.is_dir()) {
+            return Err(ApiError::new(StatusCode::NOT_FOUND, "unknown_tenant", "no such tenant").with("tenant", name));
+        }
+    }
+    Ok(Some(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for ok in ["acme", "Acme-2", "a_b", "9lives", &"t".repeat(64)] {
+            assert!(valid_name(ok), "{ok}");
+        }
+        for bad in ["", "..", ".acme", "-acme", "a/b", "a b", "ünï", &"t".repeat(65)] {
+            assert!(!valid_name(bad), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// src/throttle.rs
+//
+// Login throttling: failed password checks are counted per username and per
+// client address. LOGIN_MAX_FAILURES failures inside LOGIN_WINDOW_SECS lock
+// that name or address out for LOGIN_LOCKOUT_SECS (429 with Retry-After),
+// and a success clears the username's count. In memory only; a pruner drops
+// entries that have run out.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::{http::header, http::StatusCode, HttpRequest, ResponseError};
+
+use crate::access_log::client_ip;
+use crate::consts::Config;
+use crate::error::ApiError;
+use crate::pending::now_secs;
+use crate::AppState;
+
+/// How often run-out entries are dropped.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Failures {
+    count: u32,
+    /// When the current window opened.
+    since: u64,
+    locked_until: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct LoginThrottle {
+    entries: Mutex<HashMap<String, Failures>>,
+}
+
+/// The throttle keys of one attempt: its username and its client address.
+pub fn keys(username: &str, ip: &str) -> [String; 2] {
+    [format!("user:{username}"), format!("ip:{ip}")]
+}
+
+impl LoginThrottle {
+    /// Seconds until `keys` may try again, if any of them is locked out.
+    pub fn locked(&self, keys: &[String], now: u64) -> Option<u64> {
+        let entries = self.entries.lock().unwrap();
+        keys.iter()
+            .filter_map(|k| entries.get(k)?.locked_until)
+            .filter(|until| *until > now)
+            .max()
+            .map(|until| until - now)
+    }
+
+    /// Counts a failure against each of `keys`; returns the lockout (in
+    /// seconds) if this failure started one. No-op when LOGIN_MAX_FAILURES is 0.
+    pub fn fail(&self, cfg: &Config, keys: &[String], now: u64) -> Option<u64> {
+        if cfg.login_max_failures == 0 {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let mut locked = None;
+        for key in keys {
+            let f = entries.entry(key.clone()).or_insert(Failures { count: 0, since: now, locked_until: None });
+            if now >= f.since + cfg.login_window_secs {
+                *f = Failures { count: 0, since: now, locked_until: None };
+            }
+            f.count += 1;
+            if f.count >= cfg.login_max_failures && f.locked_until.is_none_or(|until| until <= now) {
+                f.locked_until = Some(now + cfg.login_lockout_secs);
+                // the window restarts once the lockout ends
+                f.since = now + cfg.login_lockout_secs;
+                f.count = 0;
+                locked = Some(cfg.login_lockout_secs);
+            }
+        }
+        locked
+    }
+
+    /// A successful login clears `key`.
+    pub fn succeed(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops entries whose window and lockout have both passed.
+    fn prune(&self, window: u64, now: u64) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, f| f.locked_until.is_some_and(|until| until > now) || now < f.since + window);
+    }
+}
+
+/// A password check in progress: who is trying, from where.
+pub struct Attempt<'a> {
+    username: &'a str,
+    ip: String,
+    keys: [String; 2],
+}
+
+impl<'a> Attempt<'a> {
+    pub fn new(req: &HttpRequest, cfg: &Config, username: &'a str) -> Self {
+        let ip = client_ip(req, cfg.trust_proxy);
+        let keys = keys(username, &ip);
+        Self { username, ip, keys }
+    }
+
+    /// Refuses the attempt up front while its username or address is locked out.
+    pub fn check(&self, throttle: &LoginThrottle) -> Result<(), actix_web::Error> {
+        match throttle.locked(&self.keys, now_secs()) {
+            Some(retry_after) => Err(locked_out(retry_after)),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a failure (logged by username, never password) and waits out
+    /// LOGIN_FAILURE_DELAY_MS. Returns the 429 when this failure started a
+    /// lockout; otherwise the caller answers with its usual 401.
+    pub async fn failed(&self, throttle: &LoginThrottle, cfg: &Config) -> Result<(), actix_web::Error> {
+        eprintln!("⚠️  failed login for {:?} from {}", self.username, self.ip);
+        let lockout = throttle.fail(cfg, &self.keys, now_secs());
+        failure_delay(cfg).await;
+        match lockout {
+            Some(secs) => {
+                eprintln!("⚠️  locking out {:?} / {} for {secs}s", self.username, self.ip);
+                Err(locked_out(secs))
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn succeeded(&self, throttle: &LoginThrottle) {
+        throttle.succeed(&self.keys[0]);
+    }
+}
+
+/// The 429 for a locked-out attempt, with Retry-After.
+pub fn locked_out(retry_after: u64) -> actix_web::Error {
+    let err = ApiError::new(StatusCode::TOO_MANY_REQUESTS, "too_many_attempts", "too many failed logins; try again later")
+        .with("retry_after", retry_after);
+    let mut resp = err.error_response();
+    resp.headers_mut().insert(header::RETRY_AFTER, header::HeaderValue::from(retry_after));
+    actix_web::error::InternalError::from_response(err, resp).into()
+}
+
+/// The fixed pause every failed attempt costs (LOGIN_FAILURE_DELAY_MS).
+pub async fn failure_delay(cfg: &Config) {
+    if cfg.login_failure_delay_ms > 0 {
+        actix_web::rt::time::sleep(Duration::from_millis(cfg.login_failure_delay_ms)).await;
+    }
+}
+
+/// Starts the background task that prunes the throttle. Call once per
+/// process, from inside the actix runtime.
+pub fn spawn_pruner(state: AppState, cfg: &Config) {
+    if cfg.login_max_failures == 0 {
+        return;
+    }
+    let window = cfg.login_window_secs;
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(PRUNE_INTERVAL);
+        loop {
+            tick.tick().await;
+            state.login_throttle.prune(window, now_secs());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_out_after_max_failures_until_the_cooldown() {
+        let mut cfg = Config::from_env();
+        cfg.login_max_failures = 3;
+        cfg.login_window_secs = 60;
+        cfg.login_lockout_secs = 300;
+        let t = LoginThrottle::default();
+        let alice = keys("alice", "10.0.0.1");
+
+        assert_eq!(t.fail(&cfg, &alice, 100), None);
+        assert_eq!(t.fail(&cfg, &alice, 101), None);
+        assert_eq!(t.locked(&alice, 102), None);
+        assert_eq!(t.fail(&cfg, &alice, 102), Some(300));
+        assert_eq!(t.locked(&alice, 102), Some(300));
+        // the address is locked for other names too
+        assert_eq!(t.locked(&keys("bob", "10.0.0.1"), 200), Some(202));
+        assert_eq!(t.locked(&keys("bob", "10.0.0.2"), 200), None);
+        assert_eq!(t.locked(&alice, 402), None);
+
+        // failures spread beyond the window don't add up
+        let carol = keys("carol", "10.0.0.3");
+        t.fail(&cfg, &carol, 0);
+        t.fail(&cfg, &carol, 30);
+        assert_eq!(t.fail(&cfg, &carol, 61), None);
+
+        // a success clears the username, not the address
+        let dave = keys("dave", "10.0.0.4");
+        t.fail(&cfg, &dave, 0);
+        t.fail(&cfg, &dave, 1);
+        t.succeed(&dave[0]);
+        assert_eq!(t.fail(&cfg, &dave[..1], 2), None);
+        assert_eq!(t.fail(&cfg, &dave[1..], 2), Some(300));
+
+        t.prune(cfg.login_window_secs, 10_000);
+        assert!(t.is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+// src/tls.rs
+//
+// Optional HTTPS: with TLS_CERT_PATH and TLS_KEY_PATH set, main.rs binds
+// with rustls instead of plain HTTP.
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::io;
+use std::sync::Arc;
+
+use crate::consts::Config;
+
+/// The rustls server config for `cfg`, `None` when TLS is not configured.
+/// Setting only one of the two paths is an error rather than silent HTTP.
+pub fn server_config(cfg: &Config) -> io::Result<Option<ServerConfig>> {
+    match (&cfg.tls_cert_path, &cfg.tls_key_path) {
+        (None, None) => Ok(None),
+        (Some(cert), Some(key)) => load(cert, key).map(Some),
+        _ => Err(invalid("TLS_CERT_PATH and TLS_KEY_PATH must be set together".into())),
+    }
+}
+
+/// Loads a PEM certificate chain and private key (PKCS#8, PKCS#1 or SEC1).
+pub fn load(cert_path: &str, key_path: &str) -> io::Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|it| it.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(format!("TLS_CERT_PATH {cert_path}: {e}")))?;
+    if certs.is_empty() {
+        return Err(invalid(format!("TLS_CERT_PATH {cert_path}: no certificates found")));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| invalid(format!("TLS_KEY_PATH {key_path}: {e}")))?;
+
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|b| b.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| invalid(format!("TLS_CERT_PATH/TLS_KEY_PATH: {e}")))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
@@ -0,0 +1,267 @@
+// src/trash.rs
+//
+// Soft delete (TRASH_ENABLED): a DELETE moves the object to
+// `<root>/.trash/<id>/<key>` instead of removing it, where `<id>` is the
+// deletion time in milliseconds plus a random suffix. The sidecar moves to
+// the mirrored `.meta/.trash/<id>/<key>.json`. A background task purges
+// entries older than TRASH_RETENTION_SECS.
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+use crate::consts::Config;
+use crate::dedup;
+use crate::meta::{self, META_DIR};
+use crate::pending::now_secs;
+use crate::storage::key_of;
+use crate::AppState;
+
+/// Directory (directly under the root) holding trashed objects.
+pub const TRASH_DIR: &str = ".trash";
+
+#[derive(Default)]
+pub struct Trash {
+    /// Set by `spawn_purger`; until then deletes are permanent.
+    enabled: AtomicBool,
+    retention_secs: AtomicU64,
+}
+
+/// One trashed object, as served by `GET /trash`.
+#[derive(Serialize, Debug)]
+pub struct TrashEntry {
+    pub id: String,
+    pub key: String,
+    pub size: u64,
+    pub deleted_at: u64,
+    /// When the purge task will remove it; absent with TRASH_RETENTION_SECS=0.
+    pub purge_after: Option<u64>,
+}
+
+pub enum RestoreError {
+    NotFound,
+    /// An object now lives at the original key.
+    Occupied(String),
+    /// Restoring would exceed MAX_OBJECT_COUNT.
+    CountExceeded,
+    Io(io::Error),
+}
+
+impl From<io::Error> for RestoreError {
+    fn from(e: io::Error) -> Self {
+        RestoreError::Io(e)
+    }
+}
+
+impl Trash {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn purge_after(&self, deleted_at: u64) -> Option<u64> {
+        match self.retention_secs.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(deleted_at + secs),
+        }
+    }
+}
+
+/// Deletes the object at `path` (with its sidecar): into the trash when
+/// enabled, for good otherwise. Decrements the object count on success.
+pub async fn discard(state: &AppState, path: &Path) -> io::Result<()> {
+    let removed = remove(state, path).await;
+    state.forget_cached(path);
+    removed
+}
+
+async fn remove(state: &AppState, path: &Path) -> io::Result<()> {
+    let root = &state.root;
+    state.expiries.clear(&key_of(root, path));
+    if !state.trash.enabled() {
+        let blob = if state.dedup.enabled() {
+            meta::load(root, path).await.ok().and_then(|m| dedup::blob_of(&m).map(str::to_string))
+        } else {
+            None
+        };
+        fs::remove_file(path).await?;
+        state.objects.decrement();
+        if let Some(sha) = blob {
+            dedup::release(state, &sha).await?;
+        }
+        return meta::remove(root, path).await;
+    }
+    let rel = path.strip_prefix(root).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path outside root"))?;
+    let dest = root.join(TRASH_DIR).join(new_id()).join(rel);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::rename(path, &dest).await?;
+    state.objects.decrement();
+    // a pending-delete flag must not follow it, or a restart would re-queue it
+    let mut m = meta::load(root, path).await?;
+    m.delete_after = None;
+    meta::store(root, &dest, &m).await?;
+    meta::remove(root, path).await
+}
+
+/// Everything in the trash, oldest first.
+pub async fn list(state: &AppState) -> io::Result<Vec<TrashEntry>> {
+    let mut out = Vec::new();
+    for id in ids(&state.root).await? {
+        let Some(deleted_at) = deleted_at(&id) else { continue };
+        for (key, size) in files(&state.root.join(TRASH_DIR).join(&id)).await? {
+            out.push(TrashEntry { id: id.clone(), key, size, deleted_at, purge_after: state.trash.purge_after(deleted_at) });
+        }
+    }
+    out.sort_by(|a, b| (a.deleted_at, &a.id).cmp(&(b.deleted_at, &b.id)));
+    Ok(out)
+}
+
+/// Moves trash entry `id` back to its original key; returns the key.
+pub async fn restore(state: &AppState, id: &str, max_objects: Option<u64>) -> Result<String, RestoreError> {
+    let dir = entry_dir(&state.root, id).ok_or(RestoreError::NotFound)?;
+    let Some((key, _)) = files(&dir).await?.into_iter().next() else { return Err(RestoreError::NotFound) };
+    let dest = state.root.join(&key);
+    if fs::metadata(&dest).await.is_ok() {
+        return Err(RestoreError::Occupied(key));
+    }
+    let slot = state.objects.try_reserve(max_objects).ok_or(RestoreError::CountExceeded)?;
+    let from = dir.join(&key);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::rename(&from, &dest).await?;
+    slot.commit();
+    let m = meta::load(&state.root, &from).await?;
+    meta::store(&state.root, &dest, &m).await?;
+    state.forget_cached(&dest);
+    if let Some(at) = m.expires_at {
+        state.expiries.set(&key, at);
+    }
+    purge(state, id).await?;
+    Ok(key)
+}
+
+/// Removes trash entry `id` for good; false if there was no such entry.
+pub async fn purge(state: &AppState, id: &str) -> io::Result<bool> {
+    let found = purge_entry(state, id).await?;
+    dedup::collect_later(state);
+    Ok(found)
+}
+
+async fn purge_entry(state: &AppState, id: &str) -> io::Result<bool> {
+    let Some(dir) = entry_dir(&state.root, id) else { return Ok(false) };
+    let found = remove_tree(&dir).await?;
+    remove_tree(&state.root.join(META_DIR).join(TRASH_DIR).join(id)).await?;
+    Ok(found)
+}
+
+/// Empties the trash (or, with `older_than`, only entries deleted before it); returns how many entries went.
+pub async fn purge_all(state: &AppState, older_than: Option<u64>) -> io::Result<usize> {
+    let mut purged = 0;
+    for id in ids(&state.root).await? {
+        let due = match (older_than, deleted_at(&id)) {
+            (None, _) => true,
+            (Some(cutoff), Some(at)) => at < cutoff,
+            (Some(_), None) => false,
+        };
+        if due && purge_entry(state, &id).await? {
+            purged += 1;
+        }
+    }
+    if purged > 0 {
+        dedup::collect_later(state);
+    }
+    Ok(purged)
+}
+
+/// Enables the trash when TRASH_ENABLED is set and starts the retention
+/// purge. Call once per process, from inside the actix runtime.
+pub fn spawn_purger(state: AppState, cfg: &Config) {
+    if !cfg.trash_enabled {
+        return;
+    }
+    state.trash.enabled.store(true, Ordering::Relaxed);
+    state.trash.retention_secs.store(cfg.trash_retention_secs, Ordering::Relaxed);
+    let retention = cfg.trash_retention_secs;
+    if retention == 0 {
+        return;
+    }
+    let every = Duration::from_secs((retention / 2).clamp(1, 60));
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(every);
+        loop {
+            tick.tick().await;
+            let cutoff = now_secs().saturating_sub(retention);
+            if let Err(e) = purge_all(&state, Some(cutoff)).await {
+                eprintln!("⚠️  trash purge failed: {e}");
+            }
+        }
+    });
+}
+
+fn new_id() -> String {
+    let ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    format!("{ms}-{}", &suffix[..8])
+}
+
+/// Deletion time (seconds) encoded in an entry id.
+fn deleted_at(id: &str) -> Option<u64> {
+    let ms: u64 = id.split_once('-')?.0.parse().ok()?;
+    Some(ms / 1000)
+}
+
+/// The entry's directory, if `id` is well-formed (no separators or dots).
+fn entry_dir(root: &Path, id: &str) -> Option<PathBuf> {
+    let ok = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    ok.then(|| root.join(TRASH_DIR).join(id))
+}
+
+async fn ids(root: &Path) -> io::Result<Vec<String>> {
+    let mut rd = match fs::read_dir(root.join(TRASH_DIR)).await {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut out = Vec::new();
+    while let Some(entry) = rd.next_entry().await? {
+        if let Some(name) = entry.file_name().to_str() {
+            out.push(name.to_string());
+        }
+    }
+    Ok(out)
+}
+
+/// Files under `dir` as ('/'-separated path relative to `dir`, size).
+async fn files(dir: &Path) -> io::Result<Vec<(String, u64)>> {
+    let mut out = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let mut rd = match fs::read_dir(&d).await {
+            Ok(rd) => rd,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = rd.next_entry().await? {
+            let ft = entry.file_type().await?;
+            if ft.is_dir() {
+                stack.push(entry.path());
+            } else if ft.is_file() {
+                let rel = key_of(dir, &entry.path());
+                out.push((rel, entry.metadata().await?.len()));
+            }
+        }
+    }
+    Ok(out)
+}
+
+async fn remove_tree(dir: &Path) -> io::Result<bool> {
+    match fs::remove_dir_all(dir).await {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
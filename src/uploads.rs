@@ -0,0 +1,131 @@
+// src/uploads.rs
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+/// In-flight PUTs, shared by every worker (lives in `AppState` behind an `Arc`).
+#[derive(Default)]
+pub struct UploadRegistry {
+    next_id: AtomicU64,
+    entries: Mutex<HashMap<u64, Entry>>,
+}
+
+struct Entry {
+    key: String,
+    sub: Option<String>,
+    total: Option<u64>,
+    received: Arc<AtomicU64>,
+    started_at: SystemTime,
+    started: Instant,
+    cancel: CancellationToken,
+}
+
+/// Snapshot of one upload, as served by `GET /admin/uploads`.
+#[derive(Serialize)]
+pub struct UploadInfo {
+    pub id: u64,
+    pub key: String,
+    pub sub: Option<String>,
+    pub received: u64,
+    pub total: Option<u64>,
+    pub started_at: u64,
+    pub bytes_per_sec: u64,
+}
+
+impl UploadRegistry {
+    /// Registers an upload; the entry lives exactly as long as the returned guard.
+    pub fn begin(self: &Arc<Self>, key: &str, sub: Option<String>, total: Option<u64>) -> UploadGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let received = Arc::new(AtomicU64::new(0));
+        let cancel = CancellationToken::new();
+        let entry = Entry {
+            key: key.to_string(),
+            sub,
+            total,
+            received: received.clone(),
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            cancel: cancel.clone(),
+        };
+        self.entries.lock().unwrap().insert(id, entry);
+        UploadGuard { id, registry: self.clone(), received, cancel }
+    }
+
+    /// Signals the upload to abort; returns false if no such upload is running.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.entries.lock().unwrap().get(&id) {
+            Some(e) => {
+                e.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<UploadInfo> {
+        let entries = self.entries.lock().unwrap();
+        let mut out: Vec<UploadInfo> = entries
+            .iter()
+            .map(|(id, e)| {
+                let received = e.received.load(Ordering::Relaxed);
+                UploadInfo {
+                    id: *id,
+                    key: e.key.clone(),
+                    sub: e.sub.clone(),
+                    received,
+                    total: e.total,
+                    started_at: e.started_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                    bytes_per_sec: rate(received, e.started),
+                }
+            })
+            .collect();
+        out.sort_by_key(|u| u.id);
+        out
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Aggregate receive rate across all in-flight uploads.
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|e| rate(e.received.load(Ordering::Relaxed), e.started))
+            .sum()
+    }
+}
+
+fn rate(bytes: u64, since: Instant) -> u64 {
+    let secs = since.elapsed().as_secs_f64();
+    if secs <= 0.0 { 0 } else { (bytes as f64 / secs) as u64 }
+}
+
+/// Held by `put_object` for the duration of the stream; dropping it deregisters.
+pub struct UploadGuard {
+    id: u64,
+    registry: Arc<UploadRegistry>,
+    received: Arc<AtomicU64>,
+    cancel: CancellationToken,
+}
+
+impl UploadGuard {
+    pub fn add(&self, n: u64) {
+        self.received.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn cancelled(&self) -> &CancellationToken {
+        &self.cancel
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        self.registry.entries.lock().unwrap().remove(&self.id);
+    }
+}
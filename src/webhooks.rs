@@ -0,0 +1,145 @@
+// src/webhooks.rs
+//
+// Object event notifications (WEBHOOK_URL). Handlers enqueue an event and
+// move on; one background task POSTs events in order, retrying with
+// exponential backoff. The queue is bounded and drops its oldest event when
+// full, so delivery never blocks or fails a client request.
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::consts::Config;
+use crate::events::Event;
+use crate::AppState;
+
+/// `sha256=<hex HMAC-SHA256 of the body>`, sent when WEBHOOK_SECRET is set.
+pub const SIGNATURE_HEADER: &str = "x-bucket-signature";
+/// The event name, so receivers can route without parsing the body.
+pub const EVENT_HEADER: &str = "x-bucket-event";
+
+const MAX_ATTEMPTS: u32 = 6;
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct Webhooks {
+    /// Set by `spawn_sender`; until then `emit` is a no-op.
+    enabled: AtomicBool,
+    capacity: AtomicUsize,
+    queue: Mutex<VecDeque<Event>>,
+    ready: Notify,
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Webhooks {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Queues `event` for delivery, evicting the oldest one if the queue is full.
+    pub fn emit(&self, event: Event) {
+        if !self.enabled() {
+            return;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.capacity.load(Ordering::Relaxed).max(1) {
+            queue.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(event);
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Events abandoned after `MAX_ATTEMPTS`.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    async fn next(&self) -> Event {
+        loop {
+            if let Some(event) = self.queue.lock().unwrap().pop_front() {
+                return event;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    async fn deliver(&self, client: &reqwest::Client, url: &str, secret: Option<&str>, event: &Event) {
+        let body = match serde_json::to_vec(event) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("⚠️  webhook event for {} not serializable: {e}", event.key);
+                return;
+            }
+        };
+        let mut backoff = FIRST_BACKOFF;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let mut req = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event.event)
+                .body(body.clone());
+            if let Some(secret) = secret {
+                req = req.header(SIGNATURE_HEADER, sign(secret, &body));
+            }
+            let outcome = match req.send().await {
+                Ok(resp) if resp.status().is_success() => {
+                    self.delivered.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Ok(resp) => format!("HTTP {}", resp.status()),
+                Err(e) => e.to_string(),
+            };
+            eprintln!("⚠️  webhook {} {} attempt {attempt}/{MAX_ATTEMPTS} failed: {outcome}", event.event, event.key);
+            if attempt < MAX_ATTEMPTS {
+                actix_web::rt::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// `sha256=` + hex HMAC-SHA256 of `body` keyed with `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Enables webhooks and starts the delivery task when WEBHOOK_URL is set.
+/// Call once per process, from inside the actix runtime.
+pub fn spawn_sender(state: AppState, cfg: &Config) {
+    let Some(url) = cfg.webhook_url.clone() else { return };
+    let secret = cfg.webhook_secret.clone();
+    let hooks = state.webhooks.clone();
+    hooks.capacity.store(cfg.webhook_queue_size, Ordering::Relaxed);
+    hooks.enabled.store(true, Ordering::Relaxed);
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default();
+    actix_web::rt::spawn(async move {
+        loop {
+            let event = hooks.next().await;
+            hooks.deliver(&client, &url, secret.as_deref(), &event).await;
+        }
+    });
+}
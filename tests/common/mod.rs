@@ -0,0 +1,146 @@
+// tests/common/mod.rs
+//
+// Shared by every integration test crate: an in-process server on a random
+// port over a temp root, plus the scenario runner.
+#![allow(dead_code)]
+
+pub mod scenario;
+
+use actix_web::HttpServer;
+use reqwest::Client;
+use std::{net::TcpListener, time::Duration};
+use tempfile::TempDir;
+
+use rust_buck3t::{app, consts, AppState};
+
+/// Config with auth disabled, so object tests don't depend on the caller's env.
+pub fn open_cfg() -> consts::Config {
+    let mut cfg = consts::Config::from_env();
+    cfg.auth_mode = consts::AuthMode::Off;
+    cfg
+}
+
+pub fn start_server(cfg: consts::Config) -> (String, TempDir) {
+    let td = TempDir::new().unwrap();
+    let state = AppState::new(td.path()).with_auth(&cfg).unwrap().with_buckets(&cfg).unwrap().with_limits(&cfg).with_caches(&cfg).with_listing(&cfg);
+    rust_buck3t::pending::spawn_sweeper(state.clone());
+    rust_buck3t::expiry::spawn_sweeper(state.clone(), &cfg);
+    rust_buck3t::webhooks::spawn_sender(state.clone(), &cfg);
+    rust_buck3t::trash::spawn_purger(state.clone(), &cfg);
+    rust_buck3t::dedup::start(state.clone(), &cfg);
+    rust_buck3t::resumable::spawn_sweeper(state.clone(), &cfg);
+    rust_buck3t::s3::spawn_sweeper(state.clone(), &cfg);
+    rust_buck3t::throttle::spawn_pruner(state.clone(), &cfg);
+    rust_buck3t::idp::spawn_rotator(state.clone(), &cfg);
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = HttpServer::new(move || app(state.clone(), cfg.clone()))
+        .listen(listener)
+        .unwrap()
+        .run();
+
+    actix_web::rt::spawn(server);
+    (format!("http://{}", addr), td)
+}
+
+pub async fn wait_alive(base: &str) {
+    let client = Client::new();
+    for _ in 0..20 {
+        if let Ok(resp) = client.get(format!("{base}/healthz")).send().await {
+            if resp.status().is_success() {
+                return;
+            }
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(25)).await;
+    }
+    panic!("server did not become ready");
+}
+
+/// SigV4 header-auth for `method` on `url` (path and query already
+/// URI-encoded, query pairs in canonical order), the way the AWS SDKs sign
+/// with an unsigned payload. Returns the headers to add.
+pub fn sigv4(method: &str, url: &str, access_key: &str, secret: &str) -> Vec<(&'static str, String)> {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+    fn mac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut m = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        m.update(data.as_bytes());
+        m.finalize().into_bytes().to_vec()
+    }
+
+    let url = reqwest::Url::parse(url).unwrap();
+    let host = format!("{}:{}", url.host_str().unwrap(), url.port().unwrap());
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let amz_date: String = rust_buck3t::s3::iso8601(now).replace(".000", "").chars().filter(|c| *c != '-' && *c != ':').collect();
+    let day = &amz_date[..8];
+    let scope = format!("{day}/us-east-1/s3/aws4_request");
+
+    let canonical = format!(
+        "{method}\n{}\n{}\nhost:{host}\nx-amz-content-sha256:UNSIGNED-PAYLOAD\nx-amz-date:{amz_date}\n\nhost;x-amz-content-sha256;x-amz-date\nUNSIGNED-PAYLOAD",
+        url.path(),
+        url.query().unwrap_or(""),
+    );
+    let to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", hex::encode(Sha256::digest(canonical.as_bytes())));
+    let mut key = mac(format!("AWS4{secret}").as_bytes(), day);
+    for part in ["us-east-1", "s3", "aws4_request"] {
+        key = mac(&key, part);
+    }
+    let signature = hex::encode(mac(&key, &to_sign));
+    vec![
+        ("x-amz-date", amz_date),
+        ("x-amz-content-sha256", "UNSIGNED-PAYLOAD".into()),
+        (
+            "authorization",
+            format!("AWS4-HMAC-SHA256 Credential={access_key}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}"),
+        ),
+    ]
+}
+
+/// A running `rust-buck3t` binary (for behaviour that lives in `main.rs`).
+/// Killed on drop.
+pub struct Bin {
+    pub child: std::process::Child,
+    pub base: String,
+    pub root: TempDir,
+}
+
+impl Drop for Bin {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Starts the binary on a free port over a temp root, auth off unless `envs`
+/// say otherwise, with stdout/stderr piped.
+pub fn spawn_bin(envs: &[(&str, &str)]) -> Bin {
+    let root = TempDir::new().unwrap();
+    let port = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+    let mut cmd = std::process::Command::new(env!("CARGO_BIN_EXE_rust-buck3t"));
+    cmd.env("HOST", "127.0.0.1")
+        .env("PORT", port.to_string())
+        .env("RUST_BUCKET_DIR", root.path())
+        .env("AUTH_MODE", "off")
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    for (k, v) in envs {
+        cmd.env(k, v);
+    }
+    let child = cmd.spawn().unwrap();
+    Bin { child, base: format!("http://127.0.0.1:{port}"), root }
+}
+
+/// Polls until the binary answers `/healthz` (over `client`, so TLS works too).
+pub async fn wait_bin(client: &Client, base: &str) {
+    for _ in 0..200 {
+        if let Ok(resp) = client.get(format!("{base}/healthz")).send().await {
+            if resp.status().is_success() {
+                return;
+            }
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(25)).await;
+    }
+    panic!("binary did not become ready at {base}");
+}
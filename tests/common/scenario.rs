@@ -0,0 +1,352 @@
+// tests/common/scenario.rs
+//
+// Table-driven HTTP scenarios: a config tweak plus an ordered list of
+// requests, each with the status/headers/body it must produce. Every
+// scenario runs against its own in-process server and temp root.
+//
+//     Scenario::new("overwrite guarded by If-Match")
+//         .step(put("/objects/a.txt").body("x").expect(201))
+//         .step(head("/objects/a.txt").expect(200).capture("etag", "etag"))
+//         .step(put("/objects/a.txt").header("if-match", "${etag}").body("y").expect(200))
+//
+// `${name}` in paths and header values expands to a value captured earlier.
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_buck3t::consts::{AuthMode, Config};
+
+use super::{open_cfg, start_server, wait_alive};
+
+/// HS256 secret used by `.config(hs256)` and `.auth(scopes)`.
+pub const TEST_SECRET: &str = "scenario-secret";
+
+pub struct Scenario {
+    name: &'static str,
+    cfg: Config,
+    steps: Vec<Step>,
+}
+
+pub struct Step {
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    json: Option<Value>,
+    scopes: Option<String>,
+    status: u16,
+    expect_headers: Vec<(String, HeaderCheck)>,
+    expect_body: Vec<BodyCheck>,
+    captures: Vec<(String, String)>,
+}
+
+enum HeaderCheck {
+    Equals(String),
+    StartsWith(String),
+    Present,
+    Absent,
+}
+
+enum BodyCheck {
+    Exact(Vec<u8>),
+    Contains(String),
+    /// JSON pointer → expected value.
+    Json(String, Value),
+    /// JSON pointer → array length.
+    JsonLen(String, usize),
+}
+
+/// Config tweak: HS256 auth on every route class, signed with `TEST_SECRET`.
+pub fn hs256(cfg: &mut Config) {
+    cfg.auth_mode = AuthMode::JwtHs256;
+    cfg.jwt_hs_secret = Some(TEST_SECRET.into());
+    cfg.auth_write = true;
+    cfg.auth_read = true;
+    cfg.auth_list = true;
+}
+
+impl Scenario {
+    /// Starts from `open_cfg()` (auth off).
+    pub fn new(name: &'static str) -> Self {
+        Self { name, cfg: open_cfg(), steps: Vec::new() }
+    }
+
+    pub fn config(mut self, f: impl FnOnce(&mut Config)) -> Self {
+        f(&mut self.cfg);
+        self
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Runs every step in order; returns a description of each failed expectation.
+    async fn run(self) -> Vec<String> {
+        let (base, _td) = start_server(self.cfg.clone());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let mut vars: HashMap<String, String> = HashMap::new();
+        let mut failures = Vec::new();
+
+        for (i, step) in self.steps.iter().enumerate() {
+            let path = expand(&step.path, &vars);
+            let at = format!("[{}] step {} {} {}", self.name, i + 1, step.method, path);
+            let mut req = client.request(step.method.clone(), format!("{base}{path}"));
+            for (name, value) in &step.headers {
+                req = req.header(name.as_str(), expand(value, &vars));
+            }
+            if let Some(scopes) = &step.scopes {
+                req = req.bearer_auth(mint(&self.cfg, scopes));
+            }
+            if let Some(json) = &step.json {
+                req = req.json(json);
+            } else if let Some(body) = &step.body {
+                req = req.body(body.clone());
+            }
+
+            let resp = match req.send().await {
+                Ok(r) => r,
+                Err(e) => {
+                    failures.push(format!("{at}: request failed: {e}"));
+                    break;
+                }
+            };
+            let status = resp.status().as_u16();
+            let headers = resp.headers().clone();
+            let body = resp.bytes().await.map(|b| b.to_vec()).unwrap_or_default();
+
+            if status != step.status {
+                failures.push(format!(
+                    "{at}: expected status {}, got {status} ({})",
+                    step.status,
+                    String::from_utf8_lossy(&body)
+                ));
+            }
+            for (name, check) in &step.expect_headers {
+                let got = headers.get(name.as_str()).and_then(|v| v.to_str().ok());
+                let ok = match (check, got) {
+                    (HeaderCheck::Equals(want), Some(v)) => v == expand(want, &vars),
+                    (HeaderCheck::StartsWith(want), Some(v)) => v.starts_with(want.as_str()),
+                    (HeaderCheck::Present, Some(_)) | (HeaderCheck::Absent, None) => true,
+                    _ => false,
+                };
+                if !ok {
+                    failures.push(format!("{at}: header `{name}` failed {}, got {got:?}", check.describe()));
+                }
+            }
+            for check in &step.expect_body {
+                if let Err(e) = check.verify(&body) {
+                    failures.push(format!("{at}: {e}"));
+                }
+            }
+            for (var, header) in &step.captures {
+                match headers.get(header.as_str()).and_then(|v| v.to_str().ok()) {
+                    Some(v) => {
+                        vars.insert(var.clone(), v.to_string());
+                    }
+                    None => failures.push(format!("{at}: no `{header}` header to capture")),
+                }
+            }
+        }
+        failures
+    }
+}
+
+impl HeaderCheck {
+    fn describe(&self) -> String {
+        match self {
+            HeaderCheck::Equals(v) => format!("== {v:?}"),
+            HeaderCheck::StartsWith(v) => format!("starts with {v:?}"),
+            HeaderCheck::Present => "present".into(),
+            HeaderCheck::Absent => "absent".into(),
+        }
+    }
+}
+
+impl BodyCheck {
+    fn verify(&self, body: &[u8]) -> Result<(), String> {
+        let text = || String::from_utf8_lossy(body).into_owned();
+        let json = || serde_json::from_slice::<Value>(body).map_err(|e| format!("body is not JSON ({e}): {}", text()));
+        match self {
+            BodyCheck::Exact(want) if body == want.as_slice() => Ok(()),
+            BodyCheck::Exact(want) => Err(format!("body {:?} != {:?}", text(), String::from_utf8_lossy(want))),
+            BodyCheck::Contains(want) if text().contains(want.as_str()) => Ok(()),
+            BodyCheck::Contains(want) => Err(format!("body {:?} lacks {want:?}", text())),
+            BodyCheck::Json(ptr, want) => match json()?.pointer(ptr) {
+                Some(got) if got == want => Ok(()),
+                got => Err(format!("json {ptr} == {want}, got {got:?}")),
+            },
+            BodyCheck::JsonLen(ptr, want) => match json()?.pointer(ptr).and_then(|v| v.as_array()).map(|a| a.len()) {
+                Some(n) if n == *want => Ok(()),
+                got => Err(format!("json {ptr} has {want} items, got {got:?}")),
+            },
+        }
+    }
+}
+
+impl Step {
+    fn new(method: Method, path: &str) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: None,
+            json: None,
+            scopes: None,
+            status: 200,
+            expect_headers: Vec::new(),
+            expect_body: Vec::new(),
+            captures: Vec::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn json(mut self, json: Value) -> Self {
+        self.json = Some(json);
+        self
+    }
+
+    /// Sends a fresh HS256 bearer token carrying `scopes` (space-delimited).
+    pub fn auth(mut self, scopes: &str) -> Self {
+        self.scopes = Some(scopes.to_string());
+        self
+    }
+
+    pub fn expect(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn expect_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.expect_headers.push((name.to_string(), HeaderCheck::Equals(value.into())));
+        self
+    }
+
+    pub fn expect_header_prefix(mut self, name: &str, prefix: impl Into<String>) -> Self {
+        self.expect_headers.push((name.to_string(), HeaderCheck::StartsWith(prefix.into())));
+        self
+    }
+
+    pub fn expect_header_present(mut self, name: &str) -> Self {
+        self.expect_headers.push((name.to_string(), HeaderCheck::Present));
+        self
+    }
+
+    pub fn expect_header_absent(mut self, name: &str) -> Self {
+        self.expect_headers.push((name.to_string(), HeaderCheck::Absent));
+        self
+    }
+
+    pub fn expect_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.expect_body.push(BodyCheck::Exact(body.into()));
+        self
+    }
+
+    pub fn expect_body_contains(mut self, needle: &str) -> Self {
+        self.expect_body.push(BodyCheck::Contains(needle.to_string()));
+        self
+    }
+
+    /// The JSON body's value at `pointer` (RFC 6901, e.g. `/0/key`) equals `value`.
+    pub fn expect_json(mut self, pointer: &str, value: impl Into<Value>) -> Self {
+        self.expect_body.push(BodyCheck::Json(pointer.to_string(), value.into()));
+        self
+    }
+
+    /// The JSON array at `pointer` (`""` for the whole body) has `len` items.
+    pub fn expect_json_len(mut self, pointer: &str, len: usize) -> Self {
+        self.expect_body.push(BodyCheck::JsonLen(pointer.to_string(), len));
+        self
+    }
+
+    /// Shorthand for structured errors: `{"error": code}`.
+    pub fn expect_error(self, code: &str) -> Self {
+        self.expect_json("/error", code)
+    }
+
+    /// Saves response header `header` as `${var}` for later steps.
+    pub fn capture(mut self, var: &str, header: &str) -> Self {
+        self.captures.push((var.to_string(), header.to_string()));
+        self
+    }
+}
+
+pub fn get(path: &str) -> Step {
+    Step::new(Method::GET, path)
+}
+
+pub fn head(path: &str) -> Step {
+    Step::new(Method::HEAD, path)
+}
+
+pub fn put(path: &str) -> Step {
+    Step::new(Method::PUT, path)
+}
+
+pub fn post(path: &str) -> Step {
+    Step::new(Method::POST, path)
+}
+
+pub fn patch(path: &str) -> Step {
+    Step::new(Method::PATCH, path)
+}
+
+pub fn delete(path: &str) -> Step {
+    Step::new(Method::DELETE, path)
+}
+
+pub fn options(path: &str) -> Step {
+    Step::new(Method::OPTIONS, path)
+}
+
+/// Runs each scenario on a fresh server and panics with every failed expectation.
+pub fn run(scenarios: Vec<Scenario>) {
+    actix_web::rt::System::new().block_on(async {
+        let mut failures = Vec::new();
+        for s in scenarios {
+            failures.extend(s.run().await);
+        }
+        assert!(failures.is_empty(), "scenario failures:\n  {}", failures.join("\n  "));
+    });
+}
+
+fn expand(s: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = s.to_string();
+    for (k, v) in vars {
+        out = out.replace(&format!("${{{k}}}"), v);
+    }
+    out
+}
+
+/// A `TEST_SECRET` token for `scopes`, for steps that carry it somewhere
+/// other than the Authorization header.
+pub fn hs256_token(scopes: &str) -> String {
+    let mut cfg = open_cfg();
+    hs256(&mut cfg);
+    mint(&cfg, scopes)
+}
+
+fn mint(cfg: &Config, scopes: &str) -> String {
+    let secret = cfg.jwt_hs_secret.as_deref().unwrap_or(TEST_SECRET);
+    let exp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 300;
+    let mut claims = serde_json::json!({ "sub": "scenario", "scope": scopes, "exp": exp });
+    if let Some(iss) = cfg.jwt_issuers.first() {
+        claims["iss"] = iss.clone().into();
+    }
+    if let Some(aud) = &cfg.jwt_audience {
+        claims["aud"] = aud.clone().into();
+    }
+    encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+}
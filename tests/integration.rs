@@ -1,374 +1,3510 @@
 // tests/integration.rs
-use actix_web::HttpServer;
+//
+// Flows that need more than request/response tables (stalled bodies, file
+// system inspection, restarts). Plain HTTP contracts live in tests/scenarios.rs.
+mod common;
+
+use common::{open_cfg, start_server, wait_alive};
 use reqwest::{header, Client};
-use std::{net::TcpListener, time::Duration};
+use std::time::Duration;
 use tempfile::TempDir;
 
-use rust_buck3t::{app, AppState, consts};
+use rust_buck3t::{consts, AppState};
+
+#[test]
+fn if_unmodified_since_put_and_delete() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
 
-fn start_server(cfg: consts::Config) -> (String, TempDir) {
-    let td = TempDir::new().unwrap();
-    let state = AppState { root: td.path().into() };
+        let key = "t/ius.txt";
+        let _ = client
+            .put(format!("{base}/objects/{key}"))
+            .body("x")
+            .send()
+            .await
+            .unwrap();
 
-    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
-    let addr = listener.local_addr().unwrap();
+        let mtime = std::fs::metadata(td.path().join(key)).unwrap().modified().unwrap();
+        let at_mtime = actix_web::http::header::HttpDate::from(mtime).to_string();
+        let before = actix_web::http::header::HttpDate::from(mtime - Duration::from_secs(10)).to_string();
 
-    let server = HttpServer::new(move || app(state.clone(), cfg.clone()))
-        .listen(listener)
-        .unwrap()
-        .run();
+        // modified after the supplied date -> 412, nothing changes
+        let put_stale = client
+            .put(format!("{base}/objects/{key}"))
+            .header(header::IF_UNMODIFIED_SINCE, before.clone())
+            .body("y")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(put_stale.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        let del_stale = client
+            .delete(format!("{base}/objects/{key}"))
+            .header(header::IF_UNMODIFIED_SINCE, before)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(del_stale.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        let body = client
+            .get(format!("{base}/objects/{key}"))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "x");
+
+        // mtime equal to the header (second resolution) -> allowed
+        let put_equal = client
+            .put(format!("{base}/objects/{key}"))
+            .header(header::IF_UNMODIFIED_SINCE, at_mtime)
+            .body("z")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(put_equal.status(), reqwest::StatusCode::OK);
+
+        let mtime = std::fs::metadata(td.path().join(key)).unwrap().modified().unwrap();
+        let at_mtime = actix_web::http::header::HttpDate::from(mtime).to_string();
+        let del_equal = client
+            .delete(format!("{base}/objects/{key}"))
+            .header(header::IF_UNMODIFIED_SINCE, at_mtime.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(del_equal.status(), reqwest::StatusCode::NO_CONTENT);
 
-    actix_web::rt::spawn(server);
-    (format!("http://{}", addr), td)
+        // missing target behaves as before: PUT creates, DELETE 404s
+        let del_missing = client
+            .delete(format!("{base}/objects/{key}"))
+            .header(header::IF_UNMODIFIED_SINCE, at_mtime.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(del_missing.status(), reqwest::StatusCode::NOT_FOUND);
+        let put_missing = client
+            .put(format!("{base}/objects/{key}"))
+            .header(header::IF_UNMODIFIED_SINCE, at_mtime)
+            .body("new")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(put_missing.status(), reqwest::StatusCode::CREATED);
+    });
 }
 
-async fn wait_alive(base: &str) {
-    let client = Client::new();
-    for _ in 0..20 {
-        if let Ok(resp) = client.get(format!("{base}/healthz")).send().await {
-            if resp.status().is_success() {
-                return;
+/// Body that sends `first`, then stalls until `release` fires (or forever if it's dropped).
+fn stalled_body(first: &'static [u8], release: tokio::sync::oneshot::Receiver<()>) -> reqwest::Body {
+    let stream = futures_util::stream::unfold((0u8, Some(release)), move |(step, rx)| async move {
+        match step {
+            0 => Some((Ok::<_, std::io::Error>(first.to_vec()), (1, rx))),
+            1 => {
+                if rx.unwrap().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+                Some((Ok(b"rest".to_vec()), (2, None)))
             }
+            _ => None,
+        }
+    });
+    reqwest::Body::wrap_stream(stream)
+}
+
+async fn wait_for_uploads(client: &Client, base: &str, n: usize) -> Vec<serde_json::Value> {
+    for _ in 0..80 {
+        let v: Vec<serde_json::Value> = client
+            .get(format!("{base}/admin/uploads"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if v.len() == n && v.iter().all(|u| u["received"].as_u64() > Some(0)) {
+            return v;
         }
         actix_web::rt::time::sleep(Duration::from_millis(25)).await;
     }
-    panic!("server did not become ready");
+    panic!("expected {n} in-flight uploads");
 }
 
 #[test]
-fn healthz_ok() {
+fn upload_progress_listed_and_cancellable() {
     actix_web::rt::System::new().block_on(async {
-        let (base, _td) = start_server(consts::Config::from_env());
+        let (base, td) = start_server(open_cfg());
         wait_alive(&base).await;
+        let client = Client::new();
+
+        // slow upload shows up in the admin listing and metrics before it completes
+        let (release, rx) = tokio::sync::oneshot::channel();
+        let put = actix_web::rt::spawn(
+            client
+                .put(format!("{base}/objects/t/slow.bin"))
+                .body(stalled_body(b"abcd", rx))
+                .send(),
+        );
+        let listed = wait_for_uploads(&client, &base, 1).await;
+        assert_eq!(listed[0]["key"], "t/slow.bin");
+        assert_eq!(listed[0]["received"], 4);
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_uploads_in_flight 1"));
+
+        release.send(()).unwrap();
+        let done = put.await.unwrap().unwrap();
+        assert_eq!(done.status(), reqwest::StatusCode::CREATED);
+        wait_for_uploads(&client, &base, 0).await;
 
-        let resp = Client::new()
-            .get(format!("{base}/healthz"))
+        // cancel aborts the transfer and removes the partial file
+        let (_hold, rx) = tokio::sync::oneshot::channel::<()>();
+        let put = actix_web::rt::spawn(
+            client
+                .put(format!("{base}/objects/t/cancel.bin"))
+                .body(stalled_body(b"abcd", rx))
+                .send(),
+        );
+        let listed = wait_for_uploads(&client, &base, 1).await;
+        let id = listed[0]["id"].as_u64().unwrap();
+        let cancel = client
+            .delete(format!("{base}/admin/uploads/{id}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(cancel.status(), reqwest::StatusCode::ACCEPTED);
+        if let Ok(resp) = put.await.unwrap() {
+            assert_eq!(resp.status(), reqwest::StatusCode::CONFLICT);
+        }
+        wait_for_uploads(&client, &base, 0).await;
+        assert!(!td.path().join("t/cancel.bin").exists());
+
+        let missing = client
+            .delete(format!("{base}/admin/uploads/{id}"))
             .send()
             .await
             .unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    });
+}
+
+#[test]
+fn concurrency_limits_turn_away_excess_transfers() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.max_concurrent_uploads = Some(2);
+        cfg.max_concurrent_downloads = Some(1);
+        cfg.concurrency_wait_ms = 50;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        // two slow uploads take both slots; the third is turned away
+        let (mut releases, mut puts) = (Vec::new(), Vec::new());
+        for key in ["a.bin", "b.bin"] {
+            let (release, rx) = tokio::sync::oneshot::channel();
+            releases.push(release);
+            puts.push(actix_web::rt::spawn(client.put(format!("{base}/objects/{key}")).body(stalled_body(b"abcd", rx)).send()));
+        }
+        wait_for_uploads(&client, &base, 2).await;
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_upload_slots_in_use 2"), "{metrics}");
+        assert!(metrics.contains("bucket_upload_slots_limit 2"));
+
+        let resp = client.put(format!("{base}/objects/c.bin")).body("x").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()["retry-after"], "1");
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!((body["error"].as_str(), body["limit"].as_u64()), (Some("too_busy"), Some(2)));
+        assert!(!td.path().join("c.bin").exists());
+
+        // reads don't share the upload slots
+        std::fs::write(td.path().join("big.bin"), vec![7u8; 32 * 1024 * 1024]).unwrap();
+        assert_eq!(client.head(format!("{base}/objects/big.bin")).send().await.unwrap().status(), reqwest::StatusCode::OK);
+
+        for release in releases {
+            release.send(()).unwrap();
+        }
+        for put in puts {
+            assert_eq!(put.await.unwrap().unwrap().status(), reqwest::StatusCode::CREATED);
+        }
+        let resp = client.put(format!("{base}/objects/c.bin")).body("x").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::CREATED);
+
+        // an unread download keeps its slot while it streams
+        let stalled = client.get(format!("{base}/objects/big.bin")).send().await.unwrap();
+        assert_eq!(stalled.status(), reqwest::StatusCode::OK);
+        let resp = client.get(format!("{base}/objects/a.bin")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()["retry-after"], "1");
+        drop(stalled);
+        let mut freed = false;
+        for _ in 0..100 {
+            let resp = client.get(format!("{base}/objects/a.bin")).send().await.unwrap();
+            if resp.status() == reqwest::StatusCode::OK {
+                assert_eq!(resp.bytes().await.unwrap().as_ref(), b"abcdrest");
+                freed = true;
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(freed, "the dropped download never gave its slot back");
+    });
+}
+
+#[test]
+fn upload_rate_limit_slows_puts() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.upload_rate_limit_bps = Some(256 * 1024);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        // a second's burst goes through at once; the rest is paced at the cap
+        let payload = vec![1u8; 512 * 1024];
+        let started = std::time::Instant::now();
+        let resp = client.put(format!("{base}/objects/slow.bin")).body(payload.clone()).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::CREATED);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(800), "{elapsed:?}");
+        assert_eq!(std::fs::read(td.path().join("slow.bin")).unwrap(), payload);
+    });
+}
+
+#[test]
+fn download_rate_limits_pace_bodies() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.download_rate_limit_bps = Some(1024 * 1024);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        std::fs::write(td.path().join("big.bin"), vec![3u8; 2 * 1024 * 1024]).unwrap();
+        std::fs::write(td.path().join("small.bin"), vec![4u8; 128 * 1024]).unwrap();
+
+        // a second's burst, then the global cap
+        let started = std::time::Instant::now();
+        let body = client.get(format!("{base}/objects/big.bin")).send().await.unwrap().bytes().await.unwrap();
+        assert_eq!(body.len(), 2 * 1024 * 1024);
+        assert!(started.elapsed() >= Duration::from_millis(800), "{:?}", started.elapsed());
+        let stats: serde_json::Value = client.get(format!("{base}/stats")).send().await.unwrap().json().await.unwrap();
+        assert!(stats["download_bytes_per_second"].as_u64().is_some());
+
+        // a client may slow itself further, ranges included
+        let started = std::time::Instant::now();
+        let resp = client.get(format!("{base}/objects/small.bin")).header("x-bkt-max-rate", "64K").header("range", "bytes=0-131071").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.bytes().await.unwrap().len(), 128 * 1024);
+        assert!(started.elapsed() >= Duration::from_millis(800), "{:?}", started.elapsed());
 
-        assert!(resp.status().is_success());
-        assert_eq!(resp.text().await.unwrap(), "ok");
+        // archives flow through the same pacing
+        let started = std::time::Instant::now();
+        let resp = client.get(format!("{base}/objects-archive?prefix=small.bin&format=tar")).header("x-bkt-max-rate", "64K").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert!(resp.bytes().await.unwrap().len() > 128 * 1024);
+        assert!(started.elapsed() >= Duration::from_millis(800), "{:?}", started.elapsed());
+
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_download_rate_limit_bytes_per_second 1048576"));
+        assert!(metrics.contains("bucket_download_bytes_per_second "));
+
+        let resp = client.get(format!("{base}/objects/small.bin")).header("x-bkt-max-rate", "fast").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_max_rate");
     });
 }
 
 #[test]
-fn put_and_head_inline_attachment() {
+fn stalled_upload_bodies_time_out() {
     actix_web::rt::System::new().block_on(async {
-        let (base, _td) = start_server(consts::Config::from_env());
+        let mut cfg = open_cfg();
+        cfg.upload_idle_timeout_secs = 1;
+        cfg.upload_deadline_secs = 2;
+        cfg.max_concurrent_uploads = Some(1);
+        let (base, td) = start_server(cfg);
         wait_alive(&base).await;
         let client = Client::new();
 
-        // PUT
-        let key = "t/one.txt";
+        // one byte, then silence: 408, no partial file, the slot is free again
+        let (_hold, rx) = tokio::sync::oneshot::channel::<()>();
+        let resp = client.put(format!("{base}/objects/stalled.bin")).body(stalled_body(b"a", rx)).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::REQUEST_TIMEOUT);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"], "body_timeout");
+        assert!(!td.path().join("stalled.bin").exists());
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).map(|d| d.count()).unwrap_or(0), 0);
+        wait_for_uploads(&client, &base, 0).await;
+        let resp = client.put(format!("{base}/objects/next.bin")).body("x").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::CREATED);
+
+        // an append stalls out the same way and is truncated back off
+        let (_hold, rx) = tokio::sync::oneshot::channel::<()>();
         let resp = client
-            .put(format!("{base}/objects/{key}"))
-            .body("abc")
+            .patch(format!("{base}/objects/next.bin"))
+            .header("content-range", "bytes 1-*/*")
+            .body(stalled_body(b"a", rx))
             .send()
             .await
             .unwrap();
-        assert!(resp.status().is_success());
+        assert_eq!(resp.status(), reqwest::StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(std::fs::read_to_string(td.path().join("next.bin")).unwrap(), "x");
+        wait_for_uploads(&client, &base, 0).await;
 
-        // HEAD default (attachment)
-        let head = client
-            .head(format!("{base}/objects/{key}"))
-            .send()
-            .await
-            .unwrap();
-        assert!(head.status().is_success());
-        let disp = head
-            .headers()
-            .get(header::CONTENT_DISPOSITION)
-            .unwrap()
-            .to_str()
-            .unwrap();
-        assert!(disp.starts_with("attachment"));
-        assert_eq!(
-            head.headers()
-                .get(header::ACCEPT_RANGES)
-                .unwrap()
-                .to_str()
-                .unwrap(),
-            "bytes"
-        );
-        assert!(head.headers().get(header::ETAG).is_some());
+        // a body that keeps trickling in still hits the overall deadline
+        let trickle = futures_util::stream::unfold(0u32, |n| async move {
+            actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+            (n < 50).then(|| (Ok::<_, std::io::Error>(b"x".to_vec()), n + 1))
+        });
+        let started = std::time::Instant::now();
+        let resp = client.put(format!("{base}/objects/trickle.bin")).body(reqwest::Body::wrap_stream(trickle)).send().await;
+        if let Ok(resp) = resp {
+            assert_eq!(resp.status(), reqwest::StatusCode::REQUEST_TIMEOUT);
+            let body: serde_json::Value = resp.json().await.unwrap();
+            assert_eq!(body["error"], "upload_deadline");
+        }
+        assert!(started.elapsed() < Duration::from_secs(8));
+        assert!(!td.path().join("trickle.bin").exists());
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).map(|d| d.count()).unwrap_or(0), 0);
+    });
+}
 
-        // HEAD inline
-        let head_inline = client
-            .head(format!("{base}/objects/{key}?download=0"))
-            .send()
-            .await
-            .unwrap();
-        let disp_inline = head_inline
-            .headers()
-            .get(header::CONTENT_DISPOSITION)
-            .unwrap()
-            .to_str()
-            .unwrap();
-        assert!(disp_inline.starts_with("inline"));
+#[test]
+fn uploads_refused_when_the_volume_is_nearly_full() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        // more than any test machine has free
+        cfg.min_free_bytes = u64::MAX / 4;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let ready: serde_json::Value = client.get(format!("{base}/readyz")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(ready["status"], "ready");
+        ready["free_bytes"].as_u64().expect("free space is known on this platform");
+        assert_eq!(ready["low_space"], true);
+        let stats: serde_json::Value = client.get(format!("{base}/stats")).send().await.unwrap().json().await.unwrap();
+        assert!(stats["free_bytes"].as_u64().is_some());
+        assert_eq!(stats["min_free_bytes"], u64::MAX / 4);
+
+        let resp = client.put(format!("{base}/objects/a.txt")).body("hello").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::INSUFFICIENT_STORAGE);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"], "insufficient_space");
+        assert_eq!(body["needed"], 5);
+
+        // a body of unknown length is refused too
+        let chunks = futures_util::stream::iter([Ok::<_, std::io::Error>(b"abc".to_vec())]);
+        let resp = client.put(format!("{base}/objects/b.txt")).body(reqwest::Body::wrap_stream(chunks)).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!td.path().join("a.txt").exists() && !td.path().join("b.txt").exists());
+
+        // and so is an append
+        let resp = client.patch(format!("{base}/objects/c.txt")).header("content-range", "bytes 0-*/*").body("abc").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!td.path().join("c.txt").exists());
+    });
+}
+
+#[test]
+fn get_bodies_go_out_in_stream_chunk_bytes() {
+    use actix_web::body::MessageBody;
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.stream_chunk_bytes = 16 * 1024;
+        let td = TempDir::new().unwrap();
+        std::fs::write(td.path().join("big.bin"), vec![7u8; 40 * 1024]).unwrap();
+        let svc = actix_web::test::init_service(rust_buck3t::app(AppState::new(td.path()), cfg)).await;
+        let req = actix_web::test::TestRequest::get().uri("/objects/big.bin").to_request();
+        let resp = actix_web::test::call_service(&svc, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+
+        // each read of the file is one chunk of the body
+        let mut body = resp.into_body();
+        let mut sizes = Vec::new();
+        while let Some(chunk) = futures_util::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_next(cx)).await {
+            let Ok(chunk) = chunk else { panic!("body failed") };
+            sizes.push(chunk.len());
+        }
+        assert_eq!(sizes, [16 * 1024, 16 * 1024, 8 * 1024]);
+    });
+}
+
+#[test]
+fn cached_headers_follow_writes_and_outside_edits() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let url = format!("{base}/objects/c/page.txt");
+        let etag = |r: &reqwest::Response| r.headers()["etag"].to_str().unwrap().to_string();
+
+        client.put(&url).body("first").send().await.unwrap();
+        let first = client.get(&url).send().await.unwrap();
+        let again = client.head(&url).send().await.unwrap();
+        assert_eq!(etag(&first), etag(&again));
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_meta_cache_hits_total 1"), "{metrics}");
+        assert!(metrics.contains("bucket_meta_cache_misses_total 1"), "{metrics}");
+
+        // a metadata edit leaves the file alone but must not serve the old type
+        let r = client.post(format!("{url}?metadata=1")).header("content-type", "text/markdown").send().await.unwrap();
+        assert!(r.status().is_success());
+        let r = client.head(&url).send().await.unwrap();
+        assert_eq!(r.headers()["content-type"], "text/markdown");
+
+        // rewritten behind the server's back: new size, new etag
+        std::fs::write(td.path().join("c/page.txt"), "edited outside").unwrap();
+        let r = client.get(&url).send().await.unwrap();
+        assert_ne!(etag(&r), etag(&first));
+        assert_eq!(r.text().await.unwrap(), "edited outside");
+
+        client.delete(&url).send().await.unwrap();
+        assert_eq!(client.head(&url).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+    });
+}
+
+#[test]
+fn hot_cache_serves_small_objects_from_memory() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.hot_cache_max_object_bytes = 16;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let url = format!("{base}/objects/cfg.json");
+        let get = |range: Option<&'static str>| {
+            let mut req = client.get(&url);
+            if let Some(r) = range {
+                req = req.header("range", r);
+            }
+            async move { req.send().await.unwrap().text().await.unwrap() }
+        };
+
+        client.put(&url).body("{\"v\":1}").send().await.unwrap();
+        client.put(format!("{base}/objects/large.bin")).body(vec![b'x'; 64]).send().await.unwrap();
+        assert_eq!(get(None).await, "{\"v\":1}");
+        assert_eq!(get(None).await, "{\"v\":1}");
+        assert_eq!(get(Some("bytes=1-3")).await, "\"v\"");
+        let large = client.get(format!("{base}/objects/large.bin")).send().await.unwrap().bytes().await.unwrap();
+        assert_eq!(large.len(), 64);
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_hot_cache_hits_total 2"), "{metrics}");
+        assert!(metrics.contains("bucket_hot_cache_misses_total 1"), "{metrics}");
+        assert!(metrics.contains("bucket_hot_cache_bytes 7"), "{metrics}");
+
+        // an overwrite and an edit on disk are both seen
+        client.put(&url).body("{\"v\":2}").send().await.unwrap();
+        assert_eq!(get(None).await, "{\"v\":2}");
+        std::fs::write(td.path().join("cfg.json"), "{\"v\":33}").unwrap();
+        assert_eq!(get(None).await, "{\"v\":33}");
+        client.delete(&url).send().await.unwrap();
+        assert_eq!(client.get(&url).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
     });
 }
 
 #[test]
-fn get_full_and_etag_304() {
+fn query_duplicates_booleans_and_strict_mode() {
     actix_web::rt::System::new().block_on(async {
-        let (base, _td) = start_server(consts::Config::from_env());
+        let (base, _td) = start_server(open_cfg());
         wait_alive(&base).await;
         let client = Client::new();
 
-        let key = "t/two.txt";
         let _ = client
-            .put(format!("{base}/objects/{key}"))
-            .body("abc")
+            .put(format!("{base}/objects/q/a.txt"))
+            .body("x")
             .send()
             .await
             .unwrap();
 
-        // GET full body
-        let resp = client
-            .get(format!("{base}/objects/{key}"))
+        // duplicate param -> 400 naming it
+        let dup = client
+            .get(format!("{base}/objects?prefix=q&prefix=b"))
             .send()
             .await
             .unwrap();
-        assert!(resp.status().is_success());
-        assert_eq!(resp.text().await.unwrap(), "abc");
+        assert_eq!(dup.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = dup.json().await.unwrap();
+        assert_eq!(body["error"], "duplicate_query_param");
+        assert_eq!(body["param"], "prefix");
 
-        // Grab ETag via HEAD
-        let head = client
-            .head(format!("{base}/objects/{key}"))
+        // documented boolean spellings
+        for spelling in ["1", "true", "TRUE", "0", "false"] {
+            let ok = client
+                .get(format!("{base}/objects?prefix=q&recursive={spelling}"))
+                .send()
+                .await
+                .unwrap();
+            assert!(ok.status().is_success(), "recursive={spelling}");
+        }
+        let bad = client
+            .get(format!("{base}/objects/q/a.txt?download=yes"))
             .send()
             .await
             .unwrap();
-        let etag = head.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+        assert_eq!(bad.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = bad.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_query_param");
+        assert_eq!(body["param"], "download");
 
-        // If-None-Match -> 304
-        let not_modified = client
-            .get(format!("{base}/objects/{key}"))
-            .header(header::IF_NONE_MATCH, etag)
+        // unknown params are ignored unless strict
+        let typo = client
+            .get(format!("{base}/objects?recusive=1"))
+            .send()
+            .await
+            .unwrap();
+        assert!(typo.status().is_success());
+    });
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.strict_query = true;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let typo = client
+            .get(format!("{base}/objects?recusive=1"))
             .send()
             .await
             .unwrap();
-        assert_eq!(not_modified.status(), reqwest::StatusCode::NOT_MODIFIED);
+        assert_eq!(typo.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = typo.json().await.unwrap();
+        assert_eq!(body["error"], "unknown_query_param");
+        assert_eq!(body["param"], "recusive");
+        assert_eq!(
+            body["accepted"],
+            serde_json::json!([
+                "prefix", "recursive", "depth", "include_dirs", "format", "glob", "min_size", "max_size", "modified_after",
+                "modified_before", "sort", "order", "held", "stream", "hide_dotfiles"
+            ])
+        );
     });
 }
 
 #[test]
-fn get_range_variants_and_416() {
+fn get_multi_range_byteranges() {
     actix_web::rt::System::new().block_on(async {
-        let (base, _td) = start_server(consts::Config::from_env());
+        let (base, _td) = start_server(open_cfg());
         wait_alive(&base).await;
         let client = Client::new();
 
-        let key = "t/range.txt";
+        let key = "t/multi.txt";
         let _ = client
             .put(format!("{base}/objects/{key}"))
-            .body("abc")
+            .body("0123456789abcdefghij")
             .send()
             .await
             .unwrap();
 
-        // "bytes=1-" -> "bc"
-        let r1 = client
+        // two disjoint ranges -> multipart/byteranges
+        let r = client
             .get(format!("{base}/objects/{key}"))
-            .header(header::RANGE, "bytes=1-")
+            .header(header::RANGE, "bytes=0-2,5-6")
             .send()
             .await
             .unwrap();
-        assert_eq!(r1.status(), reqwest::StatusCode::PARTIAL_CONTENT);
-        assert_eq!(r1.text().await.unwrap(), "bc");
+        assert_eq!(r.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        let ctype = r.headers().get(header::CONTENT_TYPE).unwrap().to_str().unwrap().to_string();
+        let boundary = ctype.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+        let declared: usize = r.headers().get(header::CONTENT_LENGTH).unwrap().to_str().unwrap().parse().unwrap();
+        let body = r.text().await.unwrap();
+        assert_eq!(body.len(), declared);
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-2/20\r\n\r\n012\
+             \r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 5-6/20\r\n\r\n56\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(body, expected);
 
-        // "bytes=0-1" -> "ab"
-        let r2 = client
+        // overlapping ranges coalesce into a single plain 206
+        let r = client
             .get(format!("{base}/objects/{key}"))
-            .header(header::RANGE, "bytes=0-1")
+            .header(header::RANGE, "bytes=0-3,2-5")
             .send()
             .await
             .unwrap();
-        assert_eq!(r2.status(), reqwest::StatusCode::PARTIAL_CONTENT);
-        assert_eq!(r2.text().await.unwrap(), "ab");
+        assert_eq!(r.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(r.headers().get(header::CONTENT_RANGE).unwrap(), "bytes 0-5/20");
+        assert_eq!(r.text().await.unwrap(), "012345");
 
-        // "bytes=-1" -> "c"
-        let r3 = client
+        // unsatisfiable parts are dropped
+        let r = client
             .get(format!("{base}/objects/{key}"))
-            .header(header::RANGE, "bytes=-1")
+            .header(header::RANGE, "bytes=18-,99-100")
             .send()
             .await
             .unwrap();
-        assert_eq!(r3.status(), reqwest::StatusCode::PARTIAL_CONTENT);
-        assert_eq!(r3.text().await.unwrap(), "c");
+        assert_eq!(r.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(r.text().await.unwrap(), "ij");
 
-        // bad range -> 416
-        let rbad = client
+        // nothing satisfiable -> 416
+        let r = client
             .get(format!("{base}/objects/{key}"))
-            .header(header::RANGE, "bytes=99-100")
+            .header(header::RANGE, "bytes=50-60,70-80")
             .send()
             .await
             .unwrap();
-        assert_eq!(rbad.status(), reqwest::StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(r.status(), reqwest::StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(r.headers().get(header::CONTENT_RANGE).unwrap(), "bytes */20");
     });
 }
 
 #[test]
-fn list_prefix_recursive() {
+fn session_routes_enforce_json_limits() {
     actix_web::rt::System::new().block_on(async {
-        let (base, _td) = start_server(consts::Config::from_env());
+        let mut cfg = open_cfg();
+        cfg.auth_json_limit_bytes = 512;
+        let (base, _td) = start_server(cfg);
         wait_alive(&base).await;
         let client = Client::new();
 
-        // create: a/b.txt and a/c/d.txt
-        let _ = client
-            .put(format!("{base}/objects/a/b.txt"))
-            .body("x")
+        // wrong content type -> 415
+        let r = client
+            .post(format!("{base}/auth/signup"))
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(r#"{"username":"u","password":"p"}"#)
             .send()
             .await
             .unwrap();
-        let _ = client
-            .put(format!("{base}/objects/a/c/d.txt"))
-            .body("y")
+        assert_eq!(r.status(), reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "unsupported_media_type");
+
+        // body over the configured limit -> 413
+        let big = serde_json::json!({ "username": "u", "password": "p".repeat(1024) });
+        let r = client
+            .post(format!("{base}/auth/login"))
+            .json(&big)
             .send()
             .await
             .unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "payload_too_large");
+        assert_eq!(body["limit"], 512);
 
-        // shallow list (a) -> only a/b.txt
-        let l0 = client
-            .get(format!("{base}/objects?prefix=a&recursive=0"))
+        // within the body limit but over the field caps -> 400 naming the field
+        let long_pw = serde_json::json!({ "username": "u", "password": "p".repeat(300) });
+        let r = client
+            .post(format!("{base}/auth/signup"))
+            .json(&long_pw)
             .send()
             .await
-            .unwrap()
-            .text()
-            .await
             .unwrap();
-        let v0: Vec<serde_json::Value> = serde_json::from_str(&l0).unwrap();
-        let keys0: Vec<String> = v0
-            .into_iter()
-            .map(|o| o.get("key").unwrap().as_str().unwrap().to_string())
-            .collect();
-        assert_eq!(keys0, vec!["a/b.txt".to_string()]);
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "field_too_long");
+        assert_eq!(body["param"], "password");
 
-        // recursive list -> a/b.txt and a/c/d.txt (sorted)
-        let l1 = client
-            .get(format!("{base}/objects?prefix=a&recursive=1"))
+        let long_user = serde_json::json!({ "username": "u".repeat(65), "password": "p" });
+        let r = client
+            .post(format!("{base}/auth/login"))
+            .json(&long_user)
             .send()
             .await
-            .unwrap()
-            .text()
+            .unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["param"], "username");
+    });
+}
+
+#[test]
+fn self_test_over_http_and_with_hs256() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, _td) = start_server(open_cfg());
+        wait_alive(&base).await;
+
+        let r = Client::new()
+            .post(format!("{base}/admin/self-test"))
+            .send()
             .await
             .unwrap();
-        let v1: Vec<serde_json::Value> = serde_json::from_str(&l1).unwrap();
-        let keys1: Vec<String> = v1
-            .into_iter()
-            .map(|o| o.get("key").unwrap().as_str().unwrap().to_string())
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let report: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(report["ok"], true);
+        let steps: Vec<&str> = report["steps"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["step"].as_str().unwrap())
             .collect();
-        assert_eq!(keys1, vec!["a/b.txt".to_string(), "a/c/d.txt".to_string()]);
+        assert_eq!(steps, ["setup", "health", "token", "put", "head", "get", "range", "list", "delete"]);
+
+        // every class protected: the sequence must mint and use its own token
+        let mut cfg = consts::Config::from_env();
+        cfg.auth_mode = consts::AuthMode::JwtHs256;
+        cfg.jwt_hs_secret = Some("self-test-secret".into());
+        cfg.auth_write = true;
+        cfg.auth_read = true;
+        cfg.auth_list = true;
+        let report = rust_buck3t::selftest::run(&cfg).await;
+        assert!(report.ok, "{}", serde_json::to_string(&report).unwrap());
+
+        // and fail loudly when it can't
+        cfg.jwt_hs_secret = None;
+        let report = rust_buck3t::selftest::run(&cfg).await;
+        assert!(!report.ok);
     });
 }
 
+#[cfg(unix)]
 #[test]
-fn delete_twice() {
+fn read_only_root_returns_503_and_recovers() {
+    use std::os::unix::fs::PermissionsExt;
+
     actix_web::rt::System::new().block_on(async {
-        let (base, _td) = start_server(consts::Config::from_env());
+        let (base, td) = start_server(open_cfg());
         wait_alive(&base).await;
         let client = Client::new();
 
-        let key = "t/del.txt";
         let _ = client
-            .put(format!("{base}/objects/{key}"))
+            .put(format!("{base}/objects/ro.txt"))
             .body("x")
             .send()
             .await
             .unwrap();
 
-        let d1 = client
-            .delete(format!("{base}/objects/{key}"))
+        std::fs::set_permissions(td.path(), std::fs::Permissions::from_mode(0o555)).unwrap();
+        if std::fs::write(td.path().join("probe"), b"").is_ok() {
+            // permission bits don't bind this user (e.g. root); nothing to simulate
+            eprintln!("skipping: write permissions are not enforced for this user");
+            return;
+        }
+
+        let put = client
+            .put(format!("{base}/objects/ro2.txt"))
+            .body("y")
             .send()
             .await
             .unwrap();
-        assert_eq!(d1.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(put.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = put.json().await.unwrap();
+        assert_eq!(body["error"], "storage_read_only");
 
-        let d2 = client
-            .delete(format!("{base}/objects/{key}"))
+        let del = client
+            .delete(format!("{base}/objects/ro.txt"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(del.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        // reads keep working, deep health and metrics show the degraded state
+        let get = client.get(format!("{base}/objects/ro.txt")).send().await.unwrap();
+        assert!(get.status().is_success());
+        let deep = client.get(format!("{base}/healthz?deep=1")).send().await.unwrap();
+        assert_eq!(deep.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_storage_read_only 1"));
+
+        // "remount": the next successful write clears the flag
+        std::fs::set_permissions(td.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let put = client
+            .put(format!("{base}/objects/ro2.txt"))
+            .body("y")
             .send()
             .await
             .unwrap();
-        assert_eq!(d2.status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(put.status(), reqwest::StatusCode::CREATED);
+        let deep = client.get(format!("{base}/healthz?deep=1")).send().await.unwrap();
+        assert_eq!(deep.status(), reqwest::StatusCode::OK);
     });
 }
 
 #[test]
-fn put_overwrite_guards_and_413() {
+fn verbose_health_reports_diagnostics() {
     actix_web::rt::System::new().block_on(async {
-        // force tiny upload limit
-        let mut cfg = consts::Config::from_env();
-        cfg.max_upload_bytes = Some(1);
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
 
+        let plain = client.get(format!("{base}/healthz")).send().await.unwrap().text().await.unwrap();
+        assert_eq!(plain, "ok");
+        let body: serde_json::Value =
+            client.get(format!("{base}/healthz?verbose=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body["uptime_secs"].is_u64());
+        assert_eq!(body["root"], td.path().canonicalize().unwrap().display().to_string());
+        assert_eq!(body["root_writable"], true);
+        assert_eq!(body["auth_mode"], "off");
+        assert!(body["in_flight"]["requests"].as_u64().unwrap() >= 1);
+        assert_eq!(body["in_flight"]["uploads"], 0);
+        assert_eq!(body["in_flight"]["downloads"], 0);
+
+        // HEALTH_DETAILS_ADMIN puts the details behind an admin key
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::ApiKey;
+        cfg.api_keys = vec![consts::ApiKey { name: "ops".into(), key: "k-admin".into(), scopes: vec!["bkt:admin".into()] }];
+        cfg.health_details_admin = true;
         let (base, _td) = start_server(cfg);
         wait_alive(&base).await;
-        let client = Client::new();
+        let verbose = || client.get(format!("{base}/healthz?verbose=1"));
+        assert_eq!(verbose().send().await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = verbose().header("x-api-key", "k-admin").send().await.unwrap().json().await.unwrap();
+        assert_eq!(body["auth_mode"], "api_key");
+        assert_eq!(client.get(format!("{base}/healthz")).send().await.unwrap().status(), 200);
+    });
+}
 
-        let key = "t/guards.txt";
+#[test]
+fn embedded_idp_jwks_and_rs256_tokens() {
+    actix_web::rt::System::new().block_on(async {
+        let keys = TempDir::new().unwrap();
+        let mut cfg = consts::Config::from_env();
+        cfg.auth_mode = consts::AuthMode::JwtRs256;
+        cfg.idp_embed = true;
+        cfg.idp_key_dir = keys.path().join("idp").to_string_lossy().into_owned();
+        cfg.auth_write = true;
+        cfg.jwt_scopes_write = vec!["obj:write".into()];
+        cfg.auth_max_ttl_secs = 600;
+        let (base, _td) = start_server(cfg.clone());
+        wait_alive(&base).await;
+        let client = Client::new();
 
-        // First PUT should create (201 or 200 acceptable since server returns 201 on create)
-        let r1 = client
-            .put(format!("{base}/objects/{key}"))
-            .body("x")
+        let jwks: serde_json::Value = client
+            .get(format!("{base}/.well-known/jwks.json"))
             .send()
             .await
-            .unwrap();
-        assert!(r1.status().is_success());
-
-        // Fetch ETag via HEAD
-        let head = client
-            .head(format!("{base}/objects/{key}"))
-            .send()
+            .unwrap()
+            .json()
             .await
             .unwrap();
-        let etag = head.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+        let jwk = &jwks["keys"][0];
+        assert_eq!(jwk["kty"], "RSA");
+        assert_eq!(jwk["alg"], "RS256");
+        let kid = jwk["kid"].as_str().unwrap().to_string();
 
-        // If-None-Match:* should fail when exists (412)
-        let pre_fail = client
-            .put(format!("{base}/objects/{key}"))
-            .header(header::IF_NONE_MATCH, "*")
-            .body("y")
-            .send()
-            .await
-            .unwrap();
-        assert_eq!(pre_fail.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        // key persisted owner-only and reused on the next start
+        let pem = keys.path().join("idp").join(format!("{kid}.pem"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&pem).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let reloaded = rust_buck3t::idp::Idp::load_or_generate(&keys.path().join("idp")).unwrap();
+        assert_eq!(reloaded.signing_key().kid, kid);
 
-        // If-Match: correct etag -> allow overwrite
-        let ok_match = client
-            .put(format!("{base}/objects/{key}"))
-            .header(header::IF_MATCH, etag.clone())
-            .body("z")
+        let mint = |scope: &'static str| {
+            let client = client.clone();
+            let base = base.clone();
+            async move {
+                let v: serde_json::Value = client
+                    .post(format!("{base}/idp/token"))
+                    .json(&serde_json::json!({ "sub": "dev", "scope": scope, "ttl_secs": 999999 }))
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                v
+            }
+        };
+
+        let minted = mint("obj:write").await;
+        assert_eq!(minted["expires_in"], 600);
+        let token = minted["access_token"].as_str().unwrap().to_string();
+
+        let put = client
+            .put(format!("{base}/objects/idp.txt"))
+            .bearer_auth(&token)
+            .body("x")
             .send()
             .await
             .unwrap();
-        assert!(ok_match.status().is_success());
+        assert_eq!(put.status(), reqwest::StatusCode::CREATED);
 
-        // If-Match: wrong etag -> 412
-        let bad_match = client
-            .put(format!("{base}/objects/{key}"))
-            .header(header::IF_MATCH, "W/\"nope\"")
-            .body("w")
+        let anon = client
+            .put(format!("{base}/objects/idp.txt"))
+            .body("x")
             .send()
             .await
             .unwrap();
-        assert_eq!(bad_match.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        assert_eq!(anon.status(), reqwest::StatusCode::UNAUTHORIZED);
 
-        // 413 guard should fire
-        let too_big = client
-            .put(format!("{base}/objects/t/too_big.bin"))
-            .body("ab") // 2 bytes > limit 1
+        let read_only = mint("obj:read").await;
+        let forbidden = client
+            .put(format!("{base}/objects/idp.txt"))
+            .bearer_auth(read_only["access_token"].as_str().unwrap())
+            .body("x")
             .send()
             .await
             .unwrap();
-        assert_eq!(too_big.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(forbidden.status(), reqwest::StatusCode::FORBIDDEN);
 
-        // ensure partial cleaned (GET -> 404)
-        let get_clean = client
-            .get(format!("{base}/objects/t/too_big.bin"))
+        // a token signed by some other key is rejected
+        let other = TempDir::new().unwrap();
+        let foreign = rust_buck3t::idp::Idp::load_or_generate(other.path()).unwrap();
+        let claims = serde_json::json!({ "sub": "x", "scope": "obj:write", "exp": 4102444800u64 });
+        let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some(foreign.signing_key().kid.clone());
+        let forged = jsonwebtoken::encode(&header, &claims, &foreign.signing_key().encoding).unwrap();
+        let rejected = client
+            .put(format!("{base}/objects/idp.txt"))
+            .bearer_auth(forged)
+            .body("x")
             .send()
             .await
             .unwrap();
-        assert_eq!(get_clean.status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(rejected.status(), reqwest::StatusCode::UNAUTHORIZED);
+    });
+}
+
+#[test]
+fn embedded_idp_rotates_keys_with_a_grace_window() {
+    actix_web::rt::System::new().block_on(async {
+        let keys = TempDir::new().unwrap();
+        let dir = keys.path().join("idp");
+        let mut cfg = consts::Config::from_env();
+        cfg.auth_mode = consts::AuthMode::JwtRs256;
+        cfg.idp_embed = true;
+        cfg.idp_key_dir = dir.to_string_lossy().into_owned();
+        cfg.auth_write = true;
+        cfg.jwt_scopes_write = vec!["obj:write".into()];
+        cfg.jwt_scopes_admin = vec!["bkt:admin".into()];
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let mint = |scope: &'static str| {
+            let req = client.post(format!("{base}/idp/token")).json(&serde_json::json!({ "sub": "dev", "scope": scope }));
+            async move {
+                let v: serde_json::Value = req.send().await.unwrap().json().await.unwrap();
+                v["access_token"].as_str().unwrap().to_string()
+            }
+        };
+        let kid_of = |token: &str| jsonwebtoken::decode_header(token).unwrap().kid.unwrap();
+        let put = |token: String| client.put(format!("{base}/objects/rot.txt")).bearer_auth(token).body("x").send();
+
+        let old = mint("obj:write").await;
+        let admin = mint("bkt:admin").await;
+        let old_kid = kid_of(&old);
+
+        let r = client.post(format!("{base}/idp/rotate")).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let r = client.post(format!("{base}/idp/rotate")).bearer_auth(&old).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        let r = client.post(format!("{base}/idp/rotate")).bearer_auth(&admin).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+        let new_kid = r.json::<serde_json::Value>().await.unwrap()["kid"].as_str().unwrap().to_string();
+        assert_ne!(new_kid, old_kid);
+
+        // both keys published, newest first; only the new one signs
+        let jwks: serde_json::Value = client.get(format!("{base}/.well-known/jwks.json")).send().await.unwrap().json().await.unwrap();
+        let kids: Vec<&str> = jwks["keys"].as_array().unwrap().iter().map(|k| k["kid"].as_str().unwrap()).collect();
+        assert_eq!(kids, [new_kid.as_str(), old_kid.as_str()]);
+        let new = mint("obj:write").await;
+        assert_eq!(kid_of(&new), new_kid);
+
+        // tokens from the old key still verify during the grace window
+        assert!(put(old.clone()).await.unwrap().status().is_success());
+        assert!(put(new).await.unwrap().status().is_success());
+
+        // after it, the old key and its file are gone
+        let reloaded = rust_buck3t::idp::Idp::load_or_generate(&dir).unwrap();
+        assert_eq!(reloaded.jwks().keys.len(), 2);
+        assert_eq!(reloaded.signing_key().kid, new_kid);
+        assert_eq!(reloaded.prune(0, rust_buck3t::pending::now_secs()).unwrap(), 1);
+        assert_eq!(reloaded.jwks().keys.len(), 1);
+        assert!(reloaded.decoding_key(&old_kid).is_none());
+        assert!(!dir.join(format!("{old_kid}.pem")).exists());
+        assert!(dir.join(format!("{new_kid}.pem")).exists());
+    });
+}
+
+#[test]
+fn remote_jwks_refetches_on_an_unknown_kid() {
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::sync::{Arc, Mutex};
+
+    actix_web::rt::System::new().block_on(async {
+        let (dir_a, dir_b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let key_a = rust_buck3t::idp::Idp::load_or_generate(dir_a.path()).unwrap();
+        let key_b = rust_buck3t::idp::Idp::load_or_generate(dir_b.path()).unwrap();
+
+        // JWKS stub: serves whatever `served` holds, counting fetches
+        let served = Arc::new(Mutex::new((0u32, serde_json::to_value(key_a.jwks()).unwrap())));
+        let stub = served.clone();
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let jwks_url = format!("http://{}/jwks.json", listener.local_addr().unwrap());
+        let server = HttpServer::new(move || {
+            let stub = stub.clone();
+            App::new().route(
+                "/jwks.json",
+                web::get().to(move || {
+                    let stub = stub.clone();
+                    async move {
+                        let mut served = stub.lock().unwrap();
+                        served.0 += 1;
+                        HttpResponse::Ok().json(&served.1)
+                    }
+                }),
+            )
+        })
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::JwtRs256;
+        cfg.jwks_urls = vec![jwks_url];
+        cfg.jwt_issuers = vec![];
+        cfg.jwks_ttl_secs = 3600;
+        cfg.jwks_refetch_secs = 2;
+        cfg.auth_write = true;
+        cfg.jwt_scopes_write = vec!["obj:write".into()];
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let sign = |idp: &rust_buck3t::idp::Idp, kid: &str| {
+            let claims = serde_json::json!({ "sub": "x", "scope": "obj:write", "exp": 4102444800u64 });
+            let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+            header.kid = Some(kid.to_string());
+            jsonwebtoken::encode(&header, &claims, &idp.signing_key().encoding).unwrap()
+        };
+        let put = |token: String| client.put(format!("{base}/objects/k.txt")).bearer_auth(token).body("x").send();
+        let fetches = || served.lock().unwrap().0;
+
+        let token_a = sign(&key_a, &key_a.signing_key().kid);
+        assert_eq!(put(token_a.clone()).await.unwrap().status(), reqwest::StatusCode::CREATED);
+        assert_eq!(fetches(), 1);
+
+        // an unknown kid right after a fetch: no refetch yet
+        let unknown = sign(&key_a, "not-a-kid");
+        assert_eq!(put(unknown.clone()).await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(fetches(), 1);
+
+        // the IdP rotates; a token from the new key triggers one refetch and verifies
+        actix_web::rt::time::sleep(Duration::from_millis(2100)).await;
+        served.lock().unwrap().1 = serde_json::json!({
+            "keys": [key_b.jwks().keys[0].clone(), key_a.jwks().keys[0].clone()]
+        });
+        let token_b = sign(&key_b, &key_b.signing_key().kid);
+        assert_eq!(put(token_b.clone()).await.unwrap().status(), reqwest::StatusCode::OK);
+        assert_eq!(fetches(), 2);
+        assert_eq!(put(token_a).await.unwrap().status(), reqwest::StatusCode::OK);
+
+        // still-unknown kids are rate limited: refused without a fetch
+        for _ in 0..3 {
+            assert_eq!(put(unknown.clone()).await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(put(token_b).await.unwrap().status(), reqwest::StatusCode::OK);
+        assert_eq!(fetches(), 2);
+    });
+}
+
+#[test]
+fn object_custom_headers_replayed_and_merged() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.header_rules = vec![
+            rust_buck3t::headers::HeaderRule {
+                prefix: "docs/".into(),
+                headers: [("X-Robots-Tag".to_string(), "noindex".to_string()), ("Cache-Control".to_string(), "no-store".to_string())].into(),
+            },
+            rust_buck3t::headers::HeaderRule {
+                prefix: "docs/public/".into(),
+                headers: [("X-Robots-Tag".to_string(), "all".to_string())].into(),
+            },
+        ];
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let put = client
+            .put(format!("{base}/objects/docs/public/page.html"))
+            .header("x-bucket-header-content-security-policy", "default-src 'self'")
+            .header("x-bucket-header-Cache-Control", "max-age=60")
+            .body("<p>hi</p>")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(put.status(), reqwest::StatusCode::CREATED);
+
+        for resp in [
+            client.get(format!("{base}/objects/docs/public/page.html")).send().await.unwrap(),
+            client.head(format!("{base}/objects/docs/public/page.html")).send().await.unwrap(),
+            client
+                .get(format!("{base}/objects/docs/public/page.html"))
+                .header(header::RANGE, "bytes=0-1")
+                .send()
+                .await
+                .unwrap(),
+        ] {
+            let h = resp.headers();
+            assert_eq!(h["content-security-policy"], "default-src 'self'");
+            assert_eq!(h["cache-control"], "max-age=60"); // per-object beats the rule
+            assert_eq!(h["x-robots-tag"], "all"); // most specific prefix wins
+        }
+
+        // a rule-only object gets just the rule headers
+        client.put(format!("{base}/objects/docs/a.txt")).body("a").send().await.unwrap();
+        let resp = client.get(format!("{base}/objects/docs/a.txt")).send().await.unwrap();
+        assert_eq!(resp.headers()["x-robots-tag"], "noindex");
+        assert!(resp.headers().get("content-security-policy").is_none());
+
+        // re-PUT without custom headers drops them
+        client.put(format!("{base}/objects/docs/public/page.html")).body("x").send().await.unwrap();
+        let resp = client.head(format!("{base}/objects/docs/public/page.html")).send().await.unwrap();
+        assert!(resp.headers().get("content-security-policy").is_none());
+        assert_eq!(resp.headers()["cache-control"], "no-store");
+
+        // not on the allow-list
+        let resp = client
+            .put(format!("{base}/objects/x.txt"))
+            .header("x-bucket-header-set-cookie", "a=b")
+            .body("x")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"], "header_not_allowed");
+        assert_eq!(body["header"], "set-cookie");
+
+        // size cap
+        let resp = client
+            .put(format!("{base}/objects/x.txt"))
+            .header("x-bucket-header-link", "a".repeat(rust_buck3t::headers::MAX_OBJECT_HEADER_BYTES))
+            .body("x")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"], "headers_too_large");
+        assert!(!td.path().join("x.txt").exists());
+
+        // sidecars stay out of listings and aren't addressable
+        let listed: Vec<serde_json::Value> = client
+            .get(format!("{base}/objects?recursive=1"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(listed.iter().all(|o| !o["key"].as_str().unwrap().starts_with(".meta")));
+        let resp = client.get(format!("{base}/objects/.meta/docs/a.txt.json")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // DELETE removes the sidecar too
+        client
+            .put(format!("{base}/objects/docs/public/page.html"))
+            .header("x-bucket-header-link", "</app.css>; rel=preload")
+            .body("x")
+            .send()
+            .await
+            .unwrap();
+        assert!(td.path().join(".meta/docs/public/page.html.json").exists());
+        client.delete(format!("{base}/objects/docs/public/page.html")).send().await.unwrap();
+        assert!(!td.path().join(".meta/docs/public/page.html.json").exists());
+    });
+}
+
+#[test]
+fn basic_auth_checks_the_user_store() {
+    actix_web::rt::System::new().block_on(async {
+        let users_dir = TempDir::new().unwrap();
+        let db = users_dir.path().join("users.json");
+        std::fs::write(&db, r#"[{"username":"viewer","password":"look","scopes":["obj:read"]}]"#).unwrap();
+
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::Basic;
+        cfg.auth_read = true;
+        cfg.auth_user_db = db.to_string_lossy().into_owned();
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let r = client
+            .post(format!("{base}/auth/signup"))
+            .json(&serde_json::json!({ "username": "alice", "password": "s3cret" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+
+        // missing, wrong and malformed credentials all get the challenge
+        let challenges = [
+            client.put(format!("{base}/objects/b.txt")),
+            client.put(format!("{base}/objects/b.txt")).basic_auth("alice", Some("wrong")),
+            client.put(format!("{base}/objects/b.txt")).basic_auth("nobody", Some("s3cret")),
+            client.put(format!("{base}/objects/b.txt")).header(header::AUTHORIZATION, "Basic !!!"),
+            client.put(format!("{base}/objects/b.txt")).bearer_auth("s3cret"),
+        ];
+        for req in challenges {
+            let r = req.body("x").send().await.unwrap();
+            assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+            assert_eq!(r.headers()[header::WWW_AUTHENTICATE], r#"Basic realm="rust-buck3t""#);
+        }
+
+        // default scopes (BASIC_SCOPES) cover writes; a user record can narrow them
+        let r = client.put(format!("{base}/objects/b.txt")).basic_auth("alice", Some("s3cret")).body("x").send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+        let r = client.get(format!("{base}/objects/b.txt")).basic_auth("viewer", Some("look")).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        assert_eq!(r.text().await.unwrap(), "x");
+        let r = client.delete(format!("{base}/objects/b.txt")).basic_auth("viewer", Some("look")).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // the legacy plaintext entry was rewritten as a hash, scopes kept
+        let stored: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&db).unwrap()).unwrap();
+        let viewer = stored.iter().find(|u| u["username"] == "viewer").unwrap();
+        assert!(viewer["password_hash"].as_str().unwrap().starts_with("$argon2id$"));
+        assert!(viewer.get("password").is_none());
+        assert_eq!(viewer["scopes"], serde_json::json!(["obj:read"]));
+    });
+}
+
+#[test]
+fn signup_login_hashes_and_upgrades_legacy_passwords() {
+    actix_web::rt::System::new().block_on(async {
+        let users_dir = TempDir::new().unwrap();
+        let db = users_dir.path().join("users.json");
+        std::fs::write(&db, r#"[{"username":"old","password":"hunter2"}]"#).unwrap();
+
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::JwtHs256;
+        cfg.jwt_hs_secret = Some("test-secret".into());
+        cfg.auth_user_db = db.to_string_lossy().into_owned();
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let login = |user: &'static str, password: &'static str| {
+            client
+                .post(format!("{base}/auth/login"))
+                .json(&serde_json::json!({ "username": user, "password": password }))
+                .send()
+        };
+
+        let r = client
+            .post(format!("{base}/auth/signup"))
+            .json(&serde_json::json!({ "username": "alice", "password": "s3cret" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+
+        let stored: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&db).unwrap()).unwrap();
+        let alice = stored.iter().find(|u| u["username"] == "alice").unwrap();
+        assert!(alice["password_hash"].as_str().unwrap().starts_with("$argon2id$"));
+        assert!(alice.get("password").is_none());
+
+        let r = login("alice", "s3cret").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert!(body["access_token"].as_str().is_some());
+
+        let r = login("alice", "wrong").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        // legacy plaintext entry: accepted once, then rewritten as a hash
+        let r = login("old", "nope").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let r = login("old", "hunter2").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let stored: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&db).unwrap()).unwrap();
+        let old = stored.iter().find(|u| u["username"] == "old").unwrap();
+        assert!(old["password_hash"].as_str().unwrap().starts_with("$argon2id$"));
+        assert!(old.get("password").is_none());
+        let r = login("old", "hunter2").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+    });
+}
+
+#[test]
+fn signup_mode_gates_account_creation() {
+    actix_web::rt::System::new().block_on(async {
+        let client = Client::new();
+        let signup = |base: &str, user: &str, invite: Option<&str>| {
+            client
+                .post(format!("{base}/auth/signup"))
+                .json(&serde_json::json!({ "username": user, "password": "s3cret", "invite": invite }))
+                .send()
+        };
+        let start = |mode: consts::SignupMode, db: &std::path::Path| {
+            let mut cfg = open_cfg();
+            cfg.auth_mode = consts::AuthMode::ApiKey;
+            cfg.api_keys = vec![
+                consts::ApiKey { name: "ops".into(), key: "k-admin".into(), scopes: vec!["bkt:admin".into()] },
+                consts::ApiKey { name: "ci".into(), key: "k-ci".into(), scopes: vec!["obj:write".into()] },
+            ];
+            cfg.auth_user_db = db.to_string_lossy().into_owned();
+            cfg.signup_mode = mode;
+            start_server(cfg)
+        };
+
+        let users_dir = TempDir::new().unwrap();
+        let (open, _td) = start(consts::SignupMode::Open, &users_dir.path().join("open.json"));
+        wait_alive(&open).await;
+        assert_eq!(signup(&open, "alice", None).await.unwrap().status(), reqwest::StatusCode::CREATED);
+
+        let (closed, _td) = start(consts::SignupMode::Closed, &users_dir.path().join("closed.json"));
+        wait_alive(&closed).await;
+        let r = signup(&closed, "alice", None).await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "signup_closed");
+
+        let invite_dir = TempDir::new().unwrap();
+        let (base, _td) = start(consts::SignupMode::Invite, &invite_dir.path().join("users.json"));
+        wait_alive(&base).await;
+        let r = signup(&base, "alice", None).await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["error"], "invite_required");
+        let r = signup(&base, "alice", Some("made-up")).await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["error"], "invalid_invite");
+
+        // minting takes an admin scope
+        let mint = |key: &'static str| client.post(format!("{base}/auth/invites")).header("x-api-key", key).send();
+        assert_eq!(mint("k-ci").await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+        let r = mint("k-admin").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+        let body: serde_json::Value = r.json().await.unwrap();
+        let code = body["code"].as_str().unwrap().to_string();
+        assert!(body["expires_at"].as_u64().unwrap() > 0);
+        let stored = std::fs::read_to_string(invite_dir.path().join("invites.json")).unwrap();
+        assert!(!stored.contains(&code));
+
+        assert_eq!(signup(&base, "alice", Some(&code)).await.unwrap().status(), reqwest::StatusCode::CREATED);
+        // single use
+        let r = signup(&base, "bob", Some(&code)).await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["error"], "invalid_invite");
+
+        // expired codes are refused
+        let r = client
+            .post(format!("{base}/auth/invites"))
+            .header("x-api-key", "k-admin")
+            .json(&serde_json::json!({ "ttl_secs": 0 }))
+            .send()
+            .await
+            .unwrap();
+        let code = r.json::<serde_json::Value>().await.unwrap()["code"].as_str().unwrap().to_string();
+        assert_eq!(signup(&base, "bob", Some(&code)).await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+    });
+}
+
+#[test]
+fn repeated_failed_logins_lock_out_the_user_and_address() {
+    actix_web::rt::System::new().block_on(async {
+        let users_dir = TempDir::new().unwrap();
+        let db = users_dir.path().join("users.json");
+
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::JwtHs256;
+        cfg.jwt_hs_secret = Some("test-secret".into());
+        cfg.auth_user_db = db.to_string_lossy().into_owned();
+        cfg.trust_proxy = true;
+        cfg.login_max_failures = 3;
+        cfg.login_lockout_secs = 600;
+        cfg.login_failure_delay_ms = 0;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        for user in ["alice", "bob"] {
+            let r = client
+                .post(format!("{base}/auth/signup"))
+                .json(&serde_json::json!({ "username": user, "password": "s3cret" }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+        }
+        let login = |user: &'static str, password: &'static str, ip: &'static str| {
+            client
+                .post(format!("{base}/auth/login"))
+                .header("x-forwarded-for", ip)
+                .json(&serde_json::json!({ "username": user, "password": password }))
+                .send()
+        };
+
+        // a success clears alice's count
+        for _ in 0..2 {
+            assert_eq!(login("alice", "wrong", "10.0.0.1").await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(login("alice", "s3cret", "10.0.0.1").await.unwrap().status(), reqwest::StatusCode::OK);
+
+        for _ in 0..2 {
+            assert_eq!(login("alice", "wrong", "10.0.0.2").await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        }
+        let r = login("alice", "wrong", "10.0.0.2").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(r.headers()[header::RETRY_AFTER], "600");
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "too_many_attempts");
+
+        // locked out even with the right password, from anywhere
+        let r = login("alice", "s3cret", "10.0.0.3").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert!(r.headers().contains_key(header::RETRY_AFTER));
+        // the address is locked for every name; others are unaffected
+        assert_eq!(login("bob", "s3cret", "10.0.0.2").await.unwrap().status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(login("bob", "s3cret", "10.0.0.3").await.unwrap().status(), reqwest::StatusCode::OK);
+    });
+}
+
+#[test]
+fn max_object_count_limits_new_keys_only() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.max_object_count = Some(2);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let put = |key: &'static str| client.put(format!("{base}/objects/{key}")).body("x").send();
+
+        assert_eq!(put("a.txt").await.unwrap().status(), reqwest::StatusCode::CREATED);
+        assert_eq!(put("dir/b.txt").await.unwrap().status(), reqwest::StatusCode::CREATED);
+
+        let r = put("c.txt").await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::INSUFFICIENT_STORAGE);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "object_count_exceeded");
+        assert_eq!(body["limit"], 2);
+        assert!(!td.path().join("c.txt").exists());
+
+        // overwrites don't need a slot
+        assert_eq!(put("a.txt").await.unwrap().status(), reqwest::StatusCode::OK);
+
+        let r = client.delete(format!("{base}/objects/dir/b.txt")).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(put("c.txt").await.unwrap().status(), reqwest::StatusCode::CREATED);
+
+        let health: serde_json::Value =
+            client.get(format!("{base}/healthz?deep=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(health["objects"]["count"], 2);
+        assert_eq!(health["objects"]["limit"], 2);
+
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_objects 2\n"));
+        assert!(metrics.contains("bucket_objects_limit 2\n"));
+
+        // a restart seeds the count from disk
+        assert_eq!(AppState::new(td.path()).objects.get(), 2);
+    });
+}
+
+#[test]
+fn deferred_delete_hides_restores_and_purges() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.delete_grace_secs = 2;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let obj = |key: &str| format!("{base}/objects/{key}");
+
+        for key in ["keep.txt", "gone.txt", "dir/a.txt", "dir/b.txt", "over.txt"] {
+            client.put(obj(key)).body(key.to_string()).send().await.unwrap();
+        }
+        for key in ["keep.txt", "gone.txt", "dir/a.txt", "dir/b.txt", "over.txt"] {
+            let r = client.delete(obj(key)).send().await.unwrap();
+            assert_eq!(r.status(), reqwest::StatusCode::ACCEPTED);
+        }
+
+        // hidden while pending, but still on disk
+        assert_eq!(client.get(obj("keep.txt")).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(client.head(obj("keep.txt")).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(client.delete(obj("keep.txt")).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+        let listed: Vec<serde_json::Value> =
+            client.get(format!("{base}/objects?recursive=1")).send().await.unwrap().json().await.unwrap();
+        assert!(listed.is_empty());
+        assert!(td.path().join("keep.txt").exists());
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_pending_deletes 5\n"));
+
+        // a restart picks the queue back up from the sidecars
+        assert_eq!(AppState::new(td.path()).pending.len(), 5);
+
+        // undo: single key and by prefix
+        let r = client.post(format!("{}?undelete", obj("keep.txt"))).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let r = client.post(format!("{base}/objects?undelete&prefix=dir/")).send().await.unwrap();
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["restored"], serde_json::json!(["dir/a.txt", "dir/b.txt"]));
+        assert_eq!(client.get(obj("keep.txt")).send().await.unwrap().text().await.unwrap(), "keep.txt");
+
+        // an overwriting PUT cancels the pending delete; 201 since the key looked absent
+        let r = client.put(obj("over.txt")).body("new").send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+
+        let r = client.post(format!("{}?undelete", obj("nothing.txt"))).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::NOT_FOUND);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "not_pending");
+
+        // after the window only the still-pending key is really gone
+        actix_web::rt::time::sleep(Duration::from_millis(3500)).await;
+        assert!(!td.path().join("gone.txt").exists());
+        assert!(!td.path().join(".meta/gone.txt.json").exists());
+        assert_eq!(client.post(format!("{}?undelete", obj("gone.txt"))).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+        for key in ["keep.txt", "dir/a.txt", "dir/b.txt"] {
+            assert_eq!(client.get(obj(key)).send().await.unwrap().status(), reqwest::StatusCode::OK);
+        }
+        assert_eq!(client.get(obj("over.txt")).send().await.unwrap().text().await.unwrap(), "new");
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_pending_deletes 0\n"));
+        assert!(metrics.contains("bucket_objects 4\n"));
+    });
+}
+
+#[test]
+fn logout_revokes_token_until_expiry() {
+    actix_web::rt::System::new().block_on(async {
+        let users_dir = TempDir::new().unwrap();
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::JwtHs256;
+        cfg.jwt_hs_secret = Some("test-secret".into());
+        cfg.auth_user_db = users_dir.path().join("users.json").to_string_lossy().into_owned();
+        let (base, _td) = start_server(cfg.clone());
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let creds = serde_json::json!({ "username": "bob", "password": "pw" });
+        client.post(format!("{base}/auth/signup")).json(&creds).send().await.unwrap();
+        let login = |c: &serde_json::Value| client.post(format!("{base}/auth/login")).json(c).send();
+        let token: serde_json::Value = login(&creds).await.unwrap().json().await.unwrap();
+        let token = token["access_token"].as_str().unwrap().to_string();
+        let other: serde_json::Value = login(&creds).await.unwrap().json().await.unwrap();
+        let other = other["access_token"].as_str().unwrap().to_string();
+
+        let put = |t: &str| client.put(format!("{base}/objects/bob.txt")).bearer_auth(t).body("x").send();
+        assert!(put(&token).await.unwrap().status().is_success());
+
+        let r = client.post(format!("{base}/auth/logout")).bearer_auth(&token).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(put(&token).await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        let r = client.post(format!("{base}/auth/logout")).bearer_auth(&token).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        // only that token: a second session of the same user still works
+        assert!(put(&other).await.unwrap().status().is_success());
+
+        // logout needs a valid token
+        let r = client.post(format!("{base}/auth/logout")).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        // the denylist outlives a restart
+        assert!(users_dir.path().join("revoked.json").exists());
+        let restarted = AppState::new(TempDir::new().unwrap().path()).with_auth(&cfg).unwrap();
+        assert_eq!(restarted.denylist.len(), 1);
+    });
+}
+
+#[test]
+fn introspection_reports_live_tokens_only() {
+    actix_web::rt::System::new().block_on(async {
+        let users_dir = TempDir::new().unwrap();
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::JwtHs256;
+        cfg.jwt_hs_secret = Some("test-secret".into());
+        cfg.introspection_secret = Some("sidecar-secret".into());
+        cfg.auth_user_db = users_dir.path().join("users.json").to_string_lossy().into_owned();
+        std::fs::write(&cfg.auth_user_db, r#"[{"username":"bob","password":"pw","scopes":["obj:read","bkt:admin"]}]"#).unwrap();
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let creds = serde_json::json!({ "username": "bob", "password": "pw", "scope": "obj:read" });
+        let login = |c: serde_json::Value| client.post(format!("{base}/auth/login")).json(&c).send();
+        let token = |r: serde_json::Value| r["access_token"].as_str().unwrap().to_string();
+        let bob = token(login(creds).await.unwrap().json().await.unwrap());
+        let admin = serde_json::json!({ "username": "bob", "password": "pw", "scope": "bkt:admin" });
+        let admin = token(login(admin).await.unwrap().json().await.unwrap());
+        let introspect = |auth: &str, t: &str| {
+            client
+                .post(format!("{base}/auth/introspect"))
+                .bearer_auth(auth)
+                .json(&serde_json::json!({ "token": t }))
+                .send()
+        };
+
+        // the dedicated credential or an admin scope; not any token
+        assert_eq!(introspect(&bob, &bob).await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+        let r = client.post(format!("{base}/auth/introspect")).json(&serde_json::json!({ "token": bob })).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(introspect(&admin, &bob).await.unwrap().status(), reqwest::StatusCode::OK);
+
+        let r = introspect("sidecar-secret", &bob).await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["active"], true);
+        assert_eq!(body["sub"], "bob");
+        assert_eq!(body["scope"], "obj:read");
+        assert!(body["exp"].as_u64().unwrap() > 0);
+        assert!(body["iss"].as_str().unwrap().starts_with("http://"));
+
+        // form bodies too
+        let r = client
+            .post(format!("{base}/auth/introspect"))
+            .bearer_auth("sidecar-secret")
+            .form(&[("token", bob.as_str())])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["active"], true);
+
+        // a signed-up user asking for admin too gets only what they may have
+        let eve = serde_json::json!({ "username": "eve", "password": "pw" });
+        client.post(format!("{base}/auth/signup")).json(&eve).send().await.unwrap();
+        let greedy = serde_json::json!({ "username": "eve", "password": "pw", "scope": "obj:read bkt:admin" });
+        let eve = token(login(greedy).await.unwrap().json().await.unwrap());
+        let r = introspect("sidecar-secret", &eve).await.unwrap();
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["scope"], "obj:read");
+        assert_eq!(introspect(&eve, &bob).await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let expired = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+            &serde_json::json!({ "sub": "bob", "scope": "obj:read", "exp": now - 3600 }),
+            &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+        )
+        .unwrap();
+        for t in [expired.as_str(), "not-a-token"] {
+            let r = introspect("sidecar-secret", t).await.unwrap();
+            assert_eq!(r.status(), reqwest::StatusCode::OK);
+            assert_eq!(r.json::<serde_json::Value>().await.unwrap(), serde_json::json!({ "active": false }));
+        }
+
+        // revoked by logout
+        client.post(format!("{base}/auth/logout")).bearer_auth(&bob).send().await.unwrap();
+        let r = introspect("sidecar-secret", &bob).await.unwrap();
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["active"], false);
+    });
+}
+
+#[test]
+fn session_cookie_auth_with_csrf_check() {
+    actix_web::rt::System::new().block_on(async {
+        let users_dir = TempDir::new().unwrap();
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::JwtHs256;
+        cfg.jwt_hs_secret = Some("test-secret".into());
+        cfg.auth_user_db = users_dir.path().join("users.json").to_string_lossy().into_owned();
+        cfg.auth_read = true;
+        cfg.auth_cookie = true;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let creds = serde_json::json!({ "username": "web", "password": "pw" });
+        client.post(format!("{base}/auth/signup")).json(&creds).send().await.unwrap();
+        let r = client.post(format!("{base}/auth/login")).json(&creds).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let set: Vec<String> =
+            r.headers().get_all(header::SET_COOKIE).iter().map(|v| v.to_str().unwrap().to_string()).collect();
+        let cookie = |name: &str| {
+            let line = set.iter().find(|c| c.starts_with(&format!("{name}="))).unwrap().clone();
+            let value = line.split(';').next().unwrap().split_once('=').unwrap().1.to_string();
+            (line, value)
+        };
+        let (session_line, session) = cookie("bkt_session");
+        assert!(session_line.contains("HttpOnly"));
+        assert!(session_line.contains("SameSite=Lax"));
+        assert!(session_line.contains("Path=/"));
+        let (csrf_line, csrf) = cookie("bkt_csrf");
+        assert!(!csrf_line.contains("HttpOnly"));
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["access_token"], session.as_str());
+        let jar = format!("bkt_session={session}; bkt_csrf={csrf}");
+
+        // writes by cookie need the CSRF header to match the cookie
+        let put = || client.put(format!("{base}/objects/web.txt")).header(header::COOKIE, &jar).body("x");
+        let r = put().send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["error"], "csrf_failed");
+        assert_eq!(put().header("x-csrf-token", "guess").send().await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(put().header("x-csrf-token", &csrf).send().await.unwrap().status(), reqwest::StatusCode::CREATED);
+
+        // reads by cookie alone
+        let r = client.get(format!("{base}/objects/web.txt")).header(header::COOKIE, &jar).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        assert_eq!(r.text().await.unwrap(), "x");
+        let r = client.get(format!("{base}/objects/web.txt")).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        // logout (a cookie write too) revokes the token and clears the cookies
+        let r = client.post(format!("{base}/auth/logout")).header(header::COOKIE, &jar).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::FORBIDDEN);
+        let r = client
+            .post(format!("{base}/auth/logout"))
+            .header(header::COOKIE, &jar)
+            .header("x-csrf-token", &csrf)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::NO_CONTENT);
+        let cleared: Vec<&str> = r.headers().get_all(header::SET_COOKIE).iter().map(|v| v.to_str().unwrap()).collect();
+        assert!(cleared.iter().any(|c| c.starts_with("bkt_session=;") && c.contains("Max-Age=0")));
+        let r = client.get(format!("{base}/objects/web.txt")).header(header::COOKIE, &jar).send().await.unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::UNAUTHORIZED);
+    });
+}
+
+#[test]
+fn access_log_lines_from_binary() {
+    use std::io::Read;
+    actix_web::rt::System::new().block_on(async {
+        let mut bin = common::spawn_bin(&[("ACCESS_LOG", "json"), ("TRUST_PROXY", "1")]);
+        let client = Client::new();
+        common::wait_bin(&client, &bin.base).await;
+
+        let r = client
+            .put(format!("{}/objects/logged.txt?secret=shh", bin.base))
+            .header("x-forwarded-for", "203.0.113.9, 10.0.0.1")
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(r.status(), reqwest::StatusCode::CREATED);
+        client.get(format!("{}/objects/logged.txt", bin.base)).send().await.unwrap().bytes().await.unwrap();
+
+        bin.child.kill().unwrap();
+        let mut out = String::new();
+        bin.child.stdout.take().unwrap().read_to_string(&mut out).unwrap();
+        let lines: Vec<serde_json::Value> = out.lines().filter_map(|l| serde_json::from_str(l).ok()).collect();
+
+        let put = lines.iter().find(|l| l["method"] == "PUT").expect("PUT logged");
+        assert_eq!(put["path"], "/objects/logged.txt");
+        assert_eq!(put["status"], 201);
+        assert_eq!(put["ip"], "203.0.113.9");
+        assert!(put["duration_ms"].as_f64().is_some());
+        assert!(!out.contains("shh"));
+
+        let get = lines.iter().find(|l| l["method"] == "GET" && l["path"] == "/objects/logged.txt").expect("GET logged");
+        assert_eq!(get["status"], 200);
+        assert_eq!(get["bytes"], 5);
+        assert_eq!(get["ip"], "127.0.0.1");
+    });
+}
+
+#[test]
+fn tls_serves_healthz_with_self_signed_cert() {
+    actix_web::rt::System::new().block_on(async {
+        let certs = TempDir::new().unwrap();
+        let ck = rcgen::generate_simple_self_signed(vec!["localhost".into(), "127.0.0.1".into()]).unwrap();
+        let cert_path = certs.path().join("cert.pem");
+        let key_path = certs.path().join("key.pem");
+        std::fs::write(&cert_path, ck.cert.pem()).unwrap();
+        std::fs::write(&key_path, ck.key_pair.serialize_pem()).unwrap();
+
+        let bin = common::spawn_bin(&[
+            ("TLS_CERT_PATH", cert_path.to_str().unwrap()),
+            ("TLS_KEY_PATH", key_path.to_str().unwrap()),
+        ]);
+        let base = bin.base.replacen("http://", "https://", 1);
+        let client = Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(ck.cert.pem().as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        common::wait_bin(&client, &base).await;
+
+        let body = client.get(format!("{base}/healthz")).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "ok");
+        // plain HTTP on the TLS port is refused
+        assert!(Client::new().get(format!("{}/healthz", bin.base)).send().await.map(|r| !r.status().is_success()).unwrap_or(true));
+    });
+}
+
+#[test]
+fn tls_startup_fails_on_unreadable_cert() {
+    let mut bin = common::spawn_bin(&[("TLS_CERT_PATH", "/nonexistent/cert.pem"), ("TLS_KEY_PATH", "/nonexistent/key.pem")]);
+    let status = bin.child.wait().unwrap();
+    assert!(!status.success());
+    let mut err = String::new();
+    std::io::Read::read_to_string(bin.child.stderr.as_mut().unwrap(), &mut err).unwrap();
+    assert!(err.contains("TLS_CERT_PATH /nonexistent/cert.pem"), "stderr: {err}");
+
+    let mut bin = common::spawn_bin(&[("TLS_CERT_PATH", "/nonexistent/cert.pem"), ("TLS_KEY_PATH", "")]);
+    assert!(!bin.child.wait().unwrap().success());
+}
+
+#[test]
+fn startup_fails_on_invalid_config() {
+    let stderr = |bin: &mut common::Bin| {
+        assert!(!bin.child.wait().unwrap().success());
+        let mut err = String::new();
+        std::io::Read::read_to_string(bin.child.stderr.as_mut().unwrap(), &mut err).unwrap();
+        err
+    };
+    let mut bin = common::spawn_bin(&[("PORT", "abc"), ("MAX_UPLOAD_BYTES", "10QB"), ("AUTH_MODE", "jwt_h256"), ("TRASH_ENABLED", "maybe")]);
+    let err = stderr(&mut bin);
+    for needle in ["PORT=\"abc\"", "MAX_UPLOAD_BYTES=\"10QB\"", "AUTH_MODE=\"jwt_h256\"", "TRASH_ENABLED=\"maybe\"", "refusing to start"] {
+        assert!(err.contains(needle), "missing {needle} in stderr: {err}");
+    }
+
+    let mut bin = common::spawn_bin(&[("AUTH_MODE", "jwt_hs256")]);
+    assert!(stderr(&mut bin).contains("requires JWT_HS_SECRET"));
+    let mut bin = common::spawn_bin(&[("AUTH_MODE", "jwt_rs256")]);
+    assert!(stderr(&mut bin).contains("requires JWT_ISSUERS or JWKS_URLS"));
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut bin = common::spawn_bin(&[("RUST_BUCKET_DIR", file.path().to_str().unwrap())]);
+    assert!(stderr(&mut bin).contains("is not a directory"));
+
+    // the library gets the same checks as a typed error
+    let mut cfg = open_cfg();
+    assert_eq!(cfg.validate(), Ok(()));
+    cfg.auth_mode = consts::AuthMode::JwtHs256;
+    cfg.jwt_hs_secret = None;
+    cfg.root_dir = file.path().to_string_lossy().into_owned();
+    assert_eq!(cfg.validate().unwrap_err().problems.len(), 2);
+}
+
+#[test]
+fn config_file_round_trips_with_env_precedence() {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/rust-buck3t.toml");
+    let env = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>();
+
+    let cfg = consts::Config::from_sources(Some(&sample), &env(&[])).unwrap();
+    assert_eq!((cfg.host.as_str(), cfg.port, cfg.root_dir.as_str()), ("127.0.0.1", 9090, "data"));
+    assert_eq!(cfg.max_upload_bytes, Some(104857600));
+    assert_eq!(cfg.delete_grace_secs, 30);
+    assert!(cfg.trash_enabled && cfg.strict_query && cfg.ui_enabled && !cfg.trust_proxy);
+    assert!(matches!(cfg.access_log, rust_buck3t::access_log::AccessLogFormat::Json));
+    assert_eq!(cfg.list_exclude, ["**/._*", "**/*.tmp"]);
+    assert_eq!(cfg.buckets, ["public-assets", "user-uploads"]);
+    assert_eq!(cfg.webhook_queue_size, 500);
+    // the [auth] table
+    assert_eq!(cfg.auth_mode, consts::AuthMode::ApiKey);
+    assert!(cfg.auth_write && !cfg.auth_read && cfg.auth_list);
+    assert_eq!(cfg.auth_max_ttl_secs, 3600);
+    assert_eq!(cfg.jwt_scopes_admin, ["bkt:admin"]);
+    assert_eq!(cfg.jwt_issuers, ["https://idp.example.com"]);
+    assert!(cfg.jwks_urls.is_empty());
+    assert_eq!(cfg.api_keys.len(), 2);
+    assert_eq!(cfg.api_keys[0].scopes, ["obj:write", "obj:read"]);
+
+    // the environment wins over the file, key by key
+    let cfg = consts::Config::from_sources(Some(&sample), &env(&[("PORT", "7070"), ("AUTH_MODE", "off"), ("BUCKETS", "")])).unwrap();
+    assert_eq!((cfg.host.as_str(), cfg.port), ("127.0.0.1", 7070));
+    assert_eq!(cfg.auth_mode, consts::AuthMode::Off);
+    assert!(cfg.buckets.is_empty());
+
+    // unknown keys warn, or fail when strict; bad values name the file key
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("bad.toml");
+    std::fs::write(&file, "port = 1\nprot = 2\n[auth]\nmode = \"off\"\nmdoe = 1\n").unwrap();
+    let cfg = consts::Config::from_sources(Some(&file), &env(&[])).unwrap();
+    assert_eq!((cfg.port, cfg.auth_mode), (1, consts::AuthMode::Off));
+    let err = consts::Config::from_sources(Some(&file), &env(&[("RUST_BUCKET_CONFIG_STRICT", "1")])).unwrap_err();
+    assert_eq!(err.problems.len(), 2, "{err}");
+    assert!(err.problems.iter().any(|p| p.contains("\"auth.mdoe\"")), "{err}");
+
+    std::fs::write(&file, "port = \"abc\"\n[auth]\nmode = \"off\"\n").unwrap();
+    let err = consts::Config::from_sources(Some(&file), &env(&[])).unwrap_err();
+    assert_eq!(err.problems, ["port (config file)=\"abc\" is not a valid number"]);
+    std::fs::write(&file, "port = [").unwrap();
+    assert!(consts::Config::from_sources(Some(&file), &env(&[("AUTH_MODE", "off")])).is_err());
+    assert!(consts::Config::from_sources(Some(&dir.path().join("missing.toml")), &env(&[("AUTH_MODE", "off")])).is_err());
+}
+
+#[test]
+fn shutdown_drains_in_flight_and_drops_partial_uploads() {
+    use std::io::Read;
+    actix_web::rt::System::new().block_on(async {
+        let mut bin = common::spawn_bin(&[("SHUTDOWN_GRACE_SECS", "2")]);
+        let client = Client::new();
+        common::wait_bin(&client, &bin.base).await;
+
+        // two streaming PUTs: one completes during the drain, one never does
+        let streaming_put = |key: &str| {
+            let (tx, rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(4);
+            let body = futures_util::stream::unfold(rx, |mut rx| async { rx.recv().await.map(|c| (c, rx)) });
+            let req = client.put(format!("{}/objects/{key}", bin.base)).body(reqwest::Body::wrap_stream(body)).send();
+            (tx, actix_web::rt::spawn(req))
+        };
+        let (done_tx, done) = streaming_put("finishes.bin");
+        let (partial_tx, partial) = streaming_put("partial.bin");
+        done_tx.send(Ok(b"first half, ".to_vec())).await.unwrap();
+        partial_tx.send(Ok(b"never finished".to_vec())).await.unwrap();
+
+        let tmp = bin.root.path().join(".tmp");
+        for _ in 0..200 {
+            if std::fs::read_dir(&tmp).map(|d| d.count()).unwrap_or(0) == 2 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(std::fs::read_dir(&tmp).unwrap().count(), 2, "both uploads streaming");
+
+        let killed = std::process::Command::new("kill").args(["-TERM", &bin.child.id().to_string()]).status().unwrap();
+        assert!(killed.success());
+        actix_web::rt::time::sleep(Duration::from_millis(300)).await;
+
+        done_tx.send(Ok(b"second half".to_vec())).await.unwrap();
+        drop(done_tx);
+        let resp = done.await.unwrap().unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::CREATED);
+
+        let status = bin.child.wait().unwrap();
+        assert!(status.success(), "exit status {status:?}");
+        // the unfinished PUT is cut off with the connection
+        let _ = partial.await;
+        drop(partial_tx);
+
+        let mut out = String::new();
+        bin.child.stdout.take().unwrap().read_to_string(&mut out).unwrap();
+        assert!(out.contains("SIGTERM"), "stdout: {out}");
+        assert!(out.contains("1 request(s) drained, 1 cut off"), "stdout: {out}");
+
+        assert_eq!(std::fs::read(bin.root.path().join("finishes.bin")).unwrap(), b"first half, second half");
+        assert!(!bin.root.path().join("partial.bin").exists());
+
+        // a restart over the same root lists only the finished object and clears scratch space
+        let root = bin.root.path().to_str().unwrap().to_string();
+        let again = common::spawn_bin(&[("RUST_BUCKET_DIR", &root)]);
+        common::wait_bin(&client, &again.base).await;
+        let list: serde_json::Value = client.get(format!("{}/objects", again.base)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(list[0]["key"], "finishes.bin");
+        assert!(std::fs::read_dir(&tmp).map(|d| d.count()).unwrap_or(0) == 0);
+    });
+}
+
+#[test]
+fn readyz_reports_missing_and_non_directory_root() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let ready = |client: &Client| {
+            let url = format!("{base}/readyz");
+            let req = client.get(url).send();
+            async move {
+                let resp = req.await.unwrap();
+                let status = resp.status().as_u16();
+                (status, resp.json::<serde_json::Value>().await.unwrap())
+            }
+        };
+
+        assert_eq!(ready(&client).await.0, 200);
+
+        std::fs::remove_dir_all(td.path()).unwrap();
+        // the previous result is cached briefly
+        assert_eq!(ready(&client).await.0, 200);
+        actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+        let (status, body) = ready(&client).await;
+        assert_eq!(status, 503);
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["reason"], "root_missing");
+        // liveness is unaffected
+        assert_eq!(client.get(format!("{base}/healthz")).send().await.unwrap().status(), 200);
+
+        std::fs::write(td.path(), b"not a dir").unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(ready(&client).await.1["reason"], "root_not_directory");
+
+        std::fs::remove_file(td.path()).unwrap();
+        std::fs::create_dir(td.path()).unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(ready(&client).await.0, 200);
+    });
+}
+
+#[test]
+fn webhooks_deliver_signed_events_in_order_with_retry() {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::sync::{Arc, Mutex};
+
+    actix_web::rt::System::new().block_on(async {
+        // receiver: fails the first delivery, records the rest
+        type Seen = Arc<Mutex<(u32, Vec<(String, String, Vec<u8>)>)>>;
+        let seen: Seen = Arc::default();
+        let recorder = seen.clone();
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let hook_url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let receiver = HttpServer::new(move || {
+            let recorder = recorder.clone();
+            App::new().route(
+                "/hook",
+                web::post().to(move |req: HttpRequest, body: web::Bytes| {
+                    let recorder = recorder.clone();
+                    async move {
+                        let mut seen = recorder.lock().unwrap();
+                        seen.0 += 1;
+                        if seen.0 == 1 {
+                            return HttpResponse::InternalServerError().finish();
+                        }
+                        let header = |n: &str| req.headers().get(n).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+                        seen.1.push((header("x-bucket-event"), header("x-bucket-signature"), body.to_vec()));
+                        HttpResponse::NoContent().finish()
+                    }
+                }),
+            )
+        })
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(receiver);
+
+        let mut cfg = open_cfg();
+        cfg.webhook_url = Some(hook_url);
+        cfg.webhook_secret = Some("hook-secret".into());
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        assert_eq!(client.put(format!("{base}/objects/w/a.txt")).body("one").send().await.unwrap().status(), 201);
+        assert_eq!(client.put(format!("{base}/objects/w/a.txt")).body("three").send().await.unwrap().status(), 200);
+        assert_eq!(client.delete(format!("{base}/objects/w/a.txt")).send().await.unwrap().status(), 204);
+
+        for _ in 0..100 {
+            if seen.lock().unwrap().1.len() == 3 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        let (attempts, events) = seen.lock().unwrap().clone();
+        assert_eq!(attempts, 4, "first delivery retried");
+        let names: Vec<&str> = events.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, ["object.created", "object.overwritten", "object.deleted"]);
+        for (name, signature, body) in &events {
+            assert_eq!(signature, &rust_buck3t::webhooks::sign("hook-secret", body));
+            let event: serde_json::Value = serde_json::from_slice(body).unwrap();
+            assert_eq!(event["event"], name.as_str());
+            assert_eq!(event["key"], "w/a.txt");
+            assert!(event["etag"].as_str().unwrap().starts_with("W/\""));
+            assert!(event["timestamp"].as_u64().unwrap() > 0);
+        }
+        let sizes: Vec<u64> = events.iter().map(|(_, _, b)| serde_json::from_slice::<serde_json::Value>(b).unwrap()["size"].as_u64().unwrap()).collect();
+        assert_eq!(sizes, [3, 5, 5]);
+
+        // the receiver sees the body before the sender counts the delivery
+        let mut metrics = String::new();
+        for _ in 0..40 {
+            metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+            if metrics.contains("bucket_webhook_delivered_total 3") {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(25)).await;
+        }
+        assert!(metrics.contains("bucket_webhook_delivered_total 3"), "{metrics}");
+        assert!(metrics.contains("bucket_webhook_dropped_total 0"), "{metrics}");
+    });
+}
+
+#[test]
+fn webhook_queue_drops_oldest_without_blocking_requests() {
+    actix_web::rt::System::new().block_on(async {
+        // nothing listens here, so the first event sits in retry backoff
+        let closed = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap();
+        let mut cfg = open_cfg();
+        cfg.webhook_url = Some(format!("http://{closed}/hook"));
+        cfg.webhook_queue_size = 2;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        for i in 0..5 {
+            let r = client.put(format!("{base}/objects/q{i}.txt")).body("x").send().await.unwrap();
+            assert_eq!(r.status(), 201);
+        }
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        let value = |name: &str| -> u64 {
+            metrics.lines().find_map(|l| l.strip_prefix(&format!("{name} "))).unwrap().parse().unwrap()
+        };
+        assert_eq!(value("bucket_webhook_queue"), 2);
+        assert!(value("bucket_webhook_dropped_total") >= 2);
+        assert_eq!(value("bucket_webhook_delivered_total"), 0);
+    });
+}
+
+#[test]
+fn events_stream_pushes_filtered_changes() {
+    use futures_util::StreamExt;
+    actix_web::rt::System::new().block_on(async {
+        let (base, _td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        let resp = client.get(format!("{base}/events?prefix=a/")).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut stream = resp.bytes_stream();
+        let mut buf = String::new();
+        // reads until `buf` holds `n` complete frames, skipping keepalive comments
+        async fn frames(stream: &mut (impl futures_util::Stream<Item = reqwest::Result<actix_web::web::Bytes>> + Unpin), buf: &mut String, n: usize) -> Vec<String> {
+            loop {
+                let done: Vec<String> = buf.split("\n\n").filter(|f| !f.is_empty() && !f.starts_with(':')).map(String::from).collect();
+                if done.len() >= n && buf.ends_with("\n\n") {
+                    buf.clear();
+                    return done;
+                }
+                let chunk = actix_web::rt::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap().unwrap().unwrap();
+                buf.push_str(std::str::from_utf8(&chunk).unwrap());
+            }
+        }
+        // connected: the immediate heartbeat arrives first
+        frames(&mut stream, &mut buf, 0).await;
+
+        client.put(format!("{base}/objects/b/skip.txt")).body("no").send().await.unwrap();
+        client.put(format!("{base}/objects/a/x.txt")).body("hello").send().await.unwrap();
+        client.delete(format!("{base}/objects/a/x.txt")).send().await.unwrap();
+
+        let got = frames(&mut stream, &mut buf, 2).await;
+        assert_eq!(got.len(), 2, "{got:?}");
+        assert!(got[0].starts_with("event: object.created\ndata: "), "{got:?}");
+        assert!(got[1].starts_with("event: object.deleted\ndata: "), "{got:?}");
+        let data: serde_json::Value = serde_json::from_str(got[0].split("data: ").nth(1).unwrap()).unwrap();
+        assert_eq!(data["key"], "a/x.txt");
+        assert_eq!(data["size"], 5);
+        assert!(data["etag"].is_string());
+
+        client.post(format!("{base}/objects/a/copy.txt?copy_from=b/skip.txt")).send().await.unwrap();
+        let got = frames(&mut stream, &mut buf, 1).await;
+        assert!(got[0].starts_with("event: object.copied\ndata: "), "{got:?}");
+    });
+}
+
+#[test]
+fn trash_hides_restores_and_purges_deleted_objects() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.trash_enabled = true;
+        cfg.trash_retention_secs = 0;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let trash = || async { client.get(format!("{base}/trash")).send().await.unwrap().json::<Vec<serde_json::Value>>().await.unwrap() };
+
+        let r = client.put(format!("{base}/objects/a/b.txt")).header("x-bucket-header-cache-control", "no-store").body("first").send().await.unwrap();
+        assert_eq!(r.status(), 201);
+        assert_eq!(client.delete(format!("{base}/objects/a/b.txt")).send().await.unwrap().status(), 204);
+        assert_eq!(client.get(format!("{base}/objects/a/b.txt")).send().await.unwrap().status(), 404);
+        let listed: Vec<serde_json::Value> = client.get(format!("{base}/objects?recursive=1")).send().await.unwrap().json().await.unwrap();
+        assert!(listed.is_empty(), "{listed:?}");
+
+        let entries = trash().await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["key"], "a/b.txt");
+        assert_eq!(entries[0]["size"], 5);
+        assert!(entries[0]["purge_after"].is_null());
+        let first = entries[0]["id"].as_str().unwrap().to_string();
+
+        // the key is reused, so restoring over it conflicts
+        client.put(format!("{base}/objects/a/b.txt")).body("second").send().await.unwrap();
+        let r = client.post(format!("{base}/trash/{first}/restore")).send().await.unwrap();
+        assert_eq!(r.status(), 409);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["error"], "key_occupied");
+
+        client.delete(format!("{base}/objects/a/b.txt")).send().await.unwrap();
+        let entries = trash().await;
+        assert_eq!(entries.len(), 2);
+        let second = entries.iter().find(|e| e["id"] != first.as_str()).unwrap()["id"].as_str().unwrap().to_string();
+
+        let r = client.post(format!("{base}/trash/{first}/restore")).send().await.unwrap();
+        assert_eq!(r.status(), 200);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["key"], "a/b.txt");
+        let r = client.get(format!("{base}/objects/a/b.txt")).send().await.unwrap();
+        assert_eq!(r.headers()["cache-control"], "no-store");
+        assert_eq!(r.text().await.unwrap(), "first");
+        let health: serde_json::Value = client.get(format!("{base}/healthz?deep=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(health["objects"]["count"], 1);
+
+        assert_eq!(client.post(format!("{base}/trash/{first}/restore")).send().await.unwrap().status(), 404);
+        assert_eq!(client.post(format!("{base}/trash/..%2F..%2Fetc/restore")).send().await.unwrap().status(), 404);
+        assert_eq!(client.delete(format!("{base}/trash/{second}")).send().await.unwrap().status(), 204);
+        assert!(trash().await.is_empty());
+        assert!(!td.path().join(".meta/.trash").read_dir().map(|mut d| d.next().is_some()).unwrap_or(false));
+
+        client.delete(format!("{base}/objects/a/b.txt")).send().await.unwrap();
+        let r: serde_json::Value = client.delete(format!("{base}/trash")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(r["purged"], 1);
+        assert!(trash().await.is_empty());
+    });
+}
+
+#[test]
+fn trash_retention_purges_old_entries() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.trash_enabled = true;
+        cfg.trash_retention_secs = 1;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        client.put(format!("{base}/objects/old.txt")).body("x").send().await.unwrap();
+        client.delete(format!("{base}/objects/old.txt")).send().await.unwrap();
+        let entries: Vec<serde_json::Value> = client.get(format!("{base}/trash")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0]["purge_after"].as_u64().is_some());
+
+        actix_web::rt::time::sleep(Duration::from_millis(3100)).await;
+        let entries: Vec<serde_json::Value> = client.get(format!("{base}/trash")).send().await.unwrap().json().await.unwrap();
+        assert!(entries.is_empty(), "{entries:?}");
+    });
+}
+
+#[test]
+fn expired_objects_read_as_absent_and_are_cleaned_up() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.expiry_sweep_secs = 3600; // only reads clean up here
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let put = |key: &str, expires: Option<(&'static str, String)>| {
+            let mut req = client.put(format!("{base}/objects/{key}")).body("data");
+            if let Some((name, value)) = expires {
+                req = req.header(name, value);
+            }
+            req.send()
+        };
+
+        let soon = rust_buck3t::pending::now_secs() + 1;
+        assert_eq!(put("tmp/a.bin", Some(("x-bkt-expires-in", "1".into()))).await.unwrap().status(), 201);
+        assert_eq!(put("tmp/b.bin", Some(("x-bkt-expires-at", soon.to_string()))).await.unwrap().status(), 201);
+        assert_eq!(put("tmp/later.bin", Some(("x-bkt-expires-in", "3600".into()))).await.unwrap().status(), 201);
+        assert_eq!(put("tmp/keep.bin", None).await.unwrap().status(), 201);
+
+        let r = client.head(format!("{base}/objects/tmp/later.bin")).send().await.unwrap();
+        let later_at: u64 = r.headers()["x-bkt-expires-at"].to_str().unwrap().parse().unwrap();
+        assert!(later_at >= soon + 3598);
+
+        actix_web::rt::time::sleep(Duration::from_millis(2100)).await;
+
+        // listing hides expired objects and shows pending expiries
+        let listed: Vec<serde_json::Value> = client.get(format!("{base}/objects?prefix=tmp")).send().await.unwrap().json().await.unwrap();
+        let keys: Vec<&str> = listed.iter().map(|o| o["key"].as_str().unwrap()).collect();
+        assert_eq!(keys, ["tmp/keep.bin", "tmp/later.bin"]);
+        assert!(listed[0].get("expires_at").is_none());
+        assert_eq!(listed[1]["expires_at"], later_at);
+        assert!(td.path().join("tmp/a.bin").exists(), "not swept yet");
+
+        // a read 404s and removes it
+        assert_eq!(client.get(format!("{base}/objects/tmp/a.bin")).send().await.unwrap().status(), 404);
+        assert!(!td.path().join("tmp/a.bin").exists());
+        assert!(!td.path().join(".meta/tmp/a.bin.json").exists());
+
+        // an overwrite treats the expired key as new and drops the expiry
+        assert_eq!(put("tmp/b.bin", None).await.unwrap().status(), 201);
+        let r = client.get(format!("{base}/objects/tmp/b.bin")).send().await.unwrap();
+        assert_eq!(r.status(), 200);
+        assert!(r.headers().get("x-bkt-expires-at").is_none());
+
+        let health: serde_json::Value = client.get(format!("{base}/healthz?deep=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(health["objects"]["count"], 3);
+    });
+}
+
+#[test]
+fn expiry_sweeper_deletes_due_objects() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.expiry_sweep_secs = 1;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        for i in 0..50 {
+            let r = client.put(format!("{base}/objects/batch/{i}.bin")).header("x-bkt-expires-in", "1").body("x").send().await.unwrap();
+            assert_eq!(r.status(), 201);
+        }
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_expiring_objects 50"), "{metrics}");
+
+        actix_web::rt::time::sleep(Duration::from_millis(3200)).await;
+        assert_eq!(std::fs::read_dir(td.path().join("batch")).unwrap().count(), 0);
+        let metrics = client.get(format!("{base}/metrics")).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("bucket_expiring_objects 0"), "{metrics}");
+        assert!(metrics.contains("bucket_objects 0"), "{metrics}");
+    });
+}
+
+#[test]
+fn stats_are_cached_until_refreshed() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.trash_enabled = true;
+        cfg.stats_refresh_secs = 3600;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let stats = || async { client.get(format!("{base}/stats")).send().await.unwrap().json::<serde_json::Value>().await.unwrap() };
+
+        for (key, body) in [("top.txt", "1"), ("a/x.bin", "12345"), ("a/b/y.bin", "12"), ("c/z.bin", "123"), ("c/gone.bin", "1234567")] {
+            client.put(format!("{base}/objects/{key}")).body(body).send().await.unwrap();
+        }
+        client.delete(format!("{base}/objects/c/gone.bin")).send().await.unwrap();
+
+        let s = stats().await;
+        assert_eq!(s["objects"], 4);
+        assert_eq!(s["bytes"], 11);
+        assert_eq!(s["largest"], serde_json::json!({ "key": "a/x.bin", "size": 5 }));
+        assert_eq!(s["prefixes"]["a/"], serde_json::json!({ "objects": 2, "bytes": 7 }));
+        assert_eq!(s["prefixes"]["c/"], serde_json::json!({ "objects": 1, "bytes": 3 }));
+        assert_eq!(s["prefixes"][""], serde_json::json!({ "objects": 1, "bytes": 1 }));
+        assert_eq!(s["trash"], serde_json::json!({ "objects": 1, "bytes": 7 }));
+        assert!(s["last_modified"].as_u64().is_some());
+        let generated_at = s["generated_at"].clone();
+
+        // served from the cache until forced
+        client.put(format!("{base}/objects/new.txt")).body("xx").send().await.unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+        let s = stats().await;
+        assert_eq!(s["objects"], 4);
+        assert_eq!(s["generated_at"], generated_at);
+
+        let s: serde_json::Value = client.get(format!("{base}/stats?refresh=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(s["objects"], 5);
+        assert_ne!(s["generated_at"], generated_at);
+    });
+}
+
+#[test]
+fn get_compresses_text_when_enabled() {
+    use tokio::io::AsyncReadExt;
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.compression = true;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let text = "line of log output\n".repeat(500);
+        client.put(format!("{base}/objects/app.log.txt")).body(text.clone()).send().await.unwrap();
+        client.put(format!("{base}/objects/pic.png")).body(text.clone()).send().await.unwrap();
+        let get = |key: &'static str, accept: &'static str| client.get(format!("{base}/objects/{key}")).header("accept-encoding", accept).send();
+
+        let plain = get("app.log.txt", "identity").await.unwrap();
+        let plain_etag = plain.headers()["etag"].to_str().unwrap().to_string();
+        assert_eq!(plain.headers()["vary"], "Accept-Encoding");
+        assert!(plain.headers().get("content-encoding").is_none());
+        assert_eq!(plain.headers()["content-length"], text.len().to_string());
+
+        for (accept, name, suffix) in [("gzip", "gzip", "-gzip\""), ("gzip, br", "br", "-br\"")] {
+            let r = get("app.log.txt", accept).await.unwrap();
+            assert_eq!(r.status(), 200);
+            assert_eq!(r.headers()["content-encoding"], name);
+            assert_eq!(r.headers()["vary"], "Accept-Encoding");
+            assert!(r.headers().get("content-length").is_none());
+            let etag = r.headers()["etag"].to_str().unwrap().to_string();
+            assert!(etag.ends_with(suffix) && etag != plain_etag, "{etag}");
+            let body = r.bytes().await.unwrap();
+            assert!(body.len() < text.len() / 10);
+            let mut out = String::new();
+            if name == "gzip" {
+                async_compression::tokio::bufread::GzipDecoder::new(&body[..]).read_to_string(&mut out).await.unwrap();
+            } else {
+                async_compression::tokio::bufread::BrotliDecoder::new(&body[..]).read_to_string(&mut out).await.unwrap();
+            }
+            assert_eq!(out, text);
+
+            let r = client.get(format!("{base}/objects/app.log.txt")).header("accept-encoding", accept).header("if-none-match", &etag).send().await.unwrap();
+            assert_eq!(r.status(), 304);
+        }
+
+        // ranges and dense types are served as stored
+        let r = client.get(format!("{base}/objects/app.log.txt")).header("accept-encoding", "gzip").header("range", "bytes=0-3").send().await.unwrap();
+        assert_eq!(r.status(), 206);
+        assert!(r.headers().get("content-encoding").is_none());
+        assert_eq!(r.text().await.unwrap(), "line");
+        let r = get("pic.png", "gzip, br").await.unwrap();
+        assert!(r.headers().get("content-encoding").is_none());
+        assert!(r.headers().get("vary").is_none());
+        assert_eq!(r.bytes().await.unwrap().len(), text.len());
+    });
+}
+
+#[test]
+fn put_accepts_gzip_bodies() {
+    use tokio::io::AsyncReadExt;
+
+    async fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        async_compression::tokio::bufread::GzipEncoder::new(data).read_to_end(&mut out).await.unwrap();
+        out
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.max_upload_bytes = Some(10_000);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let put = |key: &'static str, body: Vec<u8>| client.put(format!("{base}/objects/{key}")).header("content-encoding", "gzip").body(body).send();
+
+        let text = "compressible text\n".repeat(300);
+        let packed = gzip(text.as_bytes()).await;
+        assert_eq!(put("up/log.txt", packed.clone()).await.unwrap().status(), 201);
+        let r = client.get(format!("{base}/objects/up/log.txt")).send().await.unwrap();
+        assert!(r.headers().get("content-encoding").is_none());
+        assert_eq!(r.text().await.unwrap(), text);
+
+        // truncated stream: 400, nothing stored
+        let r = put("up/cut.txt", packed[..packed.len() / 2].to_vec()).await.unwrap();
+        assert_eq!(r.status(), 400);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_gzip");
+        assert!(!td.path().join("up/cut.txt").exists());
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).unwrap().count(), 0);
+
+        // the limit applies to the decoded size
+        let bomb = gzip(&vec![0u8; 1_000_000]).await;
+        assert!(bomb.len() < 10_000);
+        assert_eq!(put("up/bomb.bin", bomb).await.unwrap().status(), 413);
+        assert!(!td.path().join("up/bomb.bin").exists());
+
+        let r = client.put(format!("{base}/objects/up/x.txt")).header("content-encoding", "br").body("x").send().await.unwrap();
+        assert_eq!(r.status(), 415);
+    });
+
+    // opt-out: stored compressed and served with Content-Encoding
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.store_gzip_uploads = true;
+        cfg.compression = true;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let packed = gzip(b"{\"stored\": true}").await;
+        let r = client.put(format!("{base}/objects/doc.json")).header("content-encoding", "gzip").body(packed.clone()).send().await.unwrap();
+        assert_eq!(r.status(), 201);
+        assert_eq!(std::fs::read(td.path().join("doc.json")).unwrap(), packed);
+        let r = client.get(format!("{base}/objects/doc.json")).header("accept-encoding", "gzip, br").send().await.unwrap();
+        assert_eq!(r.headers()["content-encoding"], "gzip");
+        assert_eq!(r.bytes().await.unwrap().to_vec(), packed);
+    });
+}
+
+#[test]
+fn prefix_downloads_as_zip_and_tar() {
+    use std::io::Read;
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.archive_max_entries = 3;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        for (key, body) in [("photos/2024/a.jpg", "jpeg bytes"), ("photos/2024/trip/b.txt", "notes"), ("photos/2023/c.jpg", "old")] {
+            client.put(format!("{base}/objects/{key}")).body(body).send().await.unwrap();
+        }
+        let get = |query: &str| client.get(format!("{base}/objects-archive?{query}")).send();
+        let expected = [("a.jpg".to_string(), "jpeg bytes".to_string()), ("trip/b.txt".into(), "notes".into())];
+
+        let r = get("prefix=photos/2024/&format=zip").await.unwrap();
+        assert_eq!(r.status(), 200);
+        assert_eq!(r.headers()["content-type"], "application/zip");
+        assert_eq!(r.headers()["content-disposition"], "attachment; filename=\"photos-2024.zip\"");
+        let body = r.bytes().await.unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(body.to_vec())).unwrap();
+        let mut got = Vec::new();
+        for i in 0..zip.len() {
+            let mut f = zip.by_index(i).unwrap();
+            let mut s = String::new();
+            f.read_to_string(&mut s).unwrap();
+            got.push((f.name().to_string(), s));
+        }
+        assert_eq!(got, expected);
+
+        let read_tar = |data: Vec<u8>| {
+            let mut tar = tar::Archive::new(std::io::Cursor::new(data));
+            tar.entries()
+                .unwrap()
+                .map(|e| {
+                    let mut e = e.unwrap();
+                    let mut s = String::new();
+                    e.read_to_string(&mut s).unwrap();
+                    (e.path().unwrap().to_string_lossy().into_owned(), s)
+                })
+                .collect::<Vec<_>>()
+        };
+        let r = get("prefix=photos/2024&format=tar").await.unwrap();
+        assert_eq!(r.headers()["content-disposition"], "attachment; filename=\"photos-2024.tar\"");
+        assert_eq!(read_tar(r.bytes().await.unwrap().to_vec()), expected);
+
+        let r = get("prefix=photos/2024/&format=tar.gz").await.unwrap();
+        assert_eq!(r.headers()["content-type"], "application/gzip");
+        let mut tar = Vec::new();
+        flate2::read::GzDecoder::new(&r.bytes().await.unwrap()[..]).read_to_end(&mut tar).unwrap();
+        assert_eq!(read_tar(tar), expected);
+
+        // whole bucket, still under the entry cap
+        let r = get("format=tar").await.unwrap();
+        assert_eq!(r.headers()["content-disposition"], "attachment; filename=\"bucket.tar\"");
+        assert_eq!(read_tar(r.bytes().await.unwrap().to_vec()).len(), 3);
+
+        let r = get("prefix=nothing/here/").await.unwrap();
+        assert_eq!(r.status(), 404);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "no_objects");
+        assert_eq!(get("format=rar").await.unwrap().status(), 400);
+
+        client.put(format!("{base}/objects/photos/d.txt")).body("one too many").send().await.unwrap();
+        let r = get("prefix=photos").await.unwrap();
+        assert_eq!(r.status(), 400);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "archive_too_large");
+        assert_eq!(body["entries"], 4);
+    });
+}
+
+#[test]
+fn archive_upload_expands_under_prefix() {
+    use std::io::Write;
+
+    /// A tar entry with a raw name, bypassing the builder's path checks.
+    fn raw_entry<W: Write>(tar: &mut tar::Builder<W>, name: &str, kind: tar::EntryType, data: &[u8]) {
+        let mut h = tar::Header::new_old();
+        h.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        h.set_entry_type(kind);
+        h.set_size(data.len() as u64);
+        h.set_mode(0o644);
+        if kind == tar::EntryType::Symlink {
+            h.set_link_name("/etc/passwd").unwrap();
+        }
+        h.set_cksum();
+        tar.append(&h, data).unwrap();
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.max_upload_bytes = Some(64 * 1024);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let post = |query: &str, body: Vec<u8>| client.post(format!("{base}/objects-archive?{query}")).body(body).send();
+        client.put(format!("{base}/objects/site/index.html")).body("old").send().await.unwrap();
+
+        let mut tar = tar::Builder::new(Vec::new());
+        raw_entry(&mut tar, "./index.html", tar::EntryType::Regular, b"<h1>new</h1>");
+        raw_entry(&mut tar, "css/", tar::EntryType::Directory, b"");
+        raw_entry(&mut tar, "css/app.css", tar::EntryType::Regular, b"body{}");
+        raw_entry(&mut tar, "../escape.txt", tar::EntryType::Regular, b"nope");
+        raw_entry(&mut tar, "/etc/abs.txt", tar::EntryType::Regular, b"nope");
+        raw_entry(&mut tar, "link", tar::EntryType::Symlink, b"");
+        let r = post("prefix=site/", tar.into_inner().unwrap()).await.unwrap();
+        assert_eq!(r.status(), 200);
+        let summary: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(summary["created"], serde_json::json!(["site/css/app.css"]));
+        assert_eq!(summary["overwritten"], serde_json::json!(["site/index.html"]));
+        let skipped: Vec<(&str, &str)> = summary["skipped"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["name"].as_str().unwrap(), s["reason"].as_str().unwrap()))
+            .collect();
+        assert_eq!(skipped, [("../escape.txt", "invalid_path"), ("/etc/abs.txt", "invalid_path"), ("link", "not_a_file")]);
+        assert_eq!(client.get(format!("{base}/objects/site/index.html")).send().await.unwrap().text().await.unwrap(), "<h1>new</h1>");
+        assert!(!td.path().join("escape.txt").exists());
+        assert!(!td.path().join("site/link").exists());
+
+        // zip, deflated, with a symlink
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let opts = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("data/a.json", opts).unwrap();
+        zip.write_all(b"{\"a\":1}").unwrap();
+        zip.add_symlink("data/ln", "../../etc", opts).unwrap();
+        let body = zip.finish().unwrap().into_inner();
+        let summary: serde_json::Value = post("format=zip", body).await.unwrap().json().await.unwrap();
+        assert_eq!(summary["created"], serde_json::json!(["data/a.json"]));
+        assert_eq!(summary["skipped"][0]["reason"], "not_a_file");
+
+        // a corrupt archive reports what landed before the failure
+        let mut tar = tar::Builder::new(Vec::new());
+        raw_entry(&mut tar, "first.txt", tar::EntryType::Regular, b"ok");
+        raw_entry(&mut tar, "second.txt", tar::EntryType::Regular, &[b'x'; 4096]);
+        let mut cut = tar.into_inner().unwrap();
+        cut.truncate(2048);
+        let r = post("prefix=cut", cut).await.unwrap();
+        assert_eq!(r.status(), 400);
+        let body: serde_json::Value = r.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_archive");
+        assert_eq!(body["created"], serde_json::json!(["cut/first.txt"]));
+        assert!(!td.path().join("cut/second.txt").exists());
+
+        // MAX_UPLOAD_BYTES counts extracted bytes
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best()));
+        raw_entry(&mut tar, "zeros.bin", tar::EntryType::Regular, &vec![0u8; 1 << 20]);
+        let gz = tar.into_inner().unwrap().finish().unwrap();
+        assert!(gz.len() < 64 * 1024);
+        let r = post("prefix=bomb&format=tar.gz", gz).await.unwrap();
+        assert_eq!(r.status(), 413);
+        assert!(!td.path().join("bomb/zeros.bin").exists());
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).unwrap().count(), 0);
+    });
+}
+
+#[test]
+fn form_post_stores_file_parts() {
+    fn form(parts: &[(&str, Option<&str>, &str)]) -> (String, Vec<u8>) {
+        let boundary = "----bucketform";
+        let mut body = String::new();
+        for (name, filename, data) in parts {
+            body += &format!("--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"");
+            if let Some(f) = filename {
+                body += &format!("; filename=\"{f}\"\r\nContent-Type: application/octet-stream");
+            }
+            body += &format!("\r\n\r\n{data}\r\n");
+        }
+        body += &format!("--{boundary}--\r\n");
+        (format!("multipart/form-data; boundary={boundary}"), body.into_bytes())
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.max_upload_bytes = Some(100);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let post = |path: &str, (ct, body): (String, Vec<u8>)| client.post(format!("{base}/{path}")).header("content-type", ct).body(body).send();
+
+        let r = post(
+            "objects/inbox/",
+            form(&[("note", None, "ignored"), ("file", Some("a.txt"), "alpha"), ("file", Some("client/side/b.txt"), "bravo!"), ("empty", Some(""), "")]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(r.status(), 200);
+        let stored: Vec<serde_json::Value> = r.json().await.unwrap();
+        let keys: Vec<(&str, u64)> = stored.iter().map(|s| (s["key"].as_str().unwrap(), s["size"].as_u64().unwrap())).collect();
+        assert_eq!(keys, [("inbox/a.txt", 5), ("inbox/b.txt", 6)]);
+        let r = client.get(format!("{base}/objects/inbox/b.txt")).send().await.unwrap();
+        assert_eq!(r.headers()["etag"], stored[1]["etag"].as_str().unwrap());
+        assert_eq!(r.text().await.unwrap(), "bravo!");
+
+        // bucket root
+        let stored: Vec<serde_json::Value> = post("objects", form(&[("f", Some("top.txt"), "t")])).await.unwrap().json().await.unwrap();
+        assert_eq!(stored[0]["key"], "top.txt");
+
+        assert_eq!(post("objects/x", form(&[("f", Some(".."), "nope")])).await.unwrap().status(), 400);
+
+        // each file and the request as a whole are capped
+        let big = "x".repeat(101);
+        assert_eq!(post("objects/cap", form(&[("f", Some("big.bin"), &big)])).await.unwrap().status(), 413);
+        let half = "y".repeat(60);
+        assert_eq!(post("objects/cap", form(&[("f", Some("one.bin"), &half), ("f", Some("two.bin"), &half)])).await.unwrap().status(), 413);
+        assert!(!td.path().join("cap/big.bin").exists());
+        assert!(!td.path().join("cap/two.bin").exists());
+
+        // raw bodies keep their meaning
+        let r = client.post(format!("{base}/objects/inbox/a.txt")).body("raw").send().await.unwrap();
+        assert_eq!(r.status(), 400);
+    });
+}
+
+#[cfg(feature = "ui")]
+#[test]
+fn ui_is_served_only_when_enabled() {
+    actix_web::rt::System::new().block_on(async {
+        let client = Client::new();
+        let (base, _td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        assert_eq!(client.get(format!("{base}/ui")).send().await.unwrap().status(), 404);
+
+        let mut cfg = open_cfg();
+        cfg.ui_enabled = true;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        for path in ["ui", "ui/"] {
+            let r = client.get(format!("{base}/{path}")).send().await.unwrap();
+            assert_eq!(r.status(), 200);
+            assert_eq!(r.headers()["content-type"], "text/html; charset=utf-8");
+            let csp = r.headers()["content-security-policy"].to_str().unwrap().to_string();
+            assert!(csp.starts_with("default-src 'self'"), "{csp}");
+            let html = r.text().await.unwrap();
+            assert!(html.contains("/auth/login") && html.contains("/objects"));
+            // self-contained: nothing fetched from another origin
+            assert!(!html.contains("http://") && !html.contains("https://"));
+        }
+    });
+}
+
+#[test]
+fn listing_renders_html_for_browsers() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, _td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        for key in ["top.txt", "docs/a%20b.txt", "docs/%3Cscript%3Ealert(1)%3Cb%3E", "docs/%22%26x'", "docs/deep/z.bin"] {
+            client.put(format!("{base}/objects/{key}")).body("hi").send().await.unwrap();
+        }
+
+        // API clients keep JSON; a bare */* is not a preference
+        let r = client.get(format!("{base}/objects")).header("accept", "*/*").send().await.unwrap();
+        assert!(r.headers()["content-type"].to_str().unwrap().starts_with("application/json"));
+
+        let accept = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        let r = client.get(format!("{base}/objects")).header("accept", accept).send().await.unwrap();
+        assert_eq!(r.headers()["content-type"], "text/html; charset=utf-8");
+        let html = r.text().await.unwrap();
+        assert!(html.contains("href=\"/objects/top.txt?download=0\""), "{html}");
+        assert!(html.contains("href=\"/objects?prefix=docs/&amp;format=html\">docs/</a>"), "{html}");
+        assert!(!html.contains("../"));
+
+        let html = client.get(format!("{base}/objects?prefix=docs/&format=html")).send().await.unwrap().text().await.unwrap();
+        assert!(html.contains("href=\"/objects?format=html\">../</a>"), "{html}");
+        assert!(html.contains("href=\"/objects?prefix=docs/deep/&amp;format=html\">deep/</a>"), "{html}");
+        assert!(html.contains("href=\"/objects/docs/a%20b.txt?download=0\">a b.txt</a>"), "{html}");
+        assert!(html.contains(">&lt;script&gt;alert(1)&lt;b&gt;</a>"), "{html}");
+        assert!(html.contains(">&quot;&amp;x&#39;</a>"), "{html}");
+        assert!(!html.contains("<script>"));
+
+        let html = client.get(format!("{base}/objects?prefix=docs/deep&format=html")).send().await.unwrap().text().await.unwrap();
+        assert!(html.contains("href=\"/objects?prefix=docs/&amp;format=html\">../</a>"), "{html}");
+
+        // ?format=json wins over Accept
+        let r = client.get(format!("{base}/objects?format=json")).header("accept", accept).send().await.unwrap();
+        assert!(r.headers()["content-type"].to_str().unwrap().starts_with("application/json"));
+        assert_eq!(client.get(format!("{base}/objects?format=xml")).send().await.unwrap().status(), 400);
+    });
+}
+
+#[test]
+fn overlapping_puts_to_one_key_do_not_mix() {
+    // 8 chunks of `fill`, 20ms apart, so two uploads overlap on the wire
+    fn slow_body(fill: u8) -> reqwest::Body {
+        let chunks = futures_util::stream::unfold(0, move |i| async move {
+            if i == 8 {
+                return None;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+            Some((Ok::<_, std::io::Error>(vec![fill; 4096]), i + 1))
+        });
+        reqwest::Body::wrap_stream(chunks)
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let url = format!("{base}/objects/race.bin");
+
+        let (a, b) = futures_util::join!(
+            client.put(&url).body(slow_body(b'a')).send(),
+            client.put(&url).body(slow_body(b'b')).send()
+        );
+        let mut statuses = [a.unwrap().status().as_u16(), b.unwrap().status().as_u16()];
+        statuses.sort();
+        assert_eq!(statuses, [200, 201], "the second writer waits its turn");
+        let body = std::fs::read(td.path().join("race.bin")).unwrap();
+        assert_eq!(body.len(), 8 * 4096);
+        assert!(body.iter().all(|&c| c == body[0]), "content must come from exactly one PUT");
+    });
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.reject_concurrent_writes = true;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let url = format!("{base}/objects/race.bin");
+
+        let first = client.put(&url).body(slow_body(b'a')).send();
+        let second = async {
+            actix_web::rt::time::sleep(Duration::from_millis(60)).await;
+            client.put(&url).body("b").send().await
+        };
+        let (first, second) = futures_util::join!(first, second);
+        assert_eq!(first.unwrap().status(), reqwest::StatusCode::CREATED);
+        let second = second.unwrap();
+        assert_eq!(second.status(), reqwest::StatusCode::CONFLICT);
+        let err: serde_json::Value = second.json().await.unwrap();
+        assert_eq!(err["error"], "write_in_progress");
+        assert_eq!(std::fs::read(td.path().join("race.bin")).unwrap(), vec![b'a'; 8 * 4096]);
+    });
+}
+
+#[test]
+fn client_disconnect_mid_put_stores_nothing() {
+    use tokio::io::AsyncWriteExt;
+
+    // sends `head` and `partial`, then hangs up
+    async fn cut_off(base: &str, head: &str, partial: &[u8]) {
+        let mut stream = actix_web::rt::net::TcpStream::connect(base.trim_start_matches("http://")).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(partial).await.unwrap();
+        stream.flush().await.unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        // half the declared length, then gone
+        let head = "PUT /objects/cut.bin HTTP/1.1\r\nHost: t\r\nContent-Length: 100\r\n\r\n";
+        cut_off(&base, head, &[b'x'; 50]).await;
+        // a chunked body that never sends its last chunk
+        let head = "PUT /objects/chunked.bin HTTP/1.1\r\nHost: t\r\nTransfer-Encoding: chunked\r\n\r\n";
+        cut_off(&base, head, b"5\r\nhello\r\n").await;
+        wait_for_uploads(&client, &base, 0).await;
+
+        for key in ["cut.bin", "chunked.bin"] {
+            let resp = client.get(format!("{base}/objects/{key}")).send().await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND, "{key}");
+        }
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).map(|d| d.count()).unwrap_or(0), 0);
+
+        // an interrupted overwrite leaves the old object whole
+        client.put(format!("{base}/objects/keep.bin")).body("original").send().await.unwrap();
+        let head = "PUT /objects/keep.bin HTTP/1.1\r\nHost: t\r\nContent-Length: 100\r\n\r\n";
+        cut_off(&base, head, &[b'y'; 50]).await;
+        wait_for_uploads(&client, &base, 0).await;
+        let resp = client.get(format!("{base}/objects/keep.bin")).send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "original");
+    });
+}
+
+#[test]
+fn declared_length_over_limit_is_refused_up_front() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // headers only: the server must answer without waiting for a body
+    async fn status_line(base: &str, head: &str) -> String {
+        let mut stream = actix_web::rt::net::TcpStream::connect(base.trim_start_matches("http://")).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let mut buf = vec![0u8; 1024];
+        let n = actix_web::rt::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await.unwrap().unwrap();
+        String::from_utf8_lossy(&buf[..n]).lines().next().unwrap_or("").to_string()
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.max_upload_bytes = Some(10 * 1000 * 1000);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+
+        let put = "PUT /objects/big/x.bin HTTP/1.1\r\nHost: t\r\nContent-Length: 50000000000\r\n\r\n";
+        assert!(status_line(&base, put).await.starts_with("HTTP/1.1 413"));
+        assert!(!td.path().join("big").exists(), "nothing may be created for a refused upload");
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).map(|d| d.count()).unwrap_or(0), 0);
+
+        // chunked bodies keep the streaming guard
+        let client = Client::new();
+        let chunks = futures_util::stream::iter(vec![Ok::<_, std::io::Error>(vec![0u8; 6_000_000]), Ok(vec![0u8; 6_000_000])]);
+        let resp = client.put(format!("{base}/objects/big/y.bin")).body(reqwest::Body::wrap_stream(chunks)).send().await;
+        assert!(resp.map_or(true, |r| r.status() == reqwest::StatusCode::PAYLOAD_TOO_LARGE));
+        assert!(!td.path().join("big/y.bin").exists());
+    });
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.require_content_length = true;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+
+        let chunked = "PUT /objects/c.txt HTTP/1.1\r\nHost: t\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx\r\n0\r\n\r\n";
+        assert!(status_line(&base, chunked).await.starts_with("HTTP/1.1 411"));
+        assert!(!td.path().join("c.txt").exists());
+        let resp = Client::new().put(format!("{base}/objects/c.txt")).body("x").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::CREATED);
+    });
+}
+
+#[test]
+fn expect_continue_puts_are_refused_from_headers_alone() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // sends `head` with `Expect: 100-continue` and no body; the final status
+    // line (past any interim 100) must come without one
+    async fn final_status(base: &str, head: &str) -> String {
+        let mut stream = actix_web::rt::net::TcpStream::connect(base.trim_start_matches("http://")).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let mut seen = String::new();
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let n = actix_web::rt::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+                .await
+                .expect("answered without the body")
+                .unwrap();
+            assert!(n > 0, "connection closed with {seen:?}");
+            seen.push_str(&String::from_utf8_lossy(&buf[..n]));
+            let rest = seen.trim_start_matches("HTTP/1.1 100 Continue\r\n\r\n");
+            if rest.contains("\r\n") {
+                return rest.lines().next().unwrap().to_string();
+            }
+        }
+    }
+    let put = |path: &str, extra: &str| {
+        format!("PUT {path} HTTP/1.1\r\nHost: t\r\nExpect: 100-continue\r\nContent-Length: 3\r\n{extra}\r\n")
+    };
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.auth_mode = consts::AuthMode::ApiKey;
+        cfg.api_keys = vec![consts::ApiKey { name: "ci".into(), key: "k-ci".into(), scopes: vec!["obj:write".into()] }];
+        cfg.auth_write = true;
+        cfg.max_upload_bytes = Some(1000);
+        cfg.max_object_count = Some(1);
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let key = "x-api-key: k-ci\r\n";
+        let resp = Client::new().put(format!("{base}/objects/one.txt")).header("x-api-key", "k-ci").body("one").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::CREATED);
+
+        let long_key = format!("/objects/{}", "k".repeat(300));
+        let big = "PUT /objects/big.bin HTTP/1.1\r\nHost: t\r\nExpect: 100-continue\r\nContent-Length: 5000\r\nx-api-key: k-ci\r\n\r\n";
+        for (head, status) in [
+            (put("/objects/two.txt", ""), "401"),
+            (put("/objects/two.txt", "x-api-key: wrong\r\n"), "401"),
+            (put(&long_key, key), "400"),
+            (big.to_string(), "413"),
+            (put("/objects/one.txt", &format!("{key}If-None-Match: *\r\n")), "412"),
+            (put("/objects/one.txt", &format!("{key}If-Match: \"nope\"\r\n")), "412"),
+            (put("/objects/two.txt", &format!("{key}If-Match: *\r\n")), "412"),
+            // the one object the count allows already exists
+            (put("/objects/two.txt", key), "507"),
+        ] {
+            let got = final_status(&base, &head).await;
+            assert!(got.starts_with(&format!("HTTP/1.1 {status}")), "{head:?} → {got}");
+        }
+        assert_eq!(std::fs::read_to_string(td.path().join("one.txt")).unwrap(), "one");
+        assert!(!td.path().join("two.txt").exists() && !td.path().join("big.bin").exists());
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).map(|d| d.count()).unwrap_or(0), 0);
+
+        // an accepted PUT gets its 100 and takes the body after it
+        let mut stream = actix_web::rt::net::TcpStream::connect(base.trim_start_matches("http://")).await.unwrap();
+        stream.write_all(put("/objects/one.txt", key).as_bytes()).await.unwrap();
+        let mut buf = vec![0u8; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 100 Continue"));
+        stream.write_all(b"two").await.unwrap();
+        let n = actix_web::rt::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200"));
+        assert_eq!(std::fs::read_to_string(td.path().join("one.txt")).unwrap(), "two");
+    });
+
+    // a body that can't fit on the volume
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.min_free_bytes = u64::MAX / 4;
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        assert!(final_status(&base, &put("/objects/a.txt", "")).await.starts_with("HTTP/1.1 507"));
+    });
+}
+
+#[test]
+fn unsupported_methods_get_405_with_allow() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, _td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        client.put(format!("{base}/objects/m/a.txt")).body("x").send().await.unwrap();
+        let method = |m: &str| reqwest::Method::from_bytes(m.as_bytes()).unwrap();
+        let object_allow = "GET, HEAD, PUT, POST, PATCH, DELETE, OPTIONS";
+        let listing_allow = "GET, HEAD, POST, OPTIONS";
+
+        for (m, url, allow) in [
+            ("PROPFIND", format!("{base}/objects/m/a.txt"), object_allow),
+            ("TRACE", format!("{base}/objects/m/missing.txt"), object_allow),
+            ("PUT", format!("{base}/objects"), listing_allow),
+            ("DELETE", format!("{base}/objects"), listing_allow),
+            ("PATCH", format!("{base}/b/photos/objects"), listing_allow),
+        ] {
+            let resp = client.request(method(m), &url).send().await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::METHOD_NOT_ALLOWED, "{m} {url}");
+            assert_eq!(resp.headers()[header::ALLOW], allow, "{m} {url}");
+            let body: serde_json::Value = resp.json().await.unwrap();
+            assert_eq!(body["error"], "method_not_allowed");
+        }
+        // the routed methods and unknown paths are unchanged
+        let resp = client.post(format!("{base}/objects/m/a.txt")).send().await.unwrap();
+        assert_ne!(resp.status(), reqwest::StatusCode::METHOD_NOT_ALLOWED);
+        let resp = client.get(format!("{base}/objects/m/a.txt")).send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "x");
+        let resp = client.request(method("PROPFIND"), format!("{base}/nowhere")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+    });
+}
+
+#[test]
+fn put_reports_etag_last_modified_and_location() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let url = format!("{base}/objects/p/etag.txt");
+
+        let created = client.put(&url).body("one").send().await.unwrap();
+        assert_eq!(created.status(), reqwest::StatusCode::CREATED);
+        assert_eq!(created.headers()[header::LOCATION], "/objects/p/etag.txt");
+        let etag = created.headers()[header::ETAG].to_str().unwrap().to_string();
+        let mtime = std::fs::metadata(td.path().join("p/etag.txt")).unwrap().modified().unwrap();
+        assert_eq!(
+            created.headers()[header::LAST_MODIFIED],
+            actix_web::http::header::HttpDate::from(mtime).to_string().as_str()
+        );
+        let head = client.head(&url).send().await.unwrap();
+        assert_eq!(head.headers()[header::ETAG], etag.as_str());
+
+        // the returned tag is good for the next conditional write
+        let overwritten = client.put(&url).header(header::IF_MATCH, &etag).body("second").send().await.unwrap();
+        assert_eq!(overwritten.status(), reqwest::StatusCode::OK);
+        assert!(overwritten.headers().get(header::LOCATION).is_none());
+        let etag2 = overwritten.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert_ne!(etag2, etag);
+        let head = client.head(&url).send().await.unwrap();
+        assert_eq!(head.headers()[header::ETAG], etag2.as_str());
+        let stale = client.put(&url).header(header::IF_MATCH, &etag).body("third").send().await.unwrap();
+        assert_eq!(stale.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+    });
+}
+
+#[test]
+fn put_cut_short_is_not_stored() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+
+        // ten bytes promised, three sent, then the client stops writing
+        let mut stream = actix_web::rt::net::TcpStream::connect(base.trim_start_matches("http://")).await.unwrap();
+        stream.write_all(b"PUT /objects/short.txt HTTP/1.1\r\nHost: t\r\nContent-Length: 10\r\n\r\nabc").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = Vec::new();
+        let _ = actix_web::rt::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut reply)).await;
+        let reply = String::from_utf8_lossy(&reply);
+        assert!(!reply.starts_with("HTTP/1.1 2"), "{reply}");
+
+        let head = Client::new().head(format!("{base}/objects/short.txt")).send().await.unwrap();
+        assert_eq!(head.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(!td.path().join("short.txt").exists());
+        assert_eq!(std::fs::read_dir(td.path().join(".tmp")).map(|d| d.count()).unwrap_or(0), 0);
+    });
+}
+
+#[test]
+fn listing_includes_directories_on_request() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        for key in ["ui/readme.txt", "ui/img/a.png", "ui/img/b.png"] {
+            client.put(format!("{base}/objects/{key}")).body("x").send().await.unwrap();
+        }
+        // made behind the server's back
+        std::fs::create_dir_all(td.path().join("ui/empty")).unwrap();
+        let list = |query: &'static str| {
+            let client = client.clone();
+            let url = format!("{base}/objects?{query}");
+            async move { client.get(url).send().await.unwrap().json::<serde_json::Value>().await.unwrap() }
+        };
+
+        let shallow = list("prefix=ui/&include_dirs=1").await;
+        assert_eq!(
+            shallow,
+            serde_json::json!([
+                { "key": "ui/empty/", "size": 0, "modified": shallow[0]["modified"], "type": "dir", "children": 0 },
+                { "key": "ui/img/", "size": 0, "modified": shallow[1]["modified"], "type": "dir", "children": 2 },
+                { "key": "ui/readme.txt", "size": 1, "modified": shallow[2]["modified"], "type": "file" },
+            ])
+        );
+        let deep = list("prefix=ui/&recursive=1&include_dirs=1&format=json").await;
+        assert_eq!(deep.as_array().unwrap().len(), 5, "{deep}");
+
+        // without the flag: files only, and no `type`
+        let plain = list("prefix=ui/").await;
+        assert_eq!(plain.as_array().unwrap().len(), 1);
+        assert!(plain[0].get("type").is_none());
+    });
+}
+
+#[test]
+fn resumable_upload_survives_a_dropped_connection_and_expires() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let created = client.post(format!("{base}/uploads?key=v/movie.bin")).header("Upload-Length", "10").send().await.unwrap();
+        assert_eq!(created.status(), reqwest::StatusCode::CREATED);
+        let upload = created.headers()["location"].to_str().unwrap().to_string();
+        let offset = || async {
+            let head = client.head(format!("{base}{upload}")).send().await.unwrap();
+            head.headers()["upload-offset"].to_str().unwrap().to_string()
+        };
+
+        // ten bytes promised, four sent, then the connection goes
+        let mut stream = actix_web::rt::net::TcpStream::connect(base.trim_start_matches("http://")).await.unwrap();
+        let request = format!("PATCH {upload} HTTP/1.1\r\nHost: t\r\nUpload-Offset: 0\r\nContent-Length: 10\r\n\r\nmovi");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = Vec::new();
+        let _ = actix_web::rt::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut reply)).await;
+        assert_eq!(offset().await, "4");
+        assert!(!td.path().join("v/movie.bin").exists());
+
+        let rest = client.patch(format!("{base}{upload}")).header("Upload-Offset", "4").body("e-body").send().await.unwrap();
+        assert_eq!(rest.status(), reqwest::StatusCode::NO_CONTENT);
+        let body = client.get(format!("{base}/objects/v/movie.bin")).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "movie-body");
+        assert_eq!(std::fs::read_dir(td.path().join(".uploads")).unwrap().count(), 0);
+        let listed: Vec<serde_json::Value> =
+            client.get(format!("{base}/objects?recursive=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed.len(), 1, "{listed:?}");
+    });
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.resumable_expiry_secs = 1;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let created = client.post(format!("{base}/uploads?key=idle.bin")).header("Upload-Length", "5").send().await.unwrap();
+        let upload = created.headers()["location"].to_str().unwrap().to_string();
+        client.patch(format!("{base}{upload}")).header("Upload-Offset", "0").body("ab").send().await.unwrap();
+
+        actix_web::rt::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(std::fs::read_dir(td.path().join(".uploads")).unwrap().count(), 0, "the sweeper removed it");
+        let head = client.head(format!("{base}{upload}")).send().await.unwrap();
+        assert_eq!(head.status(), reqwest::StatusCode::NOT_FOUND);
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn dedup_shares_identical_bodies_and_collects_blobs() {
+    use std::os::unix::fs::MetadataExt;
+
+    fn blobs(root: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let Ok(dirs) = std::fs::read_dir(root.join(".blobs")) else { return Vec::new() };
+        dirs.flatten().flat_map(|d| std::fs::read_dir(d.path()).unwrap().flatten().map(|b| b.path())).collect()
+    }
+
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.dedup = true;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let put = |key: &str, body: &'static str| client.put(format!("{base}/objects/{key}")).body(body).send();
+
+        assert_eq!(put("a.txt", "same bytes").await.unwrap().status(), reqwest::StatusCode::CREATED);
+        assert_eq!(put("dir/b.txt", "same bytes").await.unwrap().status(), reqwest::StatusCode::CREATED);
+        let (a, b) = (std::fs::metadata(td.path().join("a.txt")).unwrap(), std::fs::metadata(td.path().join("dir/b.txt")).unwrap());
+        assert_eq!(a.ino(), b.ino(), "identical bodies share one inode");
+        assert_eq!(blobs(td.path()).len(), 1);
+        assert_eq!(a.nlink(), 3);
+        let body = client.get(format!("{base}/objects/dir/b.txt")).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "same bytes");
+        let listed: Vec<serde_json::Value> =
+            client.get(format!("{base}/objects?recursive=1")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed.len(), 2, "{listed:?}");
+
+        // overwriting one key leaves the other's content alone
+        assert_eq!(put("a.txt", "new bytes").await.unwrap().status(), reqwest::StatusCode::OK);
+        let body = client.get(format!("{base}/objects/dir/b.txt")).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "same bytes");
+        assert_eq!(blobs(td.path()).len(), 2);
+
+        // the last link going takes the blob with it
+        client.delete(format!("{base}/objects/dir/b.txt")).send().await.unwrap();
+        assert_eq!(blobs(td.path()).len(), 1);
+        client.delete(format!("{base}/objects/a.txt")).send().await.unwrap();
+        assert!(blobs(td.path()).is_empty());
+
+        // touching a deduplicated key moves its own mtime, not its twin's
+        for key in ["t.txt", "u.txt"] {
+            assert_eq!(put(key, "touch me").await.unwrap().status(), reqwest::StatusCode::CREATED);
+        }
+        let past = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        std::fs::File::options().write(true).open(td.path().join("t.txt")).unwrap().set_modified(past).unwrap();
+        let resp = client.post(format!("{base}/objects/t.txt?metadata=1&touch=1")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let (t, u) = (std::fs::metadata(td.path().join("t.txt")).unwrap(), std::fs::metadata(td.path().join("u.txt")).unwrap());
+        assert!(t.modified().unwrap() > past);
+        assert_eq!(u.modified().unwrap(), past);
+        assert_eq!((t.nlink(), u.nlink()), (1, 2));
+        let body = client.get(format!("{base}/objects/t.txt")).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "touch me");
+        for key in ["t.txt", "u.txt"] {
+            client.delete(format!("{base}/objects/{key}")).send().await.unwrap();
+        }
+        assert!(blobs(td.path()).is_empty());
+
+        // a blob orphaned by a crash is found by the repair pass
+        let sha = "ab".repeat(32);
+        std::fs::create_dir_all(td.path().join(".blobs/ab")).unwrap();
+        std::fs::write(td.path().join(".blobs/ab").join(&sha), "orphan").unwrap();
+        let state = AppState::new(td.path());
+        assert_eq!(rust_buck3t::dedup::collect(&state).await.unwrap(), 1);
+        assert!(blobs(td.path()).is_empty());
+    });
+
+    // off: no blob store at all
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        for key in ["a.txt", "b.txt"] {
+            client.put(format!("{base}/objects/{key}")).body("same bytes").send().await.unwrap();
+        }
+        assert!(!td.path().join(".blobs").exists());
+        assert_eq!(std::fs::metadata(td.path().join("a.txt")).unwrap().nlink(), 1);
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_inside_root_are_not_followed() {
+    actix_web::rt::System::new().block_on(async {
+        let (base, td) = start_server(open_cfg());
+        wait_alive(&base).await;
+        let client = Client::new();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), td.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.txt"), td.path().join("file-link")).unwrap();
+
+        for key in ["link/secret.txt", "file-link"] {
+            let r = client.get(format!("{base}/objects/{key}")).send().await.unwrap();
+            assert_eq!(r.status(), 400, "GET {key}");
+            assert_eq!(client.head(format!("{base}/objects/{key}")).send().await.unwrap().status(), 400, "HEAD {key}");
+            assert_eq!(client.delete(format!("{base}/objects/{key}")).send().await.unwrap().status(), 400, "DELETE {key}");
+        }
+        // no writes through a linked parent or onto a linked file
+        let r = client.put(format!("{base}/objects/link/new.txt")).body("x").send().await.unwrap();
+        assert_eq!(r.status(), 400);
+        let r = client.put(format!("{base}/objects/file-link")).body("x").send().await.unwrap();
+        assert_eq!(r.status(), 400);
+        assert!(!outside.path().join("new.txt").exists());
+        assert_eq!(std::fs::read_to_string(outside.path().join("secret.txt")).unwrap(), "secret");
+
+        let r = client.get(format!("{base}/objects?prefix=link/")).send().await.unwrap();
+        assert_eq!(r.status(), 400);
+        let listed: Vec<serde_json::Value> = client.get(format!("{base}/objects?recursive=1")).send().await.unwrap().json().await.unwrap();
+        assert!(listed.is_empty(), "{listed:?}");
+    });
+}
+
+#[test]
+fn retention_blocks_changes_until_it_passes() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.trash_enabled = true;
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let url = format!("{base}/objects/legal/hold.txt");
+
+        let until = rust_buck3t::pending::now_secs() + 5;
+        let r = client.put(&url).header("x-bkt-retain-until", until.to_string()).body("v1").send().await.unwrap();
+        assert_eq!(r.status(), 201);
+        let r = client.head(&url).send().await.unwrap();
+        assert_eq!(r.headers()["x-bkt-retain-until"], until.to_string().as_str());
+
+        // no overwrite, append, delete or metadata edit while it holds
+        let locked = |r: reqwest::Response| async move {
+            assert_eq!(r.status(), 403);
+            let body: serde_json::Value = r.json().await.unwrap();
+            assert_eq!(body["error"], "object_locked", "{body}");
+        };
+        locked(client.put(&url).body("v2").send().await.unwrap()).await;
+        locked(client.patch(&url).header("content-range", "bytes 2-*/*").body("+").send().await.unwrap()).await;
+        locked(client.delete(&url).send().await.unwrap()).await;
+        locked(client.post(format!("{url}?metadata=1")).header("x-bkt-meta-owner", "me").send().await.unwrap()).await;
+        client.put(format!("{base}/objects/legal/other.txt")).body("v3").send().await.unwrap();
+        locked(client.post(format!("{url}?copy_from=legal/other.txt")).send().await.unwrap()).await;
+        // shortening is refused; extending is not
+        let r = client.post(format!("{url}?metadata=1")).header("x-bkt-retain-until", (until - 1).to_string()).send().await.unwrap();
+        locked(r).await;
+        let until = until + 1;
+        let r = client.post(format!("{url}?metadata=1")).header("x-bkt-retain-until", until.to_string()).send().await.unwrap();
+        assert_eq!(r.status(), 200);
+        assert_eq!(r.json::<serde_json::Value>().await.unwrap()["retain_until"], until);
+        assert_eq!(client.get(&url).send().await.unwrap().text().await.unwrap(), "v1");
+        assert!(!td.path().join(".trash").read_dir().is_ok_and(|mut d| d.next().is_some()), "nothing went to the trash");
+
+        // a retention can't outlast an expiry
+        let r = client.put(format!("{base}/objects/legal/short.txt"))
+            .header("x-bkt-retain-until", until.to_string())
+            .header("x-bkt-expires-in", "1")
+            .body("x")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(r.status(), 400);
+
+        while rust_buck3t::pending::now_secs() < until {
+            actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        }
+        assert_eq!(client.delete(&url).send().await.unwrap().status(), 204);
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 404);
+    });
+}
+
+#[test]
+fn s3_subset_signs_lists_and_completes_multipart() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.s3_enabled = true;
+        cfg.auth_mode = consts::AuthMode::ApiKey;
+        cfg.auth_read = true;
+        cfg.auth_list = true;
+        cfg.api_keys = vec![consts::ApiKey {
+            name: "AKTEST".into(),
+            key: "s3cr3t".into(),
+            scopes: vec!["obj:write".into(), "obj:read".into(), "obj:list".into(), "bkt:admin".into()],
+        }];
+        cfg.buckets = vec!["photos".into()];
+        let (base, _td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+        let signed = |method: reqwest::Method, path: &str| {
+            let url = format!("{base}{path}");
+            let mut req = client.request(method.clone(), &url);
+            for (k, v) in common::sigv4(method.as_str(), &url, "AKTEST", "s3cr3t") {
+                req = req.header(k, v);
+            }
+            req
+        };
+
+        // unsigned and wrongly signed requests get S3 XML errors
+        let resp = client.get(format!("{base}/photos/a/one.txt")).send().await.unwrap();
+        assert_eq!(resp.status(), 403);
+        assert!(resp.text().await.unwrap().contains("<Code>AccessDenied</Code>"));
+        let url = format!("{base}/photos/a/one.txt");
+        let mut req = client.get(&url);
+        for (k, v) in common::sigv4("GET", &url, "AKTEST", "wrong") {
+            req = req.header(k, v);
+        }
+        let resp = req.send().await.unwrap();
+        assert_eq!(resp.status(), 403);
+        assert!(resp.text().await.unwrap().contains("<Code>SignatureDoesNotMatch</Code>"));
+
+        // PUT / GET round trip with user metadata
+        let resp = signed(reqwest::Method::PUT, "/photos/a/one.txt")
+            .header("content-type", "text/markdown")
+            .header("x-amz-meta-owner", "me")
+            .body("one")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        assert!(!resp.headers()[header::ETAG].to_str().unwrap().starts_with("W/"));
+        for key in ["a/two.txt", "b.txt"] {
+            let resp = signed(reqwest::Method::PUT, &format!("/photos/{key}")).body("x").send().await.unwrap();
+            assert_eq!(resp.status(), 200);
+        }
+        let resp = signed(reqwest::Method::GET, "/photos/a/one.txt").send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["x-amz-meta-owner"], "me");
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/markdown");
+        assert!(resp.headers().contains_key(header::LAST_MODIFIED));
+        assert_eq!(resp.text().await.unwrap(), "one");
+
+        let resp = signed(reqwest::Method::GET, "/photos/missing.txt").send().await.unwrap();
+        assert_eq!(resp.status(), 404);
+        assert!(resp.text().await.unwrap().contains("<Code>NoSuchKey</Code>"));
+
+        // aws-chunked bodies are unwrapped before they land
+        let resp = signed(reqwest::Method::PUT, "/photos/chunked.txt")
+            .header("content-encoding", "aws-chunked")
+            .header("x-amz-decoded-content-length", "5")
+            .body("5\r\nhello\r\n0\r\n\r\n")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let resp = signed(reqwest::Method::GET, "/photos/chunked.txt").send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "hello");
+
+        // ListObjectsV2: delimiter folding and continuation tokens
+        let body = signed(reqwest::Method::GET, "/photos?delimiter=%2F&list-type=2").send().await.unwrap().text().await.unwrap();
+        assert!(body.contains("<Key>b.txt</Key>"), "{body}");
+        assert!(body.contains("<CommonPrefixes><Prefix>a/</Prefix></CommonPrefixes>"), "{body}");
+        assert!(!body.contains("<Key>a/one.txt</Key>"), "{body}");
+
+        let body = signed(reqwest::Method::GET, "/photos?list-type=2&max-keys=1&prefix=a%2F").send().await.unwrap().text().await.unwrap();
+        assert!(body.contains("<IsTruncated>true</IsTruncated>"), "{body}");
+        assert!(body.contains("<Key>a/one.txt</Key>"), "{body}");
+        let token = body.split("<NextContinuationToken>").nth(1).unwrap().split('<').next().unwrap().to_string();
+        let body = signed(reqwest::Method::GET, &format!("/photos?continuation-token={token}&list-type=2&max-keys=1&prefix=a%2F"))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(body.contains("<Key>a/two.txt</Key>"), "{body}");
+        assert!(body.contains("<IsTruncated>false</IsTruncated>"), "{body}");
+
+        // multipart: create, two parts, complete
+        let body = signed(reqwest::Method::POST, "/photos/big.bin?uploads=").send().await.unwrap().text().await.unwrap();
+        let upload = body.split("<UploadId>").nth(1).unwrap().split('<').next().unwrap().to_string();
+        let mut etags = vec![];
+        for (n, part) in [(1, "first-"), (2, "second")] {
+            let resp = signed(reqwest::Method::PUT, &format!("/photos/big.bin?partNumber={n}&uploadId={upload}")).body(part).send().await.unwrap();
+            assert_eq!(resp.status(), 200);
+            etags.push(resp.headers()[header::ETAG].to_str().unwrap().to_string());
+        }
+        let complete = format!(
+            "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{}</ETag></Part><Part><PartNumber>2</PartNumber><ETag>{}</ETag></Part></CompleteMultipartUpload>",
+            etags[0], etags[1]
+        );
+        let resp = signed(reqwest::Method::POST, &format!("/photos/big.bin?uploadId={upload}")).body(complete).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert!(resp.text().await.unwrap().contains("<CompleteMultipartUploadResult"));
+        let resp = signed(reqwest::Method::GET, "/photos/big.bin").send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "first-second");
+
+        // deletes answer 204, even when the key is already gone
+        for _ in 0..2 {
+            let resp = signed(reqwest::Method::DELETE, "/photos/a/one.txt").send().await.unwrap();
+            assert_eq!(resp.status(), 204);
+        }
+    });
+}
+
+#[test]
+fn reserved_prefixes_are_refused_and_never_listed() {
+    actix_web::rt::System::new().block_on(async {
+        let mut cfg = open_cfg();
+        cfg.trash_enabled = true;
+        cfg.key_rules.blocked_prefixes = vec!["private/".into()];
+        let (base, td) = start_server(cfg);
+        wait_alive(&base).await;
+        let client = Client::new();
+
+        // a sidecar, a trashed object and a file placed on disk under a blocked prefix
+        let put = |key: &str| client.put(format!("{base}/objects/{key}")).header("x-bucket-header-cache-control", "no-store").body("x").send();
+        assert_eq!(put("a.txt").await.unwrap().status(), reqwest::StatusCode::CREATED);
+        assert_eq!(put("gone.txt").await.unwrap().status(), reqwest::StatusCode::CREATED);
+        client.delete(format!("{base}/objects/gone.txt")).send().await.unwrap();
+        std::fs::create_dir_all(td.path().join("private")).unwrap();
+        std::fs::write(td.path().join("private/secret.txt"), "s").unwrap();
+        assert!(td.path().join(".meta").exists() && td.path().join(".trash").exists());
+
+        for key in [".meta/whatever", ".trash/x", "private/b.txt"] {
+            let resp = put(key).await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN, "{key}");
+            let body: serde_json::Value = resp.json().await.unwrap();
+            assert_eq!(body["error"], "blocked_prefix");
+        }
+        assert!(!td.path().join(".meta/whatever").exists());
+        let resp = client.get(format!("{base}/objects/private/secret.txt")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+        let resp = client.head(format!("{base}/objects/.meta/a.txt.json")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+        let resp = client.delete(format!("{base}/objects/private/secret.txt")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+        let resp = client.get(format!("{base}/objects?prefix=private/")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+
+        for query in ["", "?include_dirs=1", "?recursive=1&include_dirs=1", "?prefix=priv&recursive=1"] {
+            let listed: Vec<serde_json::Value> =
+                client.get(format!("{base}/objects{query}")).send().await.unwrap().json().await.unwrap();
+            let keys: Vec<&str> = listed.iter().map(|o| o["key"].as_str().unwrap()).collect();
+            assert!(keys.iter().all(|k| !k.starts_with('.') && !k.starts_with("private")), "{query}: {keys:?}");
+        }
     });
 }
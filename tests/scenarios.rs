@@ -0,0 +1,1264 @@
+// tests/scenarios.rs
+//
+// Behavioural contract of the HTTP API, one table per area. New features
+// add their scenarios here (see tests/common/scenario.rs for the DSL).
+mod common;
+
+use common::scenario::{delete, get, head, hs256, hs256_token, options, patch, post, put, run, Scenario};
+use rust_buck3t::content_types::ContentTypes;
+
+fn users_db() -> String {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    std::env::temp_dir()
+        .join(format!("rust-buck3t-scenario-users-{}-{nanos}.json", std::process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A well-formed write token signed with a secret the server doesn't know.
+fn foreign_token() -> String {
+    let claims = serde_json::json!({ "sub": "x", "scope": "obj:write", "exp": 4102444800u64 });
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(b"not-the-server-secret"),
+    )
+    .unwrap()
+}
+
+#[test]
+fn health() {
+    run(vec![
+        Scenario::new("liveness").step(get("/healthz").expect(200).expect_body("ok")),
+        Scenario::new("deep health")
+            .step(get("/healthz?deep=1").expect(200).expect_json("/status", "ok").expect_json("/storage_read_only", false)),
+        Scenario::new("readiness").step(get("/readyz").expect(200).expect_json("/status", "ready")),
+    ]);
+}
+
+#[test]
+fn object_basics() {
+    run(vec![
+        Scenario::new("put creates, overwrite is 200")
+            .step(
+                put("/objects/t/one.txt")
+                    .body("abc")
+                    .expect(201)
+                    .expect_json("/key", "t/one.txt")
+                    .expect_json("/size", 3)
+                    .expect_json("/created", true),
+            )
+            .step(put("/objects/t/one.txt").body("abcd").expect(200).expect_json("/size", 4).expect_json("/created", false))
+            .step(get("/objects/t/one.txt").expect(200).expect_body("abcd")),
+        Scenario::new("head attachment vs inline")
+            .step(put("/objects/t/one.txt").body("abc").expect(201))
+            .step(
+                head("/objects/t/one.txt")
+                    .expect(200)
+                    .expect_header_prefix("content-disposition", "attachment")
+                    .expect_header("accept-ranges", "bytes")
+                    .expect_header_present("etag"),
+            )
+            .step(head("/objects/t/one.txt?download=0").expect(200).expect_header_prefix("content-disposition", "inline")),
+        Scenario::new("content type from extension")
+            .step(put("/objects/p.json").body("{}").expect(201))
+            .step(get("/objects/p.json").expect(200).expect_header("content-type", "application/json")),
+        Scenario::new("configured content types win over the built-in table")
+            .config(|c| c.content_types = ContentTypes::parse("md=text/markdown; charset=utf-8,json=application/x-custom").unwrap())
+            .step(put("/objects/c/readme.md").body("# hi").expect(201))
+            .step(get("/objects/c/readme.md").expect(200).expect_header("content-type", "text/markdown; charset=utf-8"))
+            .step(head("/objects/c/readme.md").expect(200).expect_header("content-type", "text/markdown; charset=utf-8"))
+            .step(put("/objects/c/p.json").body("{}").expect(201))
+            .step(get("/objects/c/p.json").expect(200).expect_header("content-type", "application/x-custom"))
+            .step(put("/objects/c/clip.webm").body("x").expect(201))
+            .step(get("/objects/c/clip.webm").expect(200).expect_header("content-type", "video/webm"))
+            .step(put("/objects/c/blob").body("x").expect(201))
+            .step(get("/objects/c/blob").expect(200).expect_header("content-type", "application/octet-stream")),
+        Scenario::new("response-* query params override headers")
+            .step(put("/objects/o/report.txt").body("abc").expect(201))
+            .step(
+                get("/objects/o/report.txt?response-content-type=text/csv&response-content-disposition=attachment%3B%20filename%3D%22q3.csv%22&response-cache-control=no-store&download=0")
+                    .expect(200)
+                    .expect_header("content-type", "text/csv")
+                    .expect_header("content-disposition", "attachment; filename=\"q3.csv\"")
+                    .expect_header("cache-control", "no-store")
+                    .expect_body("abc"),
+            )
+            .step(
+                head("/objects/o/report.txt?response-content-type=application/x-thing&response-cache-control=max-age%3D60")
+                    .expect(200)
+                    .expect_header("content-type", "application/x-thing")
+                    .expect_header("cache-control", "max-age=60")
+                    .expect_header_prefix("content-disposition", "attachment"),
+            )
+            .step(get("/objects/o/report.txt").expect(200).expect_header("content-type", "text/plain; charset=utf-8"))
+            .step(
+                get("/objects/o/report.txt?response-content-type=text/csv")
+                    .header("range", "bytes=0-0,2-2")
+                    .expect(206)
+                    .expect_header_prefix("content-type", "multipart/byteranges"),
+            )
+            .step(get("/objects/o/report.txt?response-cache-control=no-store%0D%0AX-Evil:%201").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects/o/report.txt?response-content-type=not%20a%20type").expect(400).expect_error("invalid_query_param"))
+            .step(head("/objects/o/report.txt?response-content-disposition=evil").expect(400))
+            .step(get("/objects/o/report.txt?response-content-disposition=inline%3B%20filename%3D%22%C3%A9%22").expect(400)),
+        Scenario::new("etag round-trip gives 304")
+            .step(put("/objects/t/two.txt").body("abc").expect(201))
+            .step(head("/objects/t/two.txt").capture("etag", "etag"))
+            .step(get("/objects/t/two.txt").header("if-none-match", "${etag}").expect(304)),
+        Scenario::new("delete twice")
+            .step(put("/objects/t/del.txt").body("x").expect(201))
+            .step(delete("/objects/t/del.txt").expect(204))
+            .step(delete("/objects/t/del.txt").expect(404)),
+        Scenario::new("declared checksums are verified")
+            .step(
+                put("/objects/sum/ok.txt")
+                    .header("x-bkt-checksum-sha256", "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD")
+                    .header("x-bkt-checksum-crc32c", "364b3fb7")
+                    .body("abc")
+                    .expect(201)
+                    .expect_header("x-bkt-checksum-sha256", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                    .expect_json("/checksums/sha256", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                    .expect_json("/checksums/crc32c", "364b3fb7"),
+            )
+            .step(
+                head("/objects/sum/ok.txt")
+                    .expect(200)
+                    .expect_header("x-bkt-checksum-sha256", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                    .expect_header("x-bkt-checksum-crc32c", "364b3fb7"),
+            )
+            // one good and one bad digest: refused, the old body stays
+            .step(
+                put("/objects/sum/ok.txt")
+                    .header("x-bkt-checksum-sha256", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                    .header("x-bkt-checksum-crc32c", "00000000")
+                    .body("abc")
+                    .expect(422)
+                    .expect_error("checksum_mismatch")
+                    .expect_json("/algorithm", "crc32c")
+                    .expect_json("/actual", "364b3fb7"),
+            )
+            .step(put("/objects/sum/ok.txt").header("x-bkt-checksum-sha256", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad").body("abcd").expect(422))
+            .step(get("/objects/sum/ok.txt").expect(200).expect_body("abc"))
+            .step(put("/objects/sum/new.txt").header("x-bkt-checksum-crc32c", "00000000").body("abc").expect(422))
+            .step(get("/objects/sum/new.txt").expect(404))
+            .step(
+                put("/objects/sum/bad.txt")
+                    .header("x-bkt-checksum-sha256", "xyz")
+                    .body("abc")
+                    .expect(400)
+                    .expect_error("invalid_checksum")
+                    .expect_json("/header", "x-bkt-checksum-sha256"),
+            )
+            .step(put("/objects/sum/bad.txt").header("x-bkt-checksum-crc32c", "364b3fb7aa").body("abc").expect(400))
+            .step(get("/objects/sum/bad.txt").expect(404)),
+        Scenario::new("server-side copy")
+            .step(put("/objects/cp/src.txt").header("x-bucket-header-cache-control", "max-age=60").body("payload").expect(201))
+            .step(
+                post("/objects/cp/dst.txt?copy_from=cp/src.txt")
+                    .expect(201)
+                    .expect_header_present("x-bkt-copy-mode")
+                    .expect_header("location", "/objects/cp/dst.txt")
+                    .expect_json("/key", "cp/dst.txt")
+                    .expect_json("/size", 7)
+                    .expect_json("/created", true),
+            )
+            .step(get("/objects/cp/dst.txt").expect(200).expect_body("payload").expect_header("cache-control", "max-age=60"))
+            .step(post("/objects/cp/dst.txt?copy_from=cp/src.txt&link=1").expect(200).expect_json("/created", false))
+            .step(put("/objects/cp/src.txt").body("changed").expect(200))
+            .step(get("/objects/cp/dst.txt").expect(200).expect_body("payload"))
+            .step(post("/objects/cp/x.txt?copy_from=cp/missing.txt").expect(404).expect_error("no_such_source"))
+            .step(post("/objects/cp/src.txt?copy_from=cp/src.txt").expect(400).expect_error("same_key"))
+            .step(post("/objects/cp/x.txt?copy_from=cp/src.txt&link=maybe").expect(400).expect_error("invalid_query_param"))
+            .step(post("/objects/cp/x.txt").expect(400).expect_error("unsupported_operation")),
+        Scenario::new("append with PATCH")
+            .step(patch("/objects/log/app.log").header("content-range", "bytes 0-*/*").body("one\n").expect(201).expect_json("/size", 4))
+            .step(
+                patch("/objects/log/app.log")
+                    .header("content-range", "bytes 4-*/*")
+                    .body("two\n")
+                    .expect(200)
+                    .expect_json("/size", 8)
+                    .expect_json("/created", false)
+                    .expect_header_present("etag"),
+            )
+            .step(get("/objects/log/app.log").expect(200).expect_body("one\ntwo\n"))
+            .step(
+                patch("/objects/log/app.log")
+                    .header("content-range", "bytes 4-*/*")
+                    .body("late\n")
+                    .expect(409)
+                    .expect_error("offset_mismatch")
+                    .expect_json("/current_length", 8),
+            )
+            .step(patch("/objects/log/new.log").header("content-range", "bytes 3-*/*").body("x").expect(409).expect_json("/current_length", 0))
+            .step(get("/objects/log/new.log").expect(404))
+            .step(patch("/objects/log/app.log").body("x").expect(400).expect_error("invalid_content_range"))
+            .step(patch("/objects/log/app.log").header("content-range", "bytes 0-3/8").body("x").expect(400))
+            .step(get("/objects/log/app.log").expect(200).expect_body("one\ntwo\n"))
+            .step(
+                patch("/objects/log")
+                    .header("content-range", "bytes 0-*/*")
+                    .body("x")
+                    .expect(409)
+                    .expect_error("is_prefix")
+                    .expect_json("/listing", "/objects?prefix=log/&recursive=1"),
+            ),
+        Scenario::new("append is bounded by MAX_UPLOAD_BYTES")
+            .config(|c| c.max_upload_bytes = Some(10))
+            .step(put("/objects/log/small.log").body("12345678").expect(201))
+            .step(patch("/objects/log/small.log").header("content-range", "bytes 8-*/*").body("abc").expect(413).expect_error("payload_too_large"))
+            .step(patch("/objects/log/small.log").header("content-range", "bytes 8-*/*").body("ab").expect(200).expect_json("/size", 10))
+            .step(get("/objects/log/small.log").expect(200).expect_body("12345678ab")),
+        Scenario::new("append leaves linked copies alone")
+            .step(put("/objects/log/a.log").body("base").expect(201))
+            .step(post("/objects/log/b.log?copy_from=log/a.log&link=1").expect(201))
+            .step(patch("/objects/log/b.log").header("content-range", "bytes 4-*/*").body("+more").expect(200))
+            .step(get("/objects/log/b.log").expect(200).expect_body("base+more"))
+            .step(get("/objects/log/a.log").expect(200).expect_body("base")),
+        Scenario::new("resumable upload")
+            .step(
+                post("/uploads?key=big/file.bin")
+                    .header("upload-length", "10")
+                    .expect(201)
+                    .expect_header("tus-resumable", "1.0.0")
+                    .expect_header("upload-offset", "0")
+                    .expect_header_present("upload-expires")
+                    .expect_header_prefix("location", "/uploads/")
+                    .expect_json("/key", "big/file.bin")
+                    .expect_json("/length", 10)
+                    .capture("upload", "location"),
+            )
+            .step(head("${upload}").expect(200).expect_header("upload-offset", "0").expect_header("upload-length", "10"))
+            .step(patch("${upload}").header("upload-offset", "0").body("01234").expect(204).expect_header("upload-offset", "5"))
+            .step(
+                patch("${upload}")
+                    .header("upload-offset", "0")
+                    .body("xx")
+                    .expect(409)
+                    .expect_error("offset_mismatch")
+                    .expect_json("/current_offset", 5),
+            )
+            .step(head("${upload}").expect(200).expect_header("upload-offset", "5"))
+            .step(get("/objects/big/file.bin").expect(404))
+            .step(patch("${upload}").header("upload-offset", "5").body("56789xx").expect(413).expect_error("upload_length_exceeded"))
+            .step(
+                patch("${upload}")
+                    .header("upload-offset", "5")
+                    .body("56789")
+                    .expect(204)
+                    .expect_header("upload-offset", "10")
+                    .expect_header("location", "/objects/big/file.bin")
+                    .expect_header_present("etag"),
+            )
+            .step(get("/objects/big/file.bin").expect(200).expect_body("0123456789"))
+            .step(head("${upload}").expect(404))
+            .step(patch("${upload}").header("upload-offset", "10").body("x").expect(404).expect_error("no_such_upload"))
+            .step(post("/uploads?key=big/other.bin").expect(400).expect_error("invalid_upload_length"))
+            .step(post("/uploads").header("upload-length", "1").expect(400).expect_error("missing_key"))
+            .step(post("/uploads?key=big/gone.bin").header("upload-length", "3").expect(201).capture("gone", "location"))
+            .step(delete("${gone}").expect(204))
+            .step(head("${gone}").expect(404)),
+        Scenario::new("resumable upload limits")
+            .config(|c| c.max_upload_bytes = Some(8))
+            .step(post("/uploads?key=r/big.bin").header("upload-length", "9").expect(413).expect_error("payload_too_large"))
+            .step(post("/uploads?key=r/empty.bin").header("upload-length", "0").expect(201).expect_json("/completed", true))
+            .step(get("/objects/r/empty.bin").expect(200).expect_body("")),
+        Scenario::new("durable writes")
+            .config(|c| {
+                c.durable_writes = true;
+                c.auth_user_db = users_db();
+            })
+            .step(put("/objects/d/sync.txt").body("abc").expect(201))
+            .step(get("/objects/d/sync.txt").expect(200).expect_body("abc"))
+            .step(post("/auth/signup").json(serde_json::json!({ "username": "durable", "password": "pw" })).expect(201)),
+        Scenario::new("durable on request")
+            .step(put("/objects/d/one.txt").header("x-bkt-durable", "1").body("abc").expect(201))
+            .step(get("/objects/d/one.txt").expect(200).expect_body("abc")),
+    ]);
+}
+
+#[test]
+fn listing() {
+    run(vec![
+        Scenario::new("prefix shallow vs recursive")
+            .step(put("/objects/a/b.txt").body("x").expect(201))
+            .step(put("/objects/a/c/d.txt").body("y").expect(201))
+            .step(get("/objects?prefix=a&recursive=0").expect_json_len("", 1).expect_json("/0/key", "a/b.txt"))
+            .step(
+                get("/objects?prefix=a&recursive=1")
+                    .expect_json_len("", 2)
+                    .expect_json("/0/key", "a/b.txt")
+                    .expect_json("/1/key", "a/c/d.txt")
+                    .expect_json("/1/size", 1),
+            ),
+        Scenario::new("prefix naming a file lists just it")
+            .step(put("/objects/a/b.txt").body("x").expect(201))
+            .step(get("/objects?prefix=a/b.txt").expect_json_len("", 1)),
+        Scenario::new("missing prefix is empty").step(get("/objects?prefix=nope").expect(200).expect_json_len("", 0)),
+        Scenario::new("list formats")
+            .step(put("/objects/f/1.txt").body("x").expect(201))
+            .step(put("/objects/f/c,d \"q\".txt").body("yy").expect(201))
+            .step(put("/objects/f/sub/2.txt").body("zzz").expect(201))
+            .step(
+                get("/objects?prefix=f/&recursive=1&format=keys")
+                    .expect(200)
+                    .expect_header("content-type", "text/plain; charset=utf-8")
+                    .expect_body("f/1.txt\nf/c,d \"q\".txt\nf/sub/2.txt\n"),
+            )
+            .step(
+                get("/objects?prefix=f/&recursive=1&format=ndjson")
+                    .expect(200)
+                    .expect_header("content-type", "application/x-ndjson")
+                    .expect_body_contains("{\"key\":\"f/sub/2.txt\",\"size\":3,\"modified\":"),
+            )
+            .step(
+                get("/objects?prefix=f/&format=csv")
+                    .expect(200)
+                    .expect_header("content-type", "text/csv; charset=utf-8")
+                    .expect_body_contains("key,size,modified\r\nf/1.txt,1,")
+                    .expect_body_contains("\r\n\"f/c,d \"\"q\"\".txt\",2,"),
+            )
+            .step(get("/objects?prefix=f/&format=json").expect(200).expect_json_len("", 2).expect_json("/1/size", 2))
+            .step(get("/objects?prefix=nothing/&format=keys").expect(200).expect_body(""))
+            .step(get("/objects?format=xml").expect(400).expect_error("invalid_format")),
+        Scenario::new("list filters and sorting")
+            .step(put("/objects/s/a.log").body("1").expect(201))
+            .step(put("/objects/s/b.txt").body("333").expect(201))
+            .step(put("/objects/s/c.log").body("22").expect(201))
+            .step(put("/objects/s/deep/d.log").body("4444").expect(201))
+            .step(get("/objects?recursive=1&glob=s/*.log").expect(200).expect_body_contains("s/a.log").expect_json_len("", 2))
+            .step(get("/objects?recursive=1&glob=s/**/*.log").expect(200).expect_json_len("", 3))
+            .step(get("/objects?prefix=s/&min_size=2&max_size=3").expect(200).expect_json_len("", 2).expect_json("/0/key", "s/b.txt"))
+            .step(
+                get("/objects?prefix=s/&recursive=1&sort=size&order=desc")
+                    .expect(200)
+                    .expect_json("/0/key", "s/deep/d.log")
+                    .expect_json("/3/key", "s/a.log"),
+            )
+            .step(get("/objects?prefix=s/&recursive=1&sort=size&format=keys&max_size=3").expect_body("s/a.log\ns/c.log\ns/b.txt\n"))
+            .step(get("/objects?prefix=s/&modified_after=4000000000").expect(200).expect_json_len("", 0))
+            .step(get("/objects?prefix=s/&modified_before=4000000000").expect(200).expect_json_len("", 3))
+            .step(get("/objects?glob=s/[a").expect(400).expect_error("invalid_glob"))
+            .step(get("/objects?min_size=-1").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects?sort=name").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects?order=up").expect(400).expect_error("invalid_query_param")),
+        Scenario::new("streamed listings and the result cap")
+            .config(|c| c.list_max_results = 2)
+            .step(put("/objects/m/a.txt").body("1").expect(201))
+            .step(put("/objects/m/b.txt").body("22").expect(201))
+            .step(put("/objects/m/c.txt").body("333").expect(201))
+            .step(
+                get("/objects?prefix=m/&stream=1")
+                    .expect(200)
+                    .expect_header("content-type", "application/x-ndjson")
+                    .expect_header("x-bkt-ordered", "false")
+                    .expect_body_contains("{\"key\":\"m/c.txt\",\"size\":3,"),
+            )
+            .step(get("/objects?prefix=m/&stream=1&format=keys").expect(200).expect_body("m/a.txt\nm/b.txt\nm/c.txt\n"))
+            .step(
+                get("/objects?prefix=m/")
+                    .expect(200)
+                    .expect_header("x-bkt-ordered", "true")
+                    .expect_header("x-bkt-is-truncated", "true")
+                    .expect_json_len("", 2),
+            )
+            .step(get("/objects?prefix=m/&sort=size").expect(200).expect_header("x-bkt-is-truncated", "true").expect_json_len("", 2))
+            // a sorted, capped listing is the top of the order over every match
+            .step(put("/objects/s/a.txt").body("1").expect(201))
+            .step(put("/objects/s/b.txt").body("22").expect(201))
+            .step(put("/objects/s/c.txt").body("333").expect(201))
+            .step(put("/objects/s/d/e.txt").body("4444").expect(201))
+            .step(put("/objects/s/d/f.txt").body("55555").expect(201))
+            .step(
+                get("/objects?prefix=s/&recursive=1&sort=size&order=desc")
+                    .expect(200)
+                    .expect_header("x-bkt-is-truncated", "true")
+                    .expect_json_len("", 2)
+                    .expect_json("/0/key", "s/d/f.txt")
+                    .expect_json("/1/key", "s/d/e.txt"),
+            )
+            .step(
+                get("/objects?prefix=s/&recursive=1")
+                    .expect(200)
+                    .expect_header("x-bkt-ordered", "true")
+                    .expect_json_len("", 2)
+                    .expect_json("/0/key", "s/a.txt")
+                    .expect_json("/1/key", "s/b.txt"),
+            )
+            .step(
+                get("/objects?prefix=s/&recursive=1&sort=size")
+                    .expect_json_len("", 2)
+                    .expect_json("/0/key", "s/a.txt")
+                    .expect_json("/1/key", "s/b.txt"),
+            )
+            .step(get("/objects?prefix=s/&recursive=1&order=desc").expect_json("/0/key", "s/d/f.txt").expect_json("/1/key", "s/d/e.txt"))
+            .step(get("/objects?prefix=m/&stream=1&format=json").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects?prefix=m/&stream=1&sort=size").expect(400).expect_error("invalid_query_param")),
+        Scenario::new("listing counts over HEAD")
+            .step(put("/objects/c/a.txt").body("1").expect(201))
+            .step(put("/objects/c/d/b.txt").body("22").expect(201))
+            .step(put("/objects/c/d/e/c.log").body("333").expect(201))
+            .step(
+                head("/objects?prefix=c/&recursive=1")
+                    .expect(200)
+                    .expect_header("x-bkt-object-count", "3")
+                    .expect_header("x-bkt-total-bytes", "6")
+                    .expect_body(""),
+            )
+            .step(head("/objects?prefix=c/").expect(200).expect_header("x-bkt-object-count", "1").expect_header("x-bkt-total-bytes", "1"))
+            .step(head("/objects?prefix=c/&depth=1").expect(200).expect_header("x-bkt-object-count", "2"))
+            .step(head("/objects?prefix=c/&recursive=1&glob=c/**/*.txt").expect(200).expect_header("x-bkt-object-count", "2").expect_header("x-bkt-total-bytes", "3"))
+            .step(head("/objects?prefix=c/&recursive=1&min_size=2").expect(200).expect_header("x-bkt-object-count", "2"))
+            .step(head("/objects?prefix=nothing/&recursive=1").expect(200).expect_header("x-bkt-object-count", "0"))
+            .step(head("/objects?prefix=c/&depth=x").expect(400)),
+        Scenario::new("hidden dotfiles")
+            .step(put("/objects/h/.DS_Store").body("ds").expect(201))
+            .step(put("/objects/h/a.txt").body("a").expect(201))
+            .step(put("/objects/h/sub/.hidden").body("x").expect(201))
+            .step(get("/objects?prefix=h/&recursive=1&format=keys").expect(200).expect_body("h/.DS_Store\nh/a.txt\nh/sub/.hidden\n"))
+            .step(get("/objects?prefix=h/&recursive=1&format=keys&hide_dotfiles=1").expect(200).expect_body("h/a.txt\n"))
+            .step(get("/objects/h/.DS_Store").expect(200).expect_body("ds")),
+        Scenario::new("listing exclusions")
+            .config(|c| {
+                c.list_hide_dotfiles = true;
+                c.list_exclude = vec!["**/*.tmp".into(), "**/._*".into()];
+            })
+            .step(put("/objects/h/.DS_Store").body("ds").expect(201))
+            .step(put("/objects/h/._a.txt").body("apple").expect(201))
+            .step(put("/objects/h/a.txt").body("a").expect(201))
+            .step(put("/objects/h/d/x.tmp").body("t").expect(201))
+            .step(get("/objects?prefix=h/&recursive=1&format=keys").expect(200).expect_body("h/a.txt\n"))
+            .step(get("/objects?prefix=h/&recursive=1&format=keys&hide_dotfiles=0").expect(200).expect_body("h/.DS_Store\nh/a.txt\n"))
+            .step(head("/objects?prefix=h/&recursive=1").expect(200).expect_header("x-bkt-object-count", "1"))
+            .step(get("/objects/h/d/x.tmp").expect(200).expect_body("t"))
+            .step(get("/objects/h/._a.txt").expect(200).expect_body("apple")),
+        Scenario::new("listing depth")
+            .step(put("/objects/t/1.txt").body("x").expect(201))
+            .step(put("/objects/t/a/2.txt").body("x").expect(201))
+            .step(put("/objects/t/a/b/3.txt").body("x").expect(201))
+            .step(put("/objects/t/a/b/c/4.txt").body("x").expect(201))
+            .step(get("/objects?prefix=t/&depth=0&format=keys").expect(200).expect_body("t/1.txt\n"))
+            .step(get("/objects?prefix=t/&depth=1&format=keys").expect(200).expect_body("t/1.txt\nt/a/2.txt\n"))
+            .step(get("/objects?prefix=t/&depth=2&format=keys").expect(200).expect_body("t/1.txt\nt/a/2.txt\nt/a/b/3.txt\n"))
+            .step(get("/objects?prefix=t/&depth=2&recursive=1").expect(200).expect_json_len("", 3))
+            .step(get("/objects?prefix=t/&recursive=1").expect(200).expect_json_len("", 4))
+            .step(get("/objects?prefix=t/a&depth=1").expect(200).expect_json_len("", 2).expect_json("/1/key", "t/a/b/3.txt"))
+            .step(get("/objects?depth=-1").expect(400).expect_error("invalid_query_param")),
+        Scenario::new("prefix summary")
+            .step(put("/objects/backups/2024/jan/a.tar").body("aaa").expect(201))
+            .step(put("/objects/backups/2024/jan/b.tar").body("bbbbb").expect(201))
+            .step(put("/objects/backups/2024/feb/c.tar").body("c").expect(201))
+            .step(put("/objects/backups/2024/top.txt").body("tt").expect(201))
+            .step(put("/objects/backups/2023/old.tar").body("0123456789").expect(201))
+            .step(
+                get("/objects-summary?prefix=backups/2024/")
+                    .expect(200)
+                    .expect_json("/prefix", "backups/2024/")
+                    .expect_json("/object_count", 4)
+                    .expect_json("/total_bytes", 11)
+                    .expect_json("/largest_object/key", "backups/2024/jan/b.tar")
+                    .expect_json("/largest_object/size", 5),
+            )
+            .step(
+                get("/objects-summary?prefix=backups/2024/&group_by=first_level")
+                    .expect(200)
+                    .expect_json("/groups/backups~12024~1jan~1/objects", 2)
+                    .expect_json("/groups/backups~12024~1jan~1/bytes", 8)
+                    .expect_json("/groups/backups~12024~1feb~1/objects", 1)
+                    .expect_json("/groups/backups~12024~1/bytes", 2),
+            )
+            .step(get("/objects-summary?prefix=backups/202").expect(200).expect_json("/object_count", 5))
+            .step(get("/objects-summary").expect(200).expect_json("/object_count", 5).expect_json("/total_bytes", 21))
+            .step(get("/objects-summary?prefix=nothing/").expect(200).expect_json("/object_count", 0).expect_json("/largest_object", serde_json::Value::Null))
+            .step(get("/objects-summary?group_by=second_level").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects-summary?prefix=../etc").expect(400)),
+        Scenario::new("key search")
+            .step(put("/objects/docs/2024/Invoice-01.pdf").body("a").expect(201))
+            .step(put("/objects/docs/2024/notes.txt").body("bb").expect(201))
+            .step(put("/objects/docs/invoice-02.pdf").body("ccc").expect(201))
+            .step(put("/objects/mail/invoice-03.eml").body("d").expect(201))
+            .step(put("/objects/invoice.txt").body("e").expect(201))
+            .step(
+                get("/objects-search?q=invoice")
+                    .expect(200)
+                    .expect_json_len("/results", 3)
+                    .expect_json("/results/0/key", "docs/invoice-02.pdf")
+                    .expect_json("/results/0/size", 3)
+                    .expect_json("/results/1/key", "invoice.txt")
+                    .expect_json("/results/2/key", "mail/invoice-03.eml")
+                    .expect_json("/next", serde_json::Value::Null),
+            )
+            .step(get("/objects-search?q=INVOICE&ignore_case=1").expect(200).expect_json_len("/results", 4))
+            .step(
+                get("/objects-search?q=invoice&ignore_case=1&limit=2")
+                    .expect(200)
+                    .expect_json_len("/results", 2)
+                    .expect_json("/results/0/key", "docs/2024/Invoice-01.pdf")
+                    .expect_json("/next", "docs/invoice-02.pdf"),
+            )
+            .step(
+                get("/objects-search?q=invoice&ignore_case=1&limit=2&after=docs/invoice-02.pdf")
+                    .expect(200)
+                    .expect_json_len("/results", 2)
+                    .expect_json("/results/0/key", "invoice.txt")
+                    .expect_json("/results/1/key", "mail/invoice-03.eml")
+                    .expect_json("/next", serde_json::Value::Null),
+            )
+            .step(get("/objects-search?q=**/*.pdf&mode=glob").expect(200).expect_json_len("/results", 2))
+            .step(get("/objects-search?q=*.pdf&mode=glob").expect(200).expect_json_len("/results", 0))
+            .step(get("/objects-search?q=^docs/[0-9]{4}/&mode=regex").expect(200).expect_json_len("/results", 2))
+            .step(get("/objects-search?q=(&mode=regex").expect(400).expect_error("invalid_pattern"))
+            .step(get(&format!("/objects-search?q={}&mode=regex", "a".repeat(300))).expect(400).expect_error("pattern_too_long"))
+            .step(get("/objects-search?q=x&mode=fuzzy").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects-search?q=x&limit=0").expect(400).expect_error("invalid_query_param"))
+            .step(get("/objects-search").expect(400).expect_error("missing_query")),
+        Scenario::new("prefix is a string match over keys")
+            .step(put("/objects/a/b.txt").body("x").expect(201))
+            .step(put("/objects/a/bc/d.txt").body("yy").expect(201))
+            .step(put("/objects/a/c.txt").body("z").expect(201))
+            .step(put("/objects/ab.txt").body("w").expect(201))
+            .step(
+                get("/objects?prefix=a/b&recursive=1")
+                    .expect_json_len("", 2)
+                    .expect_json("/0/key", "a/b.txt")
+                    .expect_json("/1/key", "a/bc/d.txt"),
+            )
+            // shallow: one level past the prefix
+            .step(get("/objects?prefix=a/b").expect_json_len("", 1).expect_json("/0/key", "a/b.txt"))
+            .step(get("/objects?prefix=a/bc").expect_json_len("", 1).expect_json("/0/key", "a/bc/d.txt"))
+            .step(
+                get("/objects?prefix=a/")
+                    .expect_json_len("", 2)
+                    .expect_json("/0/key", "a/b.txt")
+                    .expect_json("/1/key", "a/c.txt"),
+            )
+            // no slash: the directory's files and sibling keys alike
+            .step(
+                get("/objects?prefix=a")
+                    .expect_json_len("", 3)
+                    .expect_json("/0/key", "a/b.txt")
+                    .expect_json("/1/key", "a/c.txt")
+                    .expect_json("/2/key", "ab.txt"),
+            )
+            .step(get("/objects?prefix=a&recursive=1").expect_json_len("", 4))
+            .step(get("/objects?prefix=a/b.txt/").expect(200).expect_json_len("", 0))
+            .step(get("/objects?prefix=").expect_json_len("", 1).expect_json("/0/key", "ab.txt"))
+            .step(get("/objects?prefix=x/y").expect(200).expect_json_len("", 0)),
+    ]);
+}
+
+#[test]
+fn ranges() {
+    let object = |name| Scenario::new(name).step(put("/objects/r.txt").body("abc").expect(201));
+    run(vec![
+        object("open-ended, bounded and suffix ranges")
+            .step(get("/objects/r.txt").header("range", "bytes=1-").expect(206).expect_body("bc"))
+            .step(
+                get("/objects/r.txt")
+                    .header("range", "bytes=0-1")
+                    .expect(206)
+                    .expect_body("ab")
+                    .expect_header("content-range", "bytes 0-1/3"),
+            )
+            .step(get("/objects/r.txt").header("range", "bytes=-1").expect(206).expect_body("c"))
+            .step(get("/objects/r.txt").header("range", "bytes=-10").expect(206).expect_body("abc"))
+            .step(get("/objects/r.txt").header("range", "bytes=-5000").expect(206).expect_header("content-range", "bytes 0-2/3")),
+        object("end past the object is clamped")
+            .step(get("/objects/r.txt").header("range", "bytes=1-5").expect(206).expect_body("bc").expect_header("content-range", "bytes 1-2/3")),
+        object("416 carries the size, ETag and Accept-Ranges")
+            .step(
+                get("/objects/r.txt")
+                    .header("range", "bytes=99-100")
+                    .expect(416)
+                    .expect_header("content-range", "bytes */3")
+                    .expect_header("accept-ranges", "bytes")
+                    .expect_header_present("etag"),
+            )
+            .step(get("/objects/r.txt").header("range", "bytes=-0").expect(416))
+            .step(get("/objects/r.txt").header("range", "bytes=3-,7-9").expect(416)),
+        object("malformed ranges are ignored")
+            .step(get("/objects/r.txt").header("range", "bytes=2-1").expect(200).expect_body("abc"))
+            .step(get("/objects/r.txt").header("range", "items=0-1").expect(200).expect_body("abc"))
+            .step(get("/objects/r.txt").header("range", "bytes=a-b").expect(200).expect_body("abc"))
+            .step(get("/objects/r.txt").header("range", "bytes=0-0,x").expect(200).expect_body("abc"))
+            .step(get("/objects/r.txt").header("range", "bytes=+1-2").expect(200).expect_body("abc")),
+        Scenario::new("an empty object ignores Range")
+            .step(put("/objects/empty.txt").body("").expect(201))
+            .step(get("/objects/empty.txt").header("range", "bytes=0-").expect(200).expect_body(""))
+            .step(get("/objects/empty.txt").header("range", "bytes=-5").expect(200).expect_body(""))
+            .step(head("/objects/empty.txt").header("range", "bytes=0-0").expect(200).expect_header("content-length", "0")),
+        object("multiple ranges become multipart").step(
+            get("/objects/r.txt")
+                .header("range", "bytes=0-0,2-2")
+                .expect(206)
+                .expect_header_prefix("content-type", "multipart/byteranges; boundary="),
+        ),
+        object("HEAD reports what GET would send")
+            .step(head("/objects/r.txt").expect(200).expect_header("content-length", "3"))
+            .step(
+                head("/objects/r.txt")
+                    .header("range", "bytes=1-")
+                    .expect(206)
+                    .expect_header("content-length", "2")
+                    .expect_header("content-range", "bytes 1-2/3")
+                    .expect_header("accept-ranges", "bytes"),
+            )
+            .step(head("/objects/r.txt").header("range", "bytes=-1").expect(206).expect_header("content-range", "bytes 2-2/3"))
+            .step(head("/objects/r.txt").header("range", "bytes=0-0,2-2").expect(206).expect_header_prefix("content-type", "multipart/byteranges"))
+            .step(head("/objects/r.txt").header("range", "bytes=99-100").expect(416).expect_header("content-range", "bytes */3").expect_header_present("etag"))
+            .step(head("/objects/r.txt").header("range", "bytes=x").expect(200).expect_header("content-length", "3")),
+    ]);
+}
+
+#[test]
+fn conditional_requests() {
+    run(vec![
+        Scenario::new("If-None-Match: * only creates")
+            .step(put("/objects/c.txt").header("if-none-match", "*").body("x").expect(201))
+            .step(put("/objects/c.txt").header("if-none-match", "*").body("y").expect(412))
+            .step(get("/objects/c.txt").expect_body("x")),
+        Scenario::new("If-Match matrix")
+            .step(put("/objects/c.txt").header("if-match", "W/\"any\"").body("x").expect(412))
+            .step(put("/objects/c.txt").body("x").expect(201))
+            .step(head("/objects/c.txt").capture("etag", "etag"))
+            .step(put("/objects/c.txt").header("if-match", "W/\"nope\"").body("w").expect(412))
+            .step(put("/objects/c.txt").header("if-match", "${etag}").body("z").expect(200))
+            .step(get("/objects/c.txt").expect_body("z")),
+        Scenario::new("If-Unmodified-Since in the past")
+            .step(put("/objects/c.txt").body("x").expect(201))
+            .step(put("/objects/c.txt").header("if-unmodified-since", "Mon, 01 Jan 2001 00:00:00 GMT").body("y").expect(412))
+            .step(delete("/objects/c.txt").header("if-unmodified-since", "Mon, 01 Jan 2001 00:00:00 GMT").expect(412))
+            .step(put("/objects/c.txt").header("if-unmodified-since", "Fri, 01 Jan 2100 00:00:00 GMT").body("y").expect(200))
+            .step(put("/objects/c.txt").header("if-unmodified-since", "not a date").body("z").expect(200)),
+        Scenario::new("If-None-Match lists and wildcards on GET/HEAD")
+            .step(put("/objects/n.txt").header("x-bucket-header-cache-control", "max-age=60").body("x").expect(201))
+            .step(head("/objects/n.txt").capture("etag", "etag"))
+            .step(
+                get("/objects/n.txt")
+                    .header("if-none-match", "\"other\", W/\"a,b\" , ${etag}")
+                    .expect(304)
+                    .expect_header("etag", "${etag}")
+                    .expect_header("cache-control", "max-age=60"),
+            )
+            .step(get("/objects/n.txt").header("if-none-match", "*").expect(304))
+            .step(head("/objects/n.txt").header("if-none-match", "${etag}").expect(304))
+            .step(get("/objects/n.txt").header("if-none-match", "\"other\", W/\"more\"").expect(200).expect_body("x"))
+            .step(get("/objects/missing.txt").header("if-none-match", "*").expect(404)),
+        Scenario::new("PUT preconditions take lists")
+            .step(put("/objects/l.txt").body("x").expect(201))
+            .step(head("/objects/l.txt").capture("etag", "etag"))
+            .step(put("/objects/l.txt").header("if-none-match", "W/\"nope\", ${etag}").body("y").expect(412))
+            .step(put("/objects/l.txt").header("if-match", "W/\"nope\", ${etag}").body("y").expect(200))
+            .step(put("/objects/l.txt").header("if-match", "*").body("z").expect(200))
+            .step(put("/objects/l2.txt").header("if-match", "*").body("z").expect(412)),
+    ]);
+}
+
+#[test]
+fn status_400() {
+    run(vec![
+        Scenario::new("prefix escaping the root").step(get("/objects?prefix=../etc").expect(400)),
+        Scenario::new("invalid expiry headers")
+            .step(put("/objects/e.txt").header("x-bkt-expires-in", "soon").body("x").expect(400).expect_error("invalid_expiry"))
+            .step(put("/objects/e.txt").header("x-bkt-expires-in", "0").body("x").expect(400).expect_json("/header", "x-bkt-expires-in"))
+            .step(put("/objects/e.txt").header("x-bkt-expires-at", "1").body("x").expect(400).expect_error("invalid_expiry"))
+            .step(
+                put("/objects/e.txt")
+                    .header("x-bkt-expires-in", "60")
+                    .header("x-bkt-expires-at", "99999999999")
+                    .body("x")
+                    .expect(400)
+                    .expect_error("invalid_expiry"),
+            )
+            .step(head("/objects/e.txt").expect(404)),
+        Scenario::new("duplicate query param")
+            .step(get("/objects?prefix=a&prefix=b").expect(400).expect_error("duplicate_query_param").expect_json("/param", "prefix")),
+        Scenario::new("bad boolean").step(get("/objects?recursive=maybe").expect(400).expect_error("invalid_query_param")),
+        Scenario::new("strict query")
+            .config(|c| c.strict_query = true)
+            .step(get("/objects?bogus=1").expect(400).expect_error("unknown_query_param")),
+        Scenario::new("custom header off the allow-list")
+            .step(put("/objects/h.txt").header("x-bucket-header-set-cookie", "a=b").body("x").expect(400).expect_error("header_not_allowed")),
+        Scenario::new("POST without an operation").step(post("/objects/x.txt").expect(400).expect_error("unsupported_operation")),
+        Scenario::new("auth body field too long")
+            .step(post("/auth/signup").json(serde_json::json!({ "username": "u".repeat(65), "password": "p" })).expect(400).expect_error("field_too_long")),
+        Scenario::new("auth body not JSON")
+            .step(post("/auth/signup").header("content-type", "application/json").body("{nope").expect(400).expect_error("invalid_json")),
+        Scenario::new("key rules")
+            .step(put("/objects/a%0Ab.txt").body("x").expect(400).expect_error("invalid_key").expect_json("/rule", "control_chars"))
+            .step(get("/objects/a%0Ab.txt").expect(400).expect_json("/rule", "control_chars"))
+            .step(head("/objects/a%09b.txt").expect(400))
+            .step(delete("/objects/a%1Bb.txt").expect(400).expect_json("/rule", "control_chars"))
+            .step(get("/objects?prefix=a%0A").expect(400).expect_json("/rule", "control_chars"))
+            .step(put(&format!("/objects/d/{}", "x".repeat(256))).body("x").expect(400).expect_json("/rule", "segment_max_bytes"))
+            .step(put(&format!("/objects/{}", "d/".repeat(600))).body("x").expect(400).expect_json("/rule", "max_bytes"))
+            // a reserved device name only matters where the filesystem reserves it
+            .step(put("/objects/CON").body("x").expect(if cfg!(windows) { 400 } else { 201 })),
+        Scenario::new("configured key rules")
+            .config(|c| {
+                c.key_rules.max_bytes = 8;
+                c.key_rules.forbidden_chars = vec!['#'];
+                c.key_rules.portable_names = true;
+            })
+            .step(put("/objects/a%23b").body("x").expect(400).expect_json("/rule", "forbidden_chars"))
+            .step(put("/objects/d/nul").body("x").expect(400).expect_json("/rule", "portable_names"))
+            .step(put("/objects/123456789").body("x").expect(400).expect_json("/rule", "max_bytes"))
+            .step(get("/objects?prefix=a%3Fb").expect(400).expect_json("/rule", "portable_names"))
+            .step(put("/objects/ok.txt").body("x").expect(201)),
+    ]);
+}
+
+#[cfg(unix)]
+#[test]
+fn backslash_keys_round_trip() {
+    run(vec![Scenario::new("a backslash is part of the name, not a separator")
+        .step(put("/objects/s/a%5Cb.txt").body("x").expect(201))
+        .step(get("/objects?prefix=s/&format=keys").expect(200).expect_body("s/a\\b.txt\n"))
+        .step(get("/objects?prefix=s/").expect(200).expect_json("/0/key", "s/a\\b.txt"))
+        .step(get("/objects/s/a%5Cb.txt").expect(200).expect_body("x"))
+        .step(get("/objects/s/a/b.txt").expect(404))]);
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_keys_round_trip() {
+    run(vec![Scenario::new("names Windows can't store are refused with the rule")
+        .step(put("/objects/CON").body("x").expect(400).expect_error("invalid_key").expect_json("/rule", "portable_names"))
+        .step(put("/objects/d/aux.txt").body("x").expect(400).expect_json("/rule", "portable_names"))
+        .step(put("/objects/a%3Ab.txt").body("x").expect(400).expect_json("/rule", "portable_names"))
+        .step(put("/objects/a%2Ab.txt").body("x").expect(400).expect_json("/rule", "portable_names"))
+        .step(put("/objects/a%7Cb.txt").body("x").expect(400).expect_json("/rule", "portable_names"))
+        // mixed separators: a `\` would split the key differently on disk
+        .step(put("/objects/s%5Ca/b.txt").body("x").expect(400).expect_json("/rule", "portable_names"))
+        .step(get("/objects?prefix=s%5C").expect(400).expect_json("/rule", "portable_names"))
+        .step(put("/objects/s/a/b.txt").body("x").expect(201))
+        .step(get("/objects?prefix=s/&recursive=1&format=keys").expect(200).expect_body("s/a/b.txt\n"))
+        .step(get("/objects/s/a/b.txt").expect(200).expect_body("x"))]);
+}
+
+#[test]
+fn status_401_403() {
+    run(vec![
+        Scenario::new("missing token")
+            .config(hs256)
+            .step(put("/objects/a.txt").body("x").expect(401))
+            .step(get("/objects/a.txt").expect(401))
+            .step(get("/objects").expect(401))
+            .step(head("/objects").expect(401))
+            .step(get("/events").expect(401)),
+        Scenario::new("garbage token")
+            .config(hs256)
+            .step(put("/objects/a.txt").header("authorization", "Bearer not.a.jwt").body("x").expect(401)),
+        Scenario::new("token signed with another secret")
+            .config(hs256)
+            .step(
+                put("/objects/a.txt")
+                    .header("authorization", format!("Bearer {}", foreign_token()))
+                    .body("x")
+                    .expect(401),
+            ),
+        Scenario::new("wrong scope")
+            .config(hs256)
+            .step(put("/objects/a.txt").auth("obj:read").body("x").expect(403))
+            .step(put("/objects/a.txt").auth("obj:write").body("x").expect(201))
+            .step(get("/objects/a.txt").auth("obj:write").expect(403))
+            .step(get("/objects/a.txt").auth("obj:read").expect(200).expect_body("x"))
+            .step(get("/objects").auth("obj:read").expect(403))
+            .step(get("/objects").auth("obj:list").expect(200).expect_json_len("", 1))
+            .step(head("/objects").auth("obj:read").expect(403))
+            .step(head("/objects").auth("obj:list").expect(200).expect_header("x-bkt-object-count", "1"))
+            .step(get("/events").auth("obj:read").expect(403))
+            .step(delete("/objects/a.txt").auth("obj:list").expect(403))
+            // a copy reads its source, so write alone isn't enough
+            .step(post("/objects/b.txt?copy_from=a.txt").auth("obj:write").expect(403))
+            .step(get("/objects/b.txt").auth("obj:read").expect(404))
+            .step(post("/objects/b.txt?copy_from=a.txt").auth("obj:write obj:read").expect(201))
+            .step(get("/objects/b.txt").auth("obj:read").expect(200).expect_body("x")),
+        Scenario::new("OPTIONS reports capabilities without a token")
+            .config(hs256)
+            .config(|c| c.max_upload_bytes = Some(1000))
+            .step(
+                options("/objects/a/b.txt")
+                    .expect(204)
+                    .expect_header("allow", "GET, HEAD, PUT, POST, PATCH, DELETE, OPTIONS")
+                    .expect_header("accept-ranges", "bytes")
+                    .expect_header("x-bkt-max-upload-bytes", "1000")
+                    .expect_body(""),
+            )
+            .step(
+                options("/objects")
+                    .expect(204)
+                    .expect_header("allow", "GET, HEAD, POST, OPTIONS")
+                    .expect_header_absent("accept-ranges"),
+            )
+            .step(options("/b/photos/objects/x.jpg").expect(204).expect_header("allow", "GET, HEAD, PUT, POST, PATCH, DELETE, OPTIONS"))
+            .step(options("/objects/a/b.txt/hold").expect(204))
+            .step(get("/objects/a/b.txt").expect(401)),
+        Scenario::new("admin needs the admin scope")
+            .config(hs256)
+            .step(get("/admin/uploads").expect(401))
+            .step(get("/admin/uploads").auth("obj:read").expect(403))
+            .step(get("/admin/uploads").auth("obj:write").expect(403))
+            .step(get("/admin/uploads").auth("bkt:admin").expect(200)),
+        Scenario::new("signing up doesn't buy an admin scope")
+            .config(hs256)
+            .config(|c| c.auth_user_db = users_db())
+            .step(post("/auth/signup").json(serde_json::json!({ "username": "eve", "password": "pw" })).expect(201))
+            .step(
+                post("/auth/login")
+                    .json(serde_json::json!({ "username": "eve", "password": "pw", "scope": "bkt:admin" }))
+                    .expect(403)
+                    .expect_error("scope_not_granted"),
+            )
+            .step(post("/auth/login").json(serde_json::json!({ "username": "eve", "password": "pw", "scope": "* obj:*" })).expect(403))
+            .step(post("/auth/login").json(serde_json::json!({ "username": "eve", "password": "pw", "scope": "obj:read" })).expect(200)),
+        Scenario::new("an admin scope covers every class")
+            .config(hs256)
+            .config(|c| c.jwt_scopes_write = vec!["obj:upload".into()])
+            .step(put("/objects/a.txt").auth("obj:write").body("x").expect(403))
+            .step(put("/objects/a.txt").auth("bkt:admin").body("x").expect(201))
+            .step(get("/objects/a.txt").auth("bkt:admin").expect(200).expect_body("x"))
+            .step(get("/objects").auth("bkt:admin").expect(200).expect_json_len("", 1))
+            .step(delete("/objects/a.txt").auth("bkt:admin").expect(204)),
+        Scenario::new("query tokens open object reads only")
+            .config(hs256)
+            .config(|c| c.allow_query_token = true)
+            .step(put("/objects/a.txt").auth("obj:write").body("x").expect(201))
+            .step(get(&format!("/objects/a.txt?access_token={}", hs256_token("obj:read"))).expect(200).expect_body("x"))
+            .step(head(&format!("/objects/a.txt?download=0&access_token={}", hs256_token("obj:read"))).expect(200))
+            .step(get("/objects/a.txt?access_token=not.a.jwt").expect(401))
+            .step(get(&format!("/objects/a.txt?access_token={}", foreign_token())).expect(401))
+            // the header wins
+            .step(get(&format!("/objects/a.txt?access_token={}", hs256_token("obj:read"))).auth("obj:write").expect(403))
+            .step(put(&format!("/objects/b.txt?access_token={}", hs256_token("obj:write"))).body("x").expect(401))
+            .step(delete(&format!("/objects/a.txt?access_token={}", hs256_token("obj:write"))).expect(401))
+            .step(get(&format!("/objects?access_token={}", hs256_token("obj:list"))).expect(401)),
+        Scenario::new("query tokens are ignored unless enabled")
+            .config(hs256)
+            .step(put("/objects/a.txt").auth("obj:write").body("x").expect(201))
+            .step(get(&format!("/objects/a.txt?access_token={}", hs256_token("obj:read"))).expect(401)),
+        Scenario::new("public read prefixes open reads and listings inside them")
+            .config(hs256)
+            .config(|c| c.public_read_prefixes = vec!["public/".into()])
+            .step(put("/objects/public/a.txt").auth("obj:write").body("x").expect(201))
+            .step(put("/objects/secret.txt").auth("obj:write").body("s").expect(201))
+            .step(get("/objects/public/a.txt").expect(200).expect_body("x"))
+            .step(head("/objects/public/a.txt").expect(200))
+            .step(get("/objects/secret.txt").expect(401))
+            .step(get("/objects?prefix=public/").expect(200).expect_json_len("", 1))
+            .step(get("/objects?prefix=public/a").expect(200))
+            // listing across the boundary still needs a token
+            .step(get("/objects?prefix=pub").expect(401))
+            .step(get("/objects").expect(401))
+            .step(put("/objects/public/b.txt").body("x").expect(401))
+            .step(delete("/objects/public/a.txt").expect(401)),
+        Scenario::new("sidecar tree is not a key")
+            .step(put("/objects/.meta/x.json").body("x").expect(403).expect_error("blocked_prefix").expect_json("/prefix", ".meta/")),
+        Scenario::new("internal dirs are not keys")
+            .step(put("/objects/.trash/x").body("x").expect(403))
+            .step(get("/objects/.tmp/x.part").expect(403))
+            .step(get("/objects?prefix=.uploads/").expect(403)),
+        Scenario::new("unprotected reads stay open")
+            .config(hs256)
+            .config(|c| {
+                c.auth_read = false;
+                c.auth_list = false;
+            })
+            .step(get("/objects/missing.txt").expect(404))
+            .step(get("/objects").expect(200)),
+    ]);
+}
+
+#[test]
+fn status_404_409() {
+    run(vec![
+        Scenario::new("missing object")
+            .step(get("/objects/nope.txt").expect(404))
+            .step(head("/objects/nope.txt").expect(404))
+            .step(delete("/objects/nope.txt").expect(404)),
+        Scenario::new("directory key is a prefix, not an object")
+            .step(put("/objects/dir/f.txt").body("x").expect(201))
+            .step(get("/objects/dir").expect(404).expect_error("is_prefix").expect_json("/listing", "/objects?prefix=dir/"))
+            .step(head("/objects/dir").expect(404))
+            .step(get("/objects/dir?list=1").expect(200).expect_json_len("", 1).expect_json("/0/key", "dir/f.txt"))
+            .step(delete("/objects/dir").expect(409).expect_error("is_prefix"))
+            .step(get("/objects/dir/f.txt").expect(200).expect_body("x")),
+        Scenario::new("unknown upload id").step(delete("/admin/uploads/999").expect(404)),
+        Scenario::new("undelete with nothing pending")
+            .step(post("/objects/nope.txt?undelete").expect(404).expect_error("not_pending")),
+        Scenario::new("duplicate signup")
+            .config(|c| c.auth_user_db = users_db())
+            .step(post("/auth/signup").json(serde_json::json!({ "username": "dup", "password": "pw" })).expect(201))
+            .step(post("/auth/signup").json(serde_json::json!({ "username": "dup", "password": "pw2" })).expect(409)),
+    ]);
+}
+
+#[test]
+fn status_413_415_507() {
+    run(vec![
+        Scenario::new("upload over MAX_UPLOAD_BYTES leaves nothing behind")
+            .config(|c| c.max_upload_bytes = Some(1))
+            .step(put("/objects/t/ok.bin").body("x").expect(201))
+            .step(put("/objects/t/too_big.bin").body("ab").expect(413))
+            .step(get("/objects/t/too_big.bin").expect(404)),
+        Scenario::new("auth body over the JSON cap")
+            .config(|c| c.auth_json_limit_bytes = 64)
+            .step(
+                post("/auth/login")
+                    .json(serde_json::json!({ "username": "u", "password": "p".repeat(100) }))
+                    .expect(413)
+                    .expect_error("payload_too_large")
+                    .expect_json("/limit", 64),
+            ),
+        Scenario::new("auth body not JSON content type")
+            .step(post("/auth/login").header("content-type", "text/plain").body("{}").expect(415)),
+        Scenario::new("object count cap")
+            .config(|c| c.max_object_count = Some(1))
+            .step(put("/objects/a.txt").body("x").expect(201))
+            .step(put("/objects/b.txt").body("x").expect(507).expect_error("object_count_exceeded"))
+            .step(put("/objects/a.txt").body("y").expect(200)),
+    ]);
+}
+
+#[test]
+fn custom_headers_and_deferred_delete() {
+    run(vec![
+        Scenario::new("per-object header replayed")
+            .step(put("/objects/p.html").header("x-bucket-header-x-robots-tag", "noindex").body("<p>").expect(201))
+            .step(get("/objects/p.html").expect_header("x-robots-tag", "noindex"))
+            .step(put("/objects/p.html").body("<p>").expect(200))
+            .step(get("/objects/p.html").expect_header_absent("x-robots-tag")),
+        Scenario::new("metadata-only update")
+            .step(put("/objects/m/report.txt").header("x-bucket-header-x-robots-tag", "noindex").body("abc").expect(201))
+            .step(head("/objects/m/report.txt").capture("etag", "etag"))
+            .step(
+                post("/objects/m/report.txt?metadata=1")
+                    .header("if-match", "${etag}")
+                    .header("content-type", "text/csv")
+                    .header("cache-control", "max-age=60")
+                    .header("x-bkt-meta-Owner", "ops")
+                    .expect(200)
+                    .expect_json("/key", "m/report.txt")
+                    .expect_json("/size", 3)
+                    .expect_json("/content_type", "text/csv")
+                    .expect_json("/headers/Cache-Control", "max-age=60")
+                    .expect_json("/headers/X-Robots-Tag", "noindex")
+                    .expect_json("/meta/owner", "ops"),
+            )
+            // the edit moved the ETag on, so a second writer holding the old one loses
+            .step(post("/objects/m/report.txt?metadata=1").header("if-match", "${etag}").header("x-bkt-meta-owner", "dev").expect(412))
+            .step(
+                get("/objects/m/report.txt")
+                    .expect(200)
+                    .expect_body("abc")
+                    .expect_header("content-type", "text/csv")
+                    .expect_header("cache-control", "max-age=60")
+                    .expect_header("x-bkt-meta-owner", "ops")
+                    .expect_header("x-robots-tag", "noindex"),
+            )
+            .step(
+                post("/objects/m/report.txt?metadata=1&touch=1")
+                    .header("cache-control", "")
+                    .header("x-bkt-meta-owner", "")
+                    .expect(200)
+                    .expect_json("/content_type", "text/csv"),
+            )
+            .step(head("/objects/m/report.txt").expect(200).expect_header_absent("cache-control").expect_header_absent("x-bkt-meta-owner"))
+            .step(post("/objects/m/report.txt?metadata=1").header("content-type", "not a type").expect(400).expect_error("invalid_content_type"))
+            .step(post("/objects/m/missing.txt?metadata=1").header("x-bkt-meta-owner", "ops").expect(404))
+            // a rewrite starts from fresh metadata
+            .step(put("/objects/m/report.txt").body("abcd").expect(200))
+            .step(get("/objects/m/report.txt").expect(200).expect_header("content-type", "text/plain; charset=utf-8")),
+        Scenario::new("legal hold")
+            .step(put("/objects/audit/ledger.csv").body("a,b").expect(201))
+            .step(put("/objects/audit/notes.txt").body("n").expect(201))
+            .step(put("/objects/audit/ledger.csv?hold").expect(200).expect_json("/legal_hold", true))
+            .step(head("/objects/audit/ledger.csv").expect(200).expect_header("x-bkt-legal-hold", "true"))
+            .step(delete("/objects/audit/ledger.csv").expect(423).expect_error("legal_hold"))
+            .step(put("/objects/audit/ledger.csv").body("x").expect(423).expect_error("legal_hold"))
+            .step(post("/objects/audit/ledger.csv?metadata=1").header("x-bkt-meta-case", "42").expect(200))
+            .step(get("/objects?prefix=audit/&held=1").expect_json_len("", 1).expect_json("/0/key", "audit/ledger.csv").expect_json("/0/legal_hold", true))
+            .step(get("/objects?prefix=audit/&held=0").expect_json_len("", 1).expect_json("/0/key", "audit/notes.txt"))
+            .step(put("/objects/audit/missing.txt?hold").expect(404))
+            .step(delete("/objects/audit/ledger.csv?hold").expect(200).expect_json("/legal_hold", false))
+            .step(head("/objects/audit/ledger.csv").expect(200).expect_header_absent("x-bkt-legal-hold"))
+            .step(delete("/objects/audit/ledger.csv").expect(204))
+            // only PUT and DELETE are taken for holds
+            .step(get("/objects/audit/notes.txt?hold").expect(200).expect_header_absent("x-bkt-legal-hold")),
+        Scenario::new("a key named hold is an ordinary key")
+            .config(hs256)
+            .step(put("/objects/docs/hold").auth("obj:write").body("h").expect(201))
+            .step(get("/objects/docs/hold").auth("obj:read").expect(200).expect_body("h"))
+            .step(put("/objects/docs/hold?hold").auth("obj:write").expect(403))
+            .step(put("/objects/docs/hold?hold").auth("bkt:admin").expect(200).expect_json("/key", "docs/hold"))
+            .step(delete("/objects/docs/hold?hold").auth("bkt:admin").expect(200))
+            .step(delete("/objects/docs/hold").auth("obj:write").expect(204)),
+        Scenario::new("deferred delete and undo")
+            .config(|c| c.delete_grace_secs = 60)
+            .step(put("/objects/d.txt").body("x").expect(201))
+            .step(delete("/objects/d.txt").expect(202).expect_json("/key", "d.txt"))
+            .step(get("/objects/d.txt").expect(404))
+            .step(get("/objects").expect_json_len("", 0))
+            .step(post("/objects/d.txt?undelete").expect(200))
+            .step(get("/objects/d.txt").expect(200).expect_body("x")),
+        Scenario::new("only a landed overwrite cancels a pending delete")
+            .config(|c| {
+                c.delete_grace_secs = 60;
+                c.max_upload_bytes = Some(4);
+            })
+            .step(put("/objects/d.txt").body("x").expect(201))
+            .step(delete("/objects/d.txt").expect(202))
+            .step(put("/objects/d.txt").header("if-match", "*").body("y").expect(412))
+            .step(put("/objects/d.txt").body("too big").expect(413))
+            .step(get("/objects/d.txt").expect(404))
+            .step(post("/objects/d.txt?undelete").expect(200))
+            .step(get("/objects/d.txt").expect(200).expect_body("x"))
+            .step(delete("/objects/d.txt").expect(202))
+            .step(put("/objects/d.txt").body("y").expect(201))
+            .step(post("/objects/d.txt?undelete").expect(404).expect_error("not_pending"))
+            .step(get("/objects/d.txt").expect(200).expect_body("y")),
+    ]);
+}
+
+#[test]
+fn api_keys() {
+    let keyed = |c: &mut rust_buck3t::consts::Config| {
+        c.auth_mode = rust_buck3t::consts::AuthMode::ApiKey;
+        c.auth_read = true;
+        c.api_keys = vec![
+            rust_buck3t::consts::ApiKey { name: "ci".into(), key: "k-ci-123".into(), scopes: vec!["obj:write".into(), "obj:read".into()] },
+            rust_buck3t::consts::ApiKey { name: "viewer".into(), key: "k-view-456".into(), scopes: vec!["obj:read".into()] },
+        ];
+    };
+    run(vec![
+        Scenario::new("x-api-key and bearer both accepted")
+            .config(keyed)
+            .step(put("/objects/k.txt").header("x-api-key", "k-ci-123").body("x").expect(201))
+            .step(get("/objects/k.txt").header("authorization", "Bearer k-view-456").expect(200).expect_body("x")),
+        Scenario::new("per-key scopes")
+            .config(keyed)
+            .step(put("/objects/k.txt").header("x-api-key", "k-view-456").body("x").expect(403))
+            .step(delete("/objects/k.txt").header("x-api-key", "k-view-456").expect(403)),
+        Scenario::new("unknown or missing key")
+            .config(keyed)
+            .step(put("/objects/k.txt").header("x-api-key", "k-ci-12").body("x").expect(401))
+            .step(get("/objects/k.txt").header("authorization", "Bearer nope").expect(401))
+            .step(get("/objects/k.txt").expect(401)),
+    ]);
+}
+
+#[test]
+fn namespace_by_sub() {
+    let jailed = |c: &mut rust_buck3t::consts::Config| {
+        let key = |name: &str, scopes: &[&str]| rust_buck3t::consts::ApiKey {
+            name: name.into(),
+            key: format!("k-{name}"),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        };
+        c.auth_mode = rust_buck3t::consts::AuthMode::ApiKey;
+        c.namespace_by_sub = true;
+        c.api_keys = vec![
+            key("alice", &["obj:write", "obj:read", "obj:list"]),
+            key("bob", &["obj:write", "obj:read", "obj:list"]),
+            key("root", &["obj:*", "bkt:admin"]),
+            key("..", &["obj:write", "obj:read"]),
+        ];
+    };
+    run(vec![
+        Scenario::new("two users, one key, two objects")
+            .config(jailed)
+            .step(put("/objects/photo.jpg").header("x-api-key", "k-alice").body("alice's").expect(201))
+            .step(put("/objects/photo.jpg").header("x-api-key", "k-bob").body("bob's").expect(201))
+            .step(get("/objects/photo.jpg").header("x-api-key", "k-alice").expect(200).expect_body("alice's"))
+            .step(get("/objects/photo.jpg").header("x-api-key", "k-bob").expect(200).expect_body("bob's"))
+            .step(get("/objects").header("x-api-key", "k-alice").expect_json_len("", 1).expect_json("/0/key", "photo.jpg"))
+            .step(delete("/objects/photo.jpg").header("x-api-key", "k-bob").expect(204))
+            .step(get("/objects/photo.jpg").header("x-api-key", "k-bob").expect(404))
+            .step(get("/objects/photo.jpg").header("x-api-key", "k-alice").expect(200).expect_body("alice's")),
+        Scenario::new("admin scopes see real keys")
+            .config(jailed)
+            .step(put("/objects/photo.jpg").header("x-api-key", "k-alice").body("alice's").expect(201))
+            .step(get("/objects/users/alice/photo.jpg").header("x-api-key", "k-root").expect(200).expect_body("alice's"))
+            .step(get("/objects/photo.jpg").header("x-api-key", "k-root").expect(404))
+            .step(get("/objects/users/alice/photo.jpg").header("x-api-key", "k-bob").expect(404)),
+        Scenario::new("cross-key routes are closed and a hostile sub can't escape")
+            .config(jailed)
+            .step(get("/objects-search?q=photo").header("x-api-key", "k-alice").expect(403).expect_error("namespaced_caller"))
+            .step(get("/stats").header("x-api-key", "k-alice").expect(403).expect_error("namespaced_caller"))
+            .step(put("/objects/x.txt").header("x-api-key", "k-..").body("x").expect(403).expect_error("invalid_subject")),
+    ]);
+}
+
+/// A `TEST_SECRET` token for `scopes` whose `tenant` claim names `tenant`.
+fn tenant_token(scopes: &str, tenant: &str) -> String {
+    let claims = serde_json::json!({ "sub": "scenario", "scope": scopes, "tenant": tenant, "exp": 4102444800u64 });
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(common::scenario::TEST_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+#[test]
+fn tenants() {
+    let by_header = |c: &mut rust_buck3t::consts::Config| {
+        c.tenant_header = Some("x-bkt-tenant".into());
+        c.tenant_auto_create = true;
+    };
+    let by_claim = |c: &mut rust_buck3t::consts::Config| {
+        hs256(c);
+        c.tenant_header = Some("x-bkt-tenant".into());
+        c.tenant_claim = Some("tenant".into());
+        c.tenant_auto_create = true;
+    };
+    let bearer = |tenant: &str| format!("Bearer {}", tenant_token("obj:write obj:read obj:list", tenant));
+    run(vec![
+        Scenario::new("one key in two tenants")
+            .config(by_header)
+            .step(put("/objects/logo.txt").header("x-bkt-tenant", "acme").body("acme's").expect(201))
+            .step(put("/objects/logo.txt").header("x-bkt-tenant", "globex").body("globex's").expect(201))
+            .step(get("/objects/logo.txt").header("x-bkt-tenant", "acme").expect(200).expect_body("acme's"))
+            .step(get("/objects/logo.txt").header("x-bkt-tenant", "globex").expect(200).expect_body("globex's"))
+            .step(get("/objects").header("x-bkt-tenant", "acme").expect_json_len("", 1).expect_json("/0/key", "logo.txt"))
+            .step(delete("/objects/logo.txt").header("x-bkt-tenant", "globex").expect(204))
+            .step(get("/objects/logo.txt").header("x-bkt-tenant", "acme").expect(200).expect_body("acme's")),
+        Scenario::new("unnamed and badly named tenants")
+            .config(by_header)
+            .step(get("/objects/logo.txt").expect(400).expect_error("tenant_required"))
+            .step(put("/objects/logo.txt").header("x-bkt-tenant", "..").body("x").expect(400).expect_error("invalid_tenant"))
+            .step(put("/objects/logo.txt").header("x-bkt-tenant", "a.b").body("x").expect(400).expect_error("invalid_tenant")),
+        Scenario::new("tenants must exist without auto-create")
+            .config(|c| c.tenant_header = Some("x-bkt-tenant".into()))
+            .step(put("/objects/logo.txt").header("x-bkt-tenant", "initech").body("x").expect(404).expect_error("unknown_tenant"))
+            .step(get("/objects").header("x-bkt-tenant", "initech").expect(404).expect_error("unknown_tenant")),
+        Scenario::new("a tenant exists per bucket")
+            .config(|c| {
+                let key = |name: &str, scopes: &[&str]| rust_buck3t::consts::ApiKey {
+                    name: name.into(),
+                    key: format!("k-{name}"),
+                    scopes: scopes.iter().map(|s| s.to_string()).collect(),
+                };
+                c.auth_mode = rust_buck3t::consts::AuthMode::ApiKey;
+                c.auth_read = true;
+                c.api_keys = vec![key("app", &["obj:write", "obj:read"]), key("root", &["obj:*", "bkt:admin"])];
+                c.buckets = vec!["photos".into()];
+                c.tenant_header = Some("x-bkt-tenant".into());
+            })
+            .step(put("/objects/tenants/acme/a.txt").header("x-api-key", "k-root").body("root acme").expect(201))
+            .step(put("/b/photos/objects/tenants/globex/b.txt").header("x-api-key", "k-root").body("photos globex").expect(201))
+            .step(get("/objects/a.txt").header("x-api-key", "k-app").header("x-bkt-tenant", "acme").expect(200).expect_body("root acme"))
+            .step(get("/b/photos/objects/a.txt").header("x-api-key", "k-app").header("x-bkt-tenant", "acme").expect(404).expect_error("unknown_tenant"))
+            .step(get("/b/photos/objects/b.txt").header("x-api-key", "k-app").header("x-bkt-tenant", "globex").expect(200).expect_body("photos globex"))
+            .step(get("/objects/b.txt").header("x-api-key", "k-app").header("x-bkt-tenant", "globex").expect(404).expect_error("unknown_tenant"))
+            .step(get("/b/nope/objects/b.txt").header("x-api-key", "k-app").header("x-bkt-tenant", "globex").expect(404).expect_error("no_such_bucket")),
+        Scenario::new("a default tenant for requests that name none")
+            .config(|c| {
+                c.tenant_header = Some("x-bkt-tenant".into());
+                c.tenant_default = Some("shared".into());
+                c.tenant_auto_create = true;
+            })
+            .step(put("/objects/logo.txt").body("shared").expect(201))
+            .step(get("/objects/logo.txt").header("x-bkt-tenant", "shared").expect(200).expect_body("shared"))
+            .step(get("/objects/logo.txt").header("x-bkt-tenant", "acme").expect(404)),
+        Scenario::new("the token's claim wins over the header")
+            .config(by_claim)
+            .step(put("/objects/logo.txt").header("authorization", bearer("acme")).header("x-bkt-tenant", "globex").body("acme's").expect(201))
+            .step(get("/objects/logo.txt").header("authorization", bearer("acme")).expect(200).expect_body("acme's"))
+            .step(get("/objects/logo.txt").header("authorization", bearer("globex")).expect(404))
+            .step(get("/objects/tenants/acme/logo.txt").auth("obj:read bkt:admin").expect(200).expect_body("acme's"))
+            .step(get("/stats").header("authorization", bearer("acme")).expect(403).expect_error("namespaced_caller")),
+        Scenario::new("a token without the claim can't name a tenant by header")
+            .config(by_claim)
+            .step(put("/objects/logo.txt").header("authorization", bearer("acme")).body("acme's").expect(201))
+            .step(get("/objects/logo.txt").auth("obj:read").header("x-bkt-tenant", "acme").expect(403).expect_error("tenant_not_in_credentials"))
+            .step(get("/objects/logo.txt").auth("obj:read").expect(400).expect_error("tenant_required")),
+    ]);
+}
+
+#[test]
+fn buckets() {
+    let two = |c: &mut rust_buck3t::consts::Config| c.buckets = vec!["public-assets".into(), "user-uploads".into()];
+    run(vec![
+        Scenario::new("same key in two buckets and the default one")
+            .config(two)
+            .step(put("/b/public-assets/objects/logo.txt").body("public").expect(201))
+            .step(put("/b/user-uploads/objects/logo.txt").body("private").expect(201))
+            .step(put("/objects/logo.txt").body("default").expect(201))
+            .step(get("/b/public-assets/objects/logo.txt").expect(200).expect_body("public"))
+            .step(get("/b/user-uploads/objects/logo.txt").expect(200).expect_body("private"))
+            .step(get("/objects/logo.txt").expect(200).expect_body("default"))
+            .step(get("/b/public-assets/objects").expect_json_len("", 1).expect_json("/0/key", "logo.txt"))
+            .step(get("/objects?recursive=1").expect_json_len("", 1).expect_json("/0/key", "logo.txt"))
+            .step(delete("/b/user-uploads/objects/logo.txt").expect(204))
+            .step(get("/b/public-assets/objects/logo.txt").expect(200)),
+        Scenario::new("no reaching across buckets")
+            .config(two)
+            .step(put("/b/public-assets/objects/a.txt").body("x").expect(201))
+            .step(get("/objects/public-assets/a.txt").expect(400))
+            .step(put("/objects/public-assets/b.txt").body("x").expect(400))
+            .step(get("/b/user-uploads/objects/..%2Fpublic-assets%2Fa.txt").expect(400))
+            .step(get("/objects?prefix=public-assets/").expect(200).expect_json_len("", 0))
+            .step(get("/objects?prefix=pub&recursive=1").expect_json_len("", 0))
+            .step(get("/objects-archive?prefix=public-assets").expect(400)),
+        Scenario::new("prefix listing inside a bucket")
+            .config(two)
+            .step(put("/b/user-uploads/objects/u1/a.txt").body("x").expect(201))
+            .step(put("/b/user-uploads/objects/u2/b.txt").body("x").expect(201))
+            .step(get("/b/user-uploads/objects?prefix=u1/").expect_json_len("", 1).expect_json("/0/key", "u1/a.txt"))
+            .step(get("/b/user-uploads/objects?prefix=u&recursive=1").expect_json_len("", 2))
+            .step(get("/b/user-uploads/objects/u1").expect(404).expect_json("/listing", "/b/user-uploads/objects?prefix=u1/")),
+        Scenario::new("unknown bucket")
+            .config(two)
+            .step(put("/b/nope/objects/a.txt").body("x").expect(404).expect_error("no_such_bucket"))
+            .step(get("/b/nope/objects").expect(404).expect_error("no_such_bucket")),
+        Scenario::new("create, list and delete buckets")
+            .step(put("/b/photos").expect(201).expect_json("/name", "photos"))
+            .step(put("/b/photos").expect(409).expect_error("bucket_exists"))
+            .step(put("/b/photos/objects/a.jpg").body("12345").expect(201))
+            .step(put("/b/photos/objects/2024/b.jpg").body("123").expect(201))
+            .step(put("/b/logs").expect(201))
+            .step(
+                get("/b")
+                    .expect(200)
+                    .expect_json_len("", 2)
+                    .expect_json("/0", serde_json::json!({ "name": "logs", "objects": 0, "bytes": 0 }))
+                    .expect_json("/1", serde_json::json!({ "name": "photos", "objects": 2, "bytes": 8 })),
+            )
+            .step(delete("/b/photos").expect(409).expect_error("bucket_not_empty").expect_json("/objects", 2))
+            .step(delete("/b/logs").expect(204))
+            .step(delete("/b/logs").expect(404).expect_error("no_such_bucket"))
+            .step(delete("/b/photos?force=1").expect(204))
+            .step(get("/b/photos/objects/a.jpg").expect(404).expect_error("no_such_bucket"))
+            .step(get("/b").expect_json_len("", 0))
+            // gone for good: the name is free again, and empty
+            .step(put("/b/photos").expect(201))
+            .step(get("/b/photos/objects?recursive=1").expect_json_len("", 0)),
+        Scenario::new("bucket names")
+            .step(put("/b/ab").expect(400).expect_error("invalid_bucket_name"))
+            .step(put("/b/...").expect(400).expect_error("invalid_bucket_name"))
+            .step(put("/b/Upper").expect(400).expect_error("invalid_bucket_name"))
+            .step(put("/b/a%2Fbc").expect(400).expect_error("invalid_bucket_name"))
+            .step(put("/b/.meta").expect(400).expect_error("invalid_bucket_name"))
+            .step(put("/objects/taken/x.txt").body("x").expect(201))
+            .step(put("/b/taken").expect(409).expect_error("name_in_use")),
+        Scenario::new("bucket management needs the admin scope")
+            .config(hs256)
+            .step(put("/b/photos").expect(401))
+            .step(put("/b/photos").auth("obj:write obj:read obj:list").expect(403))
+            .step(put("/b/photos").auth("bkt:admin").expect(201))
+            .step(get("/b").auth("obj:list").expect(403))
+            .step(get("/b").auth("bkt:admin").expect(200).expect_json("/0/name", "photos"))
+            .step(delete("/b/photos").auth("bkt:admin").expect(204)),
+        Scenario::new("bucket-scoped tokens")
+            .config(|c| {
+                two(c);
+                hs256(c);
+            })
+            .step(put("/b/user-uploads/objects/t.txt").auth("obj:write@user-uploads").body("x").expect(201))
+            .step(put("/b/public-assets/objects/t.txt").auth("obj:write@user-uploads").body("x").expect(403))
+            .step(put("/objects/t.txt").auth("obj:write@user-uploads").body("x").expect(403))
+            .step(put("/b/public-assets/objects/t.txt").auth("obj:write").body("x").expect(201)),
+    ]);
+}